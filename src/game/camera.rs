@@ -3,23 +3,24 @@ use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
 use bevy::window::PrimaryWindow;
 use crate::AppState;
 use crate::movement;
+use crate::movement::{KeyBinds, MOVE_VECTORS};
 use crate::game::camp::setup_camps;
 use crate::game::components::{Camp, CampStatus, Grade, Health, Player};
-use crate::game::{player, player::{LocalPlayer, LocalPlayerDeathEvent, LocalPlayerSpawnEvent, PLAYER_DEFAULT_HP, MAX_PLAYERS}, PlayerId};
+use crate::game::{player, player::{LocalPlayer, LocalPlayerDeathEvent, LocalPlayerSpawnEvent, PLAYER_DEFAULT_HP}, PlayerId};
 use crate::game::buffers::EventBuffer;
 use crate::game::player::SpawnEvent;
 use crate::map;
-use crate::net::{IsHost, TickNum};
+use crate::game::mapfile::SpawnPoints;
+use crate::net::{chat, lerp, IsHost, TickNum};
 
 pub const GAME_PROJ_SCALE: f32 = 0.5;
+const SPECTATOR_PAN_SPEED: f32 = 600.;
 
-const MINIMAP_DIMENSIONS: UVec2 = UVec2::new(map::MAPSIZE as u32, map::MAPSIZE as u32);
-const MINIMAP_PAD: UVec2 = UVec2::new(32, 32); // How many pixels between top right of window and top right of minimap (not border)
-const MINIMAP_TRANSLATION: Vec3 = Vec3::new(
-    ((super::WIN_W / 2.) as u32 - MINIMAP_PAD.x - (MINIMAP_DIMENSIONS.x / 2)) as f32 * GAME_PROJ_SCALE,
-    ((super::WIN_H / 2.) as u32 - MINIMAP_PAD.y - (MINIMAP_DIMENSIONS.y / 2)) as f32 * GAME_PROJ_SCALE,
-    5.
-);
+const MINIMAP_PAD: Vec2 = Vec2::new(32., 32.); // How many pixels between top right of window and top right of minimap (not border)
+const MINIMAP_Z: f32 = 5.;
+const MINIMAP_MAX_SIZE: f32 = 256.; // Largest side of the corner minimap in pixels, bigger maps get shrunk to fit
+const MINIMAP_BORDER_WIDTH: f32 = 8.; // minimap_border.png is the default map size plus this on every side
+const RESPAWN_PIXELS_PER_TILE: f32 = 2.; // Size of the respawn map unless the map is too big to fit the window that way
 
 const CAMP_MARKER_COLORS: [Color; 5] = [
     Color::Rgba{red: 0.2, green: 0.76, blue: 0.13, alpha: 1.}, // HP up
@@ -55,21 +56,29 @@ pub struct RespawnMap;
 #[derive(Component)]
 pub struct SpatialCameraBundle;
 
+/// Lives on the SpatialCameraBundle, used whenever there's no living local player to follow.
+/// follow is the id of the player being watched, None means the camera flies free.
+#[derive(Component)]
+pub struct SpectatorCamera {
+    pub follow: Option<u8>,
+}
+
 pub struct CameraPlugin;
 
 impl Plugin for CameraPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(Startup, startup)
-            .add_systems(Update, game_update.after(movement::handle_move).run_if(in_state(AppState::Game)))
+            .add_systems(Update, game_update.after(movement::handle_move).run_if(in_state(AppState::Game)).run_if(not(spectating)))
+            .add_systems(Update, (spectator_input.run_if(not(chat::typing)), spectator_follow.after(lerp::lerp_pos)).run_if(in_state(AppState::Game)).run_if(spectating))
             .add_systems(Update, spawn_update.run_if(player::local_player_dead))
             .add_systems(Update, marker_follow_local_player.run_if(not(player::local_player_dead)))
             .add_systems(OnEnter(AppState::Game), spawn_minimap.after(setup_camps))
             .add_systems(Update, configure_map_on_event)
             .add_systems(Update, spawn_camp_markers.run_if(any_with_component::<Camp>()))
             .add_systems(Update, hide_cleared_camp_markers.run_if(any_with_component::<CampMarker>()))
-            .add_systems(Update, spawn_enemy_player_markers.run_if(any_with_component::<LocalPlayer>()))
-            .add_systems(Update, show_enemy_player_markers.run_if(player::local_player_dead))
-            .add_systems(Update, hide_enemy_player_markers.run_if(not(player::local_player_dead)))
+            .add_systems(Update, spawn_enemy_player_markers.run_if(any_with_component::<Player>()))
+            .add_systems(Update, show_enemy_player_markers.run_if(spectating))
+            .add_systems(Update, hide_enemy_player_markers.run_if(not(spectating)))
             .add_systems(Update, show_hide_local_player_marker.run_if(any_with_component::<LocalPlayerMarker>()));
     }
 }
@@ -89,6 +98,7 @@ fn spawn_camera(
             ..Default::default()
         },
         SpatialCameraBundle,
+        SpectatorCamera { follow: None },
     )).with_children(|parent|{
             parent.spawn((
                 Camera2dBundle {
@@ -116,10 +126,17 @@ pub fn spawn_minimap(
                 translation: Vec3 {
                     x: 0.,
                     y: 0.,
-                    z: MINIMAP_TRANSLATION.z
+                    z: MINIMAP_Z
                 },
                 ..Default::default()
             },
+            sprite: Sprite {
+                custom_size: Some(Vec2::new(
+                    map.biome_map.width() as f32 + MINIMAP_BORDER_WIDTH * 2.,
+                    map.biome_map.height() as f32 + MINIMAP_BORDER_WIDTH * 2.
+                )),
+                ..Default::default()
+            },
             ..Default::default()
         },
         MinimapBorder,
@@ -129,7 +146,7 @@ pub fn spawn_minimap(
         commands.entity(parent).add_child(minimap_border_entity);
     }
 
-    let minimap: Image = draw_minimap(map);
+    let minimap: Image = draw_minimap(&map);
     let minimap_handle = assets.add(minimap);
 
     let minimap_entity = commands.spawn((
@@ -191,44 +208,41 @@ fn spawn_camp_markers(
     }
 }
 
+// Players can join mid game, so give a marker to anyone who doesn't have one yet
 fn spawn_enemy_player_markers(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     mut minimap: Query<Entity, With<Minimap>>,
-    enemy_player_markers: Query<Entity, With<EnemyPlayerMarker>>,
+    enemy_player_markers: Query<&EnemyPlayerMarker>,
+    players: Query<&Player>,
     local_player_id: Res<PlayerId>
 ) {
-    // Return immediately if the enemy player markers already exist
-    for _marker in &enemy_player_markers {
-        return;
-    }
-
     for parent in &mut minimap {
-        for player_id in 0..MAX_PLAYERS {
-            if player_id as u8 != local_player_id.0 {
-                let enemy_player_marker_ent = commands.spawn((
-                    SpriteBundle {
-                        texture: asset_server.load("player_marker.png"),
-                        transform: Transform {
-                            translation: Vec3 {
-                                x: 0.,
-                                y: 0.,
-                                z: 2.
-                            },
-                            ..Default::default()
-                        },
-                        sprite: Sprite {
-                            color: ENEMY_PLAYER_COLOR,
-                            ..Default::default()
+        for player in &players {
+            if player.0 == local_player_id.0 { continue }
+            if enemy_player_markers.iter().any(|marker| marker.0 == player.0) { continue }
+            let enemy_player_marker_ent = commands.spawn((
+                SpriteBundle {
+                    texture: asset_server.load("player_marker.png"),
+                    transform: Transform {
+                        translation: Vec3 {
+                            x: 0.,
+                            y: 0.,
+                            z: 2.
                         },
-                        visibility: Visibility::Hidden,
                         ..Default::default()
                     },
-                    EnemyPlayerMarker(player_id as u8),
-                )).id();
-        
-                commands.entity(parent).add_child(enemy_player_marker_ent);
-            }
+                    sprite: Sprite {
+                        color: ENEMY_PLAYER_COLOR,
+                        ..Default::default()
+                    },
+                    visibility: Visibility::Hidden,
+                    ..Default::default()
+                },
+                EnemyPlayerMarker(player.0),
+            )).id();
+
+            commands.entity(parent).add_child(enemy_player_marker_ent);
         }
     }
 }
@@ -286,17 +300,17 @@ fn hide_enemy_player_markers(
     }
 }
 
-// Creates and returns the Image of the minimap from the map data
-fn draw_minimap(
-    map: Res<map::WorldMap>,
+// Creates and returns the Image of the minimap from the map data, also what --export-map saves
+pub fn draw_minimap(
+    map: &map::WorldMap,
 ) -> Image 
 {
     let mut minimap_data: Vec<u8> = Vec::new();
 
     // Create data vec with 4 bytes per pixel from map data
-    for row in 0..map::MAPSIZE {
-        for col in 0..map::MAPSIZE {
-            let tile = map.biome_map[row][col];
+    for row in 0..map.biome_map.height() {
+        for col in 0..map.biome_map.width() {
+            let tile = map.biome_map[(row, col)];
             let mut rgba: Vec<u8>;
 
             match tile {
@@ -320,6 +334,18 @@ fn draw_minimap(
                     rgba = vec![241,213,166,255]; // SEPIA
                 
                 }
+                map::Biome::Water => {
+                    //rgba = vec![53,120,182,255]; // FULL COLOR
+                    rgba = vec![112,98,84,255]; // SEPIA
+                }
+                map::Biome::Forest => {
+                    //rgba = vec![27,86,23,255]; // FULL COLOR
+                    rgba = vec![104,70,16,255]; // SEPIA
+                }
+                map::Biome::Bridge => {
+                    //rgba = vec![139,93,50,255]; // FULL COLOR
+                    rgba = vec![204,160,98,255]; // SEPIA
+                }
             }
             minimap_data.append(&mut rgba);
         }
@@ -327,8 +353,8 @@ fn draw_minimap(
 
     let minimap = Image::new(
         Extent3d{
-            width: MINIMAP_DIMENSIONS.x,
-            height: MINIMAP_DIMENSIONS.y,
+            width: map.biome_map.width() as u32,
+            height: map.biome_map.height() as u32,
             depth_or_array_layers: 1
         },
         TextureDimension::D2,
@@ -343,7 +369,8 @@ fn draw_minimap(
 fn configure_map_on_event(
     mut minimap_border: Query<&mut Transform, With<MinimapBorder>>,
     mut death_reader: EventReader<LocalPlayerDeathEvent>,
-    mut spawn_reader: EventReader<LocalPlayerSpawnEvent>
+    mut spawn_reader: EventReader<LocalPlayerSpawnEvent>,
+    map: Res<map::WorldMap>,
 ) {
     let mut spawn_mode: Option<bool> = None;
     for _ in death_reader.iter() {
@@ -357,14 +384,7 @@ fn configure_map_on_event(
     if spawn_mode.is_none() {
         return;
     }
-    // minimap mode
-    let mut new_translation: Vec2 = Vec2::new(MINIMAP_TRANSLATION.x, MINIMAP_TRANSLATION.y);
-    let mut new_scale: f32 = GAME_PROJ_SCALE;
-
-    if spawn_mode.unwrap() {
-        new_translation = Vec2::new(0., 0.);
-        new_scale = 1.;
-    }
+    let (new_translation, new_scale) = minimap_placement(&map, spawn_mode.unwrap());
 
     // Set border translation/scale with aforementioned parameters
     for mut border_transform in &mut minimap_border {
@@ -379,14 +399,17 @@ fn configure_map_on_event(
 fn marker_follow_local_player(
     local_player: Query<&Transform, (With<LocalPlayer>, Without<LocalPlayerMarker>, Without<SpatialCameraBundle>)>,
     mut local_player_marker: Query<&mut Transform, (With<LocalPlayerMarker>, Without<SpatialCameraBundle>, Without<LocalPlayer>)>,
+    map: Res<map::WorldMap>,
 ) {
+    let half_width = ((map.biome_map.width() / 2) * map::TILESIZE) as f32;
+    let half_height = ((map.biome_map.height() / 2) * map::TILESIZE) as f32;
     for local_player_transform in &local_player {
         // Set marker position on minimap to reflect the player's current position in the game world
         for mut marker_tf in &mut local_player_marker {
-            if local_player_transform.translation.x > -(((map::MAPSIZE / 2) * map::TILESIZE) as f32) && local_player_transform.translation.x < ((map::MAPSIZE / 2) * map::TILESIZE) as f32 {
+            if local_player_transform.translation.x > -half_width && local_player_transform.translation.x < half_width {
                 marker_tf.translation.x = make_position_not_float(local_player_transform.translation.x / map::TILESIZE as f32);
             }
-            if local_player_transform.translation.y > -(((map::MAPSIZE / 2) * map::TILESIZE) as f32) && local_player_transform.translation.y < ((map::MAPSIZE / 2) * map::TILESIZE) as f32 {
+            if local_player_transform.translation.y > -half_height && local_player_transform.translation.y < half_height {
                 marker_tf.translation.y = make_position_not_float(local_player_transform.translation.y / map::TILESIZE as f32);
             }
         }
@@ -407,6 +430,27 @@ fn show_hide_local_player_marker(
     }
 }
 
+// Translation and scale of the minimap border, tucked in the corner while playing or filling the window to pick a respawn point
+fn minimap_placement(map: &map::WorldMap, respawning: bool) -> (Vec2, f32) {
+    let dims = Vec2::new(map.biome_map.width() as f32, map.biome_map.height() as f32);
+    if respawning {
+        return (Vec2::ZERO, respawn_pixels_per_tile(map) * GAME_PROJ_SCALE);
+    }
+    let pixels_per_tile = (MINIMAP_MAX_SIZE / dims.max_element()).min(1.);
+    let size = dims * pixels_per_tile;
+    let translation = Vec2::new(
+        (super::WIN_W / 2. - MINIMAP_PAD.x - size.x / 2.) * GAME_PROJ_SCALE,
+        (super::WIN_H / 2. - MINIMAP_PAD.y - size.y / 2.) * GAME_PROJ_SCALE
+    );
+    return (translation, pixels_per_tile * GAME_PROJ_SCALE);
+}
+
+fn respawn_pixels_per_tile(map: &map::WorldMap) -> f32 {
+    let fit_x = (super::WIN_W - MINIMAP_PAD.x * 2.) / map.biome_map.width() as f32;
+    let fit_y = (super::WIN_H - MINIMAP_PAD.y * 2.) / map.biome_map.height() as f32;
+    return RESPAWN_PIXELS_PER_TILE.min(fit_x).min(fit_y);
+}
+
 fn make_position_not_float(position: f32) -> f32 {
     return position as i32 as f32;
 }
@@ -416,12 +460,14 @@ fn spawn_update(
     window_query: Query<&Window, With<PrimaryWindow>>,
     mut local_player: Query<(&mut Transform, &mut Health, &mut EventBuffer, &mut Visibility), With<LocalPlayer>>,
     map: Res<map::WorldMap>,
+    spawn_points: Res<SpawnPoints>,
     is_host: Res<IsHost>,
     minimap: Query<Entity, With<Minimap>>,
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     local_player_marker: Query<Entity, With<LocalPlayerMarker>>,
     tick: Res<TickNum>,
+    res_id: Res<PlayerId>,
     mut lp_spawn_writer: EventWriter<LocalPlayerSpawnEvent>,
     mut spawn_writer: EventWriter<SpawnEvent>,
 ) {
@@ -429,15 +475,23 @@ fn spawn_update(
         let window = window_query.get_single().unwrap();
         let cursor_position = window.cursor_position().unwrap();
 
+        let (width, height) = (map.biome_map.width(), map.biome_map.height());
+        let pixels_per_tile = respawn_pixels_per_tile(&map);
+        // how far the respawn map reaches from the middle of the window in pixels
+        let half_size = Vec2::new(width as f32, height as f32) * pixels_per_tile / 2.;
         let mut cursor_to_map: UVec2 = UVec2::new(0, 0);
-        if (cursor_position.x > ((super::WIN_W / 2.) - MINIMAP_DIMENSIONS.x as f32)) &&
-            (cursor_position.x < ((super::WIN_W / 2.) + MINIMAP_DIMENSIONS.x as f32)) &&
-            (cursor_position.y > ((super::WIN_H / 2.) - MINIMAP_DIMENSIONS.y as f32)) &&
-            (cursor_position.y < ((super::WIN_H / 2.) + MINIMAP_DIMENSIONS.y as f32))
+        if (cursor_position.x > ((super::WIN_W / 2.) - half_size.x)) &&
+            (cursor_position.x < ((super::WIN_W / 2.) + half_size.x)) &&
+            (cursor_position.y > ((super::WIN_H / 2.) - half_size.y)) &&
+            (cursor_position.y < ((super::WIN_H / 2.) + half_size.y))
         {
-            cursor_to_map.x = ((cursor_position.x as u32 - ((super::WIN_W / 2.) as u32 - MINIMAP_DIMENSIONS.x)) / 2).clamp(0, (map::MAPSIZE - 1) as u32);
-            cursor_to_map.y = ((cursor_position.y as u32 - ((super::WIN_H / 2.) as u32 - MINIMAP_DIMENSIONS.y)) / 2).clamp(0, (map::MAPSIZE - 1) as u32);
-            let tile = map.biome_map[cursor_to_map.y as usize][cursor_to_map.x as usize];
+            cursor_to_map.x = (((cursor_position.x - ((super::WIN_W / 2.) - half_size.x)) / pixels_per_tile) as u32).clamp(0, (width - 1) as u32);
+            cursor_to_map.y = (((cursor_position.y - ((super::WIN_H / 2.) - half_size.y)) / pixels_per_tile) as u32).clamp(0, (height - 1) as u32);
+            // hand-made maps can pin spawns down, go to whichever is closest to the click
+            if let Some(spawn) = spawn_points.nearest(cursor_to_map.as_vec2()) {
+                cursor_to_map = spawn.as_uvec2();
+            }
+            let tile = map.biome_map[(cursor_to_map.y as usize, cursor_to_map.x as usize)];
             if tile != map::Biome::Wall {
                 let (mut lp_tf, mut lp_hp, mut lp_eb, mut lp_vis) = local_player.single_mut();
 
@@ -451,10 +505,10 @@ fn spawn_update(
                 }
                 if is_host.0 {
                     lp_spawn_writer.send(LocalPlayerSpawnEvent);
-                    spawn_writer.send(SpawnEvent { id: 0 });
+                    spawn_writer.send(SpawnEvent { id: res_id.0 });
                 }
-                lp_tf.translation.x = (cursor_to_map.x as f32 - 128.) * 16.;
-                lp_tf.translation.y = -(cursor_to_map.y as f32 - 128.) * 16.;
+                lp_tf.translation.x = (cursor_to_map.x as f32 - (width / 2) as f32) * map::TILESIZE as f32;
+                lp_tf.translation.y = -(cursor_to_map.y as f32 - (height / 2) as f32) * map::TILESIZE as f32;
 
                 // Spawn local player marker if necessary
                 if local_player_marker.get_single().is_ok() { return }
@@ -485,42 +539,109 @@ fn spawn_update(
 // Runs in Game state, makes SpatialCameraBundle follow player
 fn game_update(
     local_player: Query<&Transform, (With<LocalPlayer>, Without<LocalPlayerMarker>, Without<SpatialCameraBundle>)>,
-    mut game_camera: Query<&mut Transform, (With<SpatialCameraBundle>, Without<LocalPlayerMarker>, Without<LocalPlayer>)>
+    mut game_camera: Query<&mut Transform, (With<SpatialCameraBundle>, Without<LocalPlayerMarker>, Without<LocalPlayer>)>,
+    map: Res<map::WorldMap>,
 ) {
     for local_player_transform in &local_player {
         // Make SpatialCameraBundle follow player
         for mut camera_transform in &mut game_camera {
             camera_transform.translation.x = local_player_transform.translation.x;
             camera_transform.translation.y = local_player_transform.translation.y;
+            clamp_to_map(&mut camera_transform, &map);
+        }
+    }
+}
 
-            let clamp_pos_x: f32 = ((((map::MAPSIZE * map::TILESIZE) as isize)/2) - (((super::WIN_W * GAME_PROJ_SCALE) / 2.) as isize)) as f32;
-            let clamp_pos_y: f32 = ((((map::MAPSIZE * map::TILESIZE) as isize)/2) - (((super::WIN_H * GAME_PROJ_SCALE) / 2.) as isize)) as f32;
+// Clamp camera view to map borders
+// Center camera in axis if map dimensions < window size
+fn clamp_to_map(camera_transform: &mut Transform, map: &map::WorldMap) {
+    let map_w = map.biome_map.width() * map::TILESIZE;
+    let map_h = map.biome_map.height() * map::TILESIZE;
+    let clamp_pos_x: f32 = (((map_w as isize)/2) - (((super::WIN_W * GAME_PROJ_SCALE) / 2.) as isize)) as f32;
+    let clamp_pos_y: f32 = (((map_h as isize)/2) - (((super::WIN_H * GAME_PROJ_SCALE) / 2.) as isize)) as f32;
 
-            // Clamp camera view to map borders
-            // Center camera in axis if map dimensions < window size
-            if map::MAPSIZE * map::TILESIZE < super::WIN_W as usize {
-                camera_transform.translation.x = 0.
-            }
-            else {
-                if camera_transform.translation.x > clamp_pos_x {
-                    camera_transform.translation.x = clamp_pos_x
-                }
-                if camera_transform.translation.x < -clamp_pos_x {
-                    camera_transform.translation.x = -clamp_pos_x;
-                }
-            }
+    if map_w < super::WIN_W as usize {
+        camera_transform.translation.x = 0.
+    }
+    else {
+        if camera_transform.translation.x > clamp_pos_x {
+            camera_transform.translation.x = clamp_pos_x
+        }
+        if camera_transform.translation.x < -clamp_pos_x {
+            camera_transform.translation.x = -clamp_pos_x;
+        }
+    }
 
-            if map::MAPSIZE * map::TILESIZE < super::WIN_H as usize {
-                camera_transform.translation.y = 0.
-            }
-            else {
-                if camera_transform.translation.y > clamp_pos_y {
-                    camera_transform.translation.y = clamp_pos_y
-                }
-                if camera_transform.translation.y < -clamp_pos_y {
-                    camera_transform.translation.y = -clamp_pos_y;
-                }
+    if map_h < super::WIN_H as usize {
+        camera_transform.translation.y = 0.
+    }
+    else {
+        if camera_transform.translation.y > clamp_pos_y {
+            camera_transform.translation.y = clamp_pos_y
+        }
+        if camera_transform.translation.y < -clamp_pos_y {
+            camera_transform.translation.y = -clamp_pos_y;
+        }
+    }
+}
+
+// F cycles the camera through living players and back to free flying, WASD pans while free
+fn spectator_input(
+    input: Res<Input<KeyCode>>,
+    key_binds: Res<KeyBinds>,
+    time: Res<Time>,
+    players: Query<(&Player, &Health)>,
+    mut game_camera: Query<(&mut Transform, &mut SpectatorCamera)>,
+    map: Res<map::WorldMap>,
+) {
+    for (mut camera_transform, mut spectator) in &mut game_camera {
+        if input.just_pressed(KeyCode::F) {
+            let mut alive: Vec<u8> = players.iter().filter(|(_, hp)| !hp.dead).map(|(pl, _)| pl.0).collect();
+            alive.sort();
+            spectator.follow = match spectator.follow {
+                None => alive.first().copied(),
+                Some(id) => alive.into_iter().find(|other| *other > id),
+            };
+        }
+        if spectator.follow.is_some() { continue }
+        let mut mv: usize = input.pressed(key_binds.up) as usize * 0b0001;
+        mv |= input.pressed(key_binds.down) as usize * 0b0010;
+        mv |= input.pressed(key_binds.left) as usize * 0b0100;
+        mv |= input.pressed(key_binds.right) as usize * 0b1000;
+        let pan = MOVE_VECTORS[mv] * SPECTATOR_PAN_SPEED * time.delta_seconds();
+        camera_transform.translation.x += pan.x;
+        camera_transform.translation.y += pan.y;
+        clamp_to_map(&mut camera_transform, &map);
+    }
+}
+
+// Keeps the camera on the followed player, goes back to free flying if they die
+fn spectator_follow(
+    players: Query<(&Player, &Transform, &Health), Without<SpectatorCamera>>,
+    mut game_camera: Query<(&mut Transform, &mut SpectatorCamera)>,
+    map: Res<map::WorldMap>,
+) {
+    for (mut camera_transform, mut spectator) in &mut game_camera {
+        if spectator.follow.is_none() { continue }
+        let id = spectator.follow.unwrap();
+        for (pl, tf, hp) in &players {
+            if pl.0 != id { continue }
+            if hp.dead {
+                spectator.follow = None;
+                continue;
             }
+            camera_transform.translation.x = tf.translation.x;
+            camera_transform.translation.y = tf.translation.y;
+            clamp_to_map(&mut camera_transform, &map);
         }
     }
-}
\ No newline at end of file
+}
+
+// RUN CONDITIONS
+
+// true for spectators and replays (no local player) and for local players waiting to respawn
+pub fn spectating(local_player: Query<&Health, With<LocalPlayer>>) -> bool {
+    let health = local_player.get_single();
+    if health.is_err() { return true; }
+    return health.unwrap().dead;
+}
@@ -4,22 +4,23 @@ use rand_chacha::ChaChaRng;
 use crate::AppState;
 use crate::game::enemy;
 use crate::Atlas;
-use crate::map::{MAPSIZE, TILESIZE, CampNodes};
+use crate::map::{MapSize, SpawnSettings, TILESIZE, CampNodes, WorldMap};
 use crate::components::*;
 use crate::Decorations;
 use crate::Chests;
 use crate::buffers::*;
 use crate::game::enemy::ENEMY_MAX_HP;
 use crate::game::map::setup_map;
+use crate::game::symmetry::{fairness, MapSymmetry};
 use crate::map::MapSeed;
 use crate::map::ChestCoords;
 use crate::net::{is_host, TickNum};
 use crate::PowerupAtlas;
 
-const CAMP_ENEMIES: u8 = 5;
+pub const MAX_CAMP_ENEMIES: u8 = 5; // Enemy slots in each prefab, see get_prefab_data
+pub const DEFAULT_EID_PERCENTAGE: u8 = 50;
 const NUM_GRADES: u8 = 5;
 const DEC_SIZE: Vec2 = Vec2 {x: 32., y: 32.};
-const POWERUP_DROP_CHANCE: u32 = 50;
 const CAMP_RESPAWN_TIME: f32 = 60.;
 
 #[derive(Component)]
@@ -49,9 +50,18 @@ pub fn setup_camps(
     camp_nodes: Res<CampNodes>,
     decoration_atlas: Res<Decorations>,
     map_seed: Res<MapSeed>,
+    map_size: Res<MapSize>,
+    spawn_settings: Res<SpawnSettings>,
     asset_server: Res<AssetServer>,
+    world_map: Res<WorldMap>,
+    chest_coords: Res<ChestCoords>,
+    map_symmetry: Res<MapSymmetry>,
 ) {
     let mut rng = ChaChaRng::seed_from_u64(map_seed.0);
+    let rolls = roll_camps(&mut rng, camp_nodes.0.len(), map_symmetry.0.copies(), &spawn_settings);
+    let grades: Vec<u8> = rolls.iter().map(|roll| roll.grade).collect();
+    info!("map fairness ({} symmetry): {}", map_symmetry.0.name(),
+        fairness(map_symmetry.0, &world_map.biome_map, &camp_nodes.0, &grades, &chest_coords.0));
     // spawn a camp at a specified position
 
     //TODO: respawn enemies in a camp after a certain amount of time
@@ -59,16 +69,14 @@ pub fn setup_camps(
     // Iterate through the MST of camps generated by perlin noise and spawn a camp at each node
     let mut campid: u8 = 0; 
     let mut id: u8 = 0;
-    for camps in camp_nodes.0.iter(){
+    for (camps, roll) in camp_nodes.0.iter().zip(rolls.into_iter()){
         // x-y position of the camp
-        let camp_pos: Vec2 = get_spawn_vec(camps.x, camps.y);
+        let camp_pos: Vec2 = get_spawn_vec(camps.x, camps.y, &map_size);
         // determines camp/enemy type
-        let camp_grade: u8 = rng.gen_range(1..=NUM_GRADES);
+        let camp_grade = roll.grade;
         //get the prefab data for the given grade
         let prefab_data = get_prefab_data(camp_grade);
 
-        let special_enemy_index = rng.gen_range(0..CAMP_ENEMIES);
-
         commands.spawn((
             Camp(campid),
             SpatialBundle {
@@ -77,8 +85,8 @@ pub fn setup_camps(
             },
             Grade(camp_grade),
             CampEnemies{
-                max_enemies: CAMP_ENEMIES,
-                current_enemies: CAMP_ENEMIES,
+                max_enemies: spawn_settings.enemies_per_camp,
+                current_enemies: spawn_settings.enemies_per_camp,
             },
             CampStatus(true),
             CampRespawnTimer(Timer::from_seconds(CAMP_RESPAWN_TIME, TimerMode::Once)),
@@ -105,19 +113,19 @@ pub fn setup_camps(
                     ..default()
                 },
                 Collider(DEC_SIZE),
+                Decoration,
             ));
 
             vec_counter+=2;
         }
 
         //spawn enemies for this camp
-        for n in 0..CAMP_ENEMIES{
-            let is_special = n == special_enemy_index;
+        for n in 0..spawn_settings.enemies_per_camp{
+            let is_special = n == roll.special_enemy_index;
             //generate a random powerup to drop from each enemy
             let powerups: [PowerUpType; 5] = [PowerUpType::Meat, PowerUpType::DamageDealtUp, PowerUpType::DamageReductionUp, PowerUpType::AttackSpeedUp, PowerUpType::MovementSpeedUp];
-            //TODO: make this a random percentage based on the mapconfig resource
             let power_up_to_drop = powerups[camp_grade as usize - 1];
-            let mut chance_drop_powerup = rng.gen_range(0..100) < POWERUP_DROP_CHANCE;
+            let mut chance_drop_powerup = roll.drops_powerup[n as usize];
 
             if is_special{
                 chance_drop_powerup = true;
@@ -145,26 +153,57 @@ pub fn setup_camps(
 
 }
 
+/// Everything setup_camps draws from the seeded rng for one camp
+#[derive(Clone)]
+pub struct CampRoll {
+    pub grade: u8,
+    pub special_enemy_index: u8,
+    pub drops_powerup: Vec<bool>, // one per enemy slot
+}
+
+// Rolls the next camp, in the order setup_camps always has so a seed keeps giving the same camps
+pub fn roll_camp(rng: &mut ChaChaRng, spawn_settings: &SpawnSettings) -> CampRoll {
+    let grade = rng.gen_range(1..=NUM_GRADES);
+    let special_enemy_index = rng.gen_range(0..spawn_settings.enemies_per_camp);
+    let drops_powerup = (0..spawn_settings.enemies_per_camp)
+        .map(|_| rng.gen_range(0..100) < spawn_settings.eid_percentage as u32)
+        .collect();
+    CampRoll { grade, special_enemy_index, drops_powerup }
+}
+
+// Rolls count camps. A symmetric map's camps come one sector after another, so only the first sector's get rolled
+// and every copy of a camp gets the same grade and drops as the camp it's a copy of
+pub fn roll_camps(rng: &mut ChaChaRng, count: usize, copies: usize, spawn_settings: &SpawnSettings) -> Vec<CampRoll> {
+    let unique: Vec<CampRoll> = (0..count / copies).map(|_| roll_camp(rng, spawn_settings)).collect();
+    return (0..count).map(|i| unique[i % unique.len()].clone()).collect();
+}
+
 pub fn setup_chests(
     mut commands: Commands,
     chest_coords: Res<ChestCoords>,
     map_seed: Res<MapSeed>,
+    map_size: Res<MapSize>,
     chest_atlas: Res<Chests>,
+    map_symmetry: Res<MapSymmetry>,
 ){
 
     // for chests in chest_coords, commands.spawn with chest component and health
     let mut rng = ChaChaRng::seed_from_u64(map_seed.0);
     let mut i = 0;
+    // 5 random powerups, copies of a chest on a symmetric map hold the same as the chest they're a copy of
+    let unique = chest_coords.0.len() / map_symmetry.0.copies();
+    let contents: Vec<[u8; CHEST_CONTENTS]> = (0..unique)
+        .map(|_| [rng.gen_range(0..5), rng.gen_range(0..5), rng.gen_range(0..5), rng.gen_range(0..5), rng.gen_range(0..5)])
+        .collect();
     
     for chest in chest_coords.0.iter(){
-        let chest_pos: Vec2 = get_spawn_vec(chest.x, chest.y);
+        let chest_pos: Vec2 = get_spawn_vec(chest.x, chest.y, &map_size);
 
         let pb = PosBuffer(CircularBuffer::new_from(Some(chest_pos)));
         commands.spawn((
             ItemChest{
                 id: i,
-                // 5 random powerups
-                contents: [rng.gen_range(0..5), rng.gen_range(0..5), rng.gen_range(0..5), rng.gen_range(0..5), rng.gen_range(0..5)]
+                contents: contents[i as usize % unique],
             },
             pb,
             Health {
@@ -242,9 +281,9 @@ pub fn handle_chest_hit(
 }
 
 // convert given row and col into x and y coordinates. Returns a vec2 of these coordinates
-fn get_spawn_vec(row: f32, col:f32) -> Vec2{
-    let x_coord = TILESIZE as f32 * (row - (MAPSIZE as f32/2. + 0.5));
-    let y_coord = TILESIZE as f32 * ((MAPSIZE as f32/2. - 0.5) - col);
+fn get_spawn_vec(row: f32, col:f32, map_size: &MapSize) -> Vec2{
+    let x_coord = TILESIZE as f32 * (row - (map_size.width as f32/2. + 0.5));
+    let y_coord = TILESIZE as f32 * ((map_size.height as f32/2. - 0.5) - col);
 
     Vec2::new(x_coord, y_coord)
 }
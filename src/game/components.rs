@@ -94,6 +94,9 @@ pub struct CampStatus(pub bool); // true if camp is captured, false if not
 #[derive(Component)]
 pub struct EnemyCamp(pub u8); // holds id of enemy's parent camp
 
+#[derive(Component)]
+pub struct Decoration;
+
 #[derive(Component)]
 pub struct ItemChest{
     pub id: u8,
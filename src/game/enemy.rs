@@ -7,16 +7,18 @@ use crate::game::buffers::*;
 use crate::game::components::*;
 use crate::net::{is_client, is_host, TickNum};
 use crate::game::components::PowerUpType;
-use crate::game::map::{Biome, TILESIZE, MAPSIZE, WorldMap};
+use crate::game::map::{PathCosts, TILESIZE, WorldMap, get_tile_at_pos, in_sight};
+use crate::game::grid::Grid;
 use crate::game::movement;
-use crate::game::player::{LocalPlayer, LocalPlayerDeathEvent, LocalPlayerSpawnEvent, PLAYER_DEFAULT_DEF, PLAYER_DEFAULT_HP, PlayerShield};
+use crate::game::player::{LocalPlayer, LocalPlayerDeathEvent, LocalPlayerSpawnEvent, PLAYER_DEFAULT_DEF, PLAYER_DEFAULT_HP, PlayerNames, PlayerShield};
+use crate::net::chat::SystemMessage;
 use std::collections::{BinaryHeap, HashMap};
 use std::cmp::Ordering;
 use bevy::ecs::component::Tick;
 use crate::PowerupAtlas;
 
 pub const ENEMY_SIZE: Vec2 = Vec2 { x: 32., y: 32. };
-pub const ENEMY_SPEED: f32 = 150. / net::TICKRATE as f32;
+pub const ENEMY_SPEED: f32 = 150.;  // per second
 pub const ENEMY_MAX_HP: u8 = 100;
 pub const AGGRO_RANGE: f32 = 200.0;
 pub const ATTACK_RATE: f32 = 4.0;
@@ -50,7 +52,7 @@ pub struct SpawnEnemyWeaponTimer(Timer);
 pub struct EnemyRegenTimer(Timer);
 
 #[derive(Component)]
-pub struct IsSpecial(bool);
+pub struct IsSpecial(pub bool);
 
 #[derive(Component)]
 pub struct SpawnPosition(pub Vec2);
@@ -243,6 +245,8 @@ pub fn update_enemies(
     mut player: Query<(&mut Stats, &Player)>,
     powerup_atlas: Res<PowerupAtlas>,
     mut camp_query: Query<(&Camp, &mut CampEnemies, &CampStatus), With<Camp>>,
+    names: Res<PlayerNames>,
+    mut system_writer: EventWriter<SystemMessage>,
 ) {
     for (mut hp, hb, la, spu, mut sp, tf, ec_num, cdpu, mut vis) in enemies.iter_mut() {
         let next_hp = hb.0.get(tick.0);
@@ -281,6 +285,9 @@ pub fn update_enemies(
                         if pl.0 == la.0.expect("camp has no attacker") {
                             stats.score = stats.score.saturating_add(5);
                             stats.camps_captured = stats.camps_captured.saturating_add(1);
+                            if camp_num.0 == ec_num.0 {
+                                system_writer.send(SystemMessage(format!("{} captured camp {}", names.get(pl.0), camp_num.0)));
+                            }
                         }
                     }
                 }
@@ -307,10 +314,13 @@ pub fn fixed_aggro(
     asset_server: Res<AssetServer>,
     mut commands: Commands,
     mut enemies: Query<(Entity, &PosBuffer, &mut Aggro, &mut SpawnEnemyWeaponTimer), With<Enemy>>,
-    players: Query<(&Player, &PosBuffer, &Health), Without<Enemy>>
+    players: Query<(&Player, &PosBuffer, &Health), Without<Enemy>>,
+    map: Res<WorldMap>,
 ) {
     for (enemy_entity, epb, mut aggro, mut wep_timer) in &mut enemies {
         let prev = epb.0.get(tick.0.wrapping_sub(1));
+        if prev.is_none() { continue }
+        let prev = prev.unwrap();
         let mut closest_player = None;
         let mut best_distance = f32::MAX;
         for (pl, ppb, hp) in &players {
@@ -318,11 +328,13 @@ pub fn fixed_aggro(
             let next = ppb.0.get(tick.0);
             if next.is_none() { continue }
             let next = next.unwrap();
-            let dist = next.distance(prev.unwrap());
-            if dist < best_distance {
-                best_distance = dist;
-                closest_player = Some(pl);
-            }
+            // the sight line is the expensive part, only walk it for players that would be in range and closer
+            let dist = next.distance(prev);
+            if dist > AGGRO_RANGE || dist >= best_distance { continue }
+            // players behind forest can't be seen, even up close
+            if !in_sight(&prev.extend(0.), &next.extend(0.), &map.biome_map) { continue }
+            best_distance = dist;
+            closest_player = Some(pl);
         }
         if best_distance > AGGRO_RANGE || closest_player.is_none() {
             if aggro.0.is_some() {
@@ -356,23 +368,26 @@ pub fn fixed_aggro(
 
 pub fn fixed_move(
     tick: Res<net::TickNum>,
+    tick_rate: Res<net::TickRate>,
     mut enemies: Query<(&mut PosBuffer, &Aggro, &SpawnPosition), (With<Enemy>, Without<Player>)>,
     players: Query<(&Player, &PosBuffer), (With<Player>, Without<Enemy>)>,
-    map: Res<WorldMap>
+    map: Res<WorldMap>,
+    path_costs: Res<PathCosts>,
 ) {
     for (mut epb, aggro, spawn_pos) in &mut enemies {
         let prev = epb.0.get(tick.0.wrapping_sub(1));
         if prev.is_none() { continue }
         let prev = prev.unwrap();
         let mut next = prev.clone();
+        let speed = get_tile_at_pos(&prev.extend(0.), &map.biome_map).speed();
 
         'mov: {
             if aggro.0.is_none() {
                 // move the enemy to their spawn position
                 let displacement = spawn_pos.0 - prev;
                 if !(displacement.length() < CIRCLE_RADIUS) {
-                    let posit = find_next(&map.biome_map, prev, spawn_pos.0);
-                    let movement = (posit - prev).normalize() * ENEMY_SPEED;
+                    let posit = find_next(&path_costs.0, prev, spawn_pos.0);
+                    let movement = (posit - prev).normalize() * ENEMY_SPEED * speed * tick_rate.tick_len();
                     next += movement;
                 }
             } else {
@@ -388,8 +403,8 @@ pub fn fixed_move(
 
                 let displacement = player_pos - prev;
                 if !(displacement.length() < CIRCLE_RADIUS) {
-                    let posit = find_next(&map.biome_map, prev, player_pos);
-                    let movement = (posit - prev).normalize() * ENEMY_SPEED;
+                    let posit = find_next(&path_costs.0, prev, player_pos);
+                    let movement = (posit - prev).normalize() * ENEMY_SPEED * speed * tick_rate.tick_len();
                     next += movement;
                 }
             }
@@ -398,32 +413,17 @@ pub fn fixed_move(
     }
 }
 
+// map is what each tile costs to step on, see PathCosts
 pub fn find_next(
-    map: &[[Biome; MAPSIZE]; MAPSIZE],
+    map: &Grid<i32>,
     s: Vec2,
     t: Vec2,
 ) -> Vec2 {
-    let start = convert_vec(s);
-    let target = convert_vec(t);
-
-    // generate copy of map that uses integers instead of Biomes
-    // declare:
-    let mut u_map = [[0; MAPSIZE]; MAPSIZE];
-
-    // edit:
-    for x in 0..MAPSIZE {
-        for y in 0..MAPSIZE {
-            u_map[x][y] = match map[x][y] {
-                Biome::Wall => 1,
-                _ => 0,
-            };
-        }
-    }
-
-    u_map = process_map(&u_map);
+    let start = convert_vec(s, map);
+    let target = convert_vec(t, map);
 
     // get path
-    let path = a_star(&u_map, start, target);
+    let path = a_star(map, start, target);
     let pivots = find_pivot_points(path);
 
 
@@ -435,21 +435,21 @@ pub fn find_next(
     }
 
 
-    convert_back(go_to)
+    convert_back(go_to, map)
 }
 
 // fitting the tile values to the code below
-pub fn convert_vec(vec: Vec2) -> V2 {
-    let col = (vec.x + (TILESIZE * MAPSIZE / 2) as f32) as usize / TILESIZE;
-    let row = (-vec.y + (TILESIZE * MAPSIZE / 2) as f32) as usize / TILESIZE;
+pub fn convert_vec<T>(vec: Vec2, map: &Grid<T>) -> V2 {
+    let col = (vec.x + (TILESIZE * map.width() / 2) as f32) as usize / TILESIZE;
+    let row = (-vec.y + (TILESIZE * map.height() / 2) as f32) as usize / TILESIZE;
     let v2 = V2 { x: col, y: row };
     v2
 }
 
 // converting back to the overworld values
-pub fn convert_back(v2: V2) -> Vec2 {
-    let x = (v2.x * TILESIZE) as f32 - (TILESIZE * MAPSIZE / 2) as f32;
-    let y = -(v2.y as isize * TILESIZE as isize) as f32 + (TILESIZE * MAPSIZE / 2) as f32;
+pub fn convert_back<T>(v2: V2, map: &Grid<T>) -> Vec2 {
+    let x = (v2.x * TILESIZE) as f32 - (TILESIZE * map.width() / 2) as f32;
+    let y = -(v2.y as isize * TILESIZE as isize) as f32 + (TILESIZE * map.height() / 2) as f32;
     Vec2 { x, y }
 }
 
@@ -478,8 +478,8 @@ impl PartialOrd for Node {
 }
 
 // check if position in map is valid
-pub fn is_valid_position(map: &[[i32; MAPSIZE]]) -> Box<dyn Fn(V2) -> bool + '_> {
-    Box::new(move |pos| pos.x < MAPSIZE && pos.y < map.len() && map[pos.y][pos.x] != 1)
+pub fn is_valid_position(map: &Grid<i32>) -> Box<dyn Fn(V2) -> bool + '_> {
+    Box::new(move |pos| map.get(pos.y as isize, pos.x as isize).is_some_and(|tile| tile > 0))
 }
 
 // get path from hash table
@@ -497,35 +497,7 @@ pub fn reconstruct_path(from: HashMap<V2, V2>, mut current: V2) -> Vec<V2> {
     path
 }
 
-pub fn process_map(map: &[[i32; MAPSIZE]; MAPSIZE]) -> [[i32; MAPSIZE]; MAPSIZE] {
-    let mut u_map = [[0; MAPSIZE]; MAPSIZE];
-
-    for x in 0..MAPSIZE {
-        for y in 0..MAPSIZE {
-            u_map[x][y] = map[x][y];
-
-            if u_map[x][y] == 2 {
-                // Update horizontal and vertical neighbors
-                if x > 0 {
-                    u_map[x - 1][y] = 2;
-                }
-                if x < MAPSIZE - 1 {
-                    u_map[x + 1][y] = 2;
-                }
-                if y > 0 {
-                    u_map[x][y - 1] = 2;
-                }
-                if y < MAPSIZE - 1 {
-                    u_map[x][y + 1] = 2;
-                }
-            }
-        }
-    }
-
-    u_map
-}
-
-pub fn a_star(map: &[[i32; MAPSIZE]], start: V2, target: V2) -> Vec<V2> {
+pub fn a_star(map: &Grid<i32>, start: V2, target: V2) -> Vec<V2> {
     let is_valid_position = is_valid_position(map);
 
     // pq for open list
@@ -575,7 +547,8 @@ pub fn a_star(map: &[[i32; MAPSIZE]], start: V2, target: V2) -> Vec<V2> {
                     continue;
                 }
 
-                let tentative_g_score = g_score.get(&position).unwrap_or(&0) + 1;
+                // water and forest cost more to cross, so enemies go around when it's not much further
+                let tentative_g_score = g_score.get(&position).unwrap_or(&0) + map[(neighbor.y, neighbor.x)] as usize;
                 // if new path is better/worse
                 if !g_score.contains_key(&neighbor) || tentative_g_score < *g_score.get(&neighbor).unwrap() {
 
@@ -0,0 +1,107 @@
+use std::error::Error;
+use std::fmt::Write as _;
+use std::fs;
+use bevy::prelude::*;
+use rand_chacha::{rand_core::SeedableRng, ChaChaRng};
+use crate::cli_arg;
+use crate::game::camera::draw_minimap;
+use crate::game::camp::roll_camps;
+use crate::game::grid::Grid;
+use crate::game::map::{self, Biome, MapSize, SpawnSettings, WorldMap};
+use crate::game::matchcode::{seed_from_text, MatchCode};
+use crate::game::symmetry::{fairness, spawn_points};
+
+/// `jordquest --export-map <seed>` generates that seed's map without starting the game and writes
+/// `<out>.png`, one minimap colored pixel per tile, and `<out>.json` with the camps, their grades,
+/// the chests, the spawn points of a symmetric map, its fairness score and the path graph.
+/// Coordinates are in tiles, x across and y down, the same as the png.
+/// The seed can be text like on the host page, or a match code which brings the rest of its settings along.
+/// Otherwise the rest of the host page can be given as --camps, --map-width, --map-height, --chests,
+/// --enemies-per-camp, --eid-percentage and --symmetry, `<out>` is --out and defaults to map-<seed>.
+/// Returns the process exit code.
+pub fn run(seed: &str) -> i32 {
+    if seed.trim().is_empty() {
+        eprintln!("usage: jordquest --export-map <seed or match code> [--out <path without extension>]");
+        return 2;
+    }
+    let code = MatchCode::decode(seed).unwrap_or_else(|| from_args(seed_from_text(seed)));
+    let out = cli_arg("--out").unwrap_or(format!("map-{}", code.seed));
+    match export(&code, &out) {
+        Ok(()) => {
+            println!("wrote {}.png and {}.json", out, out);
+            0
+        },
+        Err(e) => {
+            eprintln!("can't export seed {}: {}", code.seed, e);
+            1
+        },
+    }
+}
+
+// the settings a plain seed gets, with the same fallbacks as the host page
+fn from_args(seed: u64) -> MatchCode {
+    let num_camps = cli_arg("--camps").and_then(|v| v.trim().parse::<u8>().ok()).unwrap_or(10);
+    let side = |flag| cli_arg(flag).and_then(|v| v.trim().parse::<usize>().ok()).unwrap_or(map::DEFAULT_MAPSIZE);
+    let map_size = MapSize::new(side("--map-width"), side("--map-height"));
+    let spawn_settings = SpawnSettings::parse(
+        &cli_arg("--chests").unwrap_or_default(),
+        &cli_arg("--enemies-per-camp").unwrap_or_default(),
+        &cli_arg("--eid-percentage").unwrap_or_default(),
+        &cli_arg("--symmetry").unwrap_or_default(),
+    );
+    return MatchCode { seed, num_camps, map_size, spawn_settings };
+}
+
+fn export(code: &MatchCode, out: &str) -> Result<(), Box<dyn Error>> {
+    let MatchCode { seed, num_camps, map_size, spawn_settings } = *code;
+
+    // the same steps setup_map and setup_camps take, so the files match what players get on this seed
+    let mut world_map = WorldMap { tile_size: map::TILESIZE, biome_map: Grid::new(map_size.width, map_size.height, Biome::Free) };
+    let (mut camp_nodes, mut chest_coords) = (Vec::new(), Vec::new());
+    let mut rng = ChaChaRng::seed_from_u64(seed);
+    let symmetry = spawn_settings.symmetry;
+    let graph = map::read_map(&mut world_map, &mut camp_nodes, num_camps, spawn_settings.num_chests as usize, symmetry, &mut rng, &mut chest_coords)?;
+    let mut camp_rng = ChaChaRng::seed_from_u64(seed);
+    let grades: Vec<u8> = roll_camps(&mut camp_rng, camp_nodes.len(), symmetry.copies(), &spawn_settings)
+        .into_iter().map(|roll| roll.grade).collect();
+    let fairness = fairness(symmetry, &world_map.biome_map, &camp_nodes, &grades, &chest_coords);
+    let spawns = spawn_points(symmetry, &world_map.biome_map, &camp_nodes);
+
+    draw_minimap(&world_map).try_into_dynamic()?.save(format!("{}.png", out))?;
+
+    let point = |p: &Vec2| format!("\"x\": {}, \"y\": {}", p.x, p.y);
+    let mut json = String::new();
+    writeln!(json, "{{")?;
+    writeln!(json, "  \"seed\": {},", seed)?;
+    writeln!(json, "  \"match_code\": \"{}\",", code.encode())?;
+    writeln!(json, "  \"width\": {},", map_size.width)?;
+    writeln!(json, "  \"height\": {},", map_size.height)?;
+    writeln!(json, "  \"num_camps\": {},", num_camps)?;
+    writeln!(json, "  \"num_chests\": {},", spawn_settings.num_chests)?;
+    writeln!(json, "  \"enemies_per_camp\": {},", spawn_settings.enemies_per_camp)?;
+    writeln!(json, "  \"eid_percentage\": {},", spawn_settings.eid_percentage)?;
+    writeln!(json, "  \"symmetry\": \"{}\",", symmetry.name())?;
+    writeln!(json, "  \"fairness\": {},", fairness.score)?;
+    let camps: Vec<String> = camp_nodes.iter().zip(grades.iter())
+        .map(|(camp, grade)| format!("    {{{}, \"grade\": {}}}", point(camp), grade))
+        .collect();
+    writeln!(json, "  \"camps\": [\n{}\n  ],", camps.join(",\n"))?;
+    let chests: Vec<String> = chest_coords.iter().map(|chest| format!("    {{{}}}", point(chest))).collect();
+    writeln!(json, "  \"chests\": [\n{}\n  ],", chests.join(",\n"))?;
+    let spawns: Vec<String> = spawns.iter().map(|spawn| format!("    {{{}}}", point(spawn))).collect();
+    writeln!(json, "  \"spawns\": [\n{}\n  ],", spawns.join(",\n"))?;
+    // camps come first in the node list, then the extra nodes the paths wander through.
+    // a symmetric map's graph only covers the sector that got copied
+    let nodes: Vec<String> = graph.node_indices().map(|node| format!("    {{{}}}", point(&graph[node]))).collect();
+    writeln!(json, "  \"path_nodes\": [\n{}\n  ],", nodes.join(",\n"))?;
+    let edges: Vec<String> = graph.edge_indices()
+        .map(|edge| {
+            let (a, b) = graph.edge_endpoints(edge).unwrap();
+            format!("    [{}, {}]", a.index(), b.index())
+        })
+        .collect();
+    writeln!(json, "  \"path_edges\": [\n{}\n  ]", edges.join(",\n"))?;
+    writeln!(json, "}}")?;
+    fs::write(format!("{}.json", out), json)?;
+    Ok(())
+}
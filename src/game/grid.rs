@@ -0,0 +1,68 @@
+use std::ops::{Index, IndexMut};
+
+/// A width x height block of cells kept on the heap, indexed by (row, col) with row 0 at the top of the map.
+/// Indexing panics off the edge the same way an array would, get and set are the checked versions.
+#[derive(Clone)]
+pub struct Grid<T> {
+    width: usize,
+    height: usize,
+    cells: Vec<T>,
+}
+
+impl<T> Grid<T> {
+    pub fn width(&self) -> usize {
+        return self.width;
+    }
+
+    pub fn height(&self) -> usize {
+        return self.height;
+    }
+
+    pub fn contains(&self, row: isize, col: isize) -> bool {
+        return row >= 0 && col >= 0 && (row as usize) < self.height && (col as usize) < self.width;
+    }
+
+    fn offset(&self, row: usize, col: usize) -> usize {
+        assert!(row < self.height && col < self.width, "({}, {}) is off a {}x{} grid", row, col, self.width, self.height);
+        return row * self.width + col;
+    }
+}
+
+impl<T: Copy> Grid<T> {
+    pub fn new(width: usize, height: usize, fill: T) -> Self {
+        Grid { width, height, cells: vec![fill; width * height] }
+    }
+
+    /// None if (row, col) is off the grid
+    pub fn get(&self, row: isize, col: isize) -> Option<T> {
+        if !self.contains(row, col) { return None }
+        return Some(self.cells[row as usize * self.width + col as usize]);
+    }
+
+    /// returns false and leaves the grid alone if (row, col) is off the grid
+    pub fn set(&mut self, row: isize, col: isize, value: T) -> bool {
+        if !self.contains(row, col) { return false }
+        self.cells[row as usize * self.width + col as usize] = value;
+        return true;
+    }
+
+    /// a same sized grid with f applied to every cell
+    pub fn map<U: Copy>(&self, f: impl Fn(T) -> U) -> Grid<U> {
+        Grid { width: self.width, height: self.height, cells: self.cells.iter().map(|c| f(*c)).collect() }
+    }
+}
+
+impl<T> Index<(usize, usize)> for Grid<T> {
+    type Output = T;
+
+    fn index(&self, (row, col): (usize, usize)) -> &T {
+        &self.cells[self.offset(row, col)]
+    }
+}
+
+impl<T> IndexMut<(usize, usize)> for Grid<T> {
+    fn index_mut(&mut self, (row, col): (usize, usize)) -> &mut T {
+        let offset = self.offset(row, col);
+        &mut self.cells[offset]
+    }
+}
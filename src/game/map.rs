@@ -1,12 +1,22 @@
 use bevy::prelude::*;
+use bevy::asset::LoadState;
 use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::render::view::VisibilitySystems;
+use bevy::transform::TransformSystem;
 use bevy::utils::petgraph::{algo::min_spanning_tree, visit::EdgeRef, graph::UnGraph, data::FromElements};
 use std::error::Error;
 use rand::{Rng,seq::SliceRandom,RngCore};
 use rand_chacha::{rand_core::SeedableRng,ChaChaRng};
-use crate::noise::Perlin;
+use crate::noise::{NoiseSource, Perlin, Simplex, Warp};
 use crate::AppState;
-use crate::menus::components::{NumCampsInput, MapSeedInput};
+use crate::menus::components::{NumCampsInput, MapSeedInput, MapWidthInput, MapHeightInput, NumChestsInput, EnemiesPerCampInput, EidPercentageInput, SymmetryInput};
+use crate::game::camp::{MAX_CAMP_ENEMIES, DEFAULT_EID_PERCENTAGE};
+use crate::game::grid::Grid;
+use crate::game::regions;
+use crate::game::mapfile::{MapChoice, MapFile, MapFileLoader, SpawnPoints, load_map_files, set_map_choice};
+use crate::game::matchcode::{apply_match_code, seed_from_text};
+use crate::game::symmetry::{self, MapSymmetry, Symmetry, SEAM_MARGIN};
+use crate::game::camera::GameCamera;
 
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum Biome{
@@ -15,25 +25,65 @@ pub enum Biome{
     Ground,
     Camp,
     Path,
+    Water,  // shallow, slows anyone wading through
+    Forest, // dense, walkable but nobody sees through it
+    Bridge, // a path over water
 }
 
-#[derive(Component)]
-struct Ground;
+impl Biome {
+    // multiplier on anyone's speed while standing on this tile
+    pub fn speed(&self) -> f32 {
+        match self {
+            Biome::Water => WATER_SPEED,
+            _ => 1.0,
+        }
+    }
 
-#[derive(Component)]
-struct Camp;
+    pub fn blocks_sight(&self) -> bool {
+        return *self == Biome::Forest;
+    }
 
+    // what stepping onto this tile costs an enemy's A*, None if it can't
+    pub fn path_cost(&self) -> Option<usize> {
+        match self {
+            Biome::Wall => None,
+            Biome::Water => Some(WATER_PATH_COST),
+            Biome::Forest => Some(FOREST_PATH_COST),
+            _ => Some(1),
+        }
+    }
+}
+
+/// One baked CHUNKSIZE x CHUNKSIZE block of tiles, the whole map is cleared for a restart by despawning these
 #[derive(Component)]
-struct Wall;
+pub struct Chunk {
+    half_size: Vec2, // in world units, for culling
+    tile_pixels: usize, // texture pixels per tile side, see tile_pixels
+}
 
+// Goobers still to be drawn onto a chunk's texture, (x, y, atlas index) with x and y in pixels from the chunk's top left
 #[derive(Component)]
-struct Path;
+struct Goobers(Vec<(usize, usize, usize)>);
+
+// Loaded at startup so it's usually ready by the time the first map is baked
+#[derive(Resource)]
+struct GooberSheet(Handle<Image>);
 
 #[derive(Resource)]
 pub struct WorldMap{
-    pub map_size: usize,
     pub tile_size: usize,
-    pub biome_map: [[Biome; MAPSIZE]; MAPSIZE],
+    pub biome_map: Grid<Biome>,
+}
+
+/// What each tile of the WorldMap costs an enemy to step on, 0 where they can't go.
+/// Built once with the map so pathfinding doesn't redo it every tick
+#[derive(Resource)]
+pub struct PathCosts(pub Grid<i32>);
+
+impl PathCosts {
+    pub fn new(map: &Grid<Biome>) -> Self {
+        return PathCosts(map.map(|biome| biome.path_cost().unwrap_or(0) as i32));
+    }
 }
 
 #[derive(Resource)]
@@ -48,16 +98,73 @@ pub struct NumCamps(pub u8);
 #[derive(Resource)]
 pub struct ChestCoords(pub Vec<Vec2>);
 
-// Set the size of the map in tiles (its a square)
-// CHANGE THIS TO CHANGE MAP SIZE
-pub const MAPSIZE: usize = 256;
+/// The rest of the host page's map settings, sent to clients in the LobbyState so everyone spawns the same world
+#[derive(Resource, Clone, Copy, PartialEq, Eq)]
+pub struct SpawnSettings {
+    pub num_chests: u8,
+    pub enemies_per_camp: u8, // the camp prefabs only have room for MAX_CAMP_ENEMIES
+    pub eid_percentage: u8, // chance out of 100 that a regular enemy drops a powerup
+    pub symmetry: Symmetry, // generated maps only, a hand-made map is what it is
+}
+
+impl SpawnSettings {
+    pub fn new(num_chests: u8, enemies_per_camp: u8, eid_percentage: u8, symmetry: Symmetry) -> Self {
+        SpawnSettings {
+            num_chests: num_chests.min(MAXCHESTS as u8),
+            enemies_per_camp: enemies_per_camp.clamp(1, MAX_CAMP_ENEMIES),
+            eid_percentage: eid_percentage.min(100),
+            symmetry,
+        }
+    }
+
+    // anything that doesn't parse gets the default
+    pub fn parse(num_chests: &str, enemies_per_camp: &str, eid_percentage: &str, symmetry: &str) -> Self {
+        SpawnSettings::new(
+            num_chests.trim().parse::<u8>().unwrap_or(MAXCHESTS as u8),
+            enemies_per_camp.trim().parse::<u8>().unwrap_or(MAX_CAMP_ENEMIES),
+            eid_percentage.trim().trim_end_matches('%').parse::<u8>().unwrap_or(DEFAULT_EID_PERCENTAGE),
+            Symmetry::parse(symmetry),
+        )
+    }
+}
+
+impl Default for SpawnSettings {
+    fn default() -> Self {
+        SpawnSettings::new(MAXCHESTS as u8, MAX_CAMP_ENEMIES, DEFAULT_EID_PERCENTAGE, Symmetry::Off)
+    }
+}
+
+/// Width and height in tiles of the next map, picked on the host page and sent to clients along with the seed
+#[derive(Resource, Clone, Copy, PartialEq, Eq)]
+pub struct MapSize {
+    pub width: usize,
+    pub height: usize,
+}
+
+impl MapSize {
+    // sides are kept even so tile edges land on multiples of TILESIZE, the tile lookups below rely on it
+    pub fn new(width: usize, height: usize) -> Self {
+        MapSize {
+            width: width.clamp(MIN_MAPSIZE, MAX_MAPSIZE) & !1,
+            height: height.clamp(MIN_MAPSIZE, MAX_MAPSIZE) & !1,
+        }
+    }
+}
+
+// Size of the map in tiles, the host can pick each side from MIN_MAPSIZE to MAX_MAPSIZE
+pub const DEFAULT_MAPSIZE: usize = 256;
+pub const MIN_MAPSIZE: usize = 128;
+pub const MAX_MAPSIZE: usize = 512;
 pub const TILESIZE: usize = 16;
+pub const CHUNKSIZE: usize = 32; // Side of a baked terrain chunk in tiles
+pub const MAP_TEXTURE_BUDGET: usize = 64 << 20; // Most bytes the baked chunks of one map may take, each held once in memory and once on the gpu
 pub const PATHWIDTH: usize = 5; // Width of the paths in tiles
 pub const CAMPSIZE: usize = 17; // Diameter of camp size in tiles
 pub const MAXEGGS: usize = 5;
 pub const EXTRANODES: usize = 20; // Number of extra nodes to add to the graph
 pub const EXTRAPATHS: usize = 2; // Number of extra paths to add to the graph
-pub const MAXCHESTS: usize = 10; // Maximum number of possible chests to spawn
+pub const MAXCHESTS: usize = 10; // Most chests the host can ask for
+pub const MAXCHESTTRIES: usize = 1000; // Spots to try for each chest before giving up on it
 pub const CHEST_CAMP_DIST: f32 = 50.;
 pub const CHEST_CHEST_DIST: f32 = 20.;
 
@@ -66,6 +173,19 @@ pub const BASECOLOR_GROUND: Color = Color::Rgba{red: 0.243, green: 0.621, blue:
 pub const BASECOLOR_CAMP: Color = Color::Rgba{red: 0.278, green: 0.427, blue: 0.157, alpha: 1.0};
 pub const BASECOLOR_PATH: Color = Color::Rgba{red: 0.941, green: 0.663, blue: 0.325, alpha: 1.0};
 pub const BASECOLOR_WALL: Color = Color::Rgba{red: 0.216, green: 0.231, blue: 0.369, alpha: 1.0};
+pub const BASECOLOR_WATER: Color = Color::Rgba{red: 0.208, green: 0.471, blue: 0.714, alpha: 1.0};
+pub const BASECOLOR_FOREST: Color = Color::Rgba{red: 0.106, green: 0.337, blue: 0.090, alpha: 1.0};
+pub const BASECOLOR_BRIDGE: Color = Color::Rgba{red: 0.545, green: 0.365, blue: 0.196, alpha: 1.0};
+
+// The water and forest layers, extra noise over the ground the first layer leaves
+pub const WATER_LEVEL: f64 = 0.45; // water noise under this is shallow water, about a tenth of the ground
+pub const FOREST_LEVEL: f64 = 0.88; // forest noise over this is forest, about a tenth of the ground
+const WATER_SEED_SALT: u64 = 0x5741_5445_5200_0001; // the layers are seeded off the terrain seed so they don't take from the rng
+const FOREST_SEED_SALT: u64 = 0x464f_5245_5354_0002;
+pub const WATER_WARP: f64 = 4.0; // most tiles a shoreline gets pushed from where the water noise alone puts it
+pub const WATER_SPEED: f32 = 0.5;
+pub const WATER_PATH_COST: usize = 3; // enemies would rather walk around than wade
+pub const FOREST_PATH_COST: usize = 2;
 
 #[derive(Component)]
 struct Background;
@@ -74,12 +194,23 @@ pub struct MapPlugin;
 
 impl Plugin for MapPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, initialize_map_resources);
+        app.add_asset::<MapFile>().add_asset_loader(MapFileLoader);
+        app.add_systems(Startup, (initialize_map_resources, load_map_files));
         app.add_systems(OnEnter(AppState::Hosting), set_seed);
         app.add_systems(OnEnter(AppState::Hosting), set_num_camps);
+        app.add_systems(OnEnter(AppState::Hosting), set_map_size);
+        app.add_systems(OnEnter(AppState::Hosting), set_spawn_settings);
+        app.add_systems(OnEnter(AppState::Hosting), apply_match_code.after(set_seed).after(set_num_camps).after(set_map_size).after(set_spawn_settings));
+        app.add_systems(OnEnter(AppState::Hosting), set_map_choice.after(set_map_size).after(apply_match_code));
         app.add_systems(OnExit(AppState::Hosting), set_seed);
         app.add_systems(OnExit(AppState::Hosting), set_num_camps);
+        app.add_systems(OnExit(AppState::Hosting), set_map_size);
+        app.add_systems(OnExit(AppState::Hosting), set_spawn_settings);
+        app.add_systems(OnExit(AppState::Hosting), apply_match_code.after(set_seed).after(set_num_camps).after(set_map_size).after(set_spawn_settings));
+        app.add_systems(OnExit(AppState::Hosting), set_map_choice.after(set_map_size).after(apply_match_code));
         app.add_systems(OnEnter(AppState::Game), setup_map);
+        app.add_systems(Update, stamp_goobers.run_if(any_with_component::<Goobers>()));
+        app.add_systems(PostUpdate, cull_chunks.after(TransformSystem::TransformPropagate).before(VisibilitySystems::VisibilityPropagate));
     }
 }
 
@@ -89,7 +220,7 @@ fn euclidean_distance(a: Vec2, b: Vec2) -> f32 {
 }
 
 // Remove coordinates that are too close to each other or a wall
-fn refine_coordinates(coords: &mut Vec<Vec2>) {
+fn refine_coordinates(coords: &mut Vec<Vec2>, width: usize, height: usize) {
     let mut new_coords = Vec::new();
     for &coord in coords.iter() {
         let is_far_enough = new_coords.iter().all(|&new_coord| {
@@ -97,7 +228,7 @@ fn refine_coordinates(coords: &mut Vec<Vec2>) {
         });
 
         if is_far_enough {
-            if coord.x > 30.0 && coord.x < (MAPSIZE-30) as f32 && coord.y > 30.0 && coord.y < (MAPSIZE-30) as f32 
+            if coord.x > 30.0 && coord.x < (width-30) as f32 && coord.y > 30.0 && coord.y < (height-30) as f32
             {
                 new_coords.push(coord);
             }
@@ -123,22 +254,26 @@ fn create_mst(points: Vec<Vec2>) -> UnGraph<Vec2, f32> {
     return UnGraph::<Vec2, f32>::from_elements(min_spanning_tree(&graph));
 }
 
-// Initialize the WorldMap, CampNodes, MapSeed, and NumCamps resources
-fn initialize_map_resources(mut commands: Commands) {
+// Initialize the WorldMap, PathCosts, CampNodes, MapSeed, NumCamps, MapSize, SpawnSettings, MapSymmetry, and GooberSheet resources
+fn initialize_map_resources(mut commands: Commands, asset_server: Res<AssetServer>) {
     let world_map = WorldMap{
-        map_size: MAPSIZE,
         tile_size: TILESIZE,
-        biome_map: [[Biome::Free; MAPSIZE]; MAPSIZE]
+        biome_map: Grid::new(DEFAULT_MAPSIZE, DEFAULT_MAPSIZE, Biome::Free)
     };
     let camp_nodes = CampNodes(Vec::new());
     let map_seed = MapSeed(0);
     let num_camps = NumCamps(10);
     let chest_coords = ChestCoords(Vec::new());
+    commands.insert_resource(PathCosts::new(&world_map.biome_map));
     commands.insert_resource(world_map);
     commands.insert_resource(camp_nodes);
     commands.insert_resource(map_seed);
     commands.insert_resource(num_camps);
     commands.insert_resource(chest_coords);
+    commands.insert_resource(MapSize::new(DEFAULT_MAPSIZE, DEFAULT_MAPSIZE));
+    commands.insert_resource(SpawnSettings::default());
+    commands.insert_resource(MapSymmetry::default());
+    commands.insert_resource(GooberSheet(asset_server.load("goobers.png")));
 }
 
 // Set the map seed based on the MapSeedInput resource (default 0)
@@ -148,9 +283,7 @@ fn set_seed(
 ) {
     let mut seed: u64 = 0;
     for input in map_seed_input_query.iter() {
-        if let Ok(parsed_num) = input.value.parse::<u64>() {
-            seed = parsed_num;
-        }
+        seed = seed_from_text(&input.value);
     }
     map_seed.0 = seed;
 }
@@ -169,39 +302,101 @@ fn set_num_camps(
     num_camps.0 = num;
 }
 
-// Generate the map using Perlin noise
-fn read_map(
+// Set the map size based on the MapWidthInput and MapHeightInput resources (default DEFAULT_MAPSIZE)
+fn set_map_size(
+    map_width_input_query: Query<&MapWidthInput>,
+    map_height_input_query: Query<&MapHeightInput>,
+    mut map_size: ResMut<MapSize>,
+) {
+    let mut width: usize = DEFAULT_MAPSIZE;
+    let mut height: usize = DEFAULT_MAPSIZE;
+    for input in map_width_input_query.iter() {
+        if let Ok(parsed_num) = input.value.parse::<usize>() {
+            width = parsed_num;
+        }
+    }
+    for input in map_height_input_query.iter() {
+        if let Ok(parsed_num) = input.value.parse::<usize>() {
+            height = parsed_num;
+        }
+    }
+    *map_size = MapSize::new(width, height);
+}
+
+// Set the chest count, enemies per camp, EID percentage, and symmetry from their inputs, each falls back to its default on its own
+fn set_spawn_settings(
+    num_chests_input_query: Query<&NumChestsInput>,
+    enemies_per_camp_input_query: Query<&EnemiesPerCampInput>,
+    eid_percentage_input_query: Query<&EidPercentageInput>,
+    symmetry_input_query: Query<&SymmetryInput>,
+    mut spawn_settings: ResMut<SpawnSettings>,
+) {
+    let num_chests = num_chests_input_query.iter().map(|input| input.value.clone()).last().unwrap_or_default();
+    let enemies_per_camp = enemies_per_camp_input_query.iter().map(|input| input.value.clone()).last().unwrap_or_default();
+    let eid_percentage = eid_percentage_input_query.iter().map(|input| input.value.clone()).last().unwrap_or_default();
+    let symmetry = symmetry_input_query.iter().map(|input| input.value.clone()).last().unwrap_or_default();
+    *spawn_settings = SpawnSettings::parse(&num_chests, &enemies_per_camp, &eid_percentage, &symmetry);
+}
+
+// Generate the map using Perlin noise, returning the graph of nodes the paths were laid along.
+// With a symmetry only its source sector is generated, then copied, so camps and chests come out as even shares of
+// num_camps and num_chests and the graph only covers the source sector
+pub fn read_map(
     map: &mut WorldMap,
     camp_nodes: &mut Vec<Vec2>,
-    num_camps: &Res<NumCamps>,
+    num_camps: u8,
+    num_chests: usize,
+    symmetry: Symmetry,
     mut rng: &mut ChaChaRng,
     chest_coords: &mut Vec<Vec2>,
-) -> Result<(), Box<dyn Error>> {
+) -> Result<UnGraph<Vec2, f32>, Box<dyn Error>> {
     // seed, amplitude, frequency, octaves
-    let perlin = Perlin::new(rng.next_u64(), 1.0, 0.08, 3);
+    let terrain_seed = rng.next_u64();
+    let perlin = Perlin::new(terrain_seed, 1.0, 0.08, 3);
+    // simplex pushing the water noise around so shorelines wind instead of following perlin's grid
+    let water = Warp::new(
+        Perlin::new(terrain_seed ^ WATER_SEED_SALT, 1.0, 0.04, 2),
+        Simplex::new(terrain_seed ^ WATER_SEED_SALT.rotate_left(32), 1.0, 0.05, 2),
+        WATER_WARP,
+    );
+    let forest = Perlin::new(terrain_seed ^ FOREST_SEED_SALT, 1.0, 0.06, 3);
+    let width = map.biome_map.width();
+    let height = map.biome_map.height();
 
-    for row in 0..MAPSIZE {
-        for col in 0..MAPSIZE {
-            let v = perlin.noise(row,col);
+    for row in 0..height {
+        for col in 0..width {
+            let v = perlin.noise(row as f64, col as f64);
             if v < 0.32 {
-                map.biome_map[row][col] = Biome::Ground;
-                camp_nodes.push(Vec2::new(row as f32, col as f32));
+                map.biome_map[(row, col)] = Biome::Ground;
+                camp_nodes.push(Vec2::new(col as f32, row as f32));  // x the column, y the row like everything else
             }
             else if v > 0.72 {
-                map.biome_map[row][col] = Biome::Wall;
+                map.biome_map[(row, col)] = Biome::Wall;
             }
             else {
-                map.biome_map[row][col] = Biome::Ground;
+                map.biome_map[(row, col)] = Biome::Ground;
+            }
+            // water and forest only ever take ground, paths and camps get laid over them after
+            if map.biome_map[(row, col)] == Biome::Ground {
+                if water.noise(row as f64, col as f64) < WATER_LEVEL {
+                    map.biome_map[(row, col)] = Biome::Water;
+                }
+                else if forest.noise(row as f64, col as f64) > FOREST_LEVEL {
+                    map.biome_map[(row, col)] = Biome::Forest;
+                }
             }
         }
     }
 
     // Refine the camp nodes so that they are not too close to each other or a wall, 
     // and shuffle them, then truncate the vector to the number of camps
-    refine_coordinates(camp_nodes);
+    refine_coordinates(camp_nodes, width, height);
+    // a symmetric map only places its share of the camps, far enough from the seams that the eggs don't cross them
+    let num_camps = num_camps as usize / symmetry.copies();
+    camp_nodes.retain(|node| symmetry.in_source(*node, width, height, SEAM_MARGIN));
     camp_nodes.shuffle(&mut rng);
-    if camp_nodes.len() > num_camps.0 as usize {
-        camp_nodes.truncate(num_camps.0 as usize);
+    if camp_nodes.len() > num_camps {
+        camp_nodes.truncate(num_camps);
     }
 
     // Create a vector of coordinates for extra nodes for the graph equal to EXTRANODES variable
@@ -210,11 +405,12 @@ fn read_map(
         if extra_nodes.len() >= EXTRANODES {
             break;
         }
-        let row = rng.gen_range(0..MAPSIZE) as f32;
-        let col = rng.gen_range(0..MAPSIZE) as f32;
-        extra_nodes.push(Vec2::new(row, col));
+        let x = rng.gen_range(0..width) as f32;
+        let y = rng.gen_range(0..height) as f32;
+        extra_nodes.push(Vec2::new(x, y));
     }
-    refine_coordinates(&mut extra_nodes);
+    refine_coordinates(&mut extra_nodes, width, height);
+    extra_nodes.retain(|node| symmetry.in_source(*node, width, height, 0.));
 
     // Combine the camp nodes and extra nodes into one vector
     let mut all_nodes: Vec<Vec2> = Vec::new();
@@ -243,7 +439,7 @@ fn read_map(
             let step_ratio = step as f32 / num_steps as f32;
             
             // randomize the direction vector a bit so the lines aren't completely straight
-            let noise_value = perlin.noise(step, 0); // Adjust dimension as needed
+            let noise_value = perlin.noise(step as f64, 0.); // Adjust dimension as needed
             let direction = direction + Vec2::new(noise_value as f32 * 0.15, noise_value as f32 * 0.15); // Adjust the scaling factor
 
             // Calculate the position of the current step
@@ -254,14 +450,17 @@ fn read_map(
             let col = (step_position.x) as i32; // Adjust as needed
 
             // Update the map biomes along the path to Biome::Path
-            if row < map.biome_map.len() as i32 && col < map.biome_map[0].len() as i32 {
+            if row < height as i32 && col < width as i32 {
                 for row_offset in -(PATHWIDTH as i32/2)..PATHWIDTH as i32/2 {
                     for col_offset in -(PATHWIDTH as i32/2)..PATHWIDTH as i32/2 {
-                        if row + row_offset <= MAPSIZE as i32 - 1 && col + col_offset <= MAPSIZE as i32 - 1
+                        let (path_row, path_col) = ((row + row_offset) as isize, (col + col_offset) as isize);
+                        if map.biome_map.contains(path_row, path_col)
                         {
-                            let v = perlin.noise((row + row_offset) as usize,(col + col_offset) as usize);
+                            let v = perlin.noise(path_row as f64, path_col as f64);
                             if v > 0.64 || v < 0.60 {
-                                map.biome_map[(row + row_offset) as usize][(col + col_offset) as usize] = Biome::Path;
+                                // paths cut through forest but cross water on a bridge
+                                let crossing = matches!(map.biome_map.get(path_row, path_col), Some(Biome::Water | Biome::Bridge));
+                                map.biome_map.set(path_row, path_col, if crossing { Biome::Bridge } else { Biome::Path });
                             }
                         }
                     }
@@ -307,13 +506,13 @@ fn read_map(
                 let distance_squared = (row - center_row as i32).pow(2) + (col - center_col as i32).pow(2);
                 let camp_radius_squared = (camp_radius as i32).pow(2);
 
-                if row >= 0 && row < MAPSIZE as i32 && col >= 0 && col < MAPSIZE as i32 && distance_squared <= camp_radius_squared {
+                if distance_squared <= camp_radius_squared {
 
-                    //let v = perlin.noise(row as usize, col as usize);
+                    //let v = perlin.noise(row as f64, col as f64);
                     // if distance_squared <= camp_radius_squared 
                     //&& v < 0.99 
                     {
-                        map.biome_map[row as usize][col as usize] = Biome::Camp;
+                        map.biome_map.set(row as isize, col as isize, Biome::Camp);
                     }
                 }
             }
@@ -347,10 +546,10 @@ fn read_map(
                         + ((col - egg_center_col) as f32 / (egg_width / 2.0)).powi(2);
 
                     // Check if the current position is within the egg
-                    if row >= 0 && row < MAPSIZE as i32 && col >= 0 && col < MAPSIZE as i32 && distance_squared <= 1.0{
+                    if distance_squared <= 1.0{
                         //skip over walls
-                        if map.biome_map[row as usize][col as usize] != Biome::Wall {
-                            map.biome_map[row as usize][col as usize] = Biome::Camp;
+                        if map.biome_map.get(row as isize, col as isize).is_some_and(|b| b != Biome::Wall) {
+                            map.biome_map.set(row as isize, col as isize, Biome::Camp);
                         }
                     }
                 }
@@ -358,18 +557,28 @@ fn read_map(
         }
     }
 
-    // Generate a random low number of high-tier item chests in the map
-    let numchests = MAXCHESTS;
+    // Everything from here on sees the whole map, camps included
+    symmetry.copy_source(&mut map.biome_map);
+    let source_camps = camp_nodes.clone();
+    *camp_nodes = symmetry.with_images(&source_camps, width, height);
+
+    // Generate a random low number of high-tier item chests in the map, a symmetric map only its share in the source sector
+    let numchests = num_chests / symmetry.copies();
+    // chests stay half CHEST_CHEST_DIST off the seams so they're far enough from their own copies
+    let chest_margin = CHEST_CHEST_DIST / 2.;
 
     for _ in 0..numchests {
-        loop{
-            let cur_chest = Vec2 {x: rng.gen_range(5..MAPSIZE - 5) as f32, y: rng.gen_range(5..MAPSIZE - 5) as f32};
+        // a small map can run out of room for chests, give up on this one rather than spin forever
+        for _ in 0..MAXCHESTTRIES {
+            let cur_chest = Vec2 {x: rng.gen_range(5..width - 5) as f32, y: rng.gen_range(5..height - 5) as f32};
+            if !symmetry.in_source(cur_chest, width, height, chest_margin) {
+                continue;
+            }
 
             let mut valid = true;
 
-            // check that the chest is far enough away from any camp
-            for node in camp_nodes_mst.node_indices() {
-                let node = &camp_nodes_mst[node];
+            // check that the chest is far enough away from any camp, copies included
+            for node in camp_nodes.iter() {
                 if euclidean_distance(cur_chest, *node) < CHEST_CAMP_DIST{
                     valid = false;
                 }
@@ -381,15 +590,15 @@ fn read_map(
                 }
             }
             // check that chest is not surrounded by a wall
-            if map.biome_map[cur_chest.y as usize - 3][cur_chest.x as usize - 3] == Biome::Ground
-            && map.biome_map[cur_chest.y as usize - 3][cur_chest.x as usize] == Biome::Ground
-            && map.biome_map[cur_chest.y as usize - 3][cur_chest.x as usize + 3] == Biome::Ground
-            && map.biome_map[cur_chest.y as usize][cur_chest.x as usize - 3] == Biome::Ground 
-            && map.biome_map[cur_chest.y as usize][cur_chest.x as usize] == Biome::Ground 
-            && map.biome_map[cur_chest.y as usize][cur_chest.x as usize + 3] == Biome::Ground 
-            && map.biome_map[cur_chest.y as usize + 3][cur_chest.x as usize - 3] == Biome::Ground 
-            && map.biome_map[cur_chest.y as usize + 3][cur_chest.x as usize] == Biome::Ground 
-            && map.biome_map[cur_chest.y as usize + 3][cur_chest.x as usize + 3] == Biome::Ground 
+            if map.biome_map[(cur_chest.y as usize - 3, cur_chest.x as usize - 3)] == Biome::Ground
+            && map.biome_map[(cur_chest.y as usize - 3, cur_chest.x as usize)] == Biome::Ground
+            && map.biome_map[(cur_chest.y as usize - 3, cur_chest.x as usize + 3)] == Biome::Ground
+            && map.biome_map[(cur_chest.y as usize, cur_chest.x as usize - 3)] == Biome::Ground 
+            && map.biome_map[(cur_chest.y as usize, cur_chest.x as usize)] == Biome::Ground 
+            && map.biome_map[(cur_chest.y as usize, cur_chest.x as usize + 3)] == Biome::Ground 
+            && map.biome_map[(cur_chest.y as usize + 3, cur_chest.x as usize - 3)] == Biome::Ground 
+            && map.biome_map[(cur_chest.y as usize + 3, cur_chest.x as usize)] == Biome::Ground 
+            && map.biome_map[(cur_chest.y as usize + 3, cur_chest.x as usize + 3)] == Biome::Ground 
             && valid{
                 chest_coords.push(cur_chest);
                 break;
@@ -397,124 +606,274 @@ fn read_map(
         }
     }
 
+    let source_chests = chest_coords.clone();
+    *chest_coords = symmetry.with_images(&source_chests, width, height);
+
     // Create the outer walls
-    for row in 0..MAPSIZE {
-        map.biome_map[row][0] = Biome::Wall;
-        map.biome_map[row][MAPSIZE-1] = Biome::Wall;    
+    for row in 0..height {
+        map.biome_map[(row, 0)] = Biome::Wall;
+        map.biome_map[(row, width-1)] = Biome::Wall;
     }
-    for col in 0..MAPSIZE {
-        map.biome_map[0][col] = Biome::Wall;
-        map.biome_map[MAPSIZE-1][col] = Biome::Wall;    
+    for col in 0..width {
+        map.biome_map[(0, col)] = Biome::Wall;
+        map.biome_map[(height-1, col)] = Biome::Wall;
     }
 
-    Ok(())
+    // Paths and eggs don't always reach everything the noise left open, join up or fill in whatever they missed
+    // and whatever gets dug or filled in one sector gets the same in the rest
+    let chest_tiles: Vec<(usize, usize)> = chest_coords.iter().map(|chest| (chest.y as usize, chest.x as usize)).collect();
+    let before = map.biome_map.clone();
+    regions::connect(&mut map.biome_map, &chest_tiles);
+    symmetry.copy_changes(&before, &mut map.biome_map);
+
+    Ok(all_nodes_graph)
 }
 
-// create the map, spawn the tiles, and add the WorldMap resource
+// create the map, bake the tiles into chunk textures, and add the WorldMap resource
 pub fn setup_map(
     mut commands: Commands, 
-    asset_server: Res<AssetServer>,
     mut assets: ResMut<Assets<Image>>,
-    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
     map_seed: Res<MapSeed>,
     num_camps: Res<NumCamps>,
+    mut map_size: ResMut<MapSize>,
+    spawn_settings: Res<SpawnSettings>,
+    map_choice: Res<MapChoice>,
+    map_files: Res<Assets<MapFile>>,
     mut camp_nodes: ResMut<CampNodes>,
     mut world_map: ResMut<WorldMap>,
+    mut path_costs: ResMut<PathCosts>,
     mut chest_coords: ResMut<ChestCoords>,
+    mut spawn_points: ResMut<SpawnPoints>,
+    mut map_symmetry: ResMut<MapSymmetry>,
 ) {
     //create an rng to randomly choose a goober in the near future
     let mut rng = rand_chacha::ChaChaRng::seed_from_u64(map_seed.0);
 
-    // Generate the map, camp nodes, and item nodes
-    let _ = read_map(&mut world_map, &mut camp_nodes.0, &num_camps, &mut rng, &mut chest_coords.0);
-
-    // Get a handle for a pure white TILESIZE x TILESIZE image to be colored based on tile type later
-    let tile_handle = assets.add(create_tile_image());
-
-    // Load in goobers (tile overlays) and turn them into a TextureAtlas so they can be selected later
-    let goober_handle = asset_server.load("goobers.png");
-    let goober_dims = vec![8, 4]; // 8 cols, 4 rows
-    let goober_atlas = TextureAtlas::from_grid(
-        goober_handle,
-        Vec2::splat(TILESIZE as f32),
-        goober_dims[0],
-        goober_dims[1],
-        None,
-        None
-    );
-    let goober_atlas_handle = texture_atlases.add(goober_atlas);
-
-    // Create this to center the x-positions of the map
-    let mut x_coord: f32 = -((MAPSIZE as f32)/2.) + 0.5;
-    for row in 0..MAPSIZE {
-        // Create this to center the y-positions of the map
-        let mut y_coord: f32 = ((MAPSIZE as f32)/2.) - 0.5;
-        for col in 0..MAPSIZE {
+    // a restart regenerates into the same resources, start them over
+    camp_nodes.0.clear();
+    chest_coords.0.clear();
+    spawn_points.0.clear();
+    map_symmetry.0 = Symmetry::Off;
+    world_map.biome_map = Grid::new(map_size.width, map_size.height, Biome::Free);
+
+    // A hand-made map stands in for the generator, the rng still picks the goobers
+    let map_file = map_choice.find(&map_files);
+    if !map_choice.is_generated() && map_file.is_none() {
+        error!("map {} isn't loaded, generating one instead", map_choice.name);
+    }
+    if let Some(map_file) = map_file {
+        world_map.biome_map = map_file.biome_map.clone();
+        camp_nodes.0 = map_file.camps.clone();
+        chest_coords.0 = map_file.chests.clone();
+        spawn_points.0 = map_file.spawns.clone();
+    } else {
+        // Generate the map, camp nodes, and item nodes
+        map_symmetry.0 = spawn_settings.symmetry;
+        let _ = read_map(&mut world_map, &mut camp_nodes.0, num_camps.0, spawn_settings.num_chests as usize, map_symmetry.0, &mut rng, &mut chest_coords.0);
+        // a symmetric map starts everyone off even too, one spawn per sector
+        spawn_points.0 = symmetry::spawn_points(map_symmetry.0, &world_map.biome_map, &camp_nodes.0);
+    }
+
+    // the map doesn't change from here on, so neither does what it costs to cross
+    *path_costs = PathCosts::new(&world_map.biome_map);
+
+    // a hand-made map brings its own size, bake whatever got loaded and let everything else know
+    let (width, height) = (world_map.biome_map.width(), world_map.biome_map.height());
+    if map_size.width != width || map_size.height != height {
+        *map_size = MapSize::new(width, height);
+    }
+
+    // One pixel buffer and goober list per chunk, chunks along the right and bottom edges can be smaller than CHUNKSIZE
+    let chunks_wide = (width + CHUNKSIZE - 1) / CHUNKSIZE;
+    let chunks_high = (height + CHUNKSIZE - 1) / CHUNKSIZE;
+    let tile_pixels = tile_pixels(width, height);
+    let mut chunks: Vec<(Vec<u8>, Vec<(usize, usize, usize)>)> = Vec::new();
+    for chunk_row in 0..chunks_high {
+        for chunk_col in 0..chunks_wide {
+            let (w, h) = chunk_dims(chunk_row, chunk_col, width, height);
+            chunks.push((vec![0; w * h * tile_pixels * tile_pixels * 4], Vec::new()));
+        }
+    }
+
+    let goober_dims = vec![8, 7]; // 8 cols, 7 rows
+    for col in 0..width {
+        for row in 0..height {
             let goober_index; // -1 means NO GOOBER!!!!!!!
-            let goober_chance = vec![0.5, 0.18, 0.18, 0.18]; // Wall, Ground, Camp, Path
+            let goober_chance = vec![0.5, 0.18, 0.18, 0.18, 0.12, 0.6, 0.25]; // Wall, Ground, Camp, Path, Water, Forest, Bridge
+            let color;
 
-            if world_map.biome_map[col][row] == Biome::Wall {
-                // Spawn a wall sprite if the current tile is a wall
+            if world_map.biome_map[(row, col)] == Biome::Wall {
                 // If goober roll succeeds, make goober_index a random goober for that tile type, adding sheet width to wrap around and reach the correct row
                 // The same logic applies to each instance of this line, just with different values for each tile
                 goober_index = if rng.gen_range(0.00..1.00) < goober_chance[0] { rng.gen_range(0..2) + 3 * goober_dims[0] as i32 } else { -1 };
-                spawn_tile(&mut commands, &tile_handle, &goober_atlas_handle, goober_index, Wall, &x_coord, &y_coord, BASECOLOR_WALL);
-            }else if world_map.biome_map[col][row] == Biome::Ground {
-                // Spawn a ground sprite if the current tile is Ground
+                color = BASECOLOR_WALL;
+            }else if world_map.biome_map[(row, col)] == Biome::Ground {
                 // Since we're blending grass tile color, hue must needs be calculated based on the identity of edge-sharing tiles
-                let hue = tile_blend_color(&col, &row, &world_map);
+                color = tile_blend_color(&row, &col, &world_map);
                 goober_index = if rng.gen_range(0.00..=1.00) < goober_chance[1] { rng.gen_range(0..8) } else { -1 };
-                spawn_tile(&mut commands, &tile_handle, &goober_atlas_handle, goober_index, Ground, &x_coord, &y_coord, hue);
-            }else if world_map.biome_map[col][row] == Biome::Camp {
-                // Spawn a camp sprite if the current tile is a camp
+            }else if world_map.biome_map[(row, col)] == Biome::Camp {
                 goober_index = if rng.gen_range(0.00..=1.00) < goober_chance[2] { rng.gen_range(0..8) + 2 * goober_dims[0] as i32 } else { -1 };
-                spawn_tile(&mut commands, &tile_handle, &goober_atlas_handle, goober_index, Camp, &x_coord, &y_coord, BASECOLOR_CAMP);
-            }else if world_map.biome_map[col][row] == Biome::Path {
-                // Spawn a path sprite if the current tile is a path
+                color = BASECOLOR_CAMP;
+            }else if world_map.biome_map[(row, col)] == Biome::Path {
                 goober_index = if rng.gen_range(0.00..=1.00) < goober_chance[3] { rng.gen_range(0..8) + 1 * goober_dims[0] as i32 } else { -1 };
-                spawn_tile(&mut commands, &tile_handle, &goober_atlas_handle, goober_index, Path, &x_coord, &y_coord, BASECOLOR_PATH);
+                color = BASECOLOR_PATH;
+            }else if world_map.biome_map[(row, col)] == Biome::Water {
+                goober_index = if rng.gen_range(0.00..=1.00) < goober_chance[4] { rng.gen_range(0..8) + 4 * goober_dims[0] as i32 } else { -1 };
+                color = BASECOLOR_WATER;
+            }else if world_map.biome_map[(row, col)] == Biome::Forest {
+                goober_index = if rng.gen_range(0.00..=1.00) < goober_chance[5] { rng.gen_range(0..8) + 5 * goober_dims[0] as i32 } else { -1 };
+                color = BASECOLOR_FOREST;
+            }else if world_map.biome_map[(row, col)] == Biome::Bridge {
+                goober_index = if rng.gen_range(0.00..=1.00) < goober_chance[6] { rng.gen_range(0..8) + 6 * goober_dims[0] as i32 } else { -1 };
+                color = BASECOLOR_BRIDGE;
+            }else {
+                // Free tiles stay see through
+                continue;
+            }
+
+            let (chunk_row, chunk_col) = (row / CHUNKSIZE, col / CHUNKSIZE);
+            let chunk_width = chunk_dims(chunk_row, chunk_col, width, height).0;
+            let (pixels, goobers) = &mut chunks[chunk_row * chunks_wide + chunk_col];
+            let (x, y) = ((col % CHUNKSIZE) * tile_pixels, (row % CHUNKSIZE) * tile_pixels);
+            paint_tile(pixels, chunk_width * tile_pixels, x, y, tile_pixels, color);
+            if goober_index != -1 {
+                goobers.push((x, y, goober_index as usize));
             }
-            y_coord-=1.0;
         }
-        x_coord+=1.0;
-    }
-}
-
-fn spawn_tile<T>(
-    commands: &mut Commands,
-    data: &Handle<Image>,
-    goober_handle: &Handle<TextureAtlas>,
-    goober_index: i32,
-    component: T,
-    x: &f32,
-    y: &f32,
-    hue: Color,
-) where
-    T: Component,
-{
-    let tile = commands.spawn(SpriteBundle{
-        sprite: Sprite {
-            color: hue,
-            ..default()
-        },
-        transform: Transform::from_xyz(x*TILESIZE as f32, y*TILESIZE as f32, 0.),
-        texture: data.clone(),
-        ..default()
-    })
-    .insert(component)
-    .id();
-
-    if goober_index != -1 {
-        // Goober is allowed
-        commands.entity(tile).insert(SpriteSheetBundle{
-            texture_atlas: goober_handle.clone(),
-            transform: Transform::from_xyz(x*TILESIZE as f32, y*TILESIZE as f32, 0.),
-            sprite: TextureAtlasSprite {
-                index: goober_index as usize,
+    }
+
+    for (i, (pixels, goobers)) in chunks.into_iter().enumerate() {
+        let (chunk_row, chunk_col) = (i / chunks_wide, i % chunks_wide);
+        let (w, h) = chunk_dims(chunk_row, chunk_col, width, height);
+        let image = Image::new(
+            Extent3d{
+                width: (w * tile_pixels) as u32,
+                height: (h * tile_pixels) as u32,
+                depth_or_array_layers: 1
+            },
+            TextureDimension::D2,
+            pixels,
+            TextureFormat::Rgba8UnormSrgb
+        );
+        // Center the chunk over the tiles it covers, with the whole map centered on the origin
+        let x = (chunk_col * CHUNKSIZE) as f32 + w as f32 / 2. - width as f32 / 2.;
+        let y = height as f32 / 2. - (chunk_row * CHUNKSIZE) as f32 - h as f32 / 2.;
+        commands.spawn((
+            SpriteBundle{
+                texture: assets.add(image),
+                // stretched back to TILESIZE per tile when baked smaller
+                sprite: Sprite { custom_size: Some(Vec2::new(w as f32, h as f32) * TILESIZE as f32), ..default() },
+                transform: Transform::from_xyz(x * TILESIZE as f32, y * TILESIZE as f32, 0.),
                 ..default()
             },
-            ..default()
-        });
+            Chunk { half_size: Vec2::new(w as f32, h as f32) * TILESIZE as f32 / 2., tile_pixels },
+            Goobers(goobers),
+        ));
+    }
+}
+
+// Size in tiles of the chunk at (chunk_row, chunk_col) on a width x height map
+fn chunk_dims(chunk_row: usize, chunk_col: usize, width: usize, height: usize) -> (usize, usize) {
+    let w = CHUNKSIZE.min(width - chunk_col * CHUNKSIZE);
+    let h = CHUNKSIZE.min(height - chunk_row * CHUNKSIZE);
+    (w, h)
+}
+
+// Pixels per tile side to bake a width x height map at, full TILESIZE unless that would go over MAP_TEXTURE_BUDGET
+fn tile_pixels(width: usize, height: usize) -> usize {
+    let mut tile_pixels = TILESIZE;
+    while tile_pixels > 1 && width * height * tile_pixels * tile_pixels * 4 > MAP_TEXTURE_BUDGET {
+        tile_pixels /= 2;
+    }
+    return tile_pixels;
+}
+
+// Fill the size x size square with its top left corner at (x, y) in a chunk's pixels
+fn paint_tile(
+    pixels: &mut [u8],
+    stride: usize,
+    x: usize,
+    y: usize,
+    size: usize,
+    color: Color,
+) {
+    let rgba = color.as_rgba_u8();
+    for py in y..y + size {
+        for px in x..x + size {
+            let i = (py * stride + px) * 4;
+            pixels[i..i + 4].copy_from_slice(&rgba);
+        }
+    }
+}
+
+// Draws the goobers onto their chunks once goobers.png has loaded, the terrain shows without them until then
+fn stamp_goobers(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    goober_sheet: Res<GooberSheet>,
+    mut images: ResMut<Assets<Image>>,
+    chunks: Query<(Entity, &Handle<Image>, &Chunk, &Goobers)>,
+) {
+    let sheet = images.get(&goober_sheet.0).map(|image| (image.data.clone(), image.texture_descriptor.size.width as usize));
+    if sheet.is_none() {
+        if asset_server.get_load_state(&goober_sheet.0) == LoadState::Failed {
+            warn!("goobers.png failed to load, the map won't have any goobers");
+            for (entity, _, _, _) in &chunks {
+                commands.entity(entity).remove::<Goobers>();
+            }
+        }
+        return;
+    }
+    let (sheet, sheet_width) = sheet.unwrap();
+    let sheet_cols = sheet_width / TILESIZE;
+
+    for (entity, handle, chunk_info, goobers) in &chunks {
+        commands.entity(entity).remove::<Goobers>();
+        let chunk = images.get_mut(handle);
+        if chunk.is_none() { continue }
+        let chunk = chunk.unwrap();
+        let stride = chunk.texture_descriptor.size.width as usize;
+        // a chunk baked smaller than TILESIZE takes every step'th goober pixel
+        let size = chunk_info.tile_pixels;
+        let step = TILESIZE / size;
+        for (x, y, index) in &goobers.0 {
+            let (src_x, src_y) = ((index % sheet_cols) * TILESIZE, (index / sheet_cols) * TILESIZE);
+            for py in 0..size {
+                for px in 0..size {
+                    let src = ((src_y + py * step) * sheet_width + src_x + px * step) * 4;
+                    let dst = ((y + py) * stride + x + px) * 4;
+                    if src + 4 > sheet.len() { continue }
+                    // goobers sit on top of the tile color like they did as their own sprites
+                    let alpha = sheet[src + 3] as u32;
+                    for c in 0..3 {
+                        let blended = (sheet[src + c] as u32 * alpha + chunk.data[dst + c] as u32 * (255 - alpha)) / 255;
+                        chunk.data[dst + c] = blended as u8;
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Sprites aren't culled on their own, hide every chunk outside the game camera's view
+fn cull_chunks(
+    camera: Query<(&GlobalTransform, &OrthographicProjection), With<GameCamera>>,
+    mut chunks: Query<(&Transform, &Chunk, &mut Visibility)>,
+) {
+    let camera = camera.get_single();
+    if camera.is_err() { return }
+    let (camera_tf, projection) = camera.unwrap();
+    let view_center = camera_tf.translation().truncate();
+    let view_min = view_center + projection.area.min;
+    let view_max = view_center + projection.area.max;
+    for (tf, chunk, mut visibility) in &mut chunks {
+        let chunk_min = tf.translation.truncate() - chunk.half_size;
+        let chunk_max = tf.translation.truncate() + chunk.half_size;
+        let on_screen = chunk_min.x < view_max.x && chunk_max.x > view_min.x && chunk_min.y < view_max.y && chunk_max.y > view_min.y;
+        let wanted = if on_screen { Visibility::Inherited } else { Visibility::Hidden };
+        if *visibility != wanted {
+            *visibility = wanted;
+        }
     }
 }
 
@@ -526,7 +885,7 @@ fn tile_blend_color(
     // Iterate through each edge-sharing tile of the tile at (x, y)
     // If a path tile is found, return a Color that averages the colors of a ground and path tile together
     for (tile_x, tile_y) in [(*x, y - 1), (*x, y + 1), (x - 1, *y), (x + 1, *y)].iter() {
-        if world_map.biome_map[*tile_x][*tile_y] == Biome::Path {
+        if world_map.biome_map[(*tile_x, *tile_y)] == Biome::Path {
             return Color::Rgba{
                 red: (BASECOLOR_GROUND.r() + BASECOLOR_PATH.r()) / 2.,
                 green: (BASECOLOR_GROUND.g() + BASECOLOR_PATH.g()) / 2.,
@@ -534,7 +893,7 @@ fn tile_blend_color(
                 alpha: 1.
             };
         }
-        else if world_map.biome_map[*tile_x][*tile_y] == Biome::Camp {
+        else if world_map.biome_map[(*tile_x, *tile_y)] == Biome::Camp {
             // Uncomment to have grass bordering camp biome blend color
             /*
             return Color::Rgba{
@@ -551,34 +910,12 @@ fn tile_blend_color(
     return BASECOLOR_GROUND;
 }
 
-// Returns a pure white image of size (TILESIZE, TILESIZE) for use with spawn_tile()
-fn create_tile_image() -> Image {
-    let mut pixel_data: Vec<u8> = Vec::new();
-
-    for _ in 0..TILESIZE {
-        for _ in 0..TILESIZE {
-            pixel_data.append(&mut vec![255,255,255,255]);
-        }
-    }
-
-    return Image::new(
-        Extent3d{
-            width: TILESIZE as u32,
-            height: TILESIZE as u32,
-            depth_or_array_layers: 1
-        },
-        TextureDimension::D2,
-        pixel_data,
-        TextureFormat::Rgba8UnormSrgb
-    );
-}
-
 pub fn get_surrounding_tiles(
     player_pos: &Vec3,
-    map: &[[Biome; MAPSIZE]; MAPSIZE],
+    map: &Grid<Biome>,
 ) -> [[Biome; 3]; 3] {
-    let col = (player_pos.x + (TILESIZE * MAPSIZE / 2) as f32) as isize / TILESIZE as isize;
-    let row = (-player_pos.y + (TILESIZE * MAPSIZE / 2) as f32) as isize / TILESIZE as isize;
+    let col = (player_pos.x + (TILESIZE * map.width() / 2) as f32) as isize / TILESIZE as isize;
+    let row = (-player_pos.y + (TILESIZE * map.height() / 2) as f32) as isize / TILESIZE as isize;
     let mut ret = [[Biome::Wall; 3]; 3];
     for i in 0..3 {
         for j in 0..3 {
@@ -591,7 +928,8 @@ pub fn get_surrounding_tiles(
 pub fn get_tile_midpoint_position(
     pos: &Vec3,
 ) -> Vec3 {
-    let offset = (TILESIZE * MAPSIZE) as f32;
+    // any multiple of TILESIZE big enough to keep positions on the map positive
+    let offset = (TILESIZE * MAX_MAPSIZE) as f32;
     let x = (TILESIZE / 2) as f32 - (pos.x + offset) % TILESIZE as f32;
     let y = (TILESIZE / 2) as f32 - (pos.y + offset) % TILESIZE as f32;
     //println!("player at x: {:2} y: {:2} midpoint at x: {:2} y: {:2}", pos.x, pos.y, pos.x+x, pos.y+y);
@@ -601,22 +939,36 @@ pub fn get_tile_midpoint_position(
 pub fn get_biome_from_map(
     row: isize,
     col: isize,
-    map: &[[Biome; MAPSIZE]; MAPSIZE],
+    map: &Grid<Biome>,
 ) -> Biome {
-    if row < 0 || col < 0 || row >= MAPSIZE as isize || col >= MAPSIZE as isize {
-        Biome::Wall
-    } else {
-        map[row as usize][col as usize]
-    }
+    map.get(row, col).unwrap_or(Biome::Wall)
 }
 
 pub fn get_tile_at_pos(
     player_pos: &Vec3,
-    map: &[[Biome; MAPSIZE]; MAPSIZE],
+    map: &Grid<Biome>,
 ) -> Biome {
-    let col = (player_pos.x + (TILESIZE * MAPSIZE / 2) as f32) as usize / TILESIZE;
-    let row = (-player_pos.y + (TILESIZE * MAPSIZE / 2) as f32) as usize / TILESIZE;
-    map[row.clamp(0, MAPSIZE - 1)][col.clamp(0, MAPSIZE - 1)]
+    let col = (player_pos.x + (TILESIZE * map.width() / 2) as f32) as usize / TILESIZE;
+    let row = (-player_pos.y + (TILESIZE * map.height() / 2) as f32) as usize / TILESIZE;
+    map[(row.clamp(0, map.height() - 1), col.clamp(0, map.width() - 1))]
+}
+
+// whether a line between two world positions clears every forest tile on the way,
+// the tiles either end stands on don't count so anyone can see out of the forest they're standing at the edge of
+pub fn in_sight(
+    from: &Vec3,
+    to: &Vec3,
+    map: &Grid<Biome>,
+) -> bool {
+    let distance = from.truncate().distance(to.truncate());
+    let steps = (distance / (TILESIZE as f32 / 2.)) as usize;
+    for step in 1..steps {
+        let pos = from.lerp(*to, step as f32 / steps as f32);
+        if get_tile_at_pos(&pos, map).blocks_sight() {
+            return false;
+        }
+    }
+    return true;
 }
 
 pub fn get_pos_in_tile(
@@ -625,4 +977,37 @@ pub fn get_pos_in_tile(
     let x = ((pos.x % TILESIZE as f32) + TILESIZE as f32) % TILESIZE as f32;
     let y = ((pos.y % TILESIZE as f32) + TILESIZE as f32) % TILESIZE as f32;
     Vec2::new(x, y)
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn camps_go_where_the_noise_left_ground() {
+        // wide, so a camp with its row and column swapped would land somewhere else entirely
+        for seed in 0..8 {
+            let mut world_map = WorldMap { tile_size: TILESIZE, biome_map: Grid::new(MAX_MAPSIZE, MIN_MAPSIZE, Biome::Free) };
+            let (mut camps, mut chests) = (Vec::new(), Vec::new());
+            let mut rng = ChaChaRng::seed_from_u64(seed);
+            read_map(&mut world_map, &mut camps, 10, 0, Symmetry::Off, &mut rng, &mut chests).unwrap();
+            // the same terrain noise read_map picks its camp spots from
+            let perlin = Perlin::new(ChaChaRng::seed_from_u64(seed).next_u64(), 1.0, 0.08, 3);
+            assert!(!camps.is_empty());
+            for camp in &camps {
+                assert!(perlin.noise(camp.y as f64, camp.x as f64) < 0.32, "seed {} camp at ({}, {})", seed, camp.x, camp.y);
+                assert!(world_map.biome_map[(camp.y as usize, camp.x as usize)] == Biome::Camp);
+            }
+        }
+    }
+
+    #[test]
+    fn baked_chunks_fit_the_texture_budget() {
+        // the default map keeps full resolution, bigger ones give some up to stay under the budget
+        assert_eq!(tile_pixels(DEFAULT_MAPSIZE, DEFAULT_MAPSIZE), TILESIZE);
+        for (width, height) in [(MIN_MAPSIZE, MIN_MAPSIZE), (MAX_MAPSIZE, MIN_MAPSIZE), (MAX_MAPSIZE, MAX_MAPSIZE)] {
+            let tile_pixels = tile_pixels(width, height);
+            assert!(TILESIZE % tile_pixels == 0);
+            assert!(width * height * tile_pixels * tile_pixels * 4 <= MAP_TEXTURE_BUDGET);
+        }
+    }
+}
@@ -0,0 +1,256 @@
+use bevy::prelude::*;
+use bevy::asset::{AssetLoader, Error, LoadContext, LoadedAsset, LoadState};
+use bevy::reflect::{TypePath, TypeUuid};
+use bevy::utils::BoxedFuture;
+use sha2::{Digest, Sha256};
+use crate::menus::components::MapFileInput;
+use crate::game::grid::Grid;
+use crate::game::map::{Biome, MapSize, MAXCHESTS, MIN_MAPSIZE, MAX_MAPSIZE};
+
+pub const MAPS_FOLDER: &str = "maps"; // under assets/
+
+/// A hand-made map from assets/maps/<name>.jqmap, played instead of a generated one when the host picks it.
+///
+/// The file is one character per tile, every row the same length. Blank lines and lines starting with // are skipped.
+///   #  wall        .  ground        =  path        c  camp
+///   ~  water       %  forest        +  bridge
+///   C  camp center, where a camp and its enemies go (a camp tile)
+///   $  chest (a ground tile)
+///   S  player spawn (a ground tile), respawns go to the closest one. With none players can spawn anywhere
+/// Each side has to be an even number of tiles from MIN_MAPSIZE to MAX_MAPSIZE, the outermost ring is always wall.
+#[derive(TypeUuid, TypePath)]
+#[uuid = "4d1c2f7e-9a53-4b8e-a6f1-2c7d0e5b9a34"]
+pub struct MapFile {
+    pub name: String, // file name without .jqmap
+    pub hash: u64, // first 8 bytes of the sha256 of the tiles, what clients find their copy by
+    pub biome_map: Grid<Biome>,
+    pub camps: Vec<Vec2>, // in tiles, x is the column and y the row like CampNodes
+    pub chests: Vec<Vec2>,
+    pub spawns: Vec<Vec2>,
+}
+
+/// The hand-made map the host picked, sent to clients with the seed. An empty name means generate one from the seed.
+#[derive(Resource, Clone, Default, PartialEq, Eq)]
+pub struct MapChoice {
+    pub name: String,
+    pub hash: u64,
+}
+
+impl MapChoice {
+    pub fn is_generated(&self) -> bool {
+        return self.name.is_empty();
+    }
+
+    // our copy of the picked map, by hash so a renamed copy still works but an edited one doesn't
+    pub fn find<'a>(&self, maps: &'a Assets<MapFile>) -> Option<&'a MapFile> {
+        if self.is_generated() { return None }
+        return maps.iter().map(|(_, map)| map).find(|map| map.hash == self.hash);
+    }
+}
+
+/// Handles to everything in assets/maps, held so the maps stay loaded for the host page and for joining
+#[derive(Resource)]
+pub struct MapFiles(Vec<HandleUntyped>);
+
+impl MapFiles {
+    // true until every file has either loaded or failed to
+    pub fn loading(&self, asset_server: &AssetServer) -> bool {
+        return self.0.iter().any(|handle| asset_server.get_load_state(handle.id()) == LoadState::Loading);
+    }
+}
+
+/// Where the current map lets players spawn, in tiles like MapFile::spawns. Empty for anywhere.
+#[derive(Resource, Default)]
+pub struct SpawnPoints(pub Vec<Vec2>);
+
+impl SpawnPoints {
+    pub fn nearest(&self, tile: Vec2) -> Option<Vec2> {
+        return self.0.iter().copied().min_by(|a, b| a.distance_squared(tile).total_cmp(&b.distance_squared(tile)));
+    }
+}
+
+#[derive(Default)]
+pub struct MapFileLoader;
+
+impl AssetLoader for MapFileLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let path = load_context.path().to_path_buf();
+            let name = path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+            let map = parse(&name, bytes).map_err(|e| Error::msg(format!("{}: {}", path.display(), e)))?;
+            load_context.set_default_asset(LoadedAsset::new(map));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["jqmap"]
+    }
+}
+
+pub fn parse(name: &str, bytes: &[u8]) -> Result<MapFile, String> {
+    let text = std::str::from_utf8(bytes).map_err(|_| "not a text file".to_string())?;
+    // (line number, row) so errors point at the right line of the file
+    let rows: Vec<(usize, Vec<char>)> = text.lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim_end()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with("//"))
+        .map(|(i, line)| (i, line.chars().collect()))
+        .collect();
+    if rows.is_empty() {
+        return Err("no tiles".to_string());
+    }
+    let (width, height) = (rows[0].1.len(), rows.len());
+    if let Some((line, row)) = rows.iter().find(|(_, row)| row.len() != width) {
+        return Err(format!("line {} is {} tiles wide, the first row is {}", line, row.len(), width));
+    }
+    let size = MapSize::new(width, height);
+    if size.width != width || size.height != height {
+        return Err(format!("map is {}x{}, each side has to be an even number from {} to {}", width, height, MIN_MAPSIZE, MAX_MAPSIZE));
+    }
+
+    let mut map = MapFile {
+        name: name.to_string(),
+        hash: tile_hash(&rows),
+        biome_map: Grid::new(width, height, Biome::Free),
+        camps: Vec::new(),
+        chests: Vec::new(),
+        spawns: Vec::new(),
+    };
+    for (row, (line, tiles)) in rows.iter().enumerate() {
+        for (col, tile) in tiles.iter().enumerate() {
+            let here = Vec2::new(col as f32, row as f32);
+            map.biome_map[(row, col)] = match tile {
+                '#' => Biome::Wall,
+                '.' => Biome::Ground,
+                '=' => Biome::Path,
+                'c' => Biome::Camp,
+                '~' => Biome::Water,
+                '%' => Biome::Forest,
+                '+' => Biome::Bridge,
+                'C' => { map.camps.push(here); Biome::Camp },
+                '$' => { map.chests.push(here); Biome::Ground },
+                'S' => { map.spawns.push(here); Biome::Ground },
+                _ => return Err(format!("unknown tile '{}' on line {}, column {}", tile, line, col + 1)),
+            };
+        }
+    }
+    if map.camps.len() > u8::MAX as usize {
+        return Err(format!("{} camps, at most {} fit", map.camps.len(), u8::MAX));
+    }
+    if map.chests.len() > MAXCHESTS {
+        return Err(format!("{} chests, at most {} fit", map.chests.len(), MAXCHESTS));
+    }
+
+    // same as a generated map, nothing gets out past the edge
+    for row in 0..height {
+        map.biome_map[(row, 0)] = Biome::Wall;
+        map.biome_map[(row, width-1)] = Biome::Wall;
+    }
+    for col in 0..width {
+        map.biome_map[(0, col)] = Biome::Wall;
+        map.biome_map[(height-1, col)] = Biome::Wall;
+    }
+    return Ok(map);
+}
+
+// Hashes the rows parse() kept rather than the file, so line endings, comments and trailing spaces don't change it
+fn tile_hash(rows: &[(usize, Vec<char>)]) -> u64 {
+    let mut hasher = Sha256::new();
+    for (_, row) in rows {
+        hasher.update(row.iter().collect::<String>().as_bytes());
+        hasher.update(b"\n");
+    }
+    return u64::from_be_bytes(hasher.finalize()[..8].try_into().unwrap());
+}
+
+pub fn load_map_files(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let handles = asset_server.load_folder(MAPS_FOLDER);
+    if let Err(e) = &handles {
+        warn!("no hand-made maps, can't read assets/{}: {}", MAPS_FOLDER, e);
+    }
+    commands.insert_resource(MapFiles(handles.unwrap_or_default()));
+    commands.insert_resource(MapChoice::default());
+    commands.insert_resource(SpawnPoints::default());
+}
+
+// Pick the map named in the MapFileInput (default none, generate one), a map file decides the map size.
+// Runs after set_map_size so it gets the last word
+pub fn set_map_choice(
+    map_file_input_query: Query<&MapFileInput>,
+    maps: Res<Assets<MapFile>>,
+    mut map_choice: ResMut<MapChoice>,
+    mut map_size: ResMut<MapSize>,
+) {
+    let name = map_file_input_query.iter().map(|input| input.value.trim().trim_end_matches(".jqmap").to_string()).last().unwrap_or_default();
+    *map_choice = MapChoice::default();
+    if name.is_empty() { return }
+    let map = maps.iter().map(|(_, map)| map).find(|map| map.name.eq_ignore_ascii_case(&name));
+    if map.is_none() {
+        warn!("no map called {} in assets/{}, generating one instead", name, MAPS_FOLDER);
+        return;
+    }
+    let map = map.unwrap();
+    *map_choice = MapChoice { name: map.name.clone(), hash: map.hash };
+    *map_size = MapSize::new(map.biome_map.width(), map.biome_map.height());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a MIN_MAPSIZE square of ground with a camp center, a chest and a spawn
+    fn small_map(newline: &str) -> String {
+        let mut text = String::from("// test map") + newline;
+        for row in 0..MIN_MAPSIZE {
+            let mut line = ".".repeat(MIN_MAPSIZE);
+            match row {
+                10 => line.replace_range(20..21, "C"),
+                30 => line.replace_range(40..41, "$"),
+                50 => line.replace_range(60..61, "S"),
+                _ => (),
+            }
+            text += &line;
+            text += newline;
+        }
+        return text;
+    }
+
+    #[test]
+    fn parses_tiles_and_markers() {
+        let map = parse("small", small_map("\n").as_bytes()).unwrap();
+        assert_eq!(map.name, "small");
+        assert_eq!((map.biome_map.width(), map.biome_map.height()), (MIN_MAPSIZE, MIN_MAPSIZE));
+        assert_eq!(map.camps, vec![Vec2::new(20., 10.)]);
+        assert_eq!(map.chests, vec![Vec2::new(40., 30.)]);
+        assert_eq!(map.spawns, vec![Vec2::new(60., 50.)]);
+        assert!(map.biome_map[(10, 20)] == Biome::Camp);
+        assert!(map.biome_map[(30, 40)] == Biome::Ground);
+        // the outer ring is wall whatever the file says
+        assert!(map.biome_map[(0, 5)] == Biome::Wall);
+        assert!(map.biome_map[(5, MIN_MAPSIZE - 1)] == Biome::Wall);
+    }
+
+    #[test]
+    fn hash_follows_the_tiles_not_the_file() {
+        let lf = parse("small", small_map("\n").as_bytes()).unwrap();
+        let crlf = parse("small", small_map("\r\n").as_bytes()).unwrap();
+        assert_eq!(lf.hash, crlf.hash);
+        let commented = parse("small", ("// another comment\n".to_string() + &small_map("\n")).as_bytes()).unwrap();
+        assert_eq!(lf.hash, commented.hash);
+        let edited = parse("small", small_map("\n").replacen("$", ".", 1).as_bytes()).unwrap();
+        assert_ne!(lf.hash, edited.hash);
+    }
+
+    #[test]
+    fn bad_maps_are_errors() {
+        assert!(parse("empty", b"// nothing here\n").is_err());
+        assert!(parse("tiny", b"....\n....\n").is_err());
+        assert!(parse("ragged", (small_map("\n") + ".\n").as_bytes()).is_err());
+        assert!(parse("unknown", small_map("\n").replacen("S", "?", 1).as_bytes()).is_err());
+    }
+}
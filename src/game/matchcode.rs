@@ -0,0 +1,208 @@
+use bevy::prelude::*;
+use sha2::{Digest, Sha256};
+use crate::game::MapConfig;
+use crate::game::map::{MapSeed, MapSize, NumCamps, SpawnSettings, MIN_MAPSIZE};
+use crate::game::symmetry::Symmetry;
+use crate::menus::components::MapSeedInput;
+
+pub const PREFIX: &str = "JQ-";
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ"; // crockford base32, no I, L, O or U to misread
+const CODE_BYTES: usize = 15; // 14 of settings and a check byte, 24 characters
+const GROUP: usize = 4; // characters between dashes
+const SYMMETRY_SHIFT: u8 = 6; // symmetry rides in the top bits of the enemies per camp byte
+
+/// The seed a host page seed box stands for. Numbers are used as they are so old seeds still give the same maps,
+/// any other text is hashed, so "dragons" is always the same map. Empty is seed 0 like it always was
+pub fn seed_from_text(text: &str) -> u64 {
+    let text = text.trim();
+    if text.is_empty() { return 0 }
+    if let Ok(seed) = text.parse::<u64>() {
+        return seed;
+    }
+    return u64::from_be_bytes(Sha256::digest(text.as_bytes())[..8].try_into().unwrap());
+}
+
+/// Everything that goes into generating a map, packed into something like JQ-0K3M-...-Q7TB to share.
+/// Pasted into the seed box on the host page or in the lobby it sets the rest of the settings too.
+/// Hand-made maps don't go in, they're already shared by name
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct MatchCode {
+    pub seed: u64,
+    pub num_camps: u8,
+    pub map_size: MapSize,
+    pub spawn_settings: SpawnSettings,
+}
+
+impl MatchCode {
+    pub fn encode(&self) -> String {
+        let mut bytes: Vec<u8> = Vec::new();
+        bytes.extend_from_slice(&self.seed.to_be_bytes());
+        bytes.push(self.num_camps);
+        // sides are even and at least MIN_MAPSIZE, which keeps MAX_MAPSIZE in a byte
+        bytes.push(((self.map_size.width - MIN_MAPSIZE) / 2) as u8);
+        bytes.push(((self.map_size.height - MIN_MAPSIZE) / 2) as u8);
+        bytes.push(self.spawn_settings.num_chests);
+        bytes.push(self.spawn_settings.enemies_per_camp | self.spawn_settings.symmetry.to_byte() << SYMMETRY_SHIFT);
+        bytes.push(self.spawn_settings.eid_percentage);
+        bytes.push(check_byte(&bytes));
+
+        // 5 bits a character, 120 bits come out even
+        let mut chars = String::new();
+        let (mut acc, mut bits) = (0u32, 0);
+        for b in bytes {
+            acc = (acc << 8) | b as u32;
+            bits += 8;
+            while bits >= 5 {
+                bits -= 5;
+                chars.push(ALPHABET[(acc >> bits) as usize & 31] as char);
+            }
+            acc &= (1 << bits) - 1; // only the bits not written out yet
+        }
+        let groups: Vec<&str> = chars.as_bytes().chunks(GROUP).map(|group| std::str::from_utf8(group).unwrap()).collect();
+        return format!("{}{}", PREFIX, groups.join("-"));
+    }
+
+    /// None unless it's a whole code with the right check byte. Case, dashes and spaces don't matter,
+    /// and the letters people mix up with digits read as those digits
+    pub fn decode(text: &str) -> Option<MatchCode> {
+        let text = text.trim().to_ascii_uppercase();
+        let text = text.strip_prefix(PREFIX)?;
+        let mut bytes: Vec<u8> = Vec::new();
+        let (mut acc, mut bits) = (0u32, 0);
+        for ch in text.chars().filter(|ch| *ch != '-' && !ch.is_whitespace()) {
+            let ch = match ch { 'O' => '0', 'I' | 'L' => '1', _ => ch };
+            let value = ALPHABET.iter().position(|a| *a as char == ch)? as u32;
+            acc = (acc << 5) | value;
+            bits += 5;
+            if bits >= 8 {
+                bits -= 8;
+                bytes.push((acc >> bits) as u8);
+            }
+            acc &= (1 << bits) - 1;
+        }
+        if bytes.len() != CODE_BYTES || check_byte(&bytes[..CODE_BYTES - 1]) != bytes[CODE_BYTES - 1] {
+            return None;
+        }
+        return Some(MatchCode {
+            seed: u64::from_be_bytes(bytes[..8].try_into().unwrap()),
+            num_camps: bytes[8],
+            map_size: MapSize::new(MIN_MAPSIZE + bytes[9] as usize * 2, MIN_MAPSIZE + bytes[10] as usize * 2),
+            spawn_settings: SpawnSettings::new(
+                bytes[11],
+                bytes[12] & ((1 << SYMMETRY_SHIFT) - 1),
+                bytes[13],
+                Symmetry::from_byte(bytes[12] >> SYMMETRY_SHIFT),
+            ),
+        });
+    }
+
+    // the MapConfig strings this code stands for, what the host page would have had typed in
+    pub fn write_config(&self, config: &mut MapConfig) {
+        config.num_camps = self.num_camps.to_string();
+        config.num_chests = self.spawn_settings.num_chests.to_string();
+        config.enemy_per_camp = self.spawn_settings.enemies_per_camp.to_string();
+        config.eid_percentage = self.spawn_settings.eid_percentage.to_string();
+    }
+}
+
+// catches typos, a code with one character wrong almost never checks out
+fn check_byte(bytes: &[u8]) -> u8 {
+    return Sha256::digest(bytes)[0];
+}
+
+// A match code in the seed box overrides the rest of the host page, runs after the set_* systems so it gets the last word.
+// The seed box keeps the code so the lobby shows what was pasted
+pub fn apply_match_code(
+    map_seed_input_query: Query<&MapSeedInput>,
+    mut map_config: ResMut<MapConfig>,
+    mut map_seed: ResMut<MapSeed>,
+    mut num_camps: ResMut<NumCamps>,
+    mut map_size: ResMut<MapSize>,
+    mut spawn_settings: ResMut<SpawnSettings>,
+) {
+    let code = map_seed_input_query.iter().filter_map(|input| MatchCode::decode(&input.value)).last();
+    if code.is_none() { return }
+    let code = code.unwrap();
+    map_seed.0 = code.seed;
+    num_camps.0 = code.num_camps;
+    *map_size = code.map_size;
+    *spawn_settings = code.spawn_settings;
+    code.write_config(&mut map_config);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::camp::MAX_CAMP_ENEMIES;
+    use crate::game::map::{DEFAULT_MAPSIZE, MAXCHESTS, MAX_MAPSIZE};
+
+    fn code() -> MatchCode {
+        MatchCode {
+            seed: 0x0123_4567_89AB_CDF1,
+            num_camps: 10,
+            map_size: MapSize::new(DEFAULT_MAPSIZE, DEFAULT_MAPSIZE),
+            spawn_settings: SpawnSettings::new(5, 3, 40, Symmetry::Rotate),
+        }
+    }
+
+    fn round_trips(code: MatchCode) {
+        let text = code.encode();
+        assert!(text.starts_with(PREFIX));
+        assert!(MatchCode::decode(&text) == Some(code), "{} didn't decode to what made it", text);
+    }
+
+    #[test]
+    fn every_setting_round_trips() {
+        for seed in [0, 1, 0x0123_4567_89AB_CDF1, u64::MAX] {
+            round_trips(MatchCode { seed, ..code() });
+        }
+        for num_camps in 0..=u8::MAX {
+            round_trips(MatchCode { num_camps, ..code() });
+        }
+        for side in (MIN_MAPSIZE..=MAX_MAPSIZE).step_by(2) {
+            round_trips(MatchCode { map_size: MapSize::new(side, MIN_MAPSIZE), ..code() });
+            round_trips(MatchCode { map_size: MapSize::new(MAX_MAPSIZE, side), ..code() });
+        }
+        let settings = code().spawn_settings;
+        for num_chests in 0..=MAXCHESTS as u8 {
+            round_trips(MatchCode { spawn_settings: SpawnSettings { num_chests, ..settings }, ..code() });
+        }
+        for enemies_per_camp in 1..=MAX_CAMP_ENEMIES {
+            for symmetry in Symmetry::ALL {
+                round_trips(MatchCode { spawn_settings: SpawnSettings { enemies_per_camp, symmetry, ..settings }, ..code() });
+            }
+        }
+        for eid_percentage in 0..=100 {
+            round_trips(MatchCode { spawn_settings: SpawnSettings { eid_percentage, ..settings }, ..code() });
+        }
+    }
+
+    #[test]
+    fn typos_fail_the_check_byte() {
+        let text = code().encode();
+        let body = text.strip_prefix(PREFIX).unwrap().replace('-', "");
+        for i in 0..body.len() {
+            let mut typo = body.clone().into_bytes();
+            let value = ALPHABET.iter().position(|a| *a == typo[i]).unwrap();
+            typo[i] = ALPHABET[(value + 1) % ALPHABET.len()];
+            let typo = format!("{}{}", PREFIX, String::from_utf8(typo).unwrap());
+            assert!(MatchCode::decode(&typo).is_none(), "{} decoded with character {} changed", typo, i + 1);
+        }
+        // nor is one with a character missing
+        assert!(MatchCode::decode(&text[..text.len() - 1]).is_none());
+    }
+
+    #[test]
+    fn misread_input_still_decodes() {
+        let text = code().encode();
+        // this one has both a 0 and a 1 to misread
+        assert!(text.contains('0') && text.contains('1'));
+        let same = Some(code());
+        assert!(MatchCode::decode(&text.to_ascii_lowercase()) == same);
+        assert!(MatchCode::decode(&text.replace('0', "O")) == same);
+        assert!(MatchCode::decode(&text.replace('1', "I")) == same);
+        assert!(MatchCode::decode(&text.replace('1', "l")) == same);
+        assert!(MatchCode::decode(&format!("  {}  ", text.replace('-', " "))) == same);
+        assert!(MatchCode::decode(&text.replace(PREFIX, "")).is_none());
+    }
+}
@@ -1,9 +1,16 @@
 use bevy::prelude::*;
+use crate::AppState;
 
 pub mod player;
 pub mod enemy;
 pub mod camera;
 pub mod map;
+pub mod grid;
+pub mod mapfile;
+pub mod regions;
+pub mod export;
+pub mod matchcode;
+pub mod symmetry;
 pub mod noise;
 pub mod movement;
 pub mod buffers;
@@ -110,9 +117,11 @@ impl Plugin for GamePlugin{
             ..default()
         })
             .set(ImagePlugin::default_nearest())
+            .disable::<bevy::log::LogPlugin>()  // logging::LoggingPlugin sets it up instead
         )
         .add_systems(Startup, startup)
         .add_systems(Update, update_fades)
+        .add_systems(OnEnter(AppState::Lobby), despawn_world)
         .add_plugins((
             player::PlayerPlugin,
             enemy::EnemyPlugin,
@@ -184,6 +193,25 @@ pub fn startup(mut commands: Commands, asset_server: Res<AssetServer>, mut textu
     commands.insert_resource(movement::KeyBinds::new());
 }
 
+/// Clears out the last round when the host restarts it, the next OnEnter(Game) builds everything again.
+/// Enemies are already gone, remove_enemies runs OnExit(Game).
+pub fn despawn_world(
+    mut commands: Commands,
+    world: Query<Entity, Or<(
+        With<map::Chunk>,
+        With<components::Camp>,
+        With<components::Decoration>,
+        With<components::ItemChest>,
+        With<components::PowerUp>,
+        With<components::Player>,
+        With<camera::MinimapBorder>,
+    )>>,
+) {
+    for e in &world {
+        commands.entity(e).despawn_recursive();
+    }
+}
+
 pub fn update_fades(
     mut commands: Commands,
     time: Res<Time>,
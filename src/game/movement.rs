@@ -9,6 +9,7 @@ use crate::components::*;
 use crate::game::buffers;
 use crate::game::buffers::{DirBuffer, PosBuffer};
 use crate::game::camera::SpatialCameraBundle;
+use crate::game::grid::Grid;
 use crate::game::map::Biome::Wall;
 use crate::game::map::{get_pos_in_tile, get_tile_at_pos, TILESIZE};
 use crate::net::TickNum;
@@ -20,7 +21,8 @@ pub struct KeyBinds {
     pub up: KeyCode,
     pub down: KeyCode,
     pub left: KeyCode,
-    pub right: KeyCode
+    pub right: KeyCode,
+    pub chat: KeyCode
 }
 
 impl KeyBinds {
@@ -30,7 +32,8 @@ impl KeyBinds {
             up: KeyCode::W,
             down: KeyCode::S,
             left: KeyCode::A,
-            right: KeyCode::D
+            right: KeyCode::D,
+            chat: KeyCode::Return
         }
     }
 }
@@ -81,10 +84,12 @@ pub fn handle_move(
     let dir = MOVE_VECTORS[mv];
     let can_move = true;
 
-
+    // wading through water slows you down, speed ups and all
+    let speed = (PLAYER_SPEED + spu.power_ups[PowerUpType::MovementSpeedUp as usize] as f32 * MOVEMENT_SPEED_UP as f32)
+        * get_tile_at_pos(&pos.translation, &map.biome_map).speed();
     let mut new_pos = Vec3 {
-        x: pos.translation.x + dir.x * (PLAYER_SPEED + spu.power_ups[PowerUpType::MovementSpeedUp as usize] as f32 * MOVEMENT_SPEED_UP as f32) * time.delta_seconds(),
-        y: pos.translation.y + dir.y * (PLAYER_SPEED + spu.power_ups[PowerUpType::MovementSpeedUp as usize] as f32 * MOVEMENT_SPEED_UP as f32) * time.delta_seconds(),
+        x: pos.translation.x + dir.x * speed * time.delta_seconds(),
+        y: pos.translation.y + dir.y * speed * time.delta_seconds(),
         z: 0.0,
     };
 
@@ -120,7 +125,7 @@ pub fn handle_move(
 pub fn correct_wall_collisions(
     pos: &Vec3,
     collider: &Vec2,
-    map: &[[map::Biome; map::MAPSIZE]; map::MAPSIZE],
+    map: &Grid<map::Biome>,
 ) -> Vec3 {
     let mut pos = pos.clone();
     let north = pos + Vec3::new(0.0, collider.y / 2.0, 0.0);
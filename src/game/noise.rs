@@ -2,52 +2,88 @@ use rand_chacha::ChaChaRng;
 use rand_chacha::rand_core::SeedableRng;
 use rand::Rng;
 
-// an implementation of perlin noise
-// this implementation does not include frequency, which exists outside the main function
-// it is also HEAVILY commented for a clear explanation of what's going on in the algorithm
-pub struct Perlin {
-    p: [usize; 256],
+// how much each octave's frequency and amplitude get multiplied by, unless a source is given others
+pub const DEFAULT_LACUNARITY: f64 = 2.0;
+pub const DEFAULT_GAIN: f64 = 0.3;
+
+// how far apart warp samples the two offsets, so x and y don't get pushed the same way
+const WARP_OFFSET: f64 = 173.7;
+
+/// Anything that gives a value for every point on the map, so generation can pick and mix noise types.
+/// Values are around 0 to 1 with a single octave, more octaves can push past 1.
+pub trait NoiseSource {
+    fn noise(&self, x: f64, y: f64) -> f64;
+}
+
+impl NoiseSource for Box<dyn NoiseSource> {
+    fn noise(&self, x: f64, y: f64) -> f64 {
+        self.as_ref().noise(x, y)
+    }
+}
+
+// the octave settings every source sums its layers with
+#[derive(Clone, Copy)]
+struct Octaves {
     amp: f64,
     freq: f64,
     oct: usize,
+    lacunarity: f64,
+    gain: f64,
 }
 
-impl Perlin {
-    pub fn new(seed: u64, amp: f64, freq: f64, oct: usize) -> Self {
-        let p = Self::shuffle(seed);
-        Self { p, amp, freq, oct }
-    }
-
-    pub fn noise(&self, x: usize, y: usize) -> f64 {
-        self.fbm(x as f64, y as f64, self.amp, self.freq, self.oct)
+impl Octaves {
+    fn new(amp: f64, freq: f64, oct: usize) -> Self {
+        Self { amp, freq, oct, lacunarity: DEFAULT_LACUNARITY, gain: DEFAULT_GAIN }
     }
 
-    fn fbm(&self, x: f64, y: f64, a: f64, f: f64, o: usize) -> f64 {
+    // fractal brownian motion, each octave adds base at a higher frequency and lower amplitude
+    fn fbm(&self, x: f64, y: f64, a: f64, f: f64, o: usize, base: &dyn Fn(f64, f64) -> f64) -> f64 {
         if o == 0 {
             0.0
         }
         else {
-            let r = a * (self.perlin(x * f, y * f) + self.fbm(x, y,
-                a * 0.3, // multiply amplitude by decimal (ex. 0.5) to decrease it
-                f * 2.0, // double frequency to increase frequency
-                o - 1));
-            // println!("r: {}", r);
+            let r = a * (base(x * f, y * f) + self.fbm(x, y,
+                a * self.gain, // multiply amplitude by decimal (ex. 0.5) to decrease it
+                f * self.lacunarity, // multiply frequency (ex. 2.0) to increase it
+                o - 1, base));
             r
         }
     }
 
+    fn sum(&self, x: f64, y: f64, base: &dyn Fn(f64, f64) -> f64) -> f64 {
+        self.fbm(x, y, self.amp, self.freq, self.oct, base)
+    }
+}
+
+// an implementation of perlin noise
+// this implementation does not include frequency, which exists outside the main function
+// it is also HEAVILY commented for a clear explanation of what's going on in the algorithm
+pub struct Perlin {
+    p: [usize; 256],
+    octaves: Octaves,
+}
+
+impl Perlin {
+    pub fn new(seed: u64, amp: f64, freq: f64, oct: usize) -> Self {
+        let p = shuffle(seed);
+        Self { p, octaves: Octaves::new(amp, freq, oct) }
+    }
+
+    pub fn with_falloff(mut self, lacunarity: f64, gain: f64) -> Self {
+        self.octaves.lacunarity = lacunarity;
+        self.octaves.gain = gain;
+        self
+    }
+
     fn perlin(&self, x: f64, y: f64) -> f64 {
         // find the "floor" of these floating point numbers
-        let mut xi = x.floor() as usize;
-        let mut yi = y.floor() as usize;
-    
-        // wrap values around 255 to access permutation table
-        xi = xi & 255;
-        yi = yi & 255;
+        // wrap values around 255 to access permutation table, negative ones included
+        let xi = (x.floor() as i64 & 255) as usize;
+        let yi = (y.floor() as i64 & 255) as usize;
     
         // pop off floating remainder from x and y
-        let xf = x - xi as f64;
-        let yf = y - yi as f64;
+        let xf = x - x.floor();
+        let yf = y - y.floor();
     
         // find grid point -> input point vectors
         // bot = bottom, r and l = right and left
@@ -109,49 +145,167 @@ impl Perlin {
             * t + 10.0)
             * t * t * t
     }
+}
+
+impl NoiseSource for Perlin {
+    fn noise(&self, x: f64, y: f64) -> f64 {
+        self.octaves.sum(x, y, &|x, y| self.perlin(x, y))
+    }
+}
+
+// permutation table -- shuffle the table to generate new noise fields
+// taken from Ken Perlin's implementation
+const P: [usize; 256] = 
+[151, 160, 137, 91, 90, 15, 131, 13, 201, 95, 96, 53, 194, 233, 7,
+225, 140, 36, 103, 30, 69, 142, 8, 99, 37, 240, 21, 10, 23, 190, 6, 148, 247,
+120, 234, 75, 0, 26, 197, 62, 94, 252, 219, 203, 117, 35, 11, 32, 57, 177, 33,
+88, 237, 149, 56, 87, 174, 20, 125, 136, 171, 168, 68, 175, 74, 165, 71, 134,
+139, 48, 27, 166, 77, 146, 158, 231, 83, 111, 229, 122, 60, 211, 133, 230, 220,
+105, 92, 41, 55, 46, 245, 40, 244, 102, 143, 54, 65, 25, 63, 161, 1, 216, 80,
+73, 209, 76, 132, 187, 208, 89, 18, 169, 200, 196, 135, 130, 116, 188, 159, 86,
+164, 100, 109, 198, 173, 186, 3, 64, 52, 217, 226, 250, 124, 123, 5, 202, 38,
+147, 118, 126, 255, 82, 85, 212, 207, 206, 59, 227, 47, 16, 58, 17, 182, 189,
+28, 42, 223, 183, 170, 213, 119, 248, 152, 2, 44, 154, 163, 70, 221, 153, 101,
+155, 167, 43, 172, 9, 129, 22, 39, 253, 19, 98, 108, 110, 79, 113, 224, 232,
+178, 185, 112, 104, 218, 246, 97, 228, 251, 34, 242, 193, 238, 210, 144, 12,
+191, 179, 162, 241, 81, 51, 145, 235, 249, 14, 239, 107, 49, 192, 214, 31, 181,
+199, 106, 157, 184, 84, 204, 176, 115, 121, 50, 45, 127, 4, 150, 254, 138, 236,
+205, 93, 222, 114, 67, 29, 24, 72, 243, 141, 128, 195, 78, 66, 215, 61, 156, 180];
+
+// function to shuffle permutation table and return new array of shuffled values
+fn shuffle(seed: u64) -> [usize; 256] {
+    // use rand_chacha crate to generate random numbers
+    let mut rng = ChaChaRng::seed_from_u64(seed);
+    // must create a new array because P is a const
+    let mut new_array = P;
+    // swap values in permutation table 256 times
+    for i in 0..256 {
+        let j = rng.gen_range(0..256);
+        let temp = P[i];
+        new_array[i] = P[j];
+        new_array[j] = temp;
+    }
+    // return the new array
+    new_array
+}
+
+// simplex noise, perlin's follow up on a triangle grid so it has fewer straight line artifacts
+pub struct Simplex {
+    p: [usize; 256],
+    octaves: Octaves,
+}
+
+impl Simplex {
+    // skew a square grid onto triangles and back
+    const F2: f64 = 0.3660254037844386; // (sqrt(3) - 1) / 2
+    const G2: f64 = 0.21132486540518713; // (3 - sqrt(3)) / 6
 
-    // *** planned functions: ***
-    // map(height, width) -> use fbm function to generate array of values for map of any size
-    //                    -> clamp values in a range between 0.0 and 1.0 if necessary 
-    //                       (result += 1.0, result / 2.0)
-
-    // permutation table -- shuffle the table to generate new noise fields
-    // taken from Ken Perlin's implementation
-    const P: [usize; 256] = 
-    [151, 160, 137, 91, 90, 15, 131, 13, 201, 95, 96, 53, 194, 233, 7,
-    225, 140, 36, 103, 30, 69, 142, 8, 99, 37, 240, 21, 10, 23, 190, 6, 148, 247,
-    120, 234, 75, 0, 26, 197, 62, 94, 252, 219, 203, 117, 35, 11, 32, 57, 177, 33,
-    88, 237, 149, 56, 87, 174, 20, 125, 136, 171, 168, 68, 175, 74, 165, 71, 134,
-    139, 48, 27, 166, 77, 146, 158, 231, 83, 111, 229, 122, 60, 211, 133, 230, 220,
-    105, 92, 41, 55, 46, 245, 40, 244, 102, 143, 54, 65, 25, 63, 161, 1, 216, 80,
-    73, 209, 76, 132, 187, 208, 89, 18, 169, 200, 196, 135, 130, 116, 188, 159, 86,
-    164, 100, 109, 198, 173, 186, 3, 64, 52, 217, 226, 250, 124, 123, 5, 202, 38,
-    147, 118, 126, 255, 82, 85, 212, 207, 206, 59, 227, 47, 16, 58, 17, 182, 189,
-    28, 42, 223, 183, 170, 213, 119, 248, 152, 2, 44, 154, 163, 70, 221, 153, 101,
-    155, 167, 43, 172, 9, 129, 22, 39, 253, 19, 98, 108, 110, 79, 113, 224, 232,
-    178, 185, 112, 104, 218, 246, 97, 228, 251, 34, 242, 193, 238, 210, 144, 12,
-    191, 179, 162, 241, 81, 51, 145, 235, 249, 14, 239, 107, 49, 192, 214, 31, 181,
-    199, 106, 157, 184, 84, 204, 176, 115, 121, 50, 45, 127, 4, 150, 254, 138, 236,
-    205, 93, 222, 114, 67, 29, 24, 72, 243, 141, 128, 195, 78, 66, 215, 61, 156, 180];
-
-    // function to shuffle permutation table and return new array of shuffled values
-    fn shuffle(seed: u64) -> [usize; 256] {
-        // use rand_chacha crate to generate random numbers
-        let mut rng = ChaChaRng::seed_from_u64(seed);
-        // must create a new array because P is a const
-        let mut new_array = Self::P;
-        // swap values in permutation table 256 times
-        for i in 0..256 {
-            let j = rng.gen_range(0..256);
-            let temp = Self::P[i];
-            new_array[i] = Self::P[j];
-            new_array[j] = temp;
+    // 8 directions around the compass
+    const GRADIENTS: [(f64, f64); 8] = [(1., 1.), (-1., 1.), (1., -1.), (-1., -1.), (1., 0.), (-1., 0.), (0., 1.), (0., -1.)];
+
+    pub fn new(seed: u64, amp: f64, freq: f64, oct: usize) -> Self {
+        let p = shuffle(seed);
+        Self { p, octaves: Octaves::new(amp, freq, oct) }
+    }
+
+    pub fn with_falloff(mut self, lacunarity: f64, gain: f64) -> Self {
+        self.octaves.lacunarity = lacunarity;
+        self.octaves.gain = gain;
+        self
+    }
+
+    fn simplex(&self, x: f64, y: f64) -> f64 {
+        // which triangle the point lands in, the corners are (i, j), the middle one, then (i + 1, j + 1)
+        let skew = (x + y) * Self::F2;
+        let i = (x + skew).floor();
+        let j = (y + skew).floor();
+        let unskew = (i + j) * Self::G2;
+        let x0 = x - (i - unskew);
+        let y0 = y - (j - unskew);
+        let (i1, j1) = if x0 > y0 { (1, 0) } else { (0, 1) };
+        let corners = [
+            (x0, y0, 0, 0),
+            (x0 - i1 as f64 + Self::G2, y0 - j1 as f64 + Self::G2, i1, j1),
+            (x0 - 1.0 + 2.0 * Self::G2, y0 - 1.0 + 2.0 * Self::G2, 1, 1),
+        ];
+
+        let (ii, jj) = ((i as i64 & 255) as usize, (j as i64 & 255) as usize);
+        let mut n = 0.0;
+        for (cx, cy, di, dj) in corners {
+            // each corner only reaches so far, past that it adds nothing
+            let t = 0.5 - cx * cx - cy * cy;
+            if t < 0.0 { continue }
+            let g = Self::GRADIENTS[self.p[(self.p[(ii + di) & 255] + jj + dj) & 255] % 8];
+            n += t * t * t * t * (g.0 * cx + g.1 * cy);
         }
-        // return the new array
-        new_array
+        // scaled to about -1 to 1, then moved to 0 to 1 like perlin
+        (70.0 * n + 1.0) / 2.0
+    }
+}
+
+impl NoiseSource for Simplex {
+    fn noise(&self, x: f64, y: f64) -> f64 {
+        self.octaves.sum(x, y, &|x, y| self.simplex(x, y))
     }
 }
 
+// ridged multifractal, perlin folded around its middle so the creases turn into sharp ridges.
+// Ridges in one octave make the next stronger there, so the valleys between them stay smooth
+pub struct Ridged {
+    base: Perlin,
+}
+
+impl Ridged {
+    pub fn new(seed: u64, amp: f64, freq: f64, oct: usize) -> Self {
+        Self { base: Perlin::new(seed, amp, freq, oct) }
+    }
+
+    pub fn with_falloff(mut self, lacunarity: f64, gain: f64) -> Self {
+        self.base = self.base.with_falloff(lacunarity, gain);
+        self
+    }
+}
+
+impl NoiseSource for Ridged {
+    fn noise(&self, x: f64, y: f64) -> f64 {
+        let o = &self.base.octaves;
+        let (mut a, mut f) = (o.amp, o.freq);
+        let mut weight = 1.0;
+        let mut sum = 0.0;
+        for _ in 0..o.oct {
+            let signal = 1.0 - (self.base.perlin(x * f, y * f) * 2.0 - 1.0).abs();
+            let signal = signal * signal * weight;
+            weight = (signal * 2.0).clamp(0.0, 1.0);
+            sum += signal * a;
+            a *= o.gain;
+            f *= o.lacunarity;
+        }
+        sum
+    }
+}
+
+// domain warping, looks source up somewhere nearby instead of right at the point,
+// with warp deciding where. Straight noise edges come out swirled, strength is how far in the same units as x and y
+pub struct Warp<S: NoiseSource, W: NoiseSource> {
+    source: S,
+    warp: W,
+    strength: f64,
+}
+
+impl<S: NoiseSource, W: NoiseSource> Warp<S, W> {
+    pub fn new(source: S, warp: W, strength: f64) -> Self {
+        Self { source, warp, strength }
+    }
+}
+
+impl<S: NoiseSource, W: NoiseSource> NoiseSource for Warp<S, W> {
+    fn noise(&self, x: f64, y: f64) -> f64 {
+        // warp is around 0 to 1, centered so points get pushed either way
+        let dx = (self.warp.noise(x, y) - 0.5) * 2.0 * self.strength;
+        let dy = (self.warp.noise(x + WARP_OFFSET, y + WARP_OFFSET) - 0.5) * 2.0 * self.strength;
+        self.source.noise(x + dx, y + dy)
+    }
+}
 #[derive(Clone, Copy)]
 struct Vec2 {
     x: f64,
@@ -164,3 +318,75 @@ impl Vec2 {
         (self.x * other.x) + (self.y * other.y)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEEDS: [u64; 3] = [0, 1, 0xdead_beef];
+    const GAIN: f64 = 0.5;
+    const OCTAVES: usize = 4;
+
+    // every kind of source, with the default falloff and with GAIN
+    fn sources(seed: u64) -> Vec<(&'static str, Box<dyn NoiseSource>)> {
+        vec![
+            ("perlin", Box::new(Perlin::new(seed, 1.0, 0.08, OCTAVES))),
+            ("perlin with falloff", Box::new(Perlin::new(seed, 1.0, 0.08, OCTAVES).with_falloff(DEFAULT_LACUNARITY, GAIN))),
+            ("simplex", Box::new(Simplex::new(seed, 1.0, 0.08, OCTAVES))),
+            ("simplex with falloff", Box::new(Simplex::new(seed, 1.0, 0.08, OCTAVES).with_falloff(DEFAULT_LACUNARITY, GAIN))),
+            ("ridged", Box::new(Ridged::new(seed, 1.0, 0.08, OCTAVES))),
+            ("ridged with falloff", Box::new(Ridged::new(seed, 1.0, 0.08, OCTAVES).with_falloff(DEFAULT_LACUNARITY, GAIN))),
+            ("warp", Box::new(Warp::new(Perlin::new(seed, 1.0, 0.08, OCTAVES), Simplex::new(!seed, 1.0, 0.05, 2), 4.0))),
+        ]
+    }
+
+    // a spread of points, negative ones included
+    fn points() -> impl Iterator<Item = (f64, f64)> {
+        (-40..40).flat_map(|x| (-40..40).map(move |y| (x as f64 * 3.7, y as f64 * 2.3)))
+    }
+
+    #[test]
+    fn same_seed_same_noise() {
+        for seed in SEEDS {
+            for ((name, a), (_, b)) in sources(seed).into_iter().zip(sources(seed)) {
+                for (x, y) in points() {
+                    assert!(a.noise(x, y) == b.noise(x, y), "{} seed {} differs at ({}, {})", name, seed, x, y);
+                }
+            }
+            for ((name, a), (_, b)) in sources(seed).into_iter().zip(sources(seed + 1)) {
+                assert!(points().any(|(x, y)| a.noise(x, y) != b.noise(x, y)), "{} seeds {} and {} are the same", name, seed, seed + 1);
+            }
+        }
+    }
+
+    #[test]
+    fn noise_stays_in_range() {
+        // each octave is 0 to 1 times its amplitude, so the most the sum can reach is the sum of the amplitudes
+        let most = |gain: f64| (0..OCTAVES).map(|o| gain.powi(o as i32)).sum::<f64>();
+        for seed in SEEDS {
+            for (name, source) in sources(seed) {
+                let top = if name.ends_with("falloff") || name == "warp" { most(GAIN).max(most(DEFAULT_GAIN)) } else { most(DEFAULT_GAIN) };
+                for (x, y) in points() {
+                    let n = source.noise(x, y);
+                    assert!(n >= 0.0 && n <= top, "{} seed {} gave {} at ({}, {})", name, seed, n, x, y);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn falloff_changes_the_octaves() {
+        // no gain leaves just the first octave
+        let one = Perlin::new(7, 1.0, 0.08, 1);
+        let flat = Perlin::new(7, 1.0, 0.08, OCTAVES).with_falloff(DEFAULT_LACUNARITY, 0.0);
+        let ridged_one = Ridged::new(7, 1.0, 0.08, 1);
+        let ridged_flat = Ridged::new(7, 1.0, 0.08, OCTAVES).with_falloff(DEFAULT_LACUNARITY, 0.0);
+        let simplex_one = Simplex::new(7, 1.0, 0.08, 1);
+        let simplex_flat = Simplex::new(7, 1.0, 0.08, OCTAVES).with_falloff(DEFAULT_LACUNARITY, 0.0);
+        for (x, y) in points() {
+            assert_eq!(one.noise(x, y), flat.noise(x, y));
+            assert_eq!(ridged_one.noise(x, y), ridged_flat.noise(x, y));
+            assert_eq!(simplex_one.noise(x, y), simplex_flat.noise(x, y));
+        }
+    }
+}
@@ -1,21 +1,27 @@
+use std::collections::HashMap;
 use std::time::Duration;
 use bevy::prelude::*;
 use crate::{enemy, net};
 use crate::game::movement::*;
-use crate::{Atlas, AppState};
+use crate::{Atlas, AppState, ENTITY_SHEET_DIMS};
 use crate::buffers::*;
 use crate::game::components::*;
 use crate::game::enemy::LastAttacker;
 use crate::game::PlayerId;
-use crate::net::{is_client, is_host, TICKLEN_S, TickNum};
+use crate::net::{chat, is_client, is_host, TickNum, TickRate};
 use crate::net::packets::{PlayerTickEvent, UserCmdEvent};
 use crate::menus::layout::{toggle_leaderboard, update_leaderboard};
+use crate::net::lobby::Lobby;
 
 pub const PLAYER_SPEED: f32 = 250.;
 pub const PLAYER_DEFAULT_HP: u8 = 100;
 pub const PLAYER_DEFAULT_DEF: f32 = 1.;
 pub const PLAYER_SIZE: Vec2 = Vec2 { x: 32., y: 32. };
-pub const MAX_PLAYERS: usize = 4;
+pub const MAX_PLAYERS: usize = 16;  // most the host can pick, ids have to stay below SPECTATOR_ID
+pub const DEFAULT_MAX_PLAYERS: u8 = 4;
+const PLAYER_SPRITES: usize = ENTITY_SHEET_DIMS.y as usize;  // player sprites across the top row of the sheet
+pub const SPECTATOR_ID: u8 = 0xFE;  // PlayerId of a client watching without a player
+pub const MAX_NAME_LEN: usize = 16;
 pub const SWORD_DAMAGE: u8 = 40;
 pub const SWORD_LENGTH: f32 = 90.0;
 pub const SWORD_DEGREES: f32 = 70.0;
@@ -38,6 +44,14 @@ pub struct SpawnEvent {
     pub id: u8
 }
 
+/// someone new showed up mid game and needs a player entity
+#[derive(Event)]
+pub struct PlayerJoinEvent(pub u8);
+
+/// someone left or got kicked and their player should go away
+#[derive(Event)]
+pub struct PlayerLeaveEvent(pub u8);
+
 #[derive(Event)]
 pub struct LocalPlayerDeathEvent;
 
@@ -64,6 +78,34 @@ pub struct Cooldown(pub Timer);
 #[derive(Component)]
 pub struct HealthBar;
 
+#[derive(Component)]
+pub struct Nameplate;
+
+/// What everyone is called. The host fills this in from connection requests and sends it out in NameList,
+/// anyone missing from it goes by their number.
+#[derive(Resource, Default)]
+pub struct PlayerNames(pub HashMap<u8, String>);
+
+impl PlayerNames {
+    pub fn get(&self, id: u8) -> String {
+        match self.0.get(&id) {
+            Some(name) => name.clone(),
+            None => format!("Player {}", id + 1),
+        }
+    }
+}
+
+/// Drops control characters (enter and tab end up in the text inputs) and trims to MAX_NAME_LEN.
+/// Returns None if there's nothing left.
+pub fn clean_name(name: &str) -> Option<String> {
+    let name: String = name.chars().filter(|ch| !ch.is_control()).take(MAX_NAME_LEN).collect();
+    let name = name.trim();
+    if name.is_empty() {
+        return None;
+    }
+    return Some(name.to_string());
+}
+
 #[derive(Component)]
 pub struct Shield;
 
@@ -83,7 +125,7 @@ impl Plugin for PlayerPlugin{
                 attack_input,
                 shield_input,
                 animate_sword,
-                handle_move,
+                handle_move.run_if(not(chat::typing)),
                 update_score,
                 powerup_feedback,
                 handle_player_ticks.run_if(is_client),
@@ -103,8 +145,12 @@ impl Plugin for PlayerPlugin{
                 ).run_if(in_state(AppState::Game)).before(net::client::fixed).before(net::host::fixed))
             .add_systems(Update, handle_id_events.run_if(is_client).run_if(in_state(AppState::Connecting)))
             .add_systems(OnEnter(AppState::Game), (spawn_players, reset_cooldowns))
+            .add_systems(Update, (spawn_joined_players, despawn_left_players, update_nameplates).run_if(in_state(AppState::Game)))
+            .init_resource::<PlayerNames>()
             .add_systems(OnEnter(AppState::GameOver), remove_players.after(toggle_leaderboard).after(update_leaderboard))
             .add_event::<SetIdEvent>()
+            .add_event::<PlayerJoinEvent>()
+            .add_event::<PlayerLeaveEvent>()
             .init_resource::<Events<AttackEvent>>()
             .init_resource::<Events<SpawnEvent>>()
             .add_event::<PlayerTickEvent>()
@@ -124,76 +170,167 @@ pub fn spawn_players(
     mut commands: Commands,
     entity_atlas: Res<Atlas>,
     asset_server: Res<AssetServer>,
-    res_id: Res<PlayerId>
+    res_id: Res<PlayerId>,
+    lobby: Res<Lobby>,
 ) {
-    for i in 0..MAX_PLAYERS {
-        let pl;
-        pl = commands.spawn((
-            Player(i as u8),
-            PosBuffer(CircularBuffer::new()),
-            DirBuffer(CircularBuffer::new()),
-            EventBuffer(CircularBuffer::new()),
-            HpBuffer(CircularBuffer::new()),
-            Stats {
-                score: 0,
-                enemies_killed: 0,
-                players_killed: 0,
-                camps_captured: 0,
-                deaths: 0,
-                kd_ratio: 0.
-            },
-            Health {
-                current: 0,
-                max: PLAYER_DEFAULT_HP,
-                dead: true
-            },
-            SpriteSheetBundle {
-                texture_atlas: entity_atlas.handle.clone(),
-                sprite: TextureAtlasSprite { index: entity_atlas.coord_to_index(i as i32, 0), ..default()},
-                visibility: Visibility::Hidden,
-                transform: Transform::from_xyz(0., 0., 1.),
-                ..default()
-            },
-            Collider(PLAYER_SIZE),
-            Cooldown(Timer::from_seconds(DEFAULT_COOLDOWN, TimerMode::Once)),
-            StoredPowerUps {
-                power_ups: [0; NUM_POWERUPS],
-            },
-            PlayerShield {
-                active: false,
-            },
-        )).id();
+    // everyone who was in the lobby, anyone joining later gets spawned by spawn_joined_players
+    let mut ids: Vec<u8> = lobby.players.iter().map(|(id, _)| *id).collect();
+    if (res_id.0 as usize) < MAX_PLAYERS && !ids.contains(&res_id.0) {
+        ids.push(res_id.0);
+    }
+    for id in ids {
+        spawn_player(&mut commands, &entity_atlas, &asset_server, id, id == res_id.0);
+    }
+}
 
-        if i as u8 == res_id.0 {
-            commands.entity(pl).insert(LocalPlayer);
+pub fn spawn_joined_players(
+    mut commands: Commands,
+    entity_atlas: Res<Atlas>,
+    asset_server: Res<AssetServer>,
+    res_id: Res<PlayerId>,
+    mut join_reader: EventReader<PlayerJoinEvent>,
+    players: Query<&Player>,
+) {
+    let mut spawned: Vec<u8> = players.iter().map(|pl| pl.0).collect();
+    for ev in join_reader.iter() {
+        if spawned.contains(&ev.0) || ev.0 as usize >= MAX_PLAYERS { continue }
+        spawn_player(&mut commands, &entity_atlas, &asset_server, ev.0, ev.0 == res_id.0);
+        spawned.push(ev.0);
+    }
+}
+
+pub fn despawn_left_players(
+    mut commands: Commands,
+    mut leave_reader: EventReader<PlayerLeaveEvent>,
+    players: Query<(Entity, &Player)>,
+) {
+    for ev in leave_reader.iter() {
+        for (e, pl) in &players {
+            if pl.0 == ev.0 {
+                commands.entity(e).despawn_recursive();
+            }
         }
+    }
+}
+
+/// The first few players get their own sprite from the sheet, after that sprites are reused
+/// and told apart by a tint spread around the color wheel.
+pub fn player_tint(id: u8) -> Color {
+    if (id as usize) < PLAYER_SPRITES {
+        return Color::WHITE;
+    }
+    // golden angle, so neighbouring ids never get similar colors
+    let hue = (id as f32 * 137.508) % 360.;
+    return Color::hsl(hue, 0.8, 0.75);
+}
 
-        let health_bar = commands.spawn((
-            SpriteBundle {
-                texture: asset_server.load("healthbar.png"),
-                transform: Transform {
-                    translation: Vec3::new(0., 24., 2.),
-                    ..Default::default()
-                },
-                ..Default::default()},
-            HealthBar,
-        )).id();
-
-        let shield = commands.spawn(
-            (SpriteBundle {
-            texture: asset_server.load("shield01.png").clone(),
+fn spawn_player(
+    commands: &mut Commands,
+    entity_atlas: &Atlas,
+    asset_server: &AssetServer,
+    id: u8,
+    local: bool,
+) {
+    let pl;
+    pl = commands.spawn((
+        Player(id),
+        PosBuffer(CircularBuffer::new()),
+        DirBuffer(CircularBuffer::new()),
+        EventBuffer(CircularBuffer::new()),
+        HpBuffer(CircularBuffer::new()),
+        Stats {
+            score: 0,
+            enemies_killed: 0,
+            players_killed: 0,
+            camps_captured: 0,
+            deaths: 0,
+            kd_ratio: 0.
+        },
+        Health {
+            current: 0,
+            max: PLAYER_DEFAULT_HP,
+            dead: true
+        },
+        SpriteSheetBundle {
+            texture_atlas: entity_atlas.handle.clone(),
+            sprite: TextureAtlasSprite {
+                index: entity_atlas.coord_to_index(id as i32 % PLAYER_SPRITES as i32, 0),
+                color: player_tint(id),
+                ..default()
+            },
             visibility: Visibility::Hidden,
+            transform: Transform::from_xyz(0., 0., 1.),
+            ..default()
+        },
+        Collider(PLAYER_SIZE),
+        Cooldown(Timer::from_seconds(DEFAULT_COOLDOWN, TimerMode::Once)),
+        StoredPowerUps {
+            power_ups: [0; NUM_POWERUPS],
+        },
+        PlayerShield {
+            active: false,
+        },
+    )).id();
+
+    if local {
+        commands.entity(pl).insert(LocalPlayer);
+    }
+
+    let health_bar = commands.spawn((
+        SpriteBundle {
+            texture: asset_server.load("healthbar.png"),
             transform: Transform {
-                translation: Vec3::new(0.0, 0.0, 0.5),
+                translation: Vec3::new(0., 24., 2.),
                 ..Default::default()
             },
+            ..Default::default()},
+        HealthBar,
+    )).id();
+
+    let shield = commands.spawn(
+        (SpriteBundle {
+        texture: asset_server.load("shield01.png").clone(),
+        visibility: Visibility::Hidden,
+        transform: Transform {
+            translation: Vec3::new(0.0, 0.0, 0.5),
             ..Default::default()
-            },
-            Shield)
-        ).id();
+        },
+        ..Default::default()
+        },
+        Shield)
+    ).id();
+
+    let nameplate = commands.spawn((
+        Text2dBundle {
+            text: Text::from_section("", TextStyle {
+                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                font_size: 16.,
+                color: Color::WHITE,
+            }).with_alignment(TextAlignment::Center),
+            transform: Transform::from_xyz(0., 40., 2.),
+            ..default()
+        },
+        Nameplate,
+    )).id();
+
+    commands.entity(pl).add_child(health_bar);
+    commands.entity(pl).add_child(shield);
+    commands.entity(pl).add_child(nameplate);
+}
 
-        commands.entity(pl).add_child(health_bar);
-        commands.entity(pl).add_child(shield);
+// names can show up or change any time the host sends a NameList
+pub fn update_nameplates(
+    names: Res<PlayerNames>,
+    players: Query<&Player>,
+    mut nameplates: Query<(&Parent, &mut Text), With<Nameplate>>,
+) {
+    for (parent, mut text) in &mut nameplates {
+        if let Ok(pl) = players.get(parent.get()) {
+            let name = names.get(pl.0);
+            if text.sections[0].value != name {
+                text.sections[0].value = name;
+            }
+        }
     }
 }
 
@@ -318,20 +455,20 @@ pub fn attack_input(
 }
 
 pub fn attack_host(
-    players: Query<(&EventBuffer, &PlayerShield), With<LocalPlayer>>,
+    players: Query<(&Player, &EventBuffer, &PlayerShield), With<LocalPlayer>>,
     tick: Res<TickNum>,
     mut attack_writer: EventWriter<AttackEvent>
 ) {
     let player = players.get_single();
     if player.is_err() { return }
-    let (eb, shield) = player.unwrap();
+    let (pl, eb, shield) = player.unwrap();
     if shield.active { return }
     let events = eb.0.get(tick.0);
     if events.is_none() { return }
     if events.unwrap() & ATTACK_BITFLAG != 0 {
         attack_writer.send(AttackEvent {
             seq_num: tick.0,
-            id: 0
+            id: pl.0  // not always 0, a client that took over as host keeps its id
         });
     }
 }
@@ -340,6 +477,7 @@ pub fn attack_draw(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     tick: Res<TickNum>,
+    tick_rate: Res<TickRate>,
     players: Query<(Entity, &EventBuffer, &DirBuffer, &PlayerShield, Option<&LocalPlayer>)>,
 ) {
     for (e, eb, db, shield, lp) in &players {
@@ -367,7 +505,7 @@ pub fn attack_draw(
                     SwordAnimation {
                         current: 0.0,
                         cursor_vector,
-                        max: TICKLEN_S,
+                        max: tick_rate.tick_len(),
                     })
                 );
             });
@@ -390,12 +528,12 @@ pub fn attack_simulate(
             if shield.active { continue }
             let sword_angle = db.0.get(ev.seq_num);
             let player_pos = pb.0.get(ev.seq_num);
-            if sword_angle.is_none() || player_pos.is_none() { println!("attack_simulate:none"); continue }
+            if sword_angle.is_none() || player_pos.is_none() { trace!("attack_simulate:none"); continue }
             let sword_angle = sword_angle.unwrap();
             let player_pos = player_pos.unwrap();
             for (enemy_pb, mut enemy_hb, mut last_attacker) in enemies.iter_mut() {
                 let enemy_pos = enemy_pb.0.get(ev.seq_num);
-                if enemy_pos.is_none() { println!("attack_simulate:enemynone"); continue }
+                if enemy_pos.is_none() { trace!("attack_simulate:enemynone"); continue }
                 let enemy_pos = enemy_pos.unwrap();
                 let hp = enemy_hb.0.get(tick.0).unwrap();
                 if hp <= 0 { continue }
@@ -661,8 +799,14 @@ pub fn handle_player_ticks(
     mut player_query: Query<(&Player, &mut PosBuffer, &mut HpBuffer, &mut DirBuffer, &mut EventBuffer, &mut PlayerShield, &mut Stats, &mut StoredPowerUps, &mut Cooldown, Option<&LocalPlayer>)>,
     mut commands: Commands,
     asset_server: Res<AssetServer>,
+    mut join_writer: EventWriter<PlayerJoinEvent>,
 ) {
     for ev in player_reader.iter() {
+        if !player_query.iter().any(|(pl, ..)| pl.0 == ev.tick.id) {
+            // first we've heard of them, they'll have an entity for the next tick
+            join_writer.send(PlayerJoinEvent(ev.tick.id));
+            continue;
+        }
         for (pl, mut pb, mut hb, mut db, mut eb, mut shield, mut stats, mut spu, mut cooldown, local) in &mut player_query {
             if pl.0 == ev.tick.id {
                 *stats = ev.tick.stats.clone();
@@ -686,7 +830,7 @@ pub fn handle_player_ticks(
                 if local.is_none() {
                     eb.0.set(ev.seq_num, Some(ev.tick.events));
                     if ev.tick.events & SHIELD_BITFLAG != 0 {
-                        println!("shielded client!");
+                        trace!("shielded client!");
                         shield.active = true;
                     }
                 }
@@ -703,7 +847,7 @@ pub fn handle_id_events(
 ) {
     for ev in &mut id_reader {
         res_id.0 = ev.0;
-        app_state_next_state.set(AppState::Game);
+        app_state_next_state.set(AppState::Lobby);
     }
 }
 
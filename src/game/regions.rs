@@ -0,0 +1,178 @@
+use std::collections::VecDeque;
+use crate::game::grid::Grid;
+use crate::game::map::Biome;
+
+// Regions smaller than this without a camp or chest in them get filled in instead of connected
+const MIN_REGION_SIZE: usize = 64;
+const NO_REGION: u32 = u32::MAX;
+
+// The four tiles that share an edge with a tile, the only ways a player can walk between tiles
+const NEIGHBORS: [(isize, isize); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+
+/// Labels every tile with the walkable region it belongs to, walls get NO_REGION.
+/// Also returns the size of each region in tiles.
+pub fn label(map: &Grid<Biome>) -> (Grid<u32>, Vec<usize>) {
+    let mut labels = Grid::new(map.width(), map.height(), NO_REGION);
+    let mut sizes: Vec<usize> = Vec::new();
+    for row in 0..map.height() {
+        for col in 0..map.width() {
+            if map[(row, col)] == Biome::Wall || labels[(row, col)] != NO_REGION { continue }
+            let id = sizes.len() as u32;
+            let mut size = 0;
+            let mut queue = VecDeque::from([(row as isize, col as isize)]);
+            labels[(row, col)] = id;
+            while let Some((r, c)) = queue.pop_front() {
+                size += 1;
+                for (dr, dc) in NEIGHBORS {
+                    let (nr, nc) = (r + dr, c + dc);
+                    if map.get(nr, nc).is_some_and(|b| b != Biome::Wall) && labels.get(nr, nc) == Some(NO_REGION) {
+                        labels.set(nr, nc, id);
+                        queue.push_back((nr, nc));
+                    }
+                }
+            }
+            sizes.push(size);
+        }
+    }
+    return (labels, sizes);
+}
+
+/// Makes every walkable tile reachable from every other one. Regions holding a camp, one of the
+/// `keep` tiles (row, col), or at least MIN_REGION_SIZE tiles get a corridor carved to the biggest
+/// region, everything else is turned into wall. The outer wall is never carved through.
+pub fn connect(map: &mut Grid<Biome>, keep: &[(usize, usize)]) {
+    let (labels, sizes) = label(map);
+    if sizes.len() < 2 { return }
+    let main = (0..sizes.len()).max_by_key(|id| sizes[*id]).unwrap() as u32;
+
+    let mut needed = vec![false; sizes.len()];
+    for (id, size) in sizes.iter().enumerate() {
+        needed[id] = *size >= MIN_REGION_SIZE;
+    }
+    for row in 0..map.height() {
+        for col in 0..map.width() {
+            if map[(row, col)] == Biome::Camp {
+                needed[labels[(row, col)] as usize] = true;
+            }
+        }
+    }
+    for (row, col) in keep {
+        if let Some(id) = labels.get(*row as isize, *col as isize) {
+            if id != NO_REGION { needed[id as usize] = true; }
+        }
+    }
+
+    let mut members: Vec<Vec<(usize, usize)>> = vec![Vec::new(); sizes.len()];
+    for row in 0..map.height() {
+        for col in 0..map.width() {
+            let id = labels[(row, col)];
+            if id != NO_REGION { members[id as usize].push((row, col)); }
+        }
+    }
+
+    // fill in everything not worth keeping first, so no corridor gets routed through a region that later turns to wall
+    for id in 0..sizes.len() {
+        if needed[id] { continue }
+        for (row, col) in members[id].iter() {
+            map[(*row, *col)] = Biome::Wall;
+        }
+    }
+
+    // tiles already joined up with the main region
+    let mut joined = labels.map(|id| id == main);
+    for id in 0..sizes.len() {
+        if id as u32 == main || !needed[id] { continue }
+        let corridor = shortest_corridor(map, &members[id], &joined);
+        for (row, col) in corridor {
+            // three wide so anything that fits down a path fits down the corridor
+            for (dr, dc) in [(0, 0), (-1, 0), (1, 0), (0, -1), (0, 1)] {
+                let (r, c) = (row as isize + dr, col as isize + dc);
+                if !inside_outer_wall(map, r, c) { continue }
+                if map.get(r, c) == Some(Biome::Wall) {
+                    map.set(r, c, Biome::Path);
+                }
+                joined.set(r, c, true);
+            }
+        }
+        for (row, col) in members[id].iter() {
+            joined[(*row, *col)] = true;
+        }
+    }
+}
+
+// Walls crossed on the shortest way from a region to any joined tile, found with a breadth first search
+// that starts from every tile of the region at once and walks through anything but the outer wall
+fn shortest_corridor(map: &Grid<Biome>, region: &[(usize, usize)], joined: &Grid<bool>) -> Vec<(usize, usize)> {
+    let mut came_from: Grid<(isize, isize)> = Grid::new(map.width(), map.height(), (-1, -1));
+    let mut seen = Grid::new(map.width(), map.height(), false);
+    let mut queue = VecDeque::new();
+    for (row, col) in region {
+        seen[(*row, *col)] = true;
+        queue.push_back((*row as isize, *col as isize));
+    }
+    while let Some((r, c)) = queue.pop_front() {
+        if joined[(r as usize, c as usize)] {
+            // walk back to the region, keeping only the tiles that need carving
+            let mut corridor = Vec::new();
+            let mut at = (r, c);
+            while at != (-1, -1) {
+                if map[(at.0 as usize, at.1 as usize)] == Biome::Wall {
+                    corridor.push((at.0 as usize, at.1 as usize));
+                }
+                at = came_from[(at.0 as usize, at.1 as usize)];
+            }
+            return corridor;
+        }
+        for (dr, dc) in NEIGHBORS {
+            let (nr, nc) = (r + dr, c + dc);
+            if !inside_outer_wall(map, nr, nc) || seen.get(nr, nc) != Some(false) { continue }
+            seen.set(nr, nc, true);
+            came_from.set(nr, nc, (r, c));
+            queue.push_back((nr, nc));
+        }
+    }
+    return Vec::new();
+}
+
+fn inside_outer_wall(map: &Grid<Biome>, row: isize, col: isize) -> bool {
+    return row > 0 && col > 0 && row < map.height() as isize - 1 && col < map.width() as isize - 1;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand_chacha::{rand_core::SeedableRng, ChaChaRng};
+    use crate::game::map::{self, WorldMap};
+    use crate::game::symmetry::Symmetry;
+
+    const CHECK_SEEDS: u64 = 256;
+    const CHECK_CAMPS: u8 = 10;
+    // The default and the corners of what the host page allows
+    const CHECK_SIZES: [(usize, usize); 4] = [
+        (map::DEFAULT_MAPSIZE, map::DEFAULT_MAPSIZE),
+        (map::MIN_MAPSIZE, map::MIN_MAPSIZE),
+        (map::MAX_MAPSIZE, map::MIN_MAPSIZE),
+        (map::MIN_MAPSIZE, map::MAX_MAPSIZE),
+    ];
+
+    #[test]
+    fn generated_maps_are_fully_connected() {
+        for (width, height) in CHECK_SIZES {
+            for symmetry in Symmetry::ALL {
+                for seed in 0..CHECK_SEEDS {
+                    let mut world_map = WorldMap { tile_size: map::TILESIZE, biome_map: Grid::new(width, height, Biome::Free) };
+                    let (mut camps, mut chests) = (Vec::new(), Vec::new());
+                    let mut rng = ChaChaRng::seed_from_u64(seed);
+                    let _ = map::read_map(&mut world_map, &mut camps, CHECK_CAMPS, map::MAXCHESTS, symmetry, &mut rng, &mut chests);
+                    let (labels, sizes) = label(&world_map.biome_map);
+                    let what = format!("seed {} ({}x{}, {} symmetry)", seed, width, height, symmetry.name());
+                    assert!(sizes.len() == 1, "{}: {} separate walkable regions", what, sizes.len());
+                    for point in camps.iter().chain(chests.iter()) {
+                        let region = labels.get(point.y as isize, point.x as isize).unwrap_or(NO_REGION);
+                        assert!(region != NO_REGION, "{}: camp or chest at ({}, {}) is in a wall", what, point.x, point.y);
+                    }
+                }
+            }
+        }
+    }
+}
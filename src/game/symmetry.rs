@@ -0,0 +1,375 @@
+use std::fmt;
+use bevy::prelude::*;
+use crate::game::grid::Grid;
+use crate::game::map::Biome;
+
+pub const SEAM_MARGIN: f32 = 16.; // camps and chests stay this many tiles off a seam so they don't run into their own copy
+const SPAWN_CLEARANCE: isize = 2; // spawn points need this many tiles of open ground around them
+
+/// How a generated map is split for competitive play. One sector gets generated and the rest are copies of it,
+/// so every sector has the same terrain, camps, camp grades and chests.
+///   Off       the whole map is generated, scored by its left and right halves
+///   Mirror    the left half, mirrored onto the right
+///   Rotate    the left half, turned halfway around onto the right so the copy's top is at the bottom
+///   Quarters  the top left quarter, mirrored into the other three
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum Symmetry {
+    #[default]
+    Off,
+    Mirror,
+    Rotate,
+    Quarters,
+}
+
+/// The symmetry the current map was actually built with, Off for a hand-made map whatever the host page says
+#[derive(Resource, Default)]
+pub struct MapSymmetry(pub Symmetry);
+
+impl Symmetry {
+    pub const ALL: [Symmetry; 4] = [Symmetry::Off, Symmetry::Mirror, Symmetry::Rotate, Symmetry::Quarters];
+
+    // anything that isn't one of the names is Off
+    pub fn parse(text: &str) -> Self {
+        let text = text.trim();
+        return Self::ALL.into_iter().find(|s| s.name().eq_ignore_ascii_case(text)).unwrap_or_default();
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Symmetry::Off => "off",
+            Symmetry::Mirror => "mirror",
+            Symmetry::Rotate => "rotate",
+            Symmetry::Quarters => "quarters",
+        }
+    }
+
+    pub fn from_byte(byte: u8) -> Self {
+        return Self::ALL.get(byte as usize).copied().unwrap_or_default();
+    }
+
+    pub fn to_byte(&self) -> u8 {
+        return *self as u8;
+    }
+
+    // how many copies of the generated sector make up the map, counting itself
+    pub fn copies(&self) -> usize {
+        match self {
+            Symmetry::Off => 1,
+            Symmetry::Mirror | Symmetry::Rotate => 2,
+            Symmetry::Quarters => 4,
+        }
+    }
+
+    // how many sectors fairness is scored over
+    pub fn sectors(&self) -> usize {
+        return self.copies().max(2);
+    }
+
+    pub fn sector_of(&self, row: usize, col: usize, width: usize, height: usize) -> usize {
+        let right = (col >= width / 2) as usize;
+        let bottom = (row >= height / 2) as usize;
+        match self {
+            Symmetry::Quarters => bottom * 2 + right,
+            _ => right,
+        }
+    }
+
+    // whether a point (x the column, y the row) is in the generated sector and at least margin tiles from its seams
+    pub fn in_source(&self, point: Vec2, width: usize, height: usize, margin: f32) -> bool {
+        let (half_w, half_h) = ((width / 2) as f32, (height / 2) as f32);
+        match self {
+            Symmetry::Off => true,
+            Symmetry::Mirror | Symmetry::Rotate => point.x < half_w - margin,
+            Symmetry::Quarters => point.x < half_w - margin && point.y < half_h - margin,
+        }
+    }
+
+    // where the tile at (row, col) of the generated sector lands in each of the other sectors, in sector order
+    pub fn images(&self, row: usize, col: usize, width: usize, height: usize) -> Vec<(usize, usize)> {
+        let (flip_row, flip_col) = (height - 1 - row, width - 1 - col);
+        match self {
+            Symmetry::Off => vec![],
+            Symmetry::Mirror => vec![(row, flip_col)],
+            Symmetry::Rotate => vec![(flip_row, flip_col)],
+            Symmetry::Quarters => vec![(row, flip_col), (flip_row, col), (flip_row, flip_col)],
+        }
+    }
+
+    pub fn image_points(&self, point: Vec2, width: usize, height: usize) -> Vec<Vec2> {
+        let (flip_x, flip_y) = ((width - 1) as f32 - point.x, (height - 1) as f32 - point.y);
+        match self {
+            Symmetry::Off => vec![],
+            Symmetry::Mirror => vec![Vec2::new(flip_x, point.y)],
+            Symmetry::Rotate => vec![Vec2::new(flip_x, flip_y)],
+            Symmetry::Quarters => vec![Vec2::new(flip_x, point.y), Vec2::new(point.x, flip_y), Vec2::new(flip_x, flip_y)],
+        }
+    }
+
+    /// Points from the generated sector followed by their copies, one sector after another,
+    /// so index i and index i + points.len() are the same camp or chest in the next sector
+    pub fn with_images(&self, points: &[Vec2], width: usize, height: usize) -> Vec<Vec2> {
+        let mut all = points.to_vec();
+        for sector in 0..self.copies() - 1 {
+            all.extend(points.iter().map(|p| self.image_points(*p, width, height)[sector]));
+        }
+        return all;
+    }
+
+    // Overwrite the rest of the map with copies of the generated sector
+    pub fn copy_source(&self, map: &mut Grid<Biome>) {
+        let (width, height) = (map.width(), map.height());
+        let (rows, cols) = match self {
+            Symmetry::Off => return,
+            Symmetry::Mirror | Symmetry::Rotate => (height, width / 2),
+            Symmetry::Quarters => (height / 2, width / 2),
+        };
+        for row in 0..rows {
+            for col in 0..cols {
+                for (r, c) in self.images(row, col, width, height) {
+                    map[(r, c)] = map[(row, col)];
+                }
+            }
+        }
+    }
+
+    // Whatever changed since before, wherever it was, gets changed the same way in every copy of that tile.
+    // Walls go in first so where one sector got filled in and another dug out, the digging wins and nothing gets cut off
+    pub fn copy_changes(&self, before: &Grid<Biome>, map: &mut Grid<Biome>) {
+        if *self == Symmetry::Off { return }
+        let (width, height) = (map.width(), map.height());
+        let mut changes: Vec<(usize, usize, Biome)> = Vec::new();
+        for row in 0..height {
+            for col in 0..width {
+                if map[(row, col)] != before[(row, col)] {
+                    changes.push((row, col, map[(row, col)]));
+                }
+            }
+        }
+        changes.sort_by_key(|(_, _, biome)| *biome != Biome::Wall);
+        for (row, col, biome) in changes {
+            // back to the generated sector first, then out to the rest
+            let (source_row, source_col) = self.to_source(row, col, width, height);
+            map[(source_row, source_col)] = biome;
+            for (r, c) in self.images(source_row, source_col, width, height) {
+                map[(r, c)] = biome;
+            }
+        }
+    }
+
+    // the tile in the generated sector that (row, col) is a copy of
+    fn to_source(&self, row: usize, col: usize, width: usize, height: usize) -> (usize, usize) {
+        let (flip_row, flip_col) = (height - 1 - row, width - 1 - col);
+        let right = col >= width / 2;
+        let bottom = row >= height / 2;
+        match self {
+            Symmetry::Off => (row, col),
+            Symmetry::Mirror => if right { (row, flip_col) } else { (row, col) },
+            Symmetry::Rotate => if right { (flip_row, flip_col) } else { (row, col) },
+            Symmetry::Quarters => (if bottom { flip_row } else { row }, if right { flip_col } else { col }),
+        }
+    }
+}
+
+/// One spawn point per sector, the open spot in the generated sector furthest from every camp and its copies.
+/// Empty for Off, players pick anywhere like always
+pub fn spawn_points(symmetry: Symmetry, map: &Grid<Biome>, camps: &[Vec2]) -> Vec<Vec2> {
+    if symmetry == Symmetry::Off { return Vec::new() }
+    let (width, height) = (map.width(), map.height());
+    let mut best: Option<(f32, Vec2)> = None;
+    for row in 0..height {
+        for col in 0..width {
+            let here = Vec2::new(col as f32, row as f32);
+            if !symmetry.in_source(here, width, height, SEAM_MARGIN) { continue }
+            let open = (-SPAWN_CLEARANCE..=SPAWN_CLEARANCE).all(|dr| (-SPAWN_CLEARANCE..=SPAWN_CLEARANCE).all(|dc| {
+                map.get(row as isize + dr, col as isize + dc).is_some_and(|b| b.path_cost() == Some(1) && b != Biome::Camp)
+            }));
+            if !open { continue }
+            let clearance = camps.iter().map(|camp| camp.distance(here)).fold(f32::MAX, f32::min);
+            if best.map_or(true, |(d, _)| clearance > d) {
+                best = Some((clearance, here));
+            }
+        }
+    }
+    if best.is_none() { return Vec::new() }
+    return symmetry.with_images(&[best.unwrap().1], width, height);
+}
+
+/// How evenly a map is shared out between its sectors, see fairness
+pub struct Fairness {
+    pub score: u8, // out of 100, 100 when every sector has the same
+    pub camps: Vec<usize>,
+    pub grades: Vec<u32>, // sum of the camp grades in each sector
+    pub chests: Vec<usize>,
+    pub open_tiles: Vec<usize>, // everything that isn't wall
+}
+
+impl fmt::Display for Fairness {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let join = |v: Vec<String>| v.join("/");
+        write!(f, "{}% (camps {}, grades {}, chests {}, open tiles {})",
+            self.score,
+            join(self.camps.iter().map(|n| n.to_string()).collect()),
+            join(self.grades.iter().map(|n| n.to_string()).collect()),
+            join(self.chests.iter().map(|n| n.to_string()).collect()),
+            join(self.open_tiles.iter().map(|n| n.to_string()).collect()),
+        )
+    }
+}
+
+/// Scores a map by what each sector gets, camps and chests are (x the column, y the row) like CampNodes and ChestCoords.
+/// Each of camps, grades, chests and open tiles counts (fewest / most) across the sectors, the score is their average
+pub fn fairness(symmetry: Symmetry, map: &Grid<Biome>, camps: &[Vec2], grades: &[u8], chests: &[Vec2]) -> Fairness {
+    let (width, height) = (map.width(), map.height());
+    let sectors = symmetry.sectors();
+    let sector = |p: &Vec2| symmetry.sector_of(p.y as usize, p.x as usize, width, height);
+    let mut fairness = Fairness {
+        score: 0,
+        camps: vec![0; sectors],
+        grades: vec![0; sectors],
+        chests: vec![0; sectors],
+        open_tiles: vec![0; sectors],
+    };
+    for (camp, grade) in camps.iter().zip(grades.iter()) {
+        fairness.camps[sector(camp)] += 1;
+        fairness.grades[sector(camp)] += *grade as u32;
+    }
+    for chest in chests {
+        fairness.chests[sector(chest)] += 1;
+    }
+    for row in 0..height {
+        for col in 0..width {
+            if map[(row, col)] != Biome::Wall {
+                fairness.open_tiles[symmetry.sector_of(row, col, width, height)] += 1;
+            }
+        }
+    }
+
+    let balance = |counts: Vec<f32>| {
+        let most = counts.iter().cloned().fold(0., f32::max);
+        let fewest = counts.iter().cloned().fold(f32::MAX, f32::min);
+        if most == 0. { 1. } else { fewest / most }
+    };
+    let score = (balance(fairness.camps.iter().map(|n| *n as f32).collect())
+        + balance(fairness.grades.iter().map(|n| *n as f32).collect())
+        + balance(fairness.chests.iter().map(|n| *n as f32).collect())
+        + balance(fairness.open_tiles.iter().map(|n| *n as f32).collect())) / 4.;
+    fairness.score = (score * 100.).round() as u8;
+    return fairness;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand_chacha::{rand_core::SeedableRng, ChaChaRng};
+    use crate::game::map::{self, WorldMap};
+
+    // not square so a mixed up width and height shows
+    const WIDTH: usize = 40;
+    const HEIGHT: usize = 24;
+    const CHECK_SEEDS: u64 = 16;
+    const CHECK_CAMPS: u8 = 10;
+
+    // every tile of the generated sector, by the region copy_source reads from
+    fn source_tiles(symmetry: Symmetry, width: usize, height: usize) -> Vec<(usize, usize)> {
+        let (rows, cols) = match symmetry {
+            Symmetry::Off => (height, width),
+            Symmetry::Mirror | Symmetry::Rotate => (height, width / 2),
+            Symmetry::Quarters => (height / 2, width / 2),
+        };
+        return (0..rows).flat_map(|row| (0..cols).map(move |col| (row, col))).collect();
+    }
+
+    // a walled in map with a few walls in the generated sector and copies of them everywhere else
+    fn even_map(symmetry: Symmetry) -> Grid<Biome> {
+        let mut map = Grid::new(WIDTH, HEIGHT, Biome::Ground);
+        for (row, col) in source_tiles(symmetry, WIDTH, HEIGHT) {
+            if row == 0 || col == 0 || (row + col) % 7 == 0 {
+                map[(row, col)] = Biome::Wall;
+            }
+        }
+        symmetry.copy_source(&mut map);
+        return map;
+    }
+
+    #[test]
+    fn images_map_back_to_their_source() {
+        for symmetry in Symmetry::ALL {
+            for (row, col) in source_tiles(symmetry, WIDTH, HEIGHT) {
+                assert_eq!(symmetry.to_source(row, col, WIDTH, HEIGHT), (row, col), "{} ({}, {})", symmetry.name(), row, col);
+                let images = symmetry.images(row, col, WIDTH, HEIGHT);
+                assert_eq!(images.len(), symmetry.copies() - 1);
+                for (sector, (r, c)) in images.into_iter().enumerate() {
+                    assert_eq!(symmetry.to_source(r, c, WIDTH, HEIGHT), (row, col), "{} ({}, {}) -> ({}, {})", symmetry.name(), row, col, r, c);
+                    if symmetry != Symmetry::Off {
+                        assert_eq!(symmetry.sector_of(r, c, WIDTH, HEIGHT), sector + 1);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn with_images_keeps_sector_order() {
+        let points = [Vec2::new(3., 2.), Vec2::new(10., 8.)];
+        for symmetry in Symmetry::ALL {
+            let all = symmetry.with_images(&points, WIDTH, HEIGHT);
+            assert_eq!(all.len(), points.len() * symmetry.copies());
+            for (i, point) in points.iter().enumerate() {
+                for (sector, image) in symmetry.image_points(*point, WIDTH, HEIGHT).into_iter().enumerate() {
+                    assert_eq!(all[i + (sector + 1) * points.len()], image);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn generated_maps_match_their_copies() {
+        for symmetry in Symmetry::ALL {
+            for seed in 0..CHECK_SEEDS {
+                let (width, height) = (map::MAX_MAPSIZE, map::MIN_MAPSIZE);
+                let mut world_map = WorldMap { tile_size: map::TILESIZE, biome_map: Grid::new(width, height, Biome::Free) };
+                let (mut camps, mut chests) = (Vec::new(), Vec::new());
+                let mut rng = ChaChaRng::seed_from_u64(seed);
+                let _ = map::read_map(&mut world_map, &mut camps, CHECK_CAMPS, map::MAXCHESTS, symmetry, &mut rng, &mut chests);
+                let map = &world_map.biome_map;
+                for row in 0..height {
+                    for col in 0..width {
+                        let (r, c) = symmetry.to_source(row, col, width, height);
+                        assert!(map[(row, col)] == map[(r, c)], "{} seed {} ({}, {}) isn't a copy of ({}, {})", symmetry.name(), seed, row, col, r, c);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn even_maps_score_full_and_skewed_ones_dont() {
+        let source_camps = [Vec2::new(5., 5.), Vec2::new(12., 9.)];
+        let source_chests = [Vec2::new(8., 3.)];
+        for symmetry in [Symmetry::Mirror, Symmetry::Rotate, Symmetry::Quarters] {
+            let mut map = even_map(symmetry);
+            let camps = symmetry.with_images(&source_camps, WIDTH, HEIGHT);
+            let grades: Vec<u8> = (0..camps.len()).map(|i| (i % source_camps.len()) as u8 + 1).collect();
+            let chests = symmetry.with_images(&source_chests, WIDTH, HEIGHT);
+            let even = fairness(symmetry, &map, &camps, &grades, &chests);
+            assert_eq!(even.score, 100, "{} {}", symmetry.name(), even);
+
+            // one more camp in the generated sector only
+            let mut extra_camps = camps.clone();
+            extra_camps.push(Vec2::new(3., 3.));
+            let mut extra_grades = grades.clone();
+            extra_grades.push(1);
+            let skewed = fairness(symmetry, &map, &extra_camps, &extra_grades, &chests);
+            assert!(skewed.score < 100, "{} {}", symmetry.name(), skewed);
+
+            // wall off the whole right side
+            for row in 0..HEIGHT {
+                for col in WIDTH / 2..WIDTH {
+                    map[(row, col)] = Biome::Wall;
+                }
+            }
+            let walled = fairness(symmetry, &map, &camps, &grades, &chests);
+            assert!(walled.score < 100, "{} {}", symmetry.name(), walled);
+        }
+    }
+}
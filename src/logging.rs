@@ -0,0 +1,58 @@
+use std::fs::{self, File};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use bevy::prelude::*;
+use bevy::utils::tracing::subscriber;
+use tracing_log::LogTracer;
+use tracing_subscriber::{prelude::*, registry::Registry, EnvFilter};
+use crate::cli_arg;
+
+// what gets printed when nothing else is asked for, bevy's own noise kept down like its LogPlugin does
+const DEFAULT_FILTER: &str = "info,wgpu=error,naga=warn";
+
+/// Replaces bevy's LogPlugin so log lines can also go to a file.
+///
+/// Every log line's target is the module it came from, so the filter can pick out parts of the game:
+///   `--log jordquest::net=debug`                   packet and tick sync problems
+///   `--log info,jordquest::net::host=trace`        also the per-tick spans around the host's net systems
+///   `--log warn`                                   only things going wrong
+///   `--log jordquest::net::inspect=debug`          every datagram sent or received, decoded
+/// RUST_LOG works the same way if `--log` isn't given.
+///
+/// `--log-dir <dir>` also writes everything that passes the filter to `<dir>/session-<unix time>.jsonl`,
+/// one JSON object per line.
+pub struct LoggingPlugin;
+
+impl Plugin for LoggingPlugin {
+    fn build(&self, _app: &mut App) {
+        let filter = cli_arg("--log")
+            .or_else(|| std::env::var("RUST_LOG").ok())
+            .unwrap_or(DEFAULT_FILTER.to_string());
+        let filter_layer = EnvFilter::try_new(&filter).unwrap_or_else(|e| {
+            eprintln!("bad log filter {}: {}", filter, e);
+            EnvFilter::new(DEFAULT_FILTER)
+        });
+        let fmt_layer = tracing_subscriber::fmt::layer().with_writer(std::io::stderr);
+        let json_layer = cli_arg("--log-dir").and_then(|dir| match session_file(&dir) {
+            Ok(file) => Some(tracing_subscriber::fmt::layer().json().with_writer(Mutex::new(file))),
+            Err(e) => {
+                eprintln!("can't write logs to {}: {}", dir, e);
+                None
+            },
+        });
+        let subscriber = Registry::default()
+            .with(filter_layer)
+            .with(fmt_layer)
+            .with(json_layer);
+        // wgpu and friends log through the log crate rather than tracing
+        if LogTracer::init().is_err() || subscriber::set_global_default(subscriber).is_err() {
+            eprintln!("a logger was already set up, using that one");
+        }
+    }
+}
+
+fn session_file(dir: &str) -> std::io::Result<File> {
+    fs::create_dir_all(dir)?;
+    let started = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    File::create(format!("{}/session-{}.jsonl", dir, started))
+}
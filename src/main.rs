@@ -1,6 +1,7 @@
 use bevy::prelude::*;
 
 mod game;
+mod logging;
 mod net;
 mod menus;
 use game::GamePlugin;
@@ -20,13 +21,32 @@ pub enum AppState {
     GameOver,
     Credits,
     Connecting,
+    Lobby,
     Quitting,
 }
 
+/// returns the value after `flag` on the command line, e.g. cli_arg("--latency") for `--latency 100`
+pub fn cli_arg(flag: &str) -> Option<String> {
+    let mut args = std::env::args();
+    while let Some(arg) = args.next() {
+        if arg == flag {
+            return args.next();
+        }
+    }
+    None
+}
+
 fn main() {
+    if let Some(path) = cli_arg("--inspect") {
+        std::process::exit(net::inspect::run(&path));
+    }
+    if let Some(seed) = cli_arg("--export-map") {
+        std::process::exit(game::export::run(&seed));
+    }
     App::new()
         .add_state::<AppState>()
         .add_plugins((
+            logging::LoggingPlugin,  // first, so everything after it logs through it
             GamePlugin,
             NetPlugin,
             MainMenuPlugin,
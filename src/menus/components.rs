@@ -23,9 +23,25 @@ pub struct CreditsPage;
 #[derive(Component)]
 pub struct ConnectingPage;
 
+#[derive(Component)]
+pub struct LobbyPage;
+
+#[derive(Component)]
+pub struct LobbyRosterText;
+
+#[derive(Component)]
+pub struct LobbyCountdownText;
+
+#[derive(Component)]
+pub struct LobbyMatchCodeText;
+
 #[derive(Component)]
 pub struct Popup;
 
+/// a Popup telling the player why they got sent back to the menu, goes away when its PopupTimer runs out
+#[derive(Component)]
+pub struct ErrorPopup;
+
 #[derive(Component)]
 pub struct Switch{
     pub host_port: bool,
@@ -37,6 +53,18 @@ pub struct Switch{
     pub enemy_per_camp: bool,
     pub map_seed: bool,
     pub eid_percentage: bool,
+    pub max_players: bool,
+    pub player_name: bool,
+    pub join_player_name: bool,
+    pub tick_rate: bool,
+    pub send_rate: bool,
+    pub join_send_rate: bool,
+    pub password: bool,
+    pub join_password: bool,
+    pub map_width: bool,
+    pub map_height: bool,
+    pub map_file: bool,
+    pub symmetry: bool,
 }
 
 pub trait InputType: Component {
@@ -179,6 +207,270 @@ impl InputType for EidPercentageInput {
     }
 }
 
+impl InputType for MaxPlayersInput {
+    fn push_char(&mut self, ch: char) {
+        self.value.push(ch);
+    }
+
+    fn pop_char(&mut self) {
+        self.value.pop();
+    }
+
+    fn is_empty(&self) -> bool {
+        self.value.is_empty()
+    }
+
+    fn is_active(switch: &Switch) -> bool {
+        switch.max_players
+    }
+
+    fn is_valid(active: bool) -> bool {
+        active
+    }
+}
+
+impl InputType for PlayerNameInput {
+    fn push_char(&mut self, ch: char) {
+        self.value.push(ch);
+    }
+
+    fn pop_char(&mut self) {
+        self.value.pop();
+    }
+
+    fn is_empty(&self) -> bool {
+        self.value.is_empty()
+    }
+
+    fn is_active(switch: &Switch) -> bool {
+        switch.player_name
+    }
+
+    fn is_valid(active: bool) -> bool {
+        active
+    }
+}
+
+impl InputType for JoinPlayerNameInput {
+    fn push_char(&mut self, ch: char) {
+        self.value.push(ch);
+    }
+
+    fn pop_char(&mut self) {
+        self.value.pop();
+    }
+
+    fn is_empty(&self) -> bool {
+        self.value.is_empty()
+    }
+
+    fn is_active(switch: &Switch) -> bool {
+        switch.join_player_name
+    }
+
+    fn is_valid(active: bool) -> bool {
+        active
+    }
+}
+
+impl InputType for TickRateInput {
+    fn push_char(&mut self, ch: char) {
+        self.value.push(ch);
+    }
+
+    fn pop_char(&mut self) {
+        self.value.pop();
+    }
+
+    fn is_empty(&self) -> bool {
+        self.value.is_empty()
+    }
+
+    fn is_active(switch: &Switch) -> bool {
+        switch.tick_rate
+    }
+
+    fn is_valid(active: bool) -> bool {
+        active
+    }
+}
+
+impl InputType for SendRateInput {
+    fn push_char(&mut self, ch: char) {
+        self.value.push(ch);
+    }
+
+    fn pop_char(&mut self) {
+        self.value.pop();
+    }
+
+    fn is_empty(&self) -> bool {
+        self.value.is_empty()
+    }
+
+    fn is_active(switch: &Switch) -> bool {
+        switch.send_rate
+    }
+
+    fn is_valid(active: bool) -> bool {
+        active
+    }
+}
+
+impl InputType for JoinSendRateInput {
+    fn push_char(&mut self, ch: char) {
+        self.value.push(ch);
+    }
+
+    fn pop_char(&mut self) {
+        self.value.pop();
+    }
+
+    fn is_empty(&self) -> bool {
+        self.value.is_empty()
+    }
+
+    fn is_active(switch: &Switch) -> bool {
+        switch.join_send_rate
+    }
+
+    fn is_valid(active: bool) -> bool {
+        active
+    }
+}
+
+impl InputType for PasswordInput {
+    fn push_char(&mut self, ch: char) {
+        self.value.push(ch);
+    }
+
+    fn pop_char(&mut self) {
+        self.value.pop();
+    }
+
+    fn is_empty(&self) -> bool {
+        self.value.is_empty()
+    }
+
+    fn is_active(switch: &Switch) -> bool {
+        switch.password
+    }
+
+    fn is_valid(active: bool) -> bool {
+        active
+    }
+}
+
+impl InputType for JoinPasswordInput {
+    fn push_char(&mut self, ch: char) {
+        self.value.push(ch);
+    }
+
+    fn pop_char(&mut self) {
+        self.value.pop();
+    }
+
+    fn is_empty(&self) -> bool {
+        self.value.is_empty()
+    }
+
+    fn is_active(switch: &Switch) -> bool {
+        switch.join_password
+    }
+
+    fn is_valid(active: bool) -> bool {
+        active
+    }
+}
+
+impl InputType for MapWidthInput {
+    fn push_char(&mut self, ch: char) {
+        self.value.push(ch);
+    }
+
+    fn pop_char(&mut self) {
+        self.value.pop();
+    }
+
+    fn is_empty(&self) -> bool {
+        self.value.is_empty()
+    }
+
+    fn is_active(switch: &Switch) -> bool {
+        switch.map_width
+    }
+
+    fn is_valid(active: bool) -> bool {
+        active
+    }
+}
+
+impl InputType for MapHeightInput {
+    fn push_char(&mut self, ch: char) {
+        self.value.push(ch);
+    }
+
+    fn pop_char(&mut self) {
+        self.value.pop();
+    }
+
+    fn is_empty(&self) -> bool {
+        self.value.is_empty()
+    }
+
+    fn is_active(switch: &Switch) -> bool {
+        switch.map_height
+    }
+
+    fn is_valid(active: bool) -> bool {
+        active
+    }
+}
+
+impl InputType for MapFileInput {
+    fn push_char(&mut self, ch: char) {
+        self.value.push(ch);
+    }
+
+    fn pop_char(&mut self) {
+        self.value.pop();
+    }
+
+    fn is_empty(&self) -> bool {
+        self.value.is_empty()
+    }
+
+    fn is_active(switch: &Switch) -> bool {
+        switch.map_file
+    }
+
+    fn is_valid(active: bool) -> bool {
+        active
+    }
+}
+
+impl InputType for SymmetryInput {
+    fn push_char(&mut self, ch: char) {
+        self.value.push(ch);
+    }
+
+    fn pop_char(&mut self) {
+        self.value.pop();
+    }
+
+    fn is_empty(&self) -> bool {
+        self.value.is_empty()
+    }
+
+    fn is_active(switch: &Switch) -> bool {
+        switch.symmetry
+    }
+
+    fn is_valid(active: bool) -> bool {
+        active
+    }
+}
+
 impl InputType for JoinHostPortInput {
     fn push_char(&mut self, ch: char) {
         self.port.push(ch);
@@ -319,9 +611,18 @@ pub struct BackToMainMenu;
 #[derive(Component)]
 pub struct LeaderboardUi;
 
+#[derive(Component)]
+pub struct ChatOverlay;
+
 #[derive(Component)]
 pub struct LeaderboardUiTitle;
 
+#[derive(Component)]
+pub struct LeaderboardRow(pub u8);
+
+#[derive(Component)]
+pub struct PlayerNameDisplay(pub u8);
+
 #[derive(Component)]
 pub struct PlayerStatDisplay{
     pub player_id: u8,
@@ -407,4 +708,109 @@ pub struct JoinIPInput {
 pub struct JoinSaveButton;
 
 #[derive(Component)]
-pub struct Initialized;
\ No newline at end of file
+pub struct JoinSpectateButton;
+
+#[derive(Component)]
+pub struct LobbyReadyButton;
+
+#[derive(Component)]
+pub struct CopyMatchCodeButton;
+
+#[derive(Component)]
+pub struct Initialized;
+
+#[derive(Component)]
+pub struct MaxPlayersButton;
+
+#[derive(Component)]
+pub struct MaxPlayersInput {
+    pub value: String,
+}
+
+#[derive(Component)]
+pub struct PlayerNameButton;
+
+#[derive(Component)]
+pub struct PlayerNameInput {
+    pub value: String,
+}
+
+#[derive(Component)]
+pub struct JoinPlayerNameButton;
+
+#[derive(Component)]
+pub struct JoinPlayerNameInput {
+    pub value: String,
+}
+
+#[derive(Component)]
+pub struct TickRateButton;
+
+#[derive(Component)]
+pub struct TickRateInput {
+    pub value: String,
+}
+
+#[derive(Component)]
+pub struct SendRateButton;
+
+#[derive(Component)]
+pub struct SendRateInput {
+    pub value: String,
+}
+
+#[derive(Component)]
+pub struct JoinSendRateButton;
+
+#[derive(Component)]
+pub struct JoinSendRateInput {
+    pub value: String,
+}
+
+#[derive(Component)]
+pub struct PasswordButton;
+
+#[derive(Component)]
+pub struct PasswordInput {
+    pub value: String,
+}
+
+#[derive(Component)]
+pub struct JoinPasswordButton;
+
+#[derive(Component)]
+pub struct JoinPasswordInput {
+    pub value: String,
+}
+
+#[derive(Component)]
+pub struct MapWidthButton;
+
+#[derive(Component)]
+pub struct MapWidthInput {
+    pub value: String,
+}
+
+#[derive(Component)]
+pub struct MapHeightButton;
+
+#[derive(Component)]
+pub struct MapHeightInput {
+    pub value: String,
+}
+
+#[derive(Component)]
+pub struct MapFileButton;
+
+#[derive(Component)]
+pub struct MapFileInput {
+    pub value: String,
+}
+
+#[derive(Component)]
+pub struct SymmetryButton;
+
+#[derive(Component)]
+pub struct SymmetryInput {
+    pub value: String,
+}
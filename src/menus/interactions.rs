@@ -3,8 +3,10 @@ use bevy::prelude::*;
 use crate::menus::components::*;
 use crate::AppState;
 use crate::game::PlayerId;
-use crate::menus::NetworkAddresses;
+use crate::menus::{LocalName, NetworkAddresses};
 use crate::game::MapConfig;
+use crate::game::map::{MapSeed, MapSize, NumCamps, SpawnSettings};
+use crate::game::matchcode::MatchCode;
 use rand::Rng;
 use bevy::app::AppExit;
 
@@ -55,6 +57,10 @@ pub fn update_input<T: InputType>(
 
     if let Some(new_char) = new_char {
         for (mut text, mut input_type) in query.iter_mut() {
+            if new_char.is_control() && new_char != '\u{8}' && new_char != '\u{7f}' {
+                // shortcuts like ctrl+v come through as control characters, nothing to type
+                continue;
+            }
             if new_char != '\u{8}' && new_char != '\u{7f}' {
                 text.sections[0].value.push(new_char);
                 input_type.push_char(new_char);
@@ -68,6 +74,33 @@ pub fn update_input<T: InputType>(
         }
     }
 }
+/// ctrl+v (cmd+v on mac) types whatever text is on the clipboard into the input, for match codes mostly
+pub fn paste_input<T: InputType>(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut query: Query<(&mut Text, &mut T)>,
+    switch_query: Query<&Switch>,
+) {
+    let modifier = keyboard_input.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight, KeyCode::SuperLeft, KeyCode::SuperRight]);
+    if !modifier || !keyboard_input.just_pressed(KeyCode::V) { return }
+    let mut active = false;
+    for switch in switch_query.iter() {
+        active = T::is_active(switch);
+    }
+    if !T::is_valid(active) { return }
+    let pasted = arboard::Clipboard::new().and_then(|mut clipboard| clipboard.get_text());
+    if let Err(e) = &pasted {
+        warn!("can't paste, nothing readable on the clipboard: {}", e);
+        return;
+    }
+    let pasted = pasted.unwrap();
+    for (mut text, mut input_type) in query.iter_mut() {
+        for ch in pasted.trim().chars().filter(|ch| !ch.is_control()) {
+            text.sections[0].value.push(ch);
+            input_type.push_char(ch);
+        }
+    }
+}
+
 pub fn update_host_input(
     char_events: EventReader<ReceivedCharacter>,
     query: Query<(&mut Text, &mut HostPortInput)>,
@@ -140,17 +173,117 @@ pub fn update_join_ip_input(
     update_input::<JoinIPInput>(char_events, query, Some(switch_query));
 }
 
+pub fn update_max_players_input(
+    char_events: EventReader<ReceivedCharacter>,
+    query: Query<(&mut Text, &mut MaxPlayersInput)>,
+    switch_query: Query<&Switch>,
+) {
+    update_input::<MaxPlayersInput>(char_events, query, Some(switch_query));
+}
+
+pub fn update_player_name_input(
+    char_events: EventReader<ReceivedCharacter>,
+    query: Query<(&mut Text, &mut PlayerNameInput)>,
+    switch_query: Query<&Switch>,
+) {
+    update_input::<PlayerNameInput>(char_events, query, Some(switch_query));
+}
+
+pub fn update_join_player_name_input(
+    char_events: EventReader<ReceivedCharacter>,
+    query: Query<(&mut Text, &mut JoinPlayerNameInput)>,
+    switch_query: Query<&Switch>,
+) {
+    update_input::<JoinPlayerNameInput>(char_events, query, Some(switch_query));
+}
+
+pub fn update_tick_rate_input(
+    char_events: EventReader<ReceivedCharacter>,
+    query: Query<(&mut Text, &mut TickRateInput)>,
+    switch_query: Query<&Switch>,
+) {
+    update_input::<TickRateInput>(char_events, query, Some(switch_query));
+}
+
+pub fn update_send_rate_input(
+    char_events: EventReader<ReceivedCharacter>,
+    query: Query<(&mut Text, &mut SendRateInput)>,
+    switch_query: Query<&Switch>,
+) {
+    update_input::<SendRateInput>(char_events, query, Some(switch_query));
+}
+
+pub fn update_join_send_rate_input(
+    char_events: EventReader<ReceivedCharacter>,
+    query: Query<(&mut Text, &mut JoinSendRateInput)>,
+    switch_query: Query<&Switch>,
+) {
+    update_input::<JoinSendRateInput>(char_events, query, Some(switch_query));
+}
+
+pub fn update_password_input(
+    char_events: EventReader<ReceivedCharacter>,
+    query: Query<(&mut Text, &mut PasswordInput)>,
+    switch_query: Query<&Switch>,
+) {
+    update_input::<PasswordInput>(char_events, query, Some(switch_query));
+}
+
+pub fn update_join_password_input(
+    char_events: EventReader<ReceivedCharacter>,
+    query: Query<(&mut Text, &mut JoinPasswordInput)>,
+    switch_query: Query<&Switch>,
+) {
+    update_input::<JoinPasswordInput>(char_events, query, Some(switch_query));
+}
+
+pub fn update_map_width_input(
+    char_events: EventReader<ReceivedCharacter>,
+    query: Query<(&mut Text, &mut MapWidthInput)>,
+    switch_query: Query<&Switch>,
+) {
+    update_input::<MapWidthInput>(char_events, query, Some(switch_query));
+}
+
+pub fn update_map_height_input(
+    char_events: EventReader<ReceivedCharacter>,
+    query: Query<(&mut Text, &mut MapHeightInput)>,
+    switch_query: Query<&Switch>,
+) {
+    update_input::<MapHeightInput>(char_events, query, Some(switch_query));
+}
+
+pub fn update_map_file_input(
+    char_events: EventReader<ReceivedCharacter>,
+    query: Query<(&mut Text, &mut MapFileInput)>,
+    switch_query: Query<&Switch>,
+) {
+    update_input::<MapFileInput>(char_events, query, Some(switch_query));
+}
+
+pub fn update_symmetry_input(
+    char_events: EventReader<ReceivedCharacter>,
+    query: Query<(&mut Text, &mut SymmetryInput)>,
+    switch_query: Query<&Switch>,
+) {
+    update_input::<SymmetryInput>(char_events, query, Some(switch_query));
+}
+
 pub fn save_host_input(
     mut is_host: ResMut<crate::net::IsHost>,
     mut res_id: ResMut<PlayerId>,
     mut net_address: ResMut<NetworkAddresses>,
     mut map_config: ResMut<MapConfig>,
+    mut max_players: ResMut<crate::net::host::MaxPlayers>,
     host_port_query: Query<&HostPortInput>,
     num_camps_query: Query<&NumCampsInput>,
     num_chests_query: Query<&NumChestsInput>,
     enemy_per_camp_query: Query<&EnemiesPerCampInput>,
     map_seed_query: Query<&MapSeedInput>,
     eid_percentage_query: Query<&EidPercentageInput>,
+    max_players_query: Query<&MaxPlayersInput>,
+    player_name_query: Query<&PlayerNameInput>,
+    mut local_name: ResMut<LocalName>,
     mut button_query: Query<
         (&Interaction, &mut BackgroundColor),
         (Changed<Interaction>, With<HostPortSaveButton>),
@@ -179,9 +312,16 @@ pub fn save_host_input(
                 }
                 for input in  eid_percentage_query.iter() {
                     map_config.eid_percentage = input.value.clone();
-                    //println!("eid percentage to {:?}", map_config.eid_percentage);
                 }
-                app_state_next_state.set(AppState::Game);
+                for input in max_players_query.iter() {
+                    if let Ok(parsed_num) = input.value.parse::<u8>() {
+                        max_players.0 = parsed_num.clamp(1, crate::game::player::MAX_PLAYERS as u8);
+                    }
+                }
+                for input in player_name_query.iter() {
+                    local_name.set(&input.value);
+                }
+                app_state_next_state.set(AppState::Lobby);
             }
             Interaction::Hovered => {
                 *background_color = Color::GRAY.into();
@@ -193,6 +333,39 @@ pub fn save_host_input(
     }
 }
 
+/// tick and send rates, split out of save_host_input to stay under bevy's system parameter limit
+pub fn save_host_rates(
+    mut tick_rate: ResMut<crate::net::TickRate>,
+    mut send_rate: ResMut<crate::net::SendRate>,
+    tick_rate_query: Query<&TickRateInput>,
+    send_rate_query: Query<&SendRateInput>,
+    button_query: Query<&Interaction, (Changed<Interaction>, With<HostPortSaveButton>)>,
+) {
+    if !button_query.iter().any(|interaction| *interaction == Interaction::Pressed) { return }
+    for input in tick_rate_query.iter() {
+        if let Ok(parsed_num) = input.value.parse::<u8>() {
+            tick_rate.0 = parsed_num.clamp(crate::net::MIN_TICKRATE, crate::net::MAX_TICKRATE);
+        }
+    }
+    for input in send_rate_query.iter() {
+        if let Ok(parsed_num) = input.value.parse::<u8>() {
+            send_rate.0 = parsed_num.clamp(1, crate::net::MAX_TICKRATE);
+        }
+    }
+}
+
+/// an empty password leaves the match open to anyone, same as before there were passwords
+pub fn save_host_password(
+    mut key: ResMut<crate::net::secure::MatchKey>,
+    password_query: Query<&PasswordInput>,
+    button_query: Query<&Interaction, (Changed<Interaction>, With<HostPortSaveButton>)>,
+) {
+    if !button_query.iter().any(|interaction| *interaction == Interaction::Pressed) { return }
+    for input in password_query.iter() {
+        *key = crate::net::secure::MatchKey::from_password(&input.value);
+    }
+}
+
 pub fn host_port_but(
     mut button_query: Query<
         (&Interaction, &mut BackgroundColor),
@@ -210,6 +383,15 @@ pub fn host_port_but(
                     switch.enemy_per_camp = false;
                     switch.map_seed = false;
                     switch.eid_percentage = false;
+                    switch.symmetry = false;
+                    switch.map_file = false;
+                    switch.map_height = false;
+                    switch.map_width = false;
+                    switch.password = false;
+                    switch.send_rate = false;
+                    switch.tick_rate = false;
+                    switch.player_name = false;
+                    switch.max_players = false;
                 }
             }
             Interaction::Hovered => {
@@ -239,6 +421,15 @@ pub fn num_camps_but(
                     switch.enemy_per_camp = false;
                     switch.map_seed = false;
                     switch.eid_percentage = false;
+                    switch.symmetry = false;
+                    switch.map_file = false;
+                    switch.map_height = false;
+                    switch.map_width = false;
+                    switch.password = false;
+                    switch.send_rate = false;
+                    switch.tick_rate = false;
+                    switch.player_name = false;
+                    switch.max_players = false;
                 }
             }
             Interaction::Hovered => {
@@ -267,6 +458,15 @@ pub fn num_chests_but(
                     switch.enemy_per_camp = false;
                     switch.map_seed = false;
                     switch.eid_percentage = false;
+                    switch.symmetry = false;
+                    switch.map_file = false;
+                    switch.map_height = false;
+                    switch.map_width = false;
+                    switch.password = false;
+                    switch.send_rate = false;
+                    switch.tick_rate = false;
+                    switch.player_name = false;
+                    switch.max_players = false;
                 }
             }
             Interaction::Hovered => {
@@ -296,6 +496,15 @@ pub fn enemy_per_camp_but(
                     switch.enemy_per_camp = true;
                     switch.map_seed = false;
                     switch.eid_percentage = false;
+                    switch.symmetry = false;
+                    switch.map_file = false;
+                    switch.map_height = false;
+                    switch.map_width = false;
+                    switch.password = false;
+                    switch.send_rate = false;
+                    switch.tick_rate = false;
+                    switch.player_name = false;
+                    switch.max_players = false;
                 }
             }
             Interaction::Hovered => {
@@ -325,6 +534,15 @@ pub fn map_seed_but(
                     switch.enemy_per_camp = false;
                     switch.map_seed = true;
                     switch.eid_percentage = false;
+                    switch.symmetry = false;
+                    switch.map_file = false;
+                    switch.map_height = false;
+                    switch.map_width = false;
+                    switch.password = false;
+                    switch.send_rate = false;
+                    switch.tick_rate = false;
+                    switch.player_name = false;
+                    switch.max_players = false;
                 }
             }
             Interaction::Hovered => {
@@ -353,6 +571,15 @@ pub fn eid_percentage_but(
                     switch.enemy_per_camp = false;
                     switch.map_seed = false;
                     switch.eid_percentage = true;
+                    switch.symmetry = false;
+                    switch.map_file = false;
+                    switch.map_height = false;
+                    switch.map_width = false;
+                    switch.password = false;
+                    switch.send_rate = false;
+                    switch.tick_rate = false;
+                    switch.player_name = false;
+                    switch.max_players = false;
                 }
             }
             Interaction::Hovered => {
@@ -379,6 +606,9 @@ pub fn join_port_but(
                     switch.host_port = false;
                     switch.ip = false;
                     switch.port = true;
+                    switch.join_password = false;
+                    switch.join_send_rate = false;
+                    switch.join_player_name = false;
                 }
             }
             Interaction::Hovered => {
@@ -405,6 +635,9 @@ pub fn join_ip_but(
                     switch.host_port = false;
                     switch.ip = true;
                     switch.port = false;
+                    switch.join_password = false;
+                    switch.join_send_rate = false;
+                    switch.join_player_name = false;
                 }
             }
             Interaction::Hovered => {
@@ -430,6 +663,218 @@ pub fn join_host_port_but(
                     switch.host_port = true;
                     switch.ip = false;
                     switch.port = false;
+                    switch.join_password = false;
+                    switch.join_send_rate = false;
+                    switch.join_player_name = false;
+                }
+            }
+            Interaction::Hovered => {
+                *background_color = Color::GRAY.into();
+            }
+            Interaction::None => {
+                *background_color = Color:: rgb(0.15, 0.15, 0.15).into();
+            }
+        }
+    }
+}
+pub fn max_players_but(
+    mut button_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<MaxPlayersButton>),
+    >,
+    mut switch_query: Query<&mut Switch>,
+) {
+    if let Ok((interaction, mut background_color)) = button_query.get_single_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                for mut switch in switch_query.iter_mut() {
+                    switch.host = false;
+                    switch.num_camps = false;
+                    switch.num_chests = false;
+                    switch.enemy_per_camp = false;
+                    switch.map_seed = false;
+                    switch.eid_percentage = false;
+                    switch.symmetry = false;
+                    switch.map_file = false;
+                    switch.map_height = false;
+                    switch.map_width = false;
+                    switch.password = false;
+                    switch.send_rate = false;
+                    switch.tick_rate = false;
+                    switch.player_name = false;
+                    switch.max_players = true;
+                }
+            }
+            Interaction::Hovered => {
+                *background_color = Color::GRAY.into();
+            }
+            Interaction::None => {
+                *background_color = Color:: rgb(0.15, 0.15, 0.15).into();
+            }
+        }
+    }
+}
+
+pub fn player_name_but(
+    mut button_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<PlayerNameButton>),
+    >,
+    mut switch_query: Query<&mut Switch>,
+) {
+    if let Ok((interaction, mut background_color)) = button_query.get_single_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                for mut switch in switch_query.iter_mut() {
+                    switch.host = false;
+                    switch.num_camps = false;
+                    switch.num_chests = false;
+                    switch.enemy_per_camp = false;
+                    switch.map_seed = false;
+                    switch.eid_percentage = false;
+                    switch.symmetry = false;
+                    switch.map_file = false;
+                    switch.map_height = false;
+                    switch.map_width = false;
+                    switch.password = false;
+                    switch.send_rate = false;
+                    switch.tick_rate = false;
+                    switch.max_players = false;
+                    switch.player_name = true;
+                }
+            }
+            Interaction::Hovered => {
+                *background_color = Color::GRAY.into();
+            }
+            Interaction::None => {
+                *background_color = Color:: rgb(0.15, 0.15, 0.15).into();
+            }
+        }
+    }
+}
+
+pub fn join_player_name_but(
+    mut button_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<JoinPlayerNameButton>),
+    >,
+    mut switch_query: Query<&mut Switch>,
+) {
+    if let Ok((interaction, mut background_color)) = button_query.get_single_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                for mut switch in switch_query.iter_mut() {
+                    switch.host_port = false;
+                    switch.ip = false;
+                    switch.port = false;
+                    switch.join_password = false;
+                    switch.join_send_rate = false;
+                    switch.join_player_name = true;
+                }
+            }
+            Interaction::Hovered => {
+                *background_color = Color::GRAY.into();
+            }
+            Interaction::None => {
+                *background_color = Color:: rgb(0.15, 0.15, 0.15).into();
+            }
+        }
+    }
+}
+
+pub fn tick_rate_but(
+    mut button_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<TickRateButton>),
+    >,
+    mut switch_query: Query<&mut Switch>,
+) {
+    if let Ok((interaction, mut background_color)) = button_query.get_single_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                for mut switch in switch_query.iter_mut() {
+                    switch.host = false;
+                    switch.num_camps = false;
+                    switch.num_chests = false;
+                    switch.enemy_per_camp = false;
+                    switch.map_seed = false;
+                    switch.eid_percentage = false;
+                    switch.symmetry = false;
+                    switch.map_file = false;
+                    switch.map_height = false;
+                    switch.map_width = false;
+                    switch.password = false;
+                    switch.send_rate = false;
+                    switch.player_name = false;
+                    switch.max_players = false;
+                    switch.tick_rate = true;
+                }
+            }
+            Interaction::Hovered => {
+                *background_color = Color::GRAY.into();
+            }
+            Interaction::None => {
+                *background_color = Color:: rgb(0.15, 0.15, 0.15).into();
+            }
+        }
+    }
+}
+
+pub fn send_rate_but(
+    mut button_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<SendRateButton>),
+    >,
+    mut switch_query: Query<&mut Switch>,
+) {
+    if let Ok((interaction, mut background_color)) = button_query.get_single_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                for mut switch in switch_query.iter_mut() {
+                    switch.host = false;
+                    switch.num_camps = false;
+                    switch.num_chests = false;
+                    switch.enemy_per_camp = false;
+                    switch.map_seed = false;
+                    switch.eid_percentage = false;
+                    switch.symmetry = false;
+                    switch.map_file = false;
+                    switch.map_height = false;
+                    switch.map_width = false;
+                    switch.password = false;
+                    switch.tick_rate = false;
+                    switch.player_name = false;
+                    switch.max_players = false;
+                    switch.send_rate = true;
+                }
+            }
+            Interaction::Hovered => {
+                *background_color = Color::GRAY.into();
+            }
+            Interaction::None => {
+                *background_color = Color:: rgb(0.15, 0.15, 0.15).into();
+            }
+        }
+    }
+}
+
+pub fn join_send_rate_but(
+    mut button_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<JoinSendRateButton>),
+    >,
+    mut switch_query: Query<&mut Switch>,
+) {
+    if let Ok((interaction, mut background_color)) = button_query.get_single_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                for mut switch in switch_query.iter_mut() {
+                    switch.host_port = false;
+                    switch.ip = false;
+                    switch.port = false;
+                    switch.join_password = false;
+                    switch.join_player_name = false;
+                    switch.join_send_rate = true;
                 }
             }
             Interaction::Hovered => {
@@ -441,19 +886,242 @@ pub fn join_host_port_but(
         }
     }
 }
+
+pub fn password_but(
+    mut button_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<PasswordButton>),
+    >,
+    mut switch_query: Query<&mut Switch>,
+) {
+    if let Ok((interaction, mut background_color)) = button_query.get_single_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                for mut switch in switch_query.iter_mut() {
+                    switch.host = false;
+                    switch.num_camps = false;
+                    switch.num_chests = false;
+                    switch.enemy_per_camp = false;
+                    switch.map_seed = false;
+                    switch.eid_percentage = false;
+                    switch.symmetry = false;
+                    switch.map_file = false;
+                    switch.map_height = false;
+                    switch.map_width = false;
+                    switch.send_rate = false;
+                    switch.tick_rate = false;
+                    switch.player_name = false;
+                    switch.max_players = false;
+                    switch.password = true;
+                }
+            }
+            Interaction::Hovered => {
+                *background_color = Color::GRAY.into();
+            }
+            Interaction::None => {
+                *background_color = Color:: rgb(0.15, 0.15, 0.15).into();
+            }
+        }
+    }
+}
+
+pub fn join_password_but(
+    mut button_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<JoinPasswordButton>),
+    >,
+    mut switch_query: Query<&mut Switch>,
+) {
+    if let Ok((interaction, mut background_color)) = button_query.get_single_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                for mut switch in switch_query.iter_mut() {
+                    switch.host_port = false;
+                    switch.ip = false;
+                    switch.port = false;
+                    switch.join_send_rate = false;
+                    switch.join_player_name = false;
+                    switch.join_password = true;
+                }
+            }
+            Interaction::Hovered => {
+                *background_color = Color::GRAY.into();
+            }
+            Interaction::None => {
+                *background_color = Color:: rgb(0.15, 0.15, 0.15).into();
+            }
+        }
+    }
+}
+
+pub fn map_width_but(
+    mut button_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<MapWidthButton>),
+    >,
+    mut switch_query: Query<&mut Switch>,
+) {
+    if let Ok((interaction, mut background_color)) = button_query.get_single_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                for mut switch in switch_query.iter_mut() {
+                    switch.host = false;
+                    switch.num_camps = false;
+                    switch.num_chests = false;
+                    switch.enemy_per_camp = false;
+                    switch.map_seed = false;
+                    switch.eid_percentage = false;
+                    switch.symmetry = false;
+                    switch.map_file = false;
+                    switch.map_height = false;
+                    switch.password = false;
+                    switch.send_rate = false;
+                    switch.tick_rate = false;
+                    switch.player_name = false;
+                    switch.max_players = false;
+                    switch.map_width = true;
+                }
+            }
+            Interaction::Hovered => {
+                *background_color = Color::GRAY.into();
+            }
+            Interaction::None => {
+                *background_color = Color:: rgb(0.15, 0.15, 0.15).into();
+            }
+        }
+    }
+}
+
+pub fn map_height_but(
+    mut button_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<MapHeightButton>),
+    >,
+    mut switch_query: Query<&mut Switch>,
+) {
+    if let Ok((interaction, mut background_color)) = button_query.get_single_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                for mut switch in switch_query.iter_mut() {
+                    switch.host = false;
+                    switch.num_camps = false;
+                    switch.num_chests = false;
+                    switch.enemy_per_camp = false;
+                    switch.map_seed = false;
+                    switch.eid_percentage = false;
+                    switch.symmetry = false;
+                    switch.map_file = false;
+                    switch.map_width = false;
+                    switch.password = false;
+                    switch.send_rate = false;
+                    switch.tick_rate = false;
+                    switch.player_name = false;
+                    switch.max_players = false;
+                    switch.map_height = true;
+                }
+            }
+            Interaction::Hovered => {
+                *background_color = Color::GRAY.into();
+            }
+            Interaction::None => {
+                *background_color = Color:: rgb(0.15, 0.15, 0.15).into();
+            }
+        }
+    }
+}
+
+pub fn map_file_but(
+    mut button_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<MapFileButton>),
+    >,
+    mut switch_query: Query<&mut Switch>,
+) {
+    if let Ok((interaction, mut background_color)) = button_query.get_single_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                for mut switch in switch_query.iter_mut() {
+                    switch.host = false;
+                    switch.num_camps = false;
+                    switch.num_chests = false;
+                    switch.enemy_per_camp = false;
+                    switch.map_seed = false;
+                    switch.eid_percentage = false;
+                    switch.symmetry = false;
+                    switch.map_height = false;
+                    switch.map_width = false;
+                    switch.password = false;
+                    switch.send_rate = false;
+                    switch.tick_rate = false;
+                    switch.player_name = false;
+                    switch.max_players = false;
+                    switch.map_file = true;
+                }
+            }
+            Interaction::Hovered => {
+                *background_color = Color::GRAY.into();
+            }
+            Interaction::None => {
+                *background_color = Color:: rgb(0.15, 0.15, 0.15).into();
+            }
+        }
+    }
+}
+
+pub fn symmetry_but(
+    mut button_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<SymmetryButton>),
+    >,
+    mut switch_query: Query<&mut Switch>,
+) {
+    if let Ok((interaction, mut background_color)) = button_query.get_single_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                for mut switch in switch_query.iter_mut() {
+                    switch.host = false;
+                    switch.num_camps = false;
+                    switch.num_chests = false;
+                    switch.enemy_per_camp = false;
+                    switch.map_seed = false;
+                    switch.eid_percentage = false;
+                    switch.map_file = false;
+                    switch.map_height = false;
+                    switch.map_width = false;
+                    switch.password = false;
+                    switch.send_rate = false;
+                    switch.tick_rate = false;
+                    switch.player_name = false;
+                    switch.max_players = false;
+                    switch.symmetry = true;
+                }
+            }
+            Interaction::Hovered => {
+                *background_color = Color::GRAY.into();
+            }
+            Interaction::None => {
+                *background_color = Color:: rgb(0.15, 0.15, 0.15).into();
+            }
+        }
+    }
+}
+
 pub fn save_join_input(
     mut is_host: ResMut<crate::net::IsHost>,
+    mut is_spectator: ResMut<crate::net::IsSpectator>,
     mut net_address: ResMut<NetworkAddresses>,
     join_port_query: Query<&JoinPortInput>,
     join_ip_query: Query<&JoinIPInput>,
     join_host_port_query: Query<&JoinHostPortInput>,
+    join_player_name_query: Query<&JoinPlayerNameInput>,
+    mut local_name: ResMut<LocalName>,
     mut button_query: Query<
-        (&Interaction, &mut BackgroundColor),
-        (Changed<Interaction>, With<JoinSaveButton>),
+        (&Interaction, &mut BackgroundColor, Option<&JoinSpectateButton>),
+        (Changed<Interaction>, Or<(With<JoinSaveButton>, With<JoinSpectateButton>)>),
     >,
     mut app_state_next_state: ResMut<NextState<AppState>>,
 ) {
-    if let Ok((interaction, mut background_color)) = button_query.get_single_mut() {
+    for (interaction, mut background_color, spectate) in &mut button_query {
         match *interaction {
             Interaction::Pressed => {
                 for join_port_input in join_port_query.iter() {
@@ -465,7 +1133,11 @@ pub fn save_join_input(
                 for join_host_port_input in join_host_port_query.iter() {
                     net_address.host_port =join_host_port_input.port.clone();
                 }
+                for input in join_player_name_query.iter() {
+                    local_name.set(&input.value);
+                }
                 is_host.0 = false;
+                is_spectator.0 = spectate.is_some();
                 app_state_next_state.set(AppState::Connecting);
             }
             Interaction::Hovered => {
@@ -478,6 +1150,83 @@ pub fn save_join_input(
     }
 }
 
+pub fn save_join_password(
+    mut key: ResMut<crate::net::secure::MatchKey>,
+    password_query: Query<&JoinPasswordInput>,
+    button_query: Query<&Interaction, (Changed<Interaction>, Or<(With<JoinSaveButton>, With<JoinSpectateButton>)>)>,
+) {
+    if !button_query.iter().any(|interaction| *interaction == Interaction::Pressed) { return }
+    for input in password_query.iter() {
+        *key = crate::net::secure::MatchKey::from_password(&input.value);
+    }
+}
+
+/// how many snapshots a second this client asks the host for, the host may send fewer
+pub fn save_join_rate(
+    mut send_rate: ResMut<crate::net::SendRate>,
+    send_rate_query: Query<&JoinSendRateInput>,
+    button_query: Query<&Interaction, (Changed<Interaction>, Or<(With<JoinSaveButton>, With<JoinSpectateButton>)>)>,
+) {
+    if !button_query.iter().any(|interaction| *interaction == Interaction::Pressed) { return }
+    for input in send_rate_query.iter() {
+        if let Ok(parsed_num) = input.value.parse::<u8>() {
+            send_rate.0 = parsed_num.clamp(1, crate::net::MAX_TICKRATE);
+        }
+    }
+}
+
+pub fn lobby_ready_but(
+    mut lobby: ResMut<crate::net::lobby::Lobby>,
+    mut button_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<LobbyReadyButton>),
+    >,
+) {
+    if let Ok((interaction, mut background_color)) = button_query.get_single_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                // toggles, the roster shows which way it's set
+                lobby.local_ready = !lobby.local_ready;
+            }
+            Interaction::Hovered => {
+                *background_color = Color::GRAY.into();
+            }
+            Interaction::None => {
+                *background_color = Color:: rgb(0.15, 0.15, 0.15).into();
+            }
+        }
+    }
+}
+
+pub fn copy_match_code_but(
+    seed: Res<MapSeed>,
+    num_camps: Res<NumCamps>,
+    map_size: Res<MapSize>,
+    spawn_settings: Res<SpawnSettings>,
+    mut button_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<CopyMatchCodeButton>),
+    >,
+) {
+    if let Ok((interaction, mut background_color)) = button_query.get_single_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                let code = MatchCode { seed: seed.0, num_camps: num_camps.0, map_size: *map_size, spawn_settings: *spawn_settings };
+                let copied = arboard::Clipboard::new().and_then(|mut clipboard| clipboard.set_text(code.encode()));
+                if let Err(e) = copied {
+                    warn!("can't copy the match code to the clipboard: {}", e);
+                }
+            }
+            Interaction::Hovered => {
+                *background_color = Color::GRAY.into();
+            }
+            Interaction::None => {
+                *background_color = Color:: rgb(0.15, 0.15, 0.15).into();
+            }
+        }
+    }
+}
+
 pub fn init_input_system_with_default<T: InputType>(
     default_value: &str,
     mut commands: Commands,
@@ -533,14 +1282,14 @@ pub fn init_num_chests_input_system(
     commands: Commands,
     num_chests_query: Query<(Entity, &mut Text, &mut NumChestsInput), Without<Initialized>>,
 ) {
-    init_input_system_with_default::<NumChestsInput>("WIP", commands, num_chests_query);
+    init_input_system_with_default::<NumChestsInput>("10", commands, num_chests_query);
 }
 
 pub fn init_enemies_per_camp_input_system(
     commands: Commands,
     enemies_per_camp_query: Query<(Entity, &mut Text, &mut EnemiesPerCampInput), Without<Initialized>>,
 ) {
-    init_input_system_with_default::<EnemiesPerCampInput>("WIP", commands, enemies_per_camp_query);
+    init_input_system_with_default::<EnemiesPerCampInput>("5", commands, enemies_per_camp_query);
 }
 
 pub fn init_map_seed_input_system(
@@ -559,7 +1308,93 @@ pub fn init_eid_percentage_input_system(
     commands: Commands,
     eid_percentage_query: Query<(Entity, &mut Text, &mut EidPercentageInput), Without<Initialized>>,
 ) {
-    init_input_system_with_default::<EidPercentageInput>("WIP", commands, eid_percentage_query);
+    init_input_system_with_default::<EidPercentageInput>("50", commands, eid_percentage_query);
+}
+
+pub fn init_max_players_input_system(
+    commands: Commands,
+    max_players_query: Query<(Entity, &mut Text, &mut MaxPlayersInput), Without<Initialized>>,
+) {
+    init_input_system_with_default::<MaxPlayersInput>("4", commands, max_players_query);
+}
+
+pub fn init_player_name_input_system(
+    commands: Commands,
+    player_name_query: Query<(Entity, &mut Text, &mut PlayerNameInput), Without<Initialized>>,
+    local_name: Res<LocalName>,
+) {
+    init_input_system_with_default::<PlayerNameInput>(&local_name.0, commands, player_name_query);
+}
+
+pub fn init_join_player_name_input_system(
+    commands: Commands,
+    join_player_name_query: Query<(Entity, &mut Text, &mut JoinPlayerNameInput), Without<Initialized>>,
+    local_name: Res<LocalName>,
+) {
+    init_input_system_with_default::<JoinPlayerNameInput>(&local_name.0, commands, join_player_name_query);
+}
+
+pub fn init_tick_rate_input_system(
+    commands: Commands,
+    tick_rate_query: Query<(Entity, &mut Text, &mut TickRateInput), Without<Initialized>>,
+) {
+    init_input_system_with_default::<TickRateInput>("10", commands, tick_rate_query);
+}
+
+pub fn init_send_rate_input_system(
+    commands: Commands,
+    send_rate_query: Query<(Entity, &mut Text, &mut SendRateInput), Without<Initialized>>,
+) {
+    init_input_system_with_default::<SendRateInput>("30", commands, send_rate_query);
+}
+
+pub fn init_join_send_rate_input_system(
+    commands: Commands,
+    join_send_rate_query: Query<(Entity, &mut Text, &mut JoinSendRateInput), Without<Initialized>>,
+) {
+    init_input_system_with_default::<JoinSendRateInput>("30", commands, join_send_rate_query);
+}
+
+pub fn init_password_input_system(
+    commands: Commands,
+    password_query: Query<(Entity, &mut Text, &mut PasswordInput), Without<Initialized>>,
+) {
+    init_input_system_with_default::<PasswordInput>("", commands, password_query);
+}
+
+pub fn init_join_password_input_system(
+    commands: Commands,
+    join_password_query: Query<(Entity, &mut Text, &mut JoinPasswordInput), Without<Initialized>>,
+) {
+    init_input_system_with_default::<JoinPasswordInput>("", commands, join_password_query);
+}
+
+pub fn init_map_width_input_system(
+    commands: Commands,
+    map_width_query: Query<(Entity, &mut Text, &mut MapWidthInput), Without<Initialized>>,
+) {
+    init_input_system_with_default::<MapWidthInput>("256", commands, map_width_query);
+}
+
+pub fn init_map_height_input_system(
+    commands: Commands,
+    map_height_query: Query<(Entity, &mut Text, &mut MapHeightInput), Without<Initialized>>,
+) {
+    init_input_system_with_default::<MapHeightInput>("256", commands, map_height_query);
+}
+
+pub fn init_map_file_input_system(
+    commands: Commands,
+    map_file_query: Query<(Entity, &mut Text, &mut MapFileInput), Without<Initialized>>,
+) {
+    init_input_system_with_default::<MapFileInput>("", commands, map_file_query);
+}
+
+pub fn init_symmetry_input_system(
+    commands: Commands,
+    symmetry_query: Query<(Entity, &mut Text, &mut SymmetryInput), Without<Initialized>>,
+) {
+    init_input_system_with_default::<SymmetryInput>("off", commands, symmetry_query);
 }
 
 pub fn exit_system(mut exit: EventWriter<AppExit>) {
@@ -6,13 +6,23 @@ use bevy::prelude::Timer;
 use bevy::prelude::*;
 use crate::game::camera::SpatialCameraBundle;
 use crate::game::components::*;
-use crate::game::ROUND_TIME;
+use crate::game::{MapConfig, PlayerId, ROUND_TIME};
+use crate::game::map::{MapSeed, MapSize, NumCamps, SpawnSettings};
+use crate::game::mapfile::MapChoice;
+use crate::game::matchcode::MatchCode;
+use crate::game::player::{player_tint, PlayerNames, SPECTATOR_ID};
 use crate::AppState;
-use crate::net::{TICKLEN_S, TickNum};
+use crate::net::{TickNum, TickRate, IsHost};
+use crate::net::chat::{Chat, SYSTEM_ID};
+use crate::net::lobby::Lobby;
 
 pub const SCREEN_WIDTH: f32 = 1280.0;
 pub const SCREEN_HEIGHT: f32 = 720.0;
 pub const PADDING: f32 = 20.0;
+const LEADERBOARD_ROW_HEIGHT: f32 = 28.0;  // small enough for MAX_PLAYERS rows to fit on screen
+const CHAT_SHOW_SECS: f32 = 8.0;  // chat lines stay solid this long, then fade out over CHAT_FADE_SECS
+const CHAT_FADE_SECS: f32 = 2.0;
+const ERROR_POPUP_SECS: f32 = 5.0;
 
 #[derive(Component, Deref, DerefMut)]
 pub struct PopupTimer(Timer);
@@ -35,6 +45,45 @@ pub fn show_popup(
     }
 }
 
+/// Shows an error along the bottom of the screen over whatever menu page is up.
+/// It's its own root so it stays put while the pages change underneath it.
+pub fn spawn_error_popup(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    message: &str,
+) {
+    commands.spawn((
+        TextBundle::from_section(
+            message,
+            TextStyle {
+                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                font_size: 24.0,
+                color: Color::RED,
+            },
+        ).with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(24.0),
+            left: Val::Px(24.0),
+            ..default()
+        }),
+        Popup,
+        ErrorPopup,
+        PopupTimer(Timer::from_seconds(ERROR_POPUP_SECS, TimerMode::Once)),
+    ));
+}
+
+// show_popup ticks the timer
+pub fn expire_error_popups(
+    mut commands: Commands,
+    popups: Query<(Entity, &PopupTimer), With<ErrorPopup>>,
+) {
+    for (entity, timer) in &popups {
+        if timer.finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
 fn spawn_title(
     parent: &mut EntityCommands,
     font: &Handle<Font>,
@@ -198,6 +247,18 @@ fn spawn_input<T: Bundle, U: Bundle>(
             enemy_per_camp: false,
             map_seed: false,
             eid_percentage: false,
+            max_players: false,
+            player_name: false,
+            join_player_name: false,
+            tick_rate: false,
+            send_rate: false,
+            join_send_rate: false,
+            password: false,
+            join_password: false,
+            map_width: false,
+            map_height: false,
+            map_file: false,
+            symmetry: false,
         },
         button,
     )).id();
@@ -383,15 +444,24 @@ pub fn spawn_host_page(
     let host_page_left_id = spawn_flex_column(&mut commands, ());
     commands.entity(host_page_row_id).add_child(host_page_left_id);
     let mut host_page_left = commands.entity(host_page_left_id);
+    spawn_input(&mut host_page_left, &font, PlayerNameButton, PlayerNameInput { value: String::new() }, "Name: ");
     spawn_input(&mut host_page_left, &font, (), HostPortInput { port: String::new() }, "Port: ");
     spawn_input(&mut host_page_left, &font, NumCampsButton, NumCampsInput { value: String::new() }, "Number of Camps: ");
     spawn_input(&mut host_page_left, &font, NumChestsButton, NumChestsInput { value: String::new() }, "Number of Chests: ");
     spawn_input(&mut host_page_left, &font, EnemiesPerCampButton, EnemiesPerCampInput { value: String::new() }, "Number of Enemies Per Camp: ");
+    spawn_input(&mut host_page_left, &font, MapWidthButton, MapWidthInput { value: String::new() }, "Map Width: ");
+    spawn_input(&mut host_page_left, &font, MapHeightButton, MapHeightInput { value: String::new() }, "Map Height: ");
+    spawn_input(&mut host_page_left, &font, MapFileButton, MapFileInput { value: String::new() }, "Map File: ");
+    spawn_input(&mut host_page_left, &font, SymmetryButton, SymmetryInput { value: String::new() }, "Symmetry (off/mirror/rotate/quarters): ");
     let host_page_right_id = spawn_flex_column(&mut commands, ());
     commands.entity(host_page_row_id).add_child(host_page_right_id);
     let mut host_page_right = commands.entity(host_page_right_id);
     spawn_input(&mut host_page_right, &font, MapSeedButton, MapSeedInput { value: String::new() }, "Map Seed: ");
     spawn_input(&mut host_page_right, &font, EidPercentageButton, EidPercentageInput { value: String::new() }, "EID Percentage: ");
+    spawn_input(&mut host_page_right, &font, MaxPlayersButton, MaxPlayersInput { value: String::new() }, "Max Players: ");
+    spawn_input(&mut host_page_right, &font, TickRateButton, TickRateInput { value: String::new() }, "Tick Rate: ");
+    spawn_input(&mut host_page_right, &font, SendRateButton, SendRateInput { value: String::new() }, "Snapshots Per Second: ");
+    spawn_input(&mut host_page_right, &font, PasswordButton, PasswordInput { value: String::new() }, "Match Password: ");
     spawn_button(&mut host_page_right, &font, HostPortSaveButton, "Host Now");
     spawn_button(&mut host_page_right, &font, BackToMainMenu, "Back");
 }
@@ -414,10 +484,14 @@ pub fn spawn_join_page(
     let join_page_id = spawn_flex_column(&mut commands, JoinPage);
     let mut join_page = commands.entity(join_page_id);
     spawn_title(&mut join_page, &font, "Join a game");
+    spawn_input(&mut join_page, &font, JoinPlayerNameButton, JoinPlayerNameInput { value: String::new() }, "Name: ");
     spawn_input(&mut join_page, &font, JoinPortButton, JoinPortInput { port: String::new() }, "Your Port: ");
     spawn_input(&mut join_page, &font, JoinHostPortButton, JoinHostPortInput { port: String::new() }, "Host Port: ");
     spawn_input(&mut join_page, &font, JoinIpButton, JoinIPInput { ip: String::new() }, "Host IP: ");
+    spawn_input(&mut join_page, &font, JoinSendRateButton, JoinSendRateInput { value: String::new() }, "Snapshots Per Second: ");
+    spawn_input(&mut join_page, &font, JoinPasswordButton, JoinPasswordInput { value: String::new() }, "Match Password: ");
     spawn_button(&mut join_page, &font, JoinSaveButton, "Join Now");
+    spawn_button(&mut join_page, &font, JoinSpectateButton, "Spectate");
     spawn_button(&mut join_page, &font, BackToMainMenu, "Back");
 }
 
@@ -439,6 +513,9 @@ pub fn spawn_controls_page(
         "Movement - WASD\n\
         Attack - Left Click\n\
         Interact - E\n\
+        Chat - Enter to open, Enter to send\n\
+        Host Commands - /players, /kick <id>, /ban <id> (their whole ip), /end, /restart [seed]\n\
+        Spectator Camera - WASD, F to follow a player\n\
         Quit Game - Esc",
         TextStyle {
             font: font.clone(),
@@ -457,7 +534,8 @@ pub fn despawn_in_game_ui(
     mut commands: Commands, 
     in_game_ui: Query<Entity, With<InGameUi>>
 ) {
-    if let Ok(in_game_ui) = in_game_ui.get_single() {
+    // score, timer and powerups are all separate roots
+    for in_game_ui in &in_game_ui {
         commands.entity(in_game_ui).despawn_recursive();
     }
 }
@@ -549,14 +627,81 @@ pub fn spawn_in_game_ui(
     });
 }
 
+pub fn spawn_chat_overlay(
+    mut commands: Commands,
+) {
+    commands.spawn((TextBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            left: Val::Px(PADDING),
+            bottom: Val::Px(PADDING + 80.0),
+            max_width: Val::Px(SCREEN_WIDTH / 2.0),
+            ..Default::default()
+        },
+        text: Text::from_sections([]).with_alignment(TextAlignment::Left),
+        ..Default::default()},
+        ChatOverlay));
+}
+
+pub fn despawn_chat_overlay(
+    mut commands: Commands,
+    chat_overlay: Query<Entity, With<ChatOverlay>>,
+) {
+    for entity in &chat_overlay {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Recent chat, oldest on top. Lines fade out after a while unless the chat box is open.
+pub fn update_chat_overlay(
+    asset_server: Res<AssetServer>,
+    time: Res<Time>,
+    chat: Res<Chat>,
+    names: Res<PlayerNames>,
+    mut chat_overlay: Query<&mut Text, With<ChatOverlay>>,
+) {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let now = time.elapsed_seconds();
+    let mut sections: Vec<TextSection> = Vec::new();
+    for line in &chat.lines {
+        let age = now - line.time;
+        let alpha = if chat.typing.is_some() { 1.0 } else { (1.0 - (age - CHAT_SHOW_SECS) / CHAT_FADE_SECS).clamp(0.0, 1.0) };
+        if alpha == 0.0 { continue }
+        let style = |color: Color| TextStyle {
+            font: font.clone(),
+            font_size: 24.0,
+            color: color.with_a(alpha),
+        };
+        if line.from == SYSTEM_ID {
+            sections.push(TextSection::new(format!("{}\n", line.text), style(Color::YELLOW)));
+            continue;
+        }
+        let name = if line.from == SPECTATOR_ID { "Spectator".to_string() } else { names.get(line.from) };
+        let name_color = if line.from == SPECTATOR_ID { Color::GRAY } else { player_tint(line.from) };
+        sections.push(TextSection::new(format!("{}: ", name), style(name_color)));
+        sections.push(TextSection::new(format!("{}\n", line.text), style(Color::WHITE)));
+    }
+    if let Some(typed) = &chat.typing {
+        sections.push(TextSection::new(format!("> {}_", typed), TextStyle {
+            font: font.clone(),
+            font_size: 24.0,
+            color: Color::WHITE,
+        }));
+    }
+    for mut text in &mut chat_overlay {
+        text.sections = sections.clone();
+    }
+}
+
 pub fn update_time_remaining_system(
     mut game_timer: Query<(&mut GameTimer, &mut Text)>,
     tick: Res<TickNum>,
+    tick_rate: Res<TickRate>,
     mut app_state_next_state: ResMut<NextState<AppState>>,
 ) {
     for (mut timer, mut text) in &mut game_timer {
         if timer.remaining_time > 0.0 {
-            timer.remaining_time = ROUND_TIME - (tick.0 as f32 * TICKLEN_S);
+            timer.remaining_time = ROUND_TIME - (tick.0 as f32 * tick_rate.tick_len());
             let minutes = (timer.remaining_time / 60.0) as i32;
             let seconds = (timer.remaining_time % 60.0) as i32;
 
@@ -586,12 +731,108 @@ pub fn spawn_connecting_page(
     spawn_title(&mut connecting, &font, "Connecting...");
 }
 
+pub fn despawn_lobby_page(
+    mut commands: Commands,
+    lobby_page_entity: Query<Entity, With<LobbyPage>>
+) {
+    if let Ok(lobby_page_entity) = lobby_page_entity.get_single() {
+        commands.entity(lobby_page_entity).despawn_recursive();
+    }
+}
+
+pub fn spawn_lobby_page(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    is_host: Res<IsHost>,
+    res_id: Res<PlayerId>,
+    map_config: Res<MapConfig>,
+) {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let text_style = TextStyle {
+        font: font.clone(),
+        font_size: 24.0,
+        color: Color::BLACK,
+    };
+    let roster = commands.spawn((
+        TextBundle::from_section("", text_style.clone()).with_text_alignment(TextAlignment::Center),
+        LobbyRosterText,
+    )).id();
+    let countdown = commands.spawn((
+        TextBundle::from_section("", text_style.clone()).with_text_alignment(TextAlignment::Center),
+        LobbyCountdownText,
+    )).id();
+    let match_code = commands.spawn((
+        TextBundle::from_section("", text_style).with_text_alignment(TextAlignment::Center),
+        LobbyMatchCodeText,
+    )).id();
+    let lobby_page_id = spawn_flex_column(&mut commands, LobbyPage);
+    let mut lobby_page = commands.entity(lobby_page_id);
+    spawn_title(&mut lobby_page, &font, "Lobby");
+    lobby_page.add_child(roster);
+    lobby_page.add_child(countdown);
+    lobby_page.add_child(match_code);
+    if is_host.0 {
+        // filled in with what was picked on the host page instead of the usual defaults
+        let num_camps = map_config.num_camps.clone();
+        let map_seed = map_config.map_seed.clone();
+        spawn_input(&mut lobby_page, &font, NumCampsButton, NumCampsInput { value: num_camps.clone() }, &format!("Number of Camps:  {}", num_camps));
+        spawn_input(&mut lobby_page, &font, MapSeedButton, MapSeedInput { value: map_seed.clone() }, &format!("Map Seed:  {}", map_seed));
+    }
+    spawn_button(&mut lobby_page, &font, CopyMatchCodeButton, "Copy Match Code");
+    if res_id.0 != SPECTATOR_ID {
+        spawn_button(&mut lobby_page, &font, LobbyReadyButton, "Ready");
+    }
+    spawn_button(&mut lobby_page, &font, BackToMainMenu, "Leave");
+}
+
+pub fn update_lobby_page(
+    lobby: Res<Lobby>,
+    tick_rate: Res<TickRate>,
+    res_id: Res<PlayerId>,
+    names: Res<PlayerNames>,
+    mut roster_query: Query<&mut Text, (With<LobbyRosterText>, Without<LobbyCountdownText>)>,
+    mut countdown_query: Query<&mut Text, (With<LobbyCountdownText>, Without<LobbyRosterText>)>,
+    mut match_code_query: Query<&mut Text, (With<LobbyMatchCodeText>, Without<LobbyRosterText>, Without<LobbyCountdownText>)>,
+    seed: Res<MapSeed>,
+    num_camps: Res<NumCamps>,
+    map_size: Res<MapSize>,
+    spawn_settings: Res<SpawnSettings>,
+    map_choice: Res<MapChoice>,
+) {
+    let mut roster = String::new();
+    for (id, ready) in &lobby.players {
+        let you = if *id == res_id.0 { " (you)" } else { "" };
+        let status = if *ready { "Ready" } else { "Not Ready" };
+        roster.push_str(&format!("{}{} - {}\n", names.get(*id), you, status));
+    }
+    for mut text in &mut roster_query {
+        text.sections[0].value = roster.clone();
+    }
+    let countdown = match lobby.countdown {
+        Some(ticks) => format!("Starting in {}...", (ticks as f32 * tick_rate.tick_len()).ceil() as u32),
+        None => "Waiting for everyone to be ready".to_string(),
+    };
+    for mut text in &mut countdown_query {
+        text.sections[0].value = countdown.clone();
+    }
+    // what to pass around to play this map again, hand-made maps go by name instead
+    let match_code = if map_choice.is_generated() {
+        let code = MatchCode { seed: seed.0, num_camps: num_camps.0, map_size: *map_size, spawn_settings: *spawn_settings };
+        format!("Match Code: {}", code.encode())
+    } else {
+        format!("Map: {}", map_choice.name)
+    };
+    for mut text in &mut match_code_query {
+        text.sections[0].value = match_code.clone();
+    }
+}
+
 pub fn spawn_leaderboard_ui(
     mut commands: Commands, 
     asset_server: Res<AssetServer>,
 ) {
     let font = asset_server.load("fonts/FiraSans-Bold.ttf");
-    let measure_names = ["Player", "Score", "Enemy Kills", "Player Kills", "Camps Captured", "Deaths", "KD"];
+    let measure_names = ["Player", "Name", "Score", "Enemy Kills", "Player Kills", "Camps Captured", "Deaths", "KD"];
     let leaderboard_entity = commands
         .spawn((NodeBundle {
             style: Style {
@@ -628,7 +869,7 @@ pub fn spawn_leaderboard_ui(
         .spawn(NodeBundle {
             style: Style {
                 width: Val::Percent(70.0),
-                height: Val::Percent(20.0),
+                height: Val::Px(60.0),
                 flex_direction: FlexDirection::Row,
                 align_items: AlignItems::Center,
                 justify_content: JustifyContent::SpaceEvenly,
@@ -660,10 +901,10 @@ pub fn spawn_leaderboard_ui(
                     },
                 )
                 .with_style(Style {
-                    width: Val::Percent(100.0 / 21.0),
+                    width: Val::Percent(100.0 / 24.0),
                     margin: UiRect {
-                        left: Val::Percent(100.0 / 21.0),
-                        right: Val::Percent(100.0 / 21.0),
+                        left: Val::Percent(100.0 / 24.0),
+                        right: Val::Percent(100.0 / 24.0),
                         top: Val::Px(0.),
                         bottom: Val::Px(0.),
                     },
@@ -672,75 +913,131 @@ pub fn spawn_leaderboard_ui(
             }
         }).id();
     commands.entity(leaderboard_entity).push_children(&[measures_entity]);
-    // player stats
+    // player rows get added by add_leaderboard_rows as players show up
+}
+
+/// Gives every player a leaderboard row, including anyone who joined after the round started.
+pub fn add_leaderboard_rows(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    players: Query<(&Player, &Stats)>,
+    names: Res<PlayerNames>,
+    rows: Query<&LeaderboardRow>,
+    leaderboard_query: Query<Entity, With<LeaderboardUi>>,
+) {
+    let leaderboard_entity = leaderboard_query.get_single();
+    if leaderboard_entity.is_err() { return }
+    let leaderboard_entity = leaderboard_entity.unwrap();
+    let mut players: Vec<(&Player, &Stats)> = players.iter().filter(|(pl, _)| !rows.iter().any(|row| row.0 == pl.0)).collect();
+    players.sort_by_key(|(pl, _)| pl.0);
+    for (player, stats) in players {
+        let row = spawn_leaderboard_row(&mut commands, &asset_server, player.0, &names.get(player.0), stats);
+        commands.entity(leaderboard_entity).push_children(&[row]);
+    }
+}
+
+fn spawn_leaderboard_row(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    id: u8,
+    name: &str,
+    stats: &Stats,
+) -> Entity {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
     let player_icons = vec!["jordan_icon.png", "ian_icon.png", "sam_icon.png", "kevin_icon.png"];
-    for i in 0..4 {
-        let player_stats_entity = commands
-        .spawn(NodeBundle {
+    let values = [
+        stats.score.to_string(),
+        stats.enemies_killed.to_string(),
+        stats.players_killed.to_string(),
+        stats.camps_captured.to_string(),
+        stats.deaths.to_string(),
+        stats.kd_ratio.to_string(),
+    ];
+    let cell_style = Style {
+        width: Val::Percent(100.0 / 24.0),
+        margin: UiRect {
+            left: Val::Percent(100.0 / 24.0),
+            right: Val::Percent(100.0 / 24.0),
+            top: Val::Px(0.),
+            bottom: Val::Px(0.),
+        },
+        ..default()
+    };
+    commands
+        .spawn((NodeBundle {
             style: Style {
                 width: Val::Percent(70.0),
-                height: Val::Percent(15.0),
+                height: Val::Px(LEADERBOARD_ROW_HEIGHT),
                 flex_direction: FlexDirection::Row,
                 align_items: AlignItems::Center,
                 justify_content: JustifyContent::SpaceEvenly,
                 padding: UiRect {
                     left: Val::Px(20.),
                     right: Val::Px(20.),
-                    top: Val::Px(20.),
-                    bottom: Val::Px(20.),
+                    top: Val::Px(2.),
+                    bottom: Val::Px(2.),
                 },
                 ..default()
             },
             background_color: BackgroundColor(Color::rgba(0.5, 0.5, 0.5, 0.5)),
             ..default()
-        })
+        }, LeaderboardRow(id)))
         .with_children(|parent| {
-            parent.spawn((ImageBundle {
-                image: asset_server.load(player_icons[i]).into(),
-                style: Style {
-                    width: Val::Percent(100.0 / 21.0),
-                    max_height: Val::Percent(100.0),
-                    margin: UiRect {
-                        left: Val::Percent(100.0 / 21.0),
-                        right: Val::Percent(100.0 / 21.0),
-                        top: Val::Px(0.),
-                        bottom: Val::Px(0.),
+            // the original four have portraits, everyone after that gets their number in their sprite's tint
+            if (id as usize) < player_icons.len() {
+                parent.spawn((ImageBundle {
+                    image: asset_server.load(player_icons[id as usize]).into(),
+                    style: Style {
+                        max_height: Val::Percent(100.0),
+                        ..cell_style.clone()
                     },
                     ..default()
                 },
-                ..default()
-            },
-            PlayerStatDisplay {
-                player_id: i as u8,
-                stat_id: 0,
-            }));
+                PlayerStatDisplay {
+                    player_id: id,
+                    stat_id: 0,
+                }));
+            } else {
+                parent.spawn((TextBundle::from_section(
+                    format!("P{}", id + 1),
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 22.0,
+                        color: player_tint(id),
+                    },
+                )
+                .with_style(cell_style.clone()),
+                PlayerStatDisplay {
+                    player_id: id,
+                    stat_id: 0,
+                }));
+            }
+            parent.spawn((TextBundle::from_section(
+                name,
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 22.0,
+                    color: Color::WHITE,
+                },
+            )
+            .with_style(cell_style.clone()),
+            PlayerNameDisplay(id)));
             for j in 1..7 {
                 parent.spawn((TextBundle::from_section(
-                    j.to_string(),
+                    values[j - 1].clone(),
                     TextStyle {
                         font: font.clone(),
-                        font_size: 32.0,
+                        font_size: 22.0,
                         color: Color::WHITE,
                     },
                 )
-                .with_style(Style {
-                    width: Val::Percent(100.0 / 21.0),
-                    margin: UiRect {
-                        left: Val::Percent(100.0 / 21.0),
-                        right: Val::Percent(100.0 / 21.0),
-                        top: Val::Px(0.),
-                        bottom: Val::Px(0.),
-                    },
-                    ..default()
-                }),
+                .with_style(cell_style.clone()),
                 PlayerStatDisplay {
-                    player_id: i as u8,
+                    player_id: id,
                     stat_id: j as u8,
                 }));
             }
-        }).id();
-        commands.entity(leaderboard_entity).push_children(&[player_stats_entity]);
-    }
+        }).id()
 }
 
 pub fn despawn_leaderboard_ui(
@@ -760,6 +1057,8 @@ pub fn toggle_leaderboard(
     mut minimap_query: Query<&mut Visibility, With<SpatialCameraBundle>>,
     mut leaderboard_query: Query<(Entity, &mut Style), (With<LeaderboardUi>, Without<InGameUi>)>,
     mut leaderboard_title_query: Query<&mut Text, With<LeaderboardUiTitle>>,
+    players: Query<(&Player, &Stats)>,
+    names: Res<PlayerNames>,
     app_state_current_state: ResMut<State<AppState>>,
 ) {
     if input.just_pressed(KeyCode::Tab) || *app_state_current_state.get() == AppState::GameOver {
@@ -774,8 +1073,12 @@ pub fn toggle_leaderboard(
         }
         if *app_state_current_state.get() == AppState::GameOver 
         {
+            let winner = players.iter().max_by_key(|(_, stats)| stats.score);
             for mut text in &mut leaderboard_title_query.iter_mut() {
-                text.sections[0].value = "Game Over".to_string();
+                text.sections[0].value = match winner {
+                    Some((player, _)) => format!("Game Over - {} wins!", names.get(player.0)),
+                    None => "Game Over".to_string(),
+                };
             }
             for (leaderboard_id, _) in &mut leaderboard_query.iter_mut() {
                 let font = asset_server.load("fonts/FiraSans-Bold.ttf");
@@ -817,4 +1120,15 @@ pub fn update_leaderboard(
             }
         }
     }
-}
\ No newline at end of file
+}
+
+// names can arrive from the host after the row was made
+pub fn update_leaderboard_names(
+    names: Res<PlayerNames>,
+    mut name_query: Query<(&mut Text, &PlayerNameDisplay)>,
+) {
+    if !names.is_changed() { return }
+    for (mut text, identifier) in &mut name_query {
+        text.sections[0].value = names.get(identifier.0);
+    }
+}
@@ -1,3 +1,5 @@
+use std::fs;
+use std::path::PathBuf;
 use bevy::audio::PlaybackMode;
 use bevy::prelude::*;
 pub(crate) mod layout;
@@ -8,7 +10,10 @@ use crate::AppState;
 use layout::*;
 use interactions::*;
 use crate::menus::components::*;
-use crate::game::player::{spawn_players, remove_players};
+use crate::game::player::{clean_name, spawn_players, remove_players};
+
+const NAME_FILE: &str = "jordquest/name";  // remembers the last name typed in, under the user's config dir
+const FALLBACK_NAME_FILE: &str = "name.cfg";  // in the working dir, for systems without a config dir
 
 #[derive(Component)]
 struct InGameAmbientAudio;
@@ -21,6 +26,39 @@ pub struct NetworkAddresses {
     pub ip: String,
 }
 
+/// the name this computer plays under, prefilled on the host and join pages
+#[derive(Resource)]
+pub struct LocalName(pub String);
+
+impl LocalName {
+    fn load() -> LocalName {
+        let saved = fs::read_to_string(Self::path()).ok().and_then(|s| clean_name(&s));
+        LocalName(saved.unwrap_or("Player".to_string()))
+    }
+
+    /// keeps name if it's usable and saves it as the default for next time
+    pub fn set(&mut self, name: &str) {
+        if let Some(name) = clean_name(name) {
+            let path = Self::path();
+            let saved = match path.parent() {
+                Some(dir) => fs::create_dir_all(dir).and_then(|_| fs::write(&path, &name)),
+                None => fs::write(&path, &name),
+            };
+            if let Err(e) = saved {
+                warn!("can't save name to {}: {}", path.display(), e);
+            }
+            self.0 = name;
+        }
+    }
+
+    fn path() -> PathBuf {
+        match dirs::config_dir() {
+            Some(dir) => dir.join(NAME_FILE),
+            None => PathBuf::from(FALLBACK_NAME_FILE),
+        }
+    }
+}
+
 pub struct MainMenuPlugin;
 
 impl Plugin for MainMenuPlugin{
@@ -29,10 +67,16 @@ impl Plugin for MainMenuPlugin{
         .add_systems(OnEnter(AppState::MainMenu), spawn_main_menu)
         .add_systems(OnExit(AppState::MainMenu), despawn_main_menu)
         .add_systems(Update, show_popup)
+        .add_systems(Update, expire_error_popups.after(show_popup))
         .add_systems(OnEnter(AppState::Credits), spawn_credits_page)
         .add_systems(OnExit(AppState::Credits), despawn_credits_page)
         .add_systems(OnEnter(AppState::Connecting), spawn_connecting_page)
         .add_systems(OnExit(AppState::Connecting), despawn_connecting_page)
+        .add_systems(OnEnter(AppState::Lobby), spawn_lobby_page)
+        .add_systems(OnExit(AppState::Lobby), despawn_lobby_page)
+        .add_systems(Update, update_lobby_page.run_if(in_state(AppState::Lobby)))
+        .add_systems(Update, lobby_ready_but.run_if(in_state(AppState::Lobby)))
+        .add_systems(Update, copy_match_code_but.run_if(in_state(AppState::Lobby)))
         .add_systems(OnEnter(AppState::Hosting), spawn_host_page)
         .add_systems(OnExit(AppState::Hosting), despawn_host_page)
         .add_systems(OnEnter(AppState::Joining), spawn_join_page)
@@ -41,10 +85,14 @@ impl Plugin for MainMenuPlugin{
         .add_systems(OnExit(AppState::Controls), despawn_controls_page)
         .add_systems(OnEnter(AppState::Game), spawn_in_game_ui)
         .add_systems(OnExit(AppState::Game), despawn_in_game_ui)
+        .add_systems(OnEnter(AppState::Game), spawn_chat_overlay)
+        .add_systems(OnExit(AppState::Game), despawn_chat_overlay)
+        .add_systems(Update, update_chat_overlay.run_if(in_state(AppState::Game)))
         .add_systems(OnEnter(AppState::Game), spawn_leaderboard_ui.after(spawn_players))
         .add_systems(OnEnter(AppState::GameOver), update_leaderboard.before(remove_players))
         .add_systems(OnEnter(AppState::GameOver), toggle_leaderboard.before(remove_players))
         .add_systems(OnExit(AppState::GameOver), despawn_leaderboard_ui)
+        .add_systems(OnEnter(AppState::Lobby), despawn_leaderboard_ui)  // left over if the host restarted mid round
         .add_systems(OnEnter(AppState::Quitting), exit_system)
         .add_systems(OnEnter(AppState::Game), play_ambient)
         .add_systems(OnExit(AppState::Game), despawn_ambient_audio)
@@ -54,6 +102,8 @@ impl Plugin for MainMenuPlugin{
         .add_systems(Update, interact_with_button::<CreditsButtonType>.run_if(in_state(AppState::MainMenu)))
         .add_systems(Update, toggle_leaderboard.run_if(in_state(AppState::Game)))
         .add_systems(Update, update_leaderboard.run_if(in_state(AppState::Game)))
+        .add_systems(Update, add_leaderboard_rows.run_if(in_state(AppState::Game)))
+        .add_systems(Update, update_leaderboard_names.run_if(in_state(AppState::Game)))
         .add_systems(Update, interact_with_button::<CreditsButtonType>.run_if(in_state(AppState::GameOver)))
         .add_systems(Update, interact_with_button::<BackButtonType>)
         .add_systems(Update, interact_with_button::<QuitButtonType>.run_if(in_state(AppState::Credits)))
@@ -62,9 +112,24 @@ impl Plugin for MainMenuPlugin{
         .add_systems(Update, update_num_chests_input)
         .add_systems(Update, update_enemies_per_camp_input)
         .add_systems(Update, update_map_seed_input)
+        .add_systems(Update, paste_input::<MapSeedInput>)
         .add_systems(Update, update_eid_percentage_input)
+        .add_systems(Update, update_symmetry_input)
+        .add_systems(Update, update_map_file_input)
+        .add_systems(Update, update_map_height_input)
+        .add_systems(Update, update_map_width_input)
+        .add_systems(Update, update_join_password_input)
+        .add_systems(Update, update_password_input)
+        .add_systems(Update, update_join_send_rate_input)
+        .add_systems(Update, update_send_rate_input)
+        .add_systems(Update, update_tick_rate_input)
+        .add_systems(Update, update_join_player_name_input)
+        .add_systems(Update, update_player_name_input)
+        .add_systems(Update, update_max_players_input)
         .add_systems(Update, update_time_remaining_system.run_if(in_state(AppState::Game)))
         .add_systems(Update, save_host_input)
+        .add_systems(Update, save_host_rates)
+        .add_systems(Update, save_host_password)
         .add_systems(Update, update_join_port_input)
         .add_systems(Update, update_join_host_port_input)
         .add_systems(Update, join_host_port_but)
@@ -73,11 +138,25 @@ impl Plugin for MainMenuPlugin{
         .add_systems(Update, enemy_per_camp_but)
         .add_systems(Update, map_seed_but)
         .add_systems(Update, eid_percentage_but)
+        .add_systems(Update, symmetry_but)
+        .add_systems(Update, map_file_but)
+        .add_systems(Update, map_height_but)
+        .add_systems(Update, map_width_but)
+        .add_systems(Update, join_password_but)
+        .add_systems(Update, password_but)
+        .add_systems(Update, join_send_rate_but)
+        .add_systems(Update, send_rate_but)
+        .add_systems(Update, tick_rate_but)
+        .add_systems(Update, join_player_name_but)
+        .add_systems(Update, player_name_but)
+        .add_systems(Update, max_players_but)
         .add_systems(Update, update_join_ip_input)
         .add_systems(Update, join_port_but)
         .add_systems(Update, host_port_but)
         .add_systems(Update, join_ip_but)
         .add_systems(Update, save_join_input)
+        .add_systems(Update, save_join_rate)
+        .add_systems(Update, save_join_password)
         .add_systems(Update, init_host_port_input_system)
         .add_systems(Update, init_join_host_port_input_system)
         .add_systems(Update, init_join_port_input_system)
@@ -87,6 +166,18 @@ impl Plugin for MainMenuPlugin{
         .add_systems(Update, init_enemies_per_camp_input_system)
         .add_systems(Update, init_map_seed_input_system)
         .add_systems(Update, init_eid_percentage_input_system)
+        .add_systems(Update, init_symmetry_input_system)
+        .add_systems(Update, init_map_file_input_system)
+        .add_systems(Update, init_map_height_input_system)
+        .add_systems(Update, init_map_width_input_system)
+        .add_systems(Update, init_join_password_input_system)
+        .add_systems(Update, init_password_input_system)
+        .add_systems(Update, init_join_send_rate_input_system)
+        .add_systems(Update, init_send_rate_input_system)
+        .add_systems(Update, init_tick_rate_input_system)
+        .add_systems(Update, init_join_player_name_input_system)
+        .add_systems(Update, init_player_name_input_system)
+        .add_systems(Update, init_max_players_input_system)
         .add_systems(Update, bevy::window::close_on_esc)
         .add_systems(Update, animate.run_if(in_state(AppState::MainMenu)))
         .add_systems(Startup, startup);
@@ -96,6 +187,7 @@ pub fn startup(mut commands: Commands) {
     commands.insert_resource( NetworkAddresses {
         host_port: String::new(), client_port: String::new(), ip: String::new(),
     });
+    commands.insert_resource(LocalName::load());
 }
 
 fn play_ambient(
@@ -0,0 +1,139 @@
+use std::net::{IpAddr, SocketAddr};
+use bevy::prelude::*;
+use rand::Rng;
+use crate::AppState;
+use crate::game::MapConfig;
+use crate::game::map::MapSeed;
+use crate::game::matchcode::{seed_from_text, MatchCode};
+use crate::game::player::{PlayerLeaveEvent, PlayerNames};
+use crate::net;
+use crate::net::chat::{Chat, SystemMessage, SYSTEM_ID};
+use crate::net::error::NetError;
+use crate::net::host::{Connections, Spectators};
+use crate::net::lobby::Lobby;
+use crate::net::packets::*;
+use crate::net::transport::Transport;
+
+// the important packets here go out a few times since there's no ack for them
+const REPEATS: usize = 3;
+
+/// a line typed into the chat box that starts with '/'
+#[derive(Event)]
+pub struct AdminCommand(pub String);
+
+/// ip addresses the host banned this session, they get Kicked as soon as they ask to connect.
+/// Any port, or a new one typed on the join page would get straight back in, so everyone behind the same NAT goes too
+#[derive(Resource, Default)]
+pub struct BanList(pub Vec<IpAddr>);
+
+fn send_repeated(bytes: &[u8], sock: &dyn Transport, addr: &SocketAddr) -> Result<(), NetError> {
+    for _ in 0..REPEATS {
+        send_buf(bytes, sock, addr).map_err(|e| NetError::Send(*addr, e))?;
+    }
+    Ok(())
+}
+
+/// Host only chat commands:
+///   /players          list everyone's id and name
+///   /kick <id>        drop a player
+///   /ban <id>         drop a player and refuse their whole ip address until the host quits
+///   /end              end the round now, straight to the leaderboard
+///   /restart [seed]   back to the lobby with everyone still connected, on a new map
+pub fn run_commands(
+    mut command_reader: EventReader<AdminCommand>,
+    is_host: Res<net::IsHost>,
+    time: Res<Time>,
+    sock: Res<net::Socket>,
+    mut conns: ResMut<Connections>,
+    spectators: Res<Spectators>,
+    mut names: ResMut<PlayerNames>,
+    mut ban_list: ResMut<BanList>,
+    mut lobby: ResMut<Lobby>,
+    mut seed: ResMut<MapSeed>,
+    mut map_config: ResMut<MapConfig>,
+    mut chat: ResMut<Chat>,
+    mut system_writer: EventWriter<SystemMessage>,
+    mut leave_writer: EventWriter<PlayerLeaveEvent>,
+    mut app_state_next_state: ResMut<NextState<AppState>>,
+) -> Result<(), NetError> {
+    let now = time.elapsed_seconds();
+    // the command still goes through for everyone else if someone can't be reached
+    let mut result = Ok(());
+    for ev in command_reader.iter() {
+        if !is_host.0 || sock.0.is_none() {
+            chat.push(SYSTEM_ID, "Only the host can use commands".to_string(), now);
+            continue;
+        }
+        let sock = sock.0.as_ref().unwrap();
+        let mut args = ev.0.split_whitespace();
+        let command = args.next().unwrap_or("");
+        let arg = args.next();
+        match command {
+            "/players" => {
+                let mut ids: Vec<u8> = names.0.keys().copied().collect();
+                ids.sort();
+                for id in ids {
+                    chat.push(SYSTEM_ID, format!("{}: {}", id, names.get(id)), now);
+                }
+            },
+            "/kick" | "/ban" => {
+                let id = arg.and_then(|arg| arg.parse::<u8>().ok());
+                let conn = id.and_then(|id| conns.0.iter().find(|conn| conn.player_id == id).copied());
+                if conn.is_none() {
+                    chat.push(SYSTEM_ID, format!("usage: {} <player id>, see /players", command), now);
+                    continue;
+                }
+                let conn = conn.unwrap();
+                let banned = command == "/ban";
+                let packet = Kicked { reason: if banned { "banned".to_string() } else { "kicked".to_string() } };
+                let mut bytes: Vec<u8> = Vec::new();
+                packet.to_buf(&mut bytes);
+                result = result.and(send_repeated(bytes.as_slice(), sock.as_ref(), &conn.addr));
+                if banned {
+                    ban_list.0.push(conn.addr.ip());
+                }
+                conns.0.retain(|c| c.addr != conn.addr);
+                sock.forget(&conn.addr);
+                system_writer.send(SystemMessage(format!("{} was {}", names.get(conn.player_id), if banned { "banned" } else { "kicked" })));
+                names.0.remove(&conn.player_id);
+                leave_writer.send(PlayerLeaveEvent(conn.player_id));
+            },
+            "/end" => {
+                for conn in conns.0.iter().chain(spectators.0.iter()) {
+                    for _ in 0..REPEATS {
+                        result = result.and(send_empty_packet(PacketType::RoundEnd, sock.as_ref(), &conn.addr).map(|_| ()).map_err(|e| NetError::Send(conn.addr, e)));
+                    }
+                }
+                app_state_next_state.set(AppState::GameOver);
+            },
+            "/restart" => {
+                // a restart only sends the seed, the rest of a match code has to be set in the lobby
+                if arg.is_some_and(|arg| MatchCode::decode(arg).is_some()) {
+                    chat.push(SYSTEM_ID, "usage: /restart [seed], match codes go in the lobby's seed box".to_string(), now);
+                    continue;
+                }
+                let new_seed = match arg {
+                    Some(arg) => seed_from_text(arg),
+                    None => rand::thread_rng().gen(),
+                };
+                seed.0 = new_seed;
+                map_config.map_seed = arg.map(|arg| arg.to_string()).unwrap_or(new_seed.to_string());
+                let packet = Restart { seed: new_seed };
+                let mut bytes: Vec<u8> = Vec::new();
+                packet.to_buf(&mut bytes);
+                for conn in conns.0.iter().chain(spectators.0.iter()) {
+                    result = result.and(send_repeated(bytes.as_slice(), sock.as_ref(), &conn.addr));
+                }
+                // everyone already agreed to play, the lobby just counts down on the new map
+                lobby.local_ready = true;
+                lobby.countdown = None;
+                for (_, ready) in &mut lobby.players {
+                    *ready = true;
+                }
+                app_state_next_state.set(AppState::Lobby);
+            },
+            _ => chat.push(SYSTEM_ID, format!("unknown command {}, try /players /kick /ban /end /restart", command), now),
+        }
+    }
+    result
+}
@@ -0,0 +1,337 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use bevy::prelude::*;
+use crate::game::movement::KeyBinds;
+use crate::net;
+use crate::net::admin::AdminCommand;
+use crate::net::error::NetError;
+use crate::net::host::{Connections, Spectators};
+use crate::net::packets::*;
+
+pub const MAX_CHAT_LEN: usize = 120;  // characters, longer messages get cut off
+pub const CHAT_LOG_LEN: usize = 8;  // lines kept on screen
+pub const SYSTEM_ID: u8 = 0xFF;  // ChatBroadcast from the host itself, joins, leaves, captures
+const RESEND_SECS: f32 = 0.3;  // how long to wait for a ChatAck before sending again
+const MAX_PENDING: usize = 32;  // per peer, so a client that stops acking doesn't pile up forever
+const SEEN_IDS: usize = 64;  // broadcast ids clients remember to drop resends
+const SPAM_LIMIT: usize = 3;  // messages a player can send per SPAM_WINDOW_SECS before the host drops them
+const SPAM_WINDOW_SECS: f32 = 5.;
+
+pub struct ChatLine {
+    pub from: u8,  // player id, SYSTEM_ID or SPECTATOR_ID
+    pub text: String,
+    pub time: f32,  // seconds since startup when it came in, the overlay fades it out after a while
+}
+
+/// What's on the chat overlay and what's being typed into it.
+#[derive(Resource, Default)]
+pub struct Chat {
+    pub lines: VecDeque<ChatLine>,
+    pub typing: Option<String>,  // Some while the chat box is open
+}
+
+impl Chat {
+    pub fn push(&mut self, from: u8, text: String, time: f32) {
+        self.lines.push_back(ChatLine { from, text, time });
+        while self.lines.len() > CHAT_LOG_LEN {
+            self.lines.pop_front();
+        }
+    }
+}
+
+/// Client side of the reliable channel. Messages go to the host one at a time, in order,
+/// and the front of the outbox gets resent until the host acks it.
+#[derive(Resource, Default)]
+pub struct ClientChat {
+    outbox: VecDeque<(u16, String)>,  // (seq, text)
+    next_seq: u16,
+    last_sent: Option<u16>,  // tick the front of the outbox was last sent on
+    seen: VecDeque<u16>,  // ChatBroadcast ids already shown
+}
+
+struct PendingBroadcast {
+    addr: SocketAddr,
+    id: u16,
+    bytes: Vec<u8>,
+    last_sent: Option<u16>,  // None until host_fixed first sends it
+}
+
+/// Host side of the reliable channel. Every broadcast is resent to each peer until that peer acks it.
+#[derive(Resource, Default)]
+pub struct HostChat {
+    next_id: u16,
+    pending: Vec<PendingBroadcast>,
+    last_seq: HashMap<SocketAddr, u16>,  // newest ChatSend seen from each client, older ones are resends
+    recent: HashMap<SocketAddr, VecDeque<u16>>,  // ticks each client's last messages came in on
+}
+
+/// something typed into the chat box on this computer
+#[derive(Event)]
+pub struct SendChatEvent(pub String);
+
+/// something the host announces to everyone, like a player joining
+#[derive(Event)]
+pub struct SystemMessage(pub String);
+
+/// a ChatSend received by the host
+#[derive(Event)]
+pub struct ChatSendEvent {
+    pub origin: SocketAddr,
+    pub packet: ChatSend,
+}
+
+/// a ChatBroadcast received by a client
+#[derive(Event)]
+pub struct ChatBroadcastEvent(pub ChatBroadcast);
+
+/// a ChatAck received by either side
+#[derive(Event)]
+pub struct ChatAckEvent {
+    pub origin: SocketAddr,
+    pub seq: u16,
+}
+
+/// drops control characters and cuts the message to MAX_CHAT_LEN, None if nothing is left
+pub fn clean_message(text: &str) -> Option<String> {
+    let text: String = text.chars().filter(|ch| !ch.is_control()).take(MAX_CHAT_LEN).collect();
+    let text = text.trim();
+    if text.is_empty() {
+        return None;
+    }
+    return Some(text.to_string());
+}
+
+pub fn reset(
+    mut chat: ResMut<Chat>,
+    mut client_chat: ResMut<ClientChat>,
+    mut host_chat: ResMut<HostChat>,
+) {
+    *chat = Chat::default();
+    *client_chat = ClientChat::default();
+    *host_chat = HostChat::default();
+}
+
+// run condition, keeps typed letters from moving the player around
+pub fn typing(chat: Res<Chat>) -> bool {
+    chat.typing.is_some()
+}
+
+/// The chat key opens the box, typing fills it and the chat key again sends it.
+/// Sending an empty box just closes it.
+pub fn input(
+    input: Res<Input<KeyCode>>,
+    key_binds: Res<KeyBinds>,
+    mut char_events: EventReader<ReceivedCharacter>,
+    mut chat: ResMut<Chat>,
+    mut send_writer: EventWriter<SendChatEvent>,
+    mut command_writer: EventWriter<AdminCommand>,
+) {
+    if chat.typing.is_none() {
+        char_events.clear();
+        if input.just_pressed(key_binds.chat) {
+            chat.typing = Some(String::new());
+        }
+        return;
+    }
+    if input.just_pressed(key_binds.chat) {
+        let text = chat.typing.take().unwrap();
+        if let Some(text) = clean_message(&text) {
+            if text.starts_with('/') {
+                command_writer.send(AdminCommand(text));
+            } else {
+                send_writer.send(SendChatEvent(text));
+            }
+        }
+        return;
+    }
+    let typed = chat.typing.as_mut().unwrap();
+    for ev in char_events.iter() {
+        if ev.char == '\u{8}' || ev.char == '\u{7f}' {
+            typed.pop();
+        } else if !ev.char.is_control() && typed.chars().count() < MAX_CHAT_LEN {
+            typed.push(ev.char);
+        }
+    }
+}
+
+impl HostChat {
+    // queues a broadcast for one peer, it goes out on the next host_fixed
+    fn queue(&mut self, addr: SocketAddr, id: u16, bytes: &[u8]) {
+        let pending: Vec<usize> = self.pending.iter().enumerate().filter(|(_, p)| p.addr == addr).map(|(i, _)| i).collect();
+        if pending.len() >= MAX_PENDING {
+            self.pending.remove(pending[0]);
+        }
+        self.pending.push(PendingBroadcast {
+            addr,
+            id,
+            bytes: bytes.to_vec(),
+            last_sent: None,
+        });
+    }
+
+    fn broadcast(&mut self, from: u8, text: &str, peers: &[SocketAddr]) {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        let packet = ChatBroadcast { id, from, text: text.to_string() };
+        let mut bytes: Vec<u8> = Vec::new();
+        packet.to_buf(&mut bytes);
+        for addr in peers {
+            self.queue(*addr, id, &bytes);
+        }
+    }
+
+    // true if this client has sent too much lately
+    fn spamming(&mut self, addr: SocketAddr, tick: u16, window: u16) -> bool {
+        let recent = self.recent.entry(addr).or_default();
+        while recent.front().is_some_and(|sent| tick.wrapping_sub(*sent) > window) {
+            recent.pop_front();
+        }
+        if recent.len() >= SPAM_LIMIT {
+            return true;
+        }
+        recent.push_back(tick);
+        return false;
+    }
+}
+
+/// Relays chat from clients, this computer and the game itself to everyone.
+pub fn host_update(
+    sock: Res<net::Socket>,
+    tick: Res<net::TickNum>,
+    tick_rate: Res<net::TickRate>,
+    time: Res<Time>,
+    conns: Res<Connections>,
+    spectators: Res<Spectators>,
+    mut chat: ResMut<Chat>,
+    mut host_chat: ResMut<HostChat>,
+    mut send_reader: EventReader<SendChatEvent>,
+    mut system_reader: EventReader<SystemMessage>,
+    mut received_reader: EventReader<ChatSendEvent>,
+    mut ack_reader: EventReader<ChatAckEvent>,
+) -> Result<(), NetError> {
+    if sock.0.is_none() { return Ok(()) }
+    let sock = sock.0.as_ref().unwrap();
+    let mut result = Ok(());
+    let peers: Vec<SocketAddr> = conns.0.iter().chain(spectators.0.iter()).map(|conn| conn.addr).collect();
+    let now = time.elapsed_seconds();
+    for ev in send_reader.iter() {
+        host_chat.broadcast(0, &ev.0, &peers);
+        chat.push(0, ev.0.clone(), now);
+    }
+    for ev in system_reader.iter() {
+        host_chat.broadcast(SYSTEM_ID, &ev.0, &peers);
+        chat.push(SYSTEM_ID, ev.0.clone(), now);
+    }
+    for ev in received_reader.iter() {
+        let from = conns.0.iter().chain(spectators.0.iter()).find(|conn| conn.addr == ev.origin);
+        if from.is_none() { continue }
+        let from = from.unwrap().player_id;
+        // always ack, even resends and spam, so the client stops sending it
+        let ack = ChatAck { seq: ev.packet.seq };
+        let mut bytes: Vec<u8> = Vec::new();
+        ack.to_buf(&mut bytes);
+        result = result.and(send_buf(bytes.as_slice(), sock.as_ref(), &ev.origin).map(|_| ()).map_err(|e| NetError::Send(ev.origin, e)));
+        let last = host_chat.last_seq.get(&ev.origin).copied();
+        if last.is_some_and(|last| ev.packet.seq <= last) { continue }
+        host_chat.last_seq.insert(ev.origin, ev.packet.seq);
+        if host_chat.spamming(ev.origin, tick.0, tick_rate.ticks(SPAM_WINDOW_SECS)) {
+            host_chat.broadcast(SYSTEM_ID, "You're sending messages too fast", &[ev.origin]);
+            continue;
+        }
+        let text = clean_message(&ev.packet.text);
+        if text.is_none() { continue }
+        let text = text.unwrap();
+        host_chat.broadcast(from, &text, &peers);
+        chat.push(from, text, now);
+    }
+    for ev in ack_reader.iter() {
+        host_chat.pending.retain(|p| !(p.addr == ev.origin && p.id == ev.seq));
+    }
+    result
+}
+
+/// resends every broadcast that hasn't been acked yet, and forgets peers that left
+pub fn host_fixed(
+    sock: Res<net::Socket>,
+    tick: Res<net::TickNum>,
+    tick_rate: Res<net::TickRate>,
+    conns: Res<Connections>,
+    spectators: Res<Spectators>,
+    mut host_chat: ResMut<HostChat>,
+) -> Result<(), NetError> {
+    if sock.0.is_none() { return Ok(()) }
+    let sock = sock.0.as_ref().unwrap();
+    let mut result = Ok(());
+    let connected = |addr: &SocketAddr| conns.0.iter().chain(spectators.0.iter()).any(|conn| conn.addr == *addr);
+    host_chat.pending.retain(|p| connected(&p.addr));
+    host_chat.last_seq.retain(|addr, _| connected(addr));
+    host_chat.recent.retain(|addr, _| connected(addr));
+    for pending in host_chat.pending.iter_mut() {
+        if pending.last_sent.is_some_and(|sent| tick.0.wrapping_sub(sent) < tick_rate.ticks(RESEND_SECS)) { continue }
+        pending.last_sent = Some(tick.0);
+        result = result.and(send_buf(pending.bytes.as_slice(), sock.as_ref(), &pending.addr).map(|_| ()).map_err(|e| NetError::Send(pending.addr, e)));
+    }
+    result
+}
+
+/// Queues what we typed for the host and shows what the host relayed.
+pub fn client_update(
+    sock: Res<net::Socket>,
+    time: Res<Time>,
+    mut chat: ResMut<Chat>,
+    mut client_chat: ResMut<ClientChat>,
+    mut send_reader: EventReader<SendChatEvent>,
+    mut broadcast_reader: EventReader<ChatBroadcastEvent>,
+    mut ack_reader: EventReader<ChatAckEvent>,
+) -> Result<(), NetError> {
+    if sock.0.is_none() { return Ok(()) }
+    let sock = sock.0.as_ref().unwrap();
+    let host = sock.peer_addr().map_err(|_| NetError::NotConnected)?;
+    for ev in send_reader.iter() {
+        // seq starts at 1 so the host can tell a fresh client from a resend
+        client_chat.next_seq = client_chat.next_seq.wrapping_add(1);
+        let seq = client_chat.next_seq;
+        client_chat.outbox.push_back((seq, ev.0.clone()));
+    }
+    for ev in ack_reader.iter() {
+        if client_chat.outbox.front().is_some_and(|(seq, _)| *seq == ev.seq) {
+            client_chat.outbox.pop_front();
+            client_chat.last_sent = None;
+        }
+    }
+    for ev in broadcast_reader.iter() {
+        let packet = &ev.0;
+        let ack = ChatAck { seq: packet.id };
+        let mut bytes: Vec<u8> = Vec::new();
+        ack.to_buf(&mut bytes);
+        send_buf(bytes.as_slice(), sock.as_ref(), &host).map_err(|e| NetError::Send(host, e))?;
+        if client_chat.seen.contains(&packet.id) { continue }
+        client_chat.seen.push_back(packet.id);
+        if client_chat.seen.len() > SEEN_IDS {
+            client_chat.seen.pop_front();
+        }
+        chat.push(packet.from, packet.text.clone(), time.elapsed_seconds());
+    }
+    Ok(())
+}
+
+/// sends the oldest unacked message, again every RESEND_SECS until the host acks it
+pub fn client_fixed(
+    sock: Res<net::Socket>,
+    tick: Res<net::TickNum>,
+    tick_rate: Res<net::TickRate>,
+    mut client_chat: ResMut<ClientChat>,
+) -> Result<(), NetError> {
+    if sock.0.is_none() { return Ok(()) }
+    let sock = sock.0.as_ref().unwrap();
+    if client_chat.outbox.is_empty() { return Ok(()) }
+    if client_chat.last_sent.is_some_and(|sent| tick.0.wrapping_sub(sent) < tick_rate.ticks(RESEND_SECS)) { return Ok(()) }
+    client_chat.last_sent = Some(tick.0);
+    let (seq, text) = client_chat.outbox.front().unwrap();
+    let packet = ChatSend { seq: *seq, text: text.clone() };
+    let mut bytes: Vec<u8> = Vec::new();
+    packet.to_buf(&mut bytes);
+    let host = sock.peer_addr().map_err(|_| NetError::NotConnected)?;
+    send_buf(bytes.as_slice(), sock.as_ref(), &host).map_err(|e| NetError::Send(host, e))?;
+    Ok(())
+}
@@ -1,31 +1,55 @@
+use std::io::ErrorKind;
 use std::net::*;
 use std::str::FromStr;
 use bevy::prelude::*;
-use crate::{menus, net};
+use bevy::ecs::system::SystemParam;
+use crate::{menus, net, AppState};
 use crate::game::buffers::{DirBuffer, EventBuffer, PosBuffer};
 use crate::game::components::{Camp, CampEnemies, CampStatus, Health, ItemChest, PowerUp};
-use crate::game::map::MapSeed;
-use crate::game::player::{LocalPlayer, SetIdEvent};
+use crate::game::map::{MapSeed, MapSize, NumCamps, SpawnSettings};
+use crate::game::mapfile::{MapChoice, MapFile};
+use crate::game::player::{LocalPlayer, PlayerLeaveEvent, PlayerNames, SetIdEvent};
 use crate::game::PowerupAtlas;
 use crate::net::{MAGIC_NUMBER, MAX_DATAGRAM_SIZE};
 use crate::net::packets::*;
+use crate::net::conditioner::NetSim;
+use crate::net::error::NetError;
+use crate::net::chat::{ChatAckEvent, ChatBroadcastEvent};
+use crate::net::lobby::Lobby;
+use crate::net::migrate::Migration;
+use crate::net::secure::MatchKey;
+use crate::net::transport::{self, LoopbackNetwork};
 
 pub fn connect(
     addresses: Res<menus::NetworkAddresses>,
-    mut sock: ResMut<net::Socket>
-) {
+    mut sock: ResMut<net::Socket>,
+    spectator: Res<net::IsSpectator>,
+    local_name: Res<menus::LocalName>,
+    send_rate: Res<net::SendRate>,
+    loopback: Option<Res<LoopbackNetwork>>,
+    sim: Res<NetSim>,
+    key: Res<MatchKey>,
+) -> Result<(), NetError> {
+    // check everything typed in before opening anything
+    let client_port = u16::from_str(&addresses.client_port).map_err(|_| NetError::BadPort(addresses.client_port.clone()))?;
+    let host_ip = Ipv4Addr::from_str(&addresses.ip).map_err(|_| NetError::BadIp(addresses.ip.clone()))?;
+    let host_port = u16::from_str(&addresses.host_port).map_err(|_| NetError::BadPort(addresses.host_port.clone()))?;
     // I think if you communicate over LAN, you have to use local ip rather than loopback ip
     let client_ip = Ipv4Addr::new(0,0,0,0);
-    let client_port = u16::from_str(&addresses.client_port).expect("bad client port");
     let client_addr = SocketAddr::new(IpAddr::from(client_ip), client_port);
-    sock.0 = Some(UdpSocket::bind(client_addr).expect("client port in use"));
-    sock.0.as_mut().unwrap().set_nonblocking(true).expect("can't set nonblocking");
-    let host_ip = Ipv4Addr::from_str(&addresses.ip).expect("bad ip");
-    let host_port = u16::from_str(&addresses.host_port).expect("bad host port");
+    sock.0 = Some(transport::bind(client_addr, loopback.as_deref(), &sim, &key).map_err(|e| NetError::Bind(client_port, e))?);
     let host_addr = SocketAddr::new(IpAddr::from(host_ip), host_port);
     let host = sock.0.as_mut().unwrap();
-    host.connect(host_addr).expect("can't connect to host");
-    send_empty_packet(PacketType::ConnectionRequest, host, &host_addr).expect("failed to request connection");
+    host.connect(host_addr).map_err(|e| NetError::Connect(host_addr, e))?;
+    let packet = ConnectionRequest {
+        spectator: spectator.0,
+        name: local_name.0.clone(),
+        send_rate: send_rate.0,
+    };
+    let mut bytes: Vec<u8> = Vec::new();
+    packet.to_buf(&mut bytes);
+    send_buf(bytes.as_slice(), host.as_ref(), &host_addr).map_err(|e| NetError::Connect(host_addr, e))?;
+    Ok(())
 }
 
 pub fn disconnect(mut sock: ResMut<net::Socket>) {
@@ -37,14 +61,15 @@ pub fn fixed(
     tick: Res<net::TickNum>,
     players: Query<(&PosBuffer, &EventBuffer, &DirBuffer), With<LocalPlayer>>,
     ack: Res<net::Ack>,
-) {
-    if sock.0.is_none() { return }
+) -> Result<(), NetError> {
+    let _span = trace_span!("client_fixed", tick = tick.0).entered();
+    if sock.0.is_none() { return Ok(()) }
     let sock = sock.0.as_mut().unwrap();
     let player = players.get_single();
-    if player.is_err() { return }
+    if player.is_err() { return Ok(()) }
     let (pb, eb, db) = player.unwrap();
     let pos = pb.0.get(tick.0);
-    if pos.is_none() { println!("client::fixed:posnone"); return }
+    if pos.is_none() { trace!("client::fixed:posnone"); return Ok(()) }
     let pos = pos.unwrap();
     let dir = if db.0.get(tick.0).is_none() { 0.0 } else {db.0.get(tick.0).unwrap()};
     let events = eb.0.get(tick.0);
@@ -61,108 +86,259 @@ pub fn fixed(
     };
     let mut bytes: Vec<u8> = Vec::new();
     packet.to_buf(&mut bytes);
-    send_buf(bytes.as_slice(), sock, &sock.peer_addr().expect("Sock not connected during fixed")).expect("ClientTick send failed");
+    let host = sock.peer_addr().map_err(|_| NetError::NotConnected)?;
+    send_buf(bytes.as_slice(), sock.as_ref(), &host).map_err(|e| NetError::Send(host, e))?;
+    Ok(())
+}
+
+/// Everything needed to apply a HostTick to the local world.
+/// Shared by the client receiving snapshots over the network and the replay player reading them from a file.
+#[derive(SystemParam)]
+pub struct SnapshotWriter<'w, 's> {
+    commands: Commands<'w, 's>,
+    player_writer: EventWriter<'w, PlayerTickEvent>,
+    enemy_writer: EventWriter<'w, EnemyTickEvent>,
+    powerup_atlas: Res<'w, PowerupAtlas>,
+    powerups: Query<'w, 's, Entity, With<PowerUp>>,
+    camps: Query<'w, 's, (&'static Camp, &'static mut CampStatus, &'static mut CampEnemies)>,
+    chests: Query<'w, 's, (&'static ItemChest, &'static mut Health)>,
+}
+
+impl SnapshotWriter<'_, '_> {
+    pub fn apply(&mut self, packet: HostTick) {
+        for tick in packet.players {
+            self.player_writer.send(PlayerTickEvent {
+                seq_num: packet.seq_num,
+                tick
+            })
+        }
+        for tick in packet.enemies {
+            self.enemy_writer.send(EnemyTickEvent {
+                seq_num: packet.seq_num,
+                tick
+            })
+        }
+        // the powerups of a tick can be spread over its parts, only the first one clears out the last tick's
+        if packet.part == 0 {
+            for e in &mut self.powerups {
+                self.commands.entity(e).despawn();
+            }
+        }
+        for (ptype, pos) in packet.powerups {
+            self.commands.spawn((
+                SpriteSheetBundle{
+                    texture_atlas: self.powerup_atlas.handle.clone(),
+                    sprite: TextureAtlasSprite {
+                        index: self.powerup_atlas.coord_to_index(0, ptype as i32),
+                        ..Default::default()
+                    },
+                    transform: Transform {
+                        translation: Vec3 { x: pos.x, y: pos.y, z: 0.0 },
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                PowerUp(ptype),
+                ));
+        }
+        for (camp_id, count) in packet.camps {
+            for (camp, mut status, mut campcount) in self.camps.iter_mut() {
+                if camp.0 == camp_id {
+                    status.0 = true;
+                    campcount.current_enemies = count;
+                }
+            }
+        }
+        for (net_ic, net_hp) in packet.chests {
+            for (ic, mut hp) in &mut self.chests {
+                if ic.id == net_ic {
+                    hp.current = net_hp;
+                }
+            }
+        }
+    }
+
+    /// marks every camp cleared, the next snapshot re-activates the ones that are still up
+    pub fn clear_camps(&mut self) {
+        for (_, mut status, _) in self.camps.iter_mut() {
+            status.0 = false;
+        }
+    }
 }
 
 pub fn update(
-    mut commands: Commands,
     mut sock: ResMut<net::Socket>,
-    mut player_writer: EventWriter<PlayerTickEvent>,
-    mut enemy_writer: EventWriter<EnemyTickEvent>,
+    mut snapshots: SnapshotWriter,
     mut id_writer: EventWriter<SetIdEvent>,
     mut tick_num: ResMut<net::TickNum>,
+    mut tick_rate: ResMut<net::TickRate>,
     mut seed: ResMut<MapSeed>,
-    powerup_atlas: Res<PowerupAtlas>,
-    mut powerups: Query<Entity, With<PowerUp>>,
-    mut camps: Query<(&Camp, &mut CampStatus, &mut CampEnemies)>,
-    mut chests: Query<(&ItemChest, &mut Health)>
-
-) {
-    if sock.0.is_none() { return }
+    (mut num_camps, mut map_size, mut spawn_settings, mut map_choice): (ResMut<NumCamps>, ResMut<MapSize>, ResMut<SpawnSettings>, ResMut<MapChoice>),
+    map_files: Res<Assets<MapFile>>,
+    mut lobby: ResMut<Lobby>,
+    mut migration: ResMut<Migration>,
+    mut names: ResMut<PlayerNames>,
+    mut leave_writer: EventWriter<PlayerLeaveEvent>,
+    mut chat_writer: EventWriter<ChatBroadcastEvent>,
+    mut chat_ack_writer: EventWriter<ChatAckEvent>,
+    app_state: Res<State<AppState>>,
+    mut app_state_next_state: ResMut<NextState<AppState>>,
+) -> Result<(), NetError> {
+    let _span = trace_span!("client_update", tick = tick_num.0).entered();
+    if sock.0.is_none() { return Ok(()) }
     let sock = sock.0.as_mut().unwrap();
     loop {
         let mut buf = [0; MAX_DATAGRAM_SIZE];
-        if sock.peek(&mut buf).is_err() { break }
-        sock.recv(&mut buf).unwrap();
+        let recv = sock.recv_from(&mut buf);
+        if recv.as_ref().is_err_and(|e| e.kind() == ErrorKind::PermissionDenied) {
+            return Err(NetError::Refused);
+        }
+        if recv.is_err() { break }
+        let (len, origin) = recv.unwrap();
+        if len < 3 { continue }
+        let buf = &buf[..len];
         let magic = u16::from_be_bytes(buf[0..2].try_into().unwrap());
         if magic != MAGIC_NUMBER { break; }
+        migration.silent_ticks = 0;
         let pt = u8::from_be_bytes(buf[2..3].try_into().unwrap());
         match pt {
             pt if pt == PacketType::ConnectionResponse as u8 => {
                 let packet = ConnectionResponse::from_buf(&buf[3..]);
                 if packet.is_err() {
-                    println!("Malformed ConnectionResponse Received!");
+                    warn!("Malformed ConnectionResponse Received!");
                     continue;
                 }
                 let packet = packet.unwrap();
-                println!("ConnectionResponse received");
+                info!("ConnectionResponse received");
+                if !packet.map_choice.is_generated() && packet.map_choice.find(&map_files).is_none() {
+                    return Err(NetError::MissingMap(packet.map_choice.name));
+                }
                 seed.0 = packet.seed;
+                tick_rate.0 = packet.tick_rate;
+                *map_size = MapSize::new(packet.map_width as usize, packet.map_height as usize);
+                *spawn_settings = packet.spawn_settings;
+                *map_choice = packet.map_choice;
                 id_writer.send(SetIdEvent(packet.player_id));
             },
             pt if pt == PacketType::HostTick as u8 => {
                 let packet = HostTick::from_buf(&buf[3..]);
                 if packet.is_err() {
-                    println!("Malformed HostTick Received!");
+                    warn!("Malformed HostTick Received!");
                     continue;
                 }
                 let packet = packet.unwrap();
-                for tick in packet.players {
-                    player_writer.send(PlayerTickEvent {
-                        seq_num: packet.seq_num,
-                        tick
-                    })
-                }
-                for tick in packet.enemies {
-                    enemy_writer.send(EnemyTickEvent {
-                        seq_num: packet.seq_num,
-                        tick
-                    })
-                }
-                for e in &mut powerups {
-                    commands.entity(e).despawn();
-                }
-                for (ptype, pos) in packet.powerups {
-                    commands.spawn((
-                        SpriteSheetBundle{
-                            texture_atlas: powerup_atlas.handle.clone(),
-                            sprite: TextureAtlasSprite {
-                                index: powerup_atlas.coord_to_index(0, ptype as i32),
-                                ..Default::default()
-                            },
-                            transform: Transform {
-                                translation: Vec3 { x: pos.x, y: pos.y, z: 0.0 },
-                                ..Default::default()
-                            },
-                            ..Default::default()
-                        },
-                        PowerUp(ptype),
-                        ));
-                }
-                for (camp_id, count) in packet.camps {
-                    for (camp, mut status, mut campcount) in camps.iter_mut() {
-                        if camp.0 == camp_id {
-                            status.0 = true;
-                            campcount.current_enemies = count;
-                        }
-                    }
+                let seq_num = packet.seq_num;
+                if *app_state.get() == AppState::Lobby {
+                    // ticks still in flight from before a restart, the lobby is already running
+                    if !lobby.players.is_empty() { continue }
+                    // we joined after the round started, skip the lobby
+                    app_state_next_state.set(AppState::Game);
+                    continue;
+                }
+                migration.rejoining = false;
+                snapshots.apply(packet);
+                if seq_num > tick_num.0 {
+                    debug!("re-syncing: changing tick from {} to {}", tick_num.0, seq_num);
+                    tick_num.0 = seq_num;
+                }
+            },
+            pt if pt == PacketType::LobbyState as u8 => {
+                let packet = LobbyState::from_buf(&buf[3..]);
+                if packet.is_err() {
+                    warn!("Malformed LobbyState Received!");
+                    continue;
                 }
-                for (net_ic, net_hp) in packet.chests {
-                    for (ic, mut hp) in &mut chests {
-                        if ic.id == net_ic {
-                            hp.current = net_hp;
+                let packet = packet.unwrap();
+                if !packet.map_choice.is_generated() && packet.map_choice.find(&map_files).is_none() {
+                    return Err(NetError::MissingMap(packet.map_choice.name));
+                }
+                let packet_size = MapSize::new(packet.map_width as usize, packet.map_height as usize);
+                if packet.seed != seed.0 || packet.num_camps != num_camps.0 || packet_size != *map_size
+                    || packet.spawn_settings != *spawn_settings || packet.map_choice != *map_choice {
+                    // host changed the map, make everyone look at it again before starting
+                    lobby.local_ready = false;
+                }
+                seed.0 = packet.seed;
+                num_camps.0 = packet.num_camps;
+                *map_size = packet_size;
+                *spawn_settings = packet.spawn_settings;
+                *map_choice = packet.map_choice;
+                lobby.players = packet.players;
+                lobby.countdown = packet.countdown;
+            },
+            pt if pt == PacketType::NameList as u8 => {
+                let packet = NameList::from_buf(&buf[3..]);
+                if packet.is_err() {
+                    warn!("Malformed NameList Received!");
+                    continue;
+                }
+                let packet = packet.unwrap();
+                if packet.names.iter().any(|(id, name)| names.0.get(id) != Some(name)) || packet.names.len() != names.0.len() {
+                    // anyone missing from the list left or got kicked
+                    for id in names.0.keys() {
+                        if !packet.names.iter().any(|(new_id, _)| new_id == id) {
+                            leave_writer.send(PlayerLeaveEvent(*id));
                         }
                     }
+                    names.0 = packet.names.into_iter().collect();
+                }
+            },
+            pt if pt == PacketType::PeerList as u8 => {
+                let packet = PeerList::from_buf(&buf[3..]);
+                if packet.is_err() {
+                    warn!("Malformed PeerList Received!");
+                    continue;
                 }
-                if packet.seq_num > tick_num.0 {
-                    println!("re-syncing: changing tick from {} to {}", tick_num.0, packet.seq_num);
-                    tick_num.0 = packet.seq_num;
+                let packet = packet.unwrap();
+                migration.peers = packet.peers;
+                migration.max_players = packet.max_players;
+            },
+            pt if pt == PacketType::ChatBroadcast as u8 => {
+                let packet = ChatBroadcast::from_buf(&buf[3..]);
+                if packet.is_err() {
+                    warn!("Malformed ChatBroadcast Received!");
+                    continue;
+                }
+                chat_writer.send(ChatBroadcastEvent(packet.unwrap()));
+            },
+            pt if pt == PacketType::ChatAck as u8 => {
+                let packet = ChatAck::from_buf(&buf[3..]);
+                if packet.is_err() {
+                    warn!("Malformed ChatAck Received!");
+                    continue;
+                }
+                chat_ack_writer.send(ChatAckEvent { origin, seq: packet.unwrap().seq });
+            },
+            pt if pt == PacketType::Kicked as u8 => {
+                let packet = Kicked::from_buf(&buf[3..]);
+                if packet.is_err() {
+                    warn!("Malformed Kicked Received!");
+                    continue;
+                }
+                return Err(NetError::Kicked(packet.unwrap().reason));
+            },
+            pt if pt == PacketType::RoundEnd as u8 => {
+                if *app_state.get() == AppState::Game {
+                    app_state_next_state.set(AppState::GameOver);
+                }
+            },
+            pt if pt == PacketType::Restart as u8 => {
+                let packet = Restart::from_buf(&buf[3..]);
+                if packet.is_err() {
+                    warn!("Malformed Restart Received!");
+                    continue;
                 }
+                if *app_state.get() != AppState::Game { continue }  // one of the repeats
+                seed.0 = packet.unwrap().seed;
+                lobby.local_ready = true;
+                lobby.countdown = None;
+                app_state_next_state.set(AppState::Lobby);
             },
             pt if pt == PacketType::ServerFull as u8 => {
-                println!("Server is full!");
-                // TODO stop trying to connect?
+                return Err(NetError::ServerFull);
             },
-            _ => panic!("Server sent some wacky packet that doesn't make sense")
+            _ => warn!("Unexpected packet type {} from the host, dropped", pt),
         }
     }
+    Ok(())
 }
@@ -0,0 +1,423 @@
+use std::collections::VecDeque;
+use std::io::{Error, ErrorKind, Result};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use bevy::prelude::*;
+use rand::Rng;
+use rand_chacha::ChaChaRng;
+use rand_chacha::rand_core::SeedableRng;
+use crate::cli_arg;
+use crate::net::MAX_DATAGRAM_SIZE;
+use crate::net::transport::Transport;
+
+/// extra time a reordered datagram is held back on top of its normal delay
+const REORDER_HOLD_MS: u64 = 40;
+
+/// Fake bad network settings. Every value is applied separately to each direction,
+/// so latency_ms is one-way and a conditioned client sees a round trip of about twice that.
+#[derive(Clone, Copy, PartialEq)]
+pub struct Conditions {
+    pub latency_ms: u64,
+    pub jitter_ms: u64,
+    pub loss: f32,  // chances are 0.0 - 1.0
+    pub duplicate: f32,
+    pub reorder: f32,
+}
+
+impl Conditions {
+    pub const OFF: Conditions = Conditions { latency_ms: 0, jitter_ms: 0, loss: 0., duplicate: 0., reorder: 0. };
+
+    /// cycled through with the debug key, (name, conditions)
+    pub const PRESETS: [(&'static str, Conditions); 5] = [
+        ("off", Conditions::OFF),
+        ("lan", Conditions { latency_ms: 5, jitter_ms: 2, loss: 0.005, duplicate: 0., reorder: 0. }),
+        ("wifi", Conditions { latency_ms: 30, jitter_ms: 15, loss: 0.02, duplicate: 0.005, reorder: 0.01 }),
+        ("mobile", Conditions { latency_ms: 80, jitter_ms: 40, loss: 0.05, duplicate: 0.01, reorder: 0.05 }),
+        ("awful", Conditions { latency_ms: 200, jitter_ms: 100, loss: 0.2, duplicate: 0.05, reorder: 0.1 }),
+    ];
+
+    /// reads --latency, --jitter (ms) and --loss, --dup, --reorder (percent) off the command line
+    pub fn from_args() -> Conditions {
+        let ms = |flag: &str| cli_arg(flag).and_then(|v| v.parse::<u64>().ok()).unwrap_or(0);
+        let pct = |flag: &str| cli_arg(flag).and_then(|v| v.parse::<f32>().ok()).unwrap_or(0.).clamp(0., 100.) / 100.;
+        Conditions {
+            latency_ms: ms("--latency"),
+            jitter_ms: ms("--jitter"),
+            loss: pct("--loss"),
+            duplicate: pct("--dup"),
+            reorder: pct("--reorder"),
+        }
+    }
+}
+
+/// A clock that only moves when told to, so tests can step time along with the Apps.
+#[derive(Clone, Default)]
+pub struct ManualClock(Arc<AtomicU64>);
+
+impl ManualClock {
+    pub fn advance(&self, ms: u64) {
+        self.0.fetch_add(ms, Ordering::SeqCst);
+    }
+}
+
+enum Clock {
+    Real(Instant),
+    Manual(ManualClock),
+}
+
+impl Clock {
+    fn now_ms(&self) -> u64 {
+        match self {
+            Clock::Real(start) => start.elapsed().as_millis() as u64,
+            Clock::Manual(clock) => clock.0.load(Ordering::SeqCst),
+        }
+    }
+}
+
+/// Shared handle to the simulator settings and rng.
+/// The same NetSim is inside the resource and every ConditionedTransport, so changing
+/// the conditions from the debug key or a test takes effect on the live socket right away.
+#[derive(Resource, Clone)]
+pub struct NetSim(Arc<Mutex<SimState>>);
+
+struct SimState {
+    conditions: Conditions,
+    rng: ChaChaRng,
+    clock: Clock,
+}
+
+impl NetSim {
+    pub fn new(conditions: Conditions, seed: u64) -> NetSim {
+        NetSim(Arc::new(Mutex::new(SimState {
+            conditions,
+            rng: ChaChaRng::seed_from_u64(seed),
+            clock: Clock::Real(Instant::now()),
+        })))
+    }
+
+    /// same as new but time only passes through the returned clock
+    pub fn new_manual(conditions: Conditions, seed: u64) -> (NetSim, ManualClock) {
+        let clock = ManualClock::default();
+        let sim = NetSim::new(conditions, seed);
+        sim.0.lock().unwrap().clock = Clock::Manual(clock.clone());
+        (sim, clock)
+    }
+
+    /// built from the command line flags, --net-seed picks the rng seed
+    pub fn from_args() -> NetSim {
+        let seed = cli_arg("--net-seed").and_then(|v| v.parse::<u64>().ok()).unwrap_or(0);
+        NetSim::new(Conditions::from_args(), seed)
+    }
+
+    pub fn conditions(&self) -> Conditions {
+        self.0.lock().unwrap().conditions
+    }
+
+    pub fn set_conditions(&self, conditions: Conditions) {
+        self.0.lock().unwrap().conditions = conditions;
+    }
+
+    /// decides the fate of one datagram, returns the times (ms) at which copies of it arrive
+    fn schedule(&self) -> Vec<u64> {
+        let mut state = self.0.lock().unwrap();
+        let c = state.conditions;
+        let now = state.clock.now_ms();
+        let mut arrivals = Vec::new();
+        if c == Conditions::OFF {
+            arrivals.push(now);
+            return arrivals;
+        }
+        if state.rng.gen::<f32>() < c.loss {
+            return arrivals;
+        }
+        let copies = if state.rng.gen::<f32>() < c.duplicate { 2 } else { 1 };
+        for _ in 0..copies {
+            let jitter = if c.jitter_ms > 0 { state.rng.gen_range(0..=c.jitter_ms * 2) } else { 0 };
+            let mut delay = (c.latency_ms + jitter).saturating_sub(c.jitter_ms);
+            if state.rng.gen::<f32>() < c.reorder {
+                delay += REORDER_HOLD_MS;
+            }
+            arrivals.push(now + delay);
+        }
+        arrivals
+    }
+
+    fn now_ms(&self) -> u64 {
+        self.0.lock().unwrap().clock.now_ms()
+    }
+}
+
+/// datagram waiting to be let through, (arrival ms, address, bytes)
+type Delayed = (u64, SocketAddr, Vec<u8>);
+
+/// Sits in front of another transport and delays, drops, duplicates and reorders datagrams in both directions.
+pub struct ConditionedTransport {
+    inner: Box<dyn Transport>,
+    sim: NetSim,
+    outgoing: Mutex<VecDeque<Delayed>>,
+    incoming: Mutex<VecDeque<Delayed>>,
+}
+
+impl ConditionedTransport {
+    pub fn new(inner: Box<dyn Transport>, sim: NetSim) -> ConditionedTransport {
+        ConditionedTransport {
+            inner,
+            sim,
+            outgoing: Mutex::new(VecDeque::new()),
+            incoming: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    fn delay(&self, queue: &Mutex<VecDeque<Delayed>>, addr: SocketAddr, buf: &[u8]) {
+        let mut queue = queue.lock().unwrap();
+        for arrival in self.sim.schedule() {
+            // keep the queue sorted by arrival so the front is always the next one due
+            let i = queue.iter().position(|(t, _, _)| *t > arrival).unwrap_or(queue.len());
+            queue.insert(i, (arrival, addr, buf.to_vec()));
+        }
+    }
+
+    fn pop_due(&self, queue: &Mutex<VecDeque<Delayed>>) -> Option<(SocketAddr, Vec<u8>)> {
+        let now = self.sim.now_ms();
+        let mut queue = queue.lock().unwrap();
+        if queue.front().is_some_and(|(t, _, _)| *t <= now) {
+            let (_, addr, buf) = queue.pop_front().unwrap();
+            return Some((addr, buf));
+        }
+        None
+    }
+
+    /// hands every outgoing datagram whose delay has passed to the real transport, and returns the first send that failed
+    fn flush(&self) -> Result<()> {
+        let mut result = Ok(());
+        while let Some((peer, buf)) = self.pop_due(&self.outgoing) {
+            let sent = self.send_now(&buf, &peer);
+            if result.is_ok() {
+                result = sent.map(|_| ());
+            }
+        }
+        result
+    }
+
+    // a connected UdpSocket has to use send, send_to on one fails with EISCONN on macOS and the BSDs
+    fn send_now(&self, buf: &[u8], peer: &SocketAddr) -> Result<usize> {
+        if self.inner.peer_addr().is_ok_and(|connected| connected == *peer) {
+            return self.inner.send(buf);
+        }
+        self.inner.send_to(buf, peer)
+    }
+}
+
+impl Transport for ConditionedTransport {
+    fn connect(&mut self, peer: SocketAddr) -> Result<()> {
+        self.inner.connect(peer)
+    }
+
+    fn send(&self, buf: &[u8]) -> Result<usize> {
+        let peer = self.inner.peer_addr()?;
+        self.send_to(buf, &peer)
+    }
+
+    fn send_to(&self, buf: &[u8], peer: &SocketAddr) -> Result<usize> {
+        if self.sim.conditions() == Conditions::OFF {
+            // nothing to hold back, anything still queued from before conditions were turned off goes first
+            self.flush()?;
+            return self.send_now(buf, peer);
+        }
+        self.delay(&self.outgoing, *peer, buf);
+        self.flush()?;
+        Ok(buf.len())
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr)> {
+        self.flush()?;
+        if self.sim.conditions() == Conditions::OFF && self.incoming.lock().unwrap().is_empty() {
+            return self.inner.recv_from(buf);
+        }
+        let mut scratch = [0; MAX_DATAGRAM_SIZE];
+        loop {
+            match self.inner.recv_from(&mut scratch) {
+                Ok((len, origin)) => self.delay(&self.incoming, origin, &scratch[..len]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+        let due = self.pop_due(&self.incoming);
+        if due.is_none() {
+            return Err(Error::from(ErrorKind::WouldBlock));
+        }
+        let (origin, datagram) = due.unwrap();
+        let len = datagram.len().min(buf.len());
+        buf[..len].copy_from_slice(&datagram[..len]);
+        Ok((len, origin))
+    }
+
+    fn local_addr(&self) -> Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    fn peer_addr(&self) -> Result<SocketAddr> {
+        self.inner.peer_addr()
+    }
+
+    fn forget(&self, peer: &SocketAddr) {
+        // whatever is still held back for them goes now, it can't be sealed once their session is gone
+        let held: Vec<Vec<u8>> = {
+            let mut outgoing = self.outgoing.lock().unwrap();
+            let (held, rest): (VecDeque<Delayed>, VecDeque<Delayed>) = outgoing.drain(..).partition(|(_, addr, _)| addr == peer);
+            *outgoing = rest;
+            held.into_iter().map(|(_, _, buf)| buf).collect()
+        };
+        for buf in held {
+            let _ = self.send_now(&buf, peer);
+        }
+        self.inner.forget(peer);
+    }
+}
+
+/// F5 cycles through the network presets while playing
+pub fn cycle_preset(
+    input: Res<Input<KeyCode>>,
+    sim: Res<NetSim>,
+) {
+    if !input.just_pressed(KeyCode::F5) { return }
+    let current = sim.conditions();
+    let i = Conditions::PRESETS.iter().position(|(_, c)| *c == current).map_or(0, |i| i + 1);
+    let (name, conditions) = Conditions::PRESETS[i % Conditions::PRESETS.len()];
+    sim.set_conditions(conditions);
+    info!("network conditions: {} ({}ms +-{}ms, {:.1}% loss, {:.1}% dup, {:.1}% reorder)",
+             name, conditions.latency_ms, conditions.jitter_ms,
+             conditions.loss * 100., conditions.duplicate * 100., conditions.reorder * 100.);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+    use super::*;
+    use crate::net::transport::LoopbackNetwork;
+
+    const BAD: Conditions = Conditions { latency_ms: 20, jitter_ms: 10, loss: 0.3, duplicate: 0.2, reorder: 0.2 };
+
+    fn addr(port: u16) -> SocketAddr {
+        SocketAddr::new(IpAddr::from(Ipv4Addr::LOCALHOST), port)
+    }
+
+    // writes down how each datagram went out, and fails every send if told to
+    #[derive(Default)]
+    struct Probe {
+        peer: Option<SocketAddr>,
+        sent: Arc<Mutex<Vec<(&'static str, SocketAddr)>>>,
+        fail: bool,
+    }
+
+    impl Probe {
+        fn record(&self, how: &'static str, peer: SocketAddr, len: usize) -> Result<usize> {
+            self.sent.lock().unwrap().push((how, peer));
+            if self.fail {
+                return Err(Error::from(ErrorKind::ConnectionRefused));
+            }
+            Ok(len)
+        }
+    }
+
+    impl Transport for Probe {
+        fn connect(&mut self, peer: SocketAddr) -> Result<()> {
+            self.peer = Some(peer);
+            Ok(())
+        }
+
+        fn send(&self, buf: &[u8]) -> Result<usize> {
+            self.record("send", self.peer_addr()?, buf.len())
+        }
+
+        fn send_to(&self, buf: &[u8], peer: &SocketAddr) -> Result<usize> {
+            self.record("send_to", *peer, buf.len())
+        }
+
+        fn recv_from(&self, _buf: &mut [u8]) -> Result<(usize, SocketAddr)> {
+            Err(Error::from(ErrorKind::WouldBlock))
+        }
+
+        fn local_addr(&self) -> Result<SocketAddr> {
+            Ok(addr(1))
+        }
+
+        fn peer_addr(&self) -> Result<SocketAddr> {
+            self.peer.ok_or(Error::from(ErrorKind::NotConnected))
+        }
+    }
+
+    fn probe(conditions: Conditions, fail: bool) -> (ConditionedTransport, Arc<Mutex<Vec<(&'static str, SocketAddr)>>>, ManualClock) {
+        let probe = Probe { peer: Some(addr(2)), fail, ..Default::default() };
+        let sent = probe.sent.clone();
+        let (sim, clock) = NetSim::new_manual(conditions, 0);
+        (ConditionedTransport::new(Box::new(probe), sim), sent, clock)
+    }
+
+    #[test]
+    fn off_passes_straight_through() {
+        let (sock, sent, _) = probe(Conditions::OFF, false);
+        sock.send(&[1]).unwrap();
+        sock.send_to(&[2], &addr(2)).unwrap();
+        sock.send_to(&[3], &addr(3)).unwrap();
+        assert_eq!(*sent.lock().unwrap(), vec![("send", addr(2)), ("send", addr(2)), ("send_to", addr(3))]);
+    }
+
+    #[test]
+    fn delayed_sends_to_the_connected_peer_use_send() {
+        let (sock, sent, clock) = probe(Conditions { latency_ms: 50, ..Conditions::OFF }, false);
+        sock.send(&[1]).unwrap();
+        sock.send_to(&[2], &addr(3)).unwrap();
+        assert!(sent.lock().unwrap().is_empty());
+        clock.advance(50);
+        let _ = sock.recv_from(&mut [0; 8]);
+        assert_eq!(*sent.lock().unwrap(), vec![("send", addr(2)), ("send_to", addr(3))]);
+    }
+
+    #[test]
+    fn send_errors_are_returned() {
+        let (sock, _, _) = probe(Conditions::OFF, true);
+        assert_eq!(sock.send(&[1]).unwrap_err().kind(), ErrorKind::ConnectionRefused);
+        // a delayed send can only fail once it's due, whatever flushes it gets the error
+        let (sock, _, clock) = probe(Conditions { latency_ms: 50, ..Conditions::OFF }, true);
+        assert!(sock.send(&[1]).is_ok());
+        clock.advance(50);
+        assert_eq!(sock.recv_from(&mut [0; 8]).unwrap_err().kind(), ErrorKind::ConnectionRefused);
+    }
+
+    // sends 100 numbered datagrams 1ms apart through BAD conditions, returns what came out the other end in order
+    fn run(seed: u64) -> Vec<u8> {
+        let net = LoopbackNetwork::new();
+        let (sim, clock) = NetSim::new_manual(BAD, seed);
+        let sender = ConditionedTransport::new(Box::new(net.bind(addr(1)).unwrap()), sim);
+        let receiver = net.bind(addr(2)).unwrap();
+        for i in 0..100 {
+            sender.send_to(&[i], &addr(2)).unwrap();
+            if i == 0 {
+                // nothing gets there in less than latency - jitter
+                assert_eq!(net.in_flight(), 0);
+            }
+            clock.advance(1);
+        }
+        clock.advance(1000);
+        let _ = sender.recv_from(&mut [0; 8]);
+        let mut got = Vec::new();
+        let mut buf = [0; 8];
+        while let Ok((_, origin)) = receiver.recv_from(&mut buf) {
+            assert_eq!(origin, addr(1));
+            got.push(buf[0]);
+        }
+        got
+    }
+
+    #[test]
+    fn same_seed_same_network() {
+        let got = run(7);
+        assert_eq!(got, run(7));
+        assert_ne!(got, run(8));
+        // some of it was lost, duplicated or reordered
+        assert!(got.len() != 100 || got.windows(2).any(|w| w[0] >= w[1]));
+    }
+}
@@ -0,0 +1,73 @@
+use std::fmt;
+use std::io::{self, ErrorKind};
+use std::net::SocketAddr;
+use bevy::prelude::*;
+use crate::AppState;
+use crate::menus::layout::spawn_error_popup;
+use crate::net;
+
+/// Everything that can go wrong setting up or using the socket that the player can do something about.
+/// Systems that can fail return Result<(), NetError> and get piped into one of the handlers below.
+pub enum NetError {
+    BadIp(String),
+    BadPort(String),
+    Bind(u16, io::Error),  // couldn't open our own port
+    Connect(SocketAddr, io::Error),
+    NotConnected,  // a client socket that somehow lost its host
+    Refused,  // the host wants a different match password, or none
+    MissingMap(String),  // the host picked a map file we don't have the same copy of
+    ServerFull,
+    Kicked(String),  // with the host's reason
+    Send(SocketAddr, io::Error),
+}
+
+impl fmt::Display for NetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetError::BadIp(ip) => write!(f, "\"{}\" isn't a valid IP address", ip),
+            NetError::BadPort(port) => write!(f, "\"{}\" isn't a valid port, use a number up to 65535", port),
+            NetError::Bind(port, e) if e.kind() == ErrorKind::AddrInUse => write!(f, "Port {} is already in use, pick another one", port),
+            NetError::Bind(port, e) => write!(f, "Can't open port {}: {}", port, e),
+            NetError::Connect(addr, e) => write!(f, "Can't reach {}: {}", addr, e),
+            NetError::NotConnected => write!(f, "Not connected to a host"),
+            NetError::Refused => write!(f, "Wrong match password, or the host doesn't use one"),
+            NetError::MissingMap(name) => write!(f, "The host is playing {}.jqmap, put the host's copy in assets/maps to join", name),
+            NetError::ServerFull => write!(f, "The game is full"),
+            NetError::Kicked(reason) => write!(f, "The host removed you from the game: {}", reason),
+            NetError::Send(addr, e) => write!(f, "Lost connection to {}: {}", addr, e),
+        }
+    }
+}
+
+/// Closes the socket and sends the player back to where they can fix the problem,
+/// the host or join page, or the leaderboard if a match was going.
+pub fn return_to_menu(
+    In(result): In<Result<(), NetError>>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut sock: ResMut<net::Socket>,
+    is_host: Res<net::IsHost>,
+    app_state: Res<State<AppState>>,
+    mut app_state_next_state: ResMut<NextState<AppState>>,
+) {
+    if let Err(e) = result {
+        error!("{}", e);
+        spawn_error_popup(&mut commands, &asset_server, &e.to_string());
+        sock.0.take();
+        if *app_state.get() == AppState::Game {
+            app_state_next_state.set(AppState::GameOver);
+        } else if is_host.0 {
+            app_state_next_state.set(AppState::Hosting);
+        } else {
+            app_state_next_state.set(AppState::Joining);
+        }
+    }
+}
+
+/// for the host's sends, one client being unreachable shouldn't end the match for everyone else.
+/// Also the client's per-tick sends, a host that just went away is migrate::watch_host's to notice
+pub fn log(In(result): In<Result<(), NetError>>) {
+    if let Err(e) = result {
+        warn!("{}", e);
+    }
+}
@@ -3,147 +3,254 @@ use std::str::FromStr;
 use bevy::math::Vec3Swizzles;
 use bevy::prelude::*;
 use crate::game::{Chests, player};
+use crate::game::player::{clean_name, PlayerJoinEvent, PlayerLeaveEvent, PlayerNames};
 use crate::{menus, net};
 use crate::game::buffers::{DirBuffer, EventBuffer, HpBuffer, PosBuffer};
 use crate::components::*;
-use crate::game::map::MapSeed;
+use crate::game::grid::Grid;
+use crate::game::map::{in_sight, Biome, MapSeed, MapSize, SpawnSettings, WorldMap};
+use crate::game::mapfile::MapChoice;
 use crate::net::packets::*;
+use crate::net::conditioner::NetSim;
+use crate::net::error::NetError;
+use crate::net::admin::BanList;
+use crate::net::chat::{ChatAckEvent, ChatSendEvent, SystemMessage};
+use crate::net::lobby::Lobby;
+use crate::net::migrate::Migration;
+use crate::net::secure::MatchKey;
+use crate::net::transport::{self, LoopbackNetwork, Transport};
 use crate::net::{MAGIC_NUMBER, MAX_DATAGRAM_SIZE};
 
 pub const RENDER_DISTANCE: f32 = 640.;
+pub const MAX_SPECTATORS: usize = 8;
 
 #[derive(Copy, Clone)]
 pub struct Connection {
     pub addr: SocketAddr,
     pub player_id: u8,
     pub rmt_num: u16,  // if the ack is older than TIMEOUT ticks ago, disconnect the player
-    pub ack: u32
+    pub ack: u32,
+    pub send_interval: u16,  // ticks between snapshots to this peer, from net::send_interval
 }
 
 #[derive(Resource)]
-pub struct Connections(pub [Option<Connection>; player::MAX_PLAYERS-1]); // -1 because host not included
+pub struct Connections(pub Vec<Connection>); // host not included
+
+/// how many players the host lets in, host included, picked on the host page up to MAX_PLAYERS
+#[derive(Resource)]
+pub struct MaxPlayers(pub u8);
+
+/// clients watching the game, they don't take a player slot and all have player_id SPECTATOR_ID
+#[derive(Resource)]
+pub struct Spectators(pub Vec<Connection>);
 
 pub fn startup(mut commands: Commands) {
-    commands.insert_resource(Connections { 0: [None; player::MAX_PLAYERS-1] });
+    commands.insert_resource(Connections(Vec::new()));
+    commands.insert_resource(MaxPlayers(player::DEFAULT_MAX_PLAYERS));
+    commands.insert_resource(Spectators(Vec::new()));
 }
 
 pub fn connect(addresses: Res<menus::NetworkAddresses>,
-    mut sock: ResMut<net::Socket>
-) {
+    mut sock: ResMut<net::Socket>,
+    loopback: Option<Res<LoopbackNetwork>>,
+    sim: Res<NetSim>,
+    key: Res<MatchKey>,
+) -> Result<(), NetError> {
+    if sock.0.is_some() { return Ok(()) }  // still open from before a restart
     let host_ip = Ipv4Addr::new(0,0,0,0);
-    let host_port = u16::from_str(&addresses.host_port).expect("bad host port");
+    let host_port = u16::from_str(&addresses.host_port).map_err(|_| NetError::BadPort(addresses.host_port.clone()))?;
     let host_addr = SocketAddr::new(IpAddr::from(host_ip), host_port);
-    sock.0 = Some(UdpSocket::bind(host_addr).expect("host port in use"));
-    sock.0.as_mut().unwrap().set_nonblocking(true).expect("can't set nonblocking");
+    sock.0 = Some(transport::bind(host_addr, loopback.as_deref(), &sim, &key).map_err(|e| NetError::Bind(host_port, e))?);
+    Ok(())
+}
+
+/// sends to every peer even if some of them fail, and returns the first failure
+pub fn send_all<'a>(bytes: &[u8], sock: &dyn Transport, peers: impl Iterator<Item = &'a Connection>) -> Result<(), NetError> {
+    let mut result = Ok(());
+    for conn in peers {
+        if let Err(e) = send_buf(bytes, sock, &conn.addr) {
+            if result.is_ok() {
+                result = Err(NetError::Send(conn.addr, e));
+            }
+        }
+    }
+    result
+}
+
+/// splits a snapshot into datagram sized parts and sends every part to every peer
+pub fn send_snapshot(packet: HostTick, sock: &dyn Transport, peers: &[&Connection]) -> Result<(), NetError> {
+    let mut result = Ok(());
+    for part in packet.split(MAX_DATAGRAM_SIZE) {
+        let mut bytes: Vec<u8> = Vec::new();
+        part.to_buf(&mut bytes);
+        result = result.and(send_all(bytes.as_slice(), sock, peers.iter().copied()));
+    }
+    result
 }
 
 pub fn disconnect(
     mut sock: ResMut<net::Socket>,
-    mut conns: ResMut<Connections>
+    mut conns: ResMut<Connections>,
+    mut spectators: ResMut<Spectators>,
 ) {
     sock.0.take();
-    for conn in conns.0.iter_mut() {
-        conn.take();
+    conns.0.clear();
+    spectators.0.clear();
+}
+
+/// tells every client and spectator what everyone is called
+pub fn send_name_list(names: &PlayerNames, sock: &dyn Transport, conns: &Connections, spectators: &Spectators) -> Result<(), NetError> {
+    let mut list: Vec<(u8, String)> = names.0.iter().map(|(id, name)| (*id, name.clone())).collect();
+    list.sort_by_key(|(id, _)| *id);
+    let packet = NameList { names: list };
+    let mut bytes: Vec<u8> = Vec::new();
+    packet.to_buf(&mut bytes);
+    send_all(bytes.as_slice(), sock, conns.0.iter().chain(spectators.0.iter()))
+}
+
+/// tells every client and spectator where everyone else is, in case this host goes away
+fn send_peer_list(sock: &dyn Transport, conns: &Connections, spectators: &Spectators, max_players: u8) -> Result<(), NetError> {
+    let peers: Vec<(u8, SocketAddr)> = conns.0.iter().chain(spectators.0.iter()).map(|conn| (conn.player_id, conn.addr)).collect();
+    let packet = PeerList { max_players, peers };
+    let mut bytes: Vec<u8> = Vec::new();
+    packet.to_buf(&mut bytes);
+    send_all(bytes.as_slice(), sock, conns.0.iter().chain(spectators.0.iter()))
+}
+
+pub type PlayerSnapshotQuery<'w, 's> = Query<'w, 's, (&'static PosBuffer, &'static HpBuffer, &'static Player, &'static EventBuffer, &'static DirBuffer, &'static Stats, &'static StoredPowerUps)>;
+pub type EnemySnapshotQuery<'w, 's> = Query<'w, 's, (&'static PosBuffer, &'static Health, &'static Enemy, &'static EventBuffer)>;
+
+/// Builds the world state for one tick as seen from viewer.
+/// Enemies further than render_distance from the viewer are left out, and if there is
+/// no viewer position (not spawned yet) no enemies are sent at all. Given a map, enemies
+/// the viewer can't see through forest are left out too.
+pub fn snapshot(
+    tick: u16,
+    viewer: Option<Vec2>,
+    render_distance: f32,
+    sight: Option<&Grid<Biome>>,
+    player_query: &PlayerSnapshotQuery,
+    enemy_query: &EnemySnapshotQuery,
+    powerups_query: &Query<(&PowerUp, &Transform)>,
+    camp_query: &Query<(&Camp, &CampStatus, &CampEnemies)>,
+    chests_query: &Query<(&ItemChest, &Health)>,
+) -> HostTick {
+    let mut players: Vec<PlayerTick> = Vec::new();
+    for (pb, hb, pl, eb, db, stats, powerups) in player_query {
+        let pos = pb.0.get(tick);
+        let hp = hb.0.get(tick);
+        let dir = db.0.get(tick);
+        let events = eb.0.get(tick);
+        if pos.is_none() || hp.is_none() || dir.is_none() || events.is_none() { continue }
+        let pos = pos.unwrap();
+        let hp = hp.unwrap();
+        let dir = dir.unwrap();
+        let events = events.unwrap();
+        players.push(PlayerTick {
+            id: pl.0,
+            pos,
+            dir,
+            hp,
+            events,
+            stats: stats.clone(),
+            powerups: powerups.clone(),
+        });
+    }
+    let mut enemies: Vec<EnemyTick> = Vec::new();
+    if viewer.is_some() {
+        let viewer = viewer.unwrap();
+        for (pb, hp, en, eb) in enemy_query {
+            let pos = pb.0.get(tick).unwrap();
+            if pos.distance(viewer) < render_distance
+                && sight.map_or(true, |map| in_sight(&viewer.extend(0.), &pos.extend(0.), map)) {
+                enemies.push(EnemyTick {
+                    id: en.0,
+                    pos,
+                    hp: hp.current,
+                    events: eb.0.get(tick).unwrap_or(0),
+                });
+            }
+        }
+    }
+    let mut powerups: Vec<(PowerUpType, Vec2)> = Vec::new();
+    for (pu, pos) in powerups_query {
+        powerups.push((pu.0, pos.translation.xy()));
+    }
+    let mut camps = Vec::new();
+    for (camp, status, enemies) in camp_query {
+        if status.0 {
+            camps.push((camp.0, enemies.current_enemies));
+        }
+    }
+    let mut chests: Vec<(u8, u8)> = Vec::new();
+    for (id, hp) in chests_query {
+        chests.push((id.id, hp.current));
+    }
+    HostTick {
+        seq_num: tick,
+        rmt_num: 0,
+        ack: 0,
+        part: 0,
+        enemies,
+        players,
+        powerups,
+        camps,
+        chests
     }
 }
 
 pub fn fixed(
     tick: Res<net::TickNum>,
     conns: Res<Connections>,
+    spectators: Res<Spectators>,
     sock: Res<net::Socket>,
-    player_query: Query<(&PosBuffer, &HpBuffer, &Player, &EventBuffer, &DirBuffer, &Stats, &StoredPowerUps)>,
-    enemy_query: Query<(&PosBuffer, &Health, &Enemy, &EventBuffer)>,
+    names: Res<PlayerNames>,
+    max_players: Res<MaxPlayers>,
+    tick_rate: Res<net::TickRate>,
+    player_query: PlayerSnapshotQuery,
+    enemy_query: EnemySnapshotQuery,
     powerups_query: Query<(&PowerUp, &Transform)>,
     camp_query: Query<(&Camp, &CampStatus, &CampEnemies)>,
-    chests_query: Query<(&ItemChest, &Health)>
-) {
-    if sock.0.is_none() { return }
+    chests_query: Query<(&ItemChest, &Health)>,
+    map: Res<WorldMap>,
+) -> Result<(), NetError> {
+    let _span = trace_span!("host_fixed", tick = tick.0).entered();
+    if sock.0.is_none() { return Ok(()) }
     let sock = sock.0.as_ref().unwrap();
+    // keep sending to everyone else if one of them fails
+    let mut result = Ok(());
     for conn in conns.0.iter() {
-        if conn.is_none() { continue; }
-        let conn = conn.unwrap();
+        if tick.0 % conn.send_interval != 0 { continue }
         for (lp_pb, _, lp_pl, _, _, _, _) in &player_query {
             if conn.player_id == lp_pl.0 {
-                // for "this" player, add self, then calculate who is close and add them.
-                let lp_pos = lp_pb.0.get(tick.0);
-                let mut players: Vec<PlayerTick> = Vec::new();
-                for (pb, hb, pl, eb, db, stats, powerups) in &player_query {
-                    let pos = pb.0.get(tick.0);
-                    let hp = hb.0.get(tick.0);
-                    let dir = db.0.get(tick.0);
-                    let events = eb.0.get(tick.0);
-                    if pos.is_none() || hp.is_none() || dir.is_none() || events.is_none() { continue }
-                    let pos = pos.unwrap();
-                    let hp = hp.unwrap();
-                    let dir = dir.unwrap();
-                    let events = events.unwrap();
-                    players.push(PlayerTick {
-                        id: pl.0,
-                        pos,
-                        dir,
-                        hp,
-                        events,
-                        stats: stats.clone(),
-                        powerups: powerups.clone(),
-                    });
-                }
-                let mut enemies: Vec<EnemyTick> = Vec::new();
-                if lp_pos.is_some() {
-                    let lp_pos = lp_pos.unwrap();
-                    for (pb, hp, en, eb) in &enemy_query {
-                        let pos = pb.0.get(tick.0).unwrap();
-                        if pos.distance(lp_pos) < RENDER_DISTANCE {
-                            enemies.push(EnemyTick {
-                                id: en.0,
-                                pos,
-                                hp: hp.current,
-                                events: eb.0.get(tick.0).unwrap_or(0),
-                            });
-                        }
-                    }
-                }
-                let mut powerups: Vec<(PowerUpType, Vec2)> = Vec::new();
-                for (pu, pos) in &powerups_query {
-                    powerups.push((pu.0, pos.translation.xy()));
-                }
-                let mut camps = Vec::new();
-                for (camp, status, enemies) in &camp_query {
-                    if status.0 {
-                        camps.push((camp.0, enemies.current_enemies));
-                    }
-                }
-                let mut chests: Vec<(u8, u8)> = Vec::new();
-                for (id, hp) in &chests_query {
-                    chests.push((id.id, hp.current));
-                }
-                let packet = HostTick {
-                    seq_num: tick.0,
-                    rmt_num: conn.rmt_num,
-                    ack: conn.ack,
-                    enemies,
-                    players,
-                    powerups,
-                    camps,
-                    chests
-                };
-                let peer = conn.addr;
-                let mut bytes: Vec<u8> = Vec::new();
-                packet.to_buf(&mut bytes);
-                send_buf(bytes.as_slice(), &sock, &peer).expect(&*format!("failed to send HostTick to {:?}", peer));
+                // for "this" player, add everyone, then calculate which enemies are close and add them.
+                let lp_pos = *lp_pb.0.get(tick.0);
+                let mut packet = snapshot(tick.0, lp_pos, RENDER_DISTANCE, Some(&map.biome_map), &player_query, &enemy_query, &powerups_query, &camp_query, &chests_query);
+                packet.rmt_num = conn.rmt_num;
+                packet.ack = conn.ack;
+                result = result.and(send_snapshot(packet, sock.as_ref(), &[conn]));
             }
         }
     }
+    if tick.0 % tick_rate.0 as u16 == 0 {
+        // once a second is plenty, names only change when someone joins
+        result = result.and(send_name_list(&names, sock.as_ref(), &conns, &spectators));
+        result = result.and(send_peer_list(sock.as_ref(), &conns, &spectators, max_players.0));
+    }
+    let due: Vec<&Connection> = spectators.0.iter().filter(|s| tick.0 % s.send_interval == 0).collect();
+    if due.is_empty() { return result }
+    // spectators can look anywhere so they all get the same full map snapshot
+    // which is too big for one datagram on all but the smallest matches
+    let packet = snapshot(tick.0, Some(Vec2::ZERO), f32::MAX, None, &player_query, &enemy_query, &powerups_query, &camp_query, &chests_query);
+    result.and(send_snapshot(packet, sock.as_ref(), &due))
 }
 
 /// tries to find a player id given an origin
 /// returns Some(player id) if successful, otherwise None
 fn get_id_of_origin(conns: &Connections, origin: &SocketAddr) -> Option<u8> {
     for conn in &conns.0 {
-        if conn.is_some() {
-            let conn = conn.unwrap();
-            if conn.addr == *origin {
-                return Some(conn.player_id);
-            }
+        if conn.addr == *origin {
+            return Some(conn.player_id);
         }
     }
     return None;
@@ -151,73 +258,123 @@ fn get_id_of_origin(conns: &Connections, origin: &SocketAddr) -> Option<u8> {
 
 /// tries to add a connection using the given origin
 /// returns Some(player id) if successful, otherwise None
-fn add_connection(conns: &mut Connections, origin: &SocketAddr) -> Option<u8> {
-    let mut unused = [false; player::MAX_PLAYERS - 1];
-    for conn in &conns.0 {
-        if let Some(conn) = conn {
-            unused[conn.player_id as usize] = true;
-        }
-    }
-    let mut fresh_id: u8 = 1;
-    for (i, b) in unused.into_iter().enumerate() {
-        if i == fresh_id as usize && b {
-            fresh_id += 1;
-        }
+fn add_connection(conns: &mut Connections, origin: &SocketAddr, max_players: u8, send_interval: u16) -> Option<u8> {
+    // id 0 is the host, hand out the lowest id nobody is using
+    let fresh_id = (1..max_players).find(|id| !conns.0.iter().any(|conn| conn.player_id == *id));
+    if fresh_id.is_none() {
+        return None;
     }
-    for conn in &mut conns.0 {
-        if conn.is_none() {
-            let _ = conn.insert(Connection {
-                addr: *origin,
-                player_id: fresh_id,
-                rmt_num: 0,
-                ack: 0,
-            });
-            return Some(fresh_id);
-        }
-    }
-    return None;
+    let fresh_id = fresh_id.unwrap();
+    conns.0.push(Connection {
+        addr: *origin,
+        player_id: fresh_id,
+        rmt_num: 0,
+        ack: 0,
+        send_interval,
+    });
+    return Some(fresh_id);
 }
 
 pub fn update(
     mut sock: ResMut<net::Socket>,
     mut conns: ResMut<Connections>,
+    mut spectators: ResMut<Spectators>,
+    mut lobby: ResMut<Lobby>,
+    max_players: Res<MaxPlayers>,
+    mut join_writer: EventWriter<PlayerJoinEvent>,
+    mut leave_writer: EventWriter<PlayerLeaveEvent>,
+    ban_list: Res<BanList>,
+    mut names: ResMut<PlayerNames>,
     tick_num: Res<net::TickNum>,
     mut usercmd_writer: EventWriter<UserCmdEvent>,
-    seed: Res<MapSeed>
-) {
-    if sock.0.is_none() { return }
+    mut chat_writer: EventWriter<ChatSendEvent>,
+    mut chat_ack_writer: EventWriter<ChatAckEvent>,
+    mut system_writer: EventWriter<SystemMessage>,
+    (seed, map_size, spawn_settings, map_choice): (Res<MapSeed>, Res<MapSize>, Res<SpawnSettings>, Res<MapChoice>),
+    (tick_rate, send_rate, mut migration): (Res<net::TickRate>, Res<net::SendRate>, ResMut<Migration>),
+) -> Result<(), NetError> {
+    let _span = trace_span!("host_update", tick = tick_num.0).entered();
+    if sock.0.is_none() { return Ok(()) }
     let sock = sock.0.as_mut().unwrap();
+    // a reply that can't go out shouldn't stop us reading everyone else's packets
+    let mut result = Ok(());
     loop {
         let mut buf = [0; MAX_DATAGRAM_SIZE];
-        if sock.peek(&mut buf).is_err() { break }
-        let (_, origin) = sock.recv_from(&mut buf).unwrap();
+        let recv = sock.recv_from(&mut buf);
+        if recv.is_err() { break }
+        let (len, origin) = recv.unwrap();
+        if len < 3 { continue }
+        let buf = &buf[..len];
         let magic = u16::from_be_bytes(buf[0..2].try_into().unwrap());
         if magic != MAGIC_NUMBER { break; }
         let pt = u8::from_be_bytes(buf[2..3].try_into().unwrap());
         match pt {
             pt if pt == PacketType::ConnectionRequest as u8 => {
-                println!("ConnectionRequest received");
+                debug!("ConnectionRequest received from {}", origin);
+                let request = ConnectionRequest::from_buf(&buf[3..]);
+                if request.is_err() {
+                    warn!("Malformed ConnectionRequest Received!");
+                    continue;
+                }
+                let request = request.unwrap();
+                let send_interval = net::send_interval(tick_rate.0, send_rate.0, request.send_rate);
                 let mut maybe_id = get_id_of_origin(&conns, &origin);
-                if maybe_id.is_some() {
+                if maybe_id.is_some() || spectators.0.iter().any(|s| s.addr == origin) {
                     continue;  // this user is already in the server
                 }
-                maybe_id = add_connection(&mut conns, &origin);
+                if ban_list.0.contains(&origin.ip()) {
+                    let packet = Kicked { reason: "banned".to_string() };
+                    let mut bytes: Vec<u8> = Vec::new();
+                    packet.to_buf(&mut bytes);
+                    result = result.and(send_buf(bytes.as_slice(), sock.as_ref(), &origin).map(|_| ()).map_err(|e| NetError::Send(origin, e)));
+                    continue;
+                }
+                if request.spectator {
+                    if spectators.0.len() >= MAX_SPECTATORS {
+                        result = result.and(send_empty_packet(PacketType::ServerFull, sock.as_ref(), &origin).map(|_| ()).map_err(|e| NetError::Send(origin, e)));
+                        continue;
+                    }
+                    spectators.0.push(Connection {
+                        addr: origin,
+                        player_id: player::SPECTATOR_ID,
+                        rmt_num: 0,
+                        ack: 0,
+                        send_interval,
+                    });
+                    maybe_id = Some(player::SPECTATOR_ID);
+                } else {
+                    maybe_id = add_connection(&mut conns, &origin, max_players.0, send_interval);
+                    if maybe_id.is_some() {
+                        let id = maybe_id.unwrap();
+                        // always in the list, even unnamed, so clients can tell who is still here
+                        let name = clean_name(&request.name).unwrap_or(names.get(id));
+                        names.0.insert(id, name);
+                        join_writer.send(PlayerJoinEvent(id));
+                        system_writer.send(SystemMessage(format!("{} joined", names.get(id))));
+                    }
+                }
                 if maybe_id.is_none() {
-                    send_empty_packet(PacketType::ServerFull, sock, &origin).expect("cant send server full");
+                    result = result.and(send_empty_packet(PacketType::ServerFull, sock.as_ref(), &origin).map(|_| ()).map_err(|e| NetError::Send(origin, e)));
+                    continue;
                 }
                 let player_id = maybe_id.unwrap();
                 let packet = ConnectionResponse {
                     player_id,
-                    seed: seed.0
+                    seed: seed.0,
+                    tick_rate: tick_rate.0,
+                    map_width: map_size.width as u16,
+                    map_height: map_size.height as u16,
+                    spawn_settings: *spawn_settings,
+                    map_choice: map_choice.clone(),
                 };
                 let mut bytes: Vec<u8> = Vec::new();
                 packet.to_buf(&mut bytes);
-                send_buf(bytes.as_slice(), sock, &origin).expect("Can't send connection response");
+                result = result.and(send_buf(bytes.as_slice(), sock.as_ref(), &origin).map(|_| ()).map_err(|e| NetError::Send(origin, e)));
             },
             pt if pt == PacketType::ClientTick as u8 => {
                 let packet = ClientTick::from_buf(&buf[3..]);
                 if packet.is_err() {
-                    println!("Malformed ClientTick Received!");
+                    warn!("Malformed ClientTick Received!");
                     continue;
                 }
                 let packet = packet.unwrap();
@@ -228,7 +385,7 @@ pub fn update(
                 let id = maybe_id.unwrap();
                 if packet.seq_num < tick_num.0 - net::DELAY {
                     // TODO deal with packet misses
-                    println!("packet late, local is {} remote is {}", tick_num.0, packet.seq_num);
+                    debug!("packet late from player {}, local is {} remote is {}", id, tick_num.0, packet.seq_num);
                     continue
                 }
                 // send event that this player has moved to this location
@@ -238,19 +395,68 @@ pub fn update(
                     tick: packet.tick
                 });
             },
+            pt if pt == PacketType::LobbyReady as u8 => {
+                let packet = LobbyReady::from_buf(&buf[3..]);
+                if packet.is_err() {
+                    warn!("Malformed LobbyReady Received!");
+                    continue;
+                }
+                let maybe_id = get_id_of_origin(&conns, &origin);
+                if maybe_id.is_none() {
+                    continue;
+                }
+                lobby.set_ready(maybe_id.unwrap(), packet.unwrap().ready);
+            },
+            pt if pt == PacketType::ChatSend as u8 => {
+                let packet = ChatSend::from_buf(&buf[3..]);
+                if packet.is_err() {
+                    warn!("Malformed ChatSend Received!");
+                    continue;
+                }
+                chat_writer.send(ChatSendEvent { origin, packet: packet.unwrap() });
+            },
+            pt if pt == PacketType::ChatAck as u8 => {
+                let packet = ChatAck::from_buf(&buf[3..]);
+                if packet.is_err() {
+                    warn!("Malformed ChatAck Received!");
+                    continue;
+                }
+                chat_ack_writer.send(ChatAckEvent { origin, seq: packet.unwrap().seq });
+            },
+            pt if pt == PacketType::Rejoin as u8 => {
+                // we took over from the last host, this client followed us here
+                let packet = Rejoin::from_buf(&buf[3..]);
+                if packet.is_err() {
+                    warn!("Malformed Rejoin Received!");
+                    continue;
+                }
+                let id = packet.unwrap().player_id;
+                // only someone from the PeerList we took over with, and only the once, anyone else has to connect like normal
+                if !migration.took_over || !migration.peers.contains(&(id, origin)) {
+                    warn!("Rejoin from {} as {} that isn't in the PeerList, ignored", origin, id);
+                    continue;
+                }
+                if migration.rejoined.contains(&(id, origin)) { continue }
+                migration.rejoined.push((id, origin));
+                // take_over already let the players back in
+                if id == player::SPECTATOR_ID && !spectators.0.iter().any(|s| s.addr == origin) && spectators.0.len() < MAX_SPECTATORS {
+                    spectators.0.push(Connection { addr: origin, player_id: id, rmt_num: 0, ack: 0, send_interval: 1 });
+                }
+            },
             pt if pt == PacketType::Disconnect as u8 => {
                 // TODO make player dead
-                println!("disconnect received");
-                for conn in &mut conns.0 {
-                    if conn.is_some() {
-                        let s = conn.unwrap().addr;
-                        if s == origin {
-                            conn.take();
-                        }
-                    }
+                debug!("disconnect received");
+                spectators.0.retain(|s| s.addr != origin);
+                if let Some(id) = get_id_of_origin(&conns, &origin) {
+                    system_writer.send(SystemMessage(format!("{} left", names.get(id))));
+                    names.0.remove(&id);
+                    leave_writer.send(PlayerLeaveEvent(id));
                 }
+                conns.0.retain(|conn| conn.addr != origin);
+                sock.forget(&origin);
             }
-            _ => panic!("Bad packet sent to host")
+            _ => warn!("Unexpected packet type {} from {}, dropped", pt, origin),
         }
     }
+    result
 }
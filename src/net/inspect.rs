@@ -0,0 +1,454 @@
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufReader, Error, ErrorKind, Read, Result, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use bevy::prelude::*;
+use bevy::utils::tracing::{self, Level};
+use crate::cli_arg;
+use crate::game::enemy;
+use crate::game::map::SpawnSettings;
+use crate::game::mapfile::MapChoice;
+use crate::game::player;
+use crate::net::MAGIC_NUMBER;
+use crate::net::packets::*;
+use crate::net::transport::Transport;
+
+// pcap link types we can find a UDP datagram in
+const LINKTYPE_NULL: u32 = 0;  // BSD loopback, what capturing on lo0 gives on a mac
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;  // starts straight at the IP header, what --tap writes
+const LINKTYPE_LINUX_SLL: u32 = 113;  // tcpdump -i any
+const LINKTYPE_LINUX_SLL2: u32 = 276;
+
+/// Turns one datagram into readable text using the same decoders the game uses.
+/// Anything that isn't ours or doesn't decode says so instead of failing.
+pub fn describe(buf: &[u8]) -> String {
+    if buf.len() < 3 || u16::from_be_bytes([buf[0], buf[1]]) != MAGIC_NUMBER {
+        return format!("not a jordquest packet ({} bytes)", buf.len());
+    }
+    let pt = PacketType::from_u8(buf[2]);
+    if pt.is_none() {
+        return format!("unknown packet type {} ({} bytes)", buf[2], buf.len());
+    }
+    let pt = pt.unwrap();
+    match decode(pt, &buf[3..]) {
+        Ok(text) => format!("{:?} ({} bytes){}", pt, buf.len(), text),
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => format!("{:?} ({} bytes) truncated", pt, buf.len()),
+        Err(e) => format!("{:?} ({} bytes) malformed: {}", pt, buf.len(), e),
+    }
+}
+
+/// the fields after the type, one line per player, enemy and so on for the big ones
+fn decode(pt: PacketType, buf: &[u8]) -> Result<String> {
+    let mut out = String::new();
+    match pt {
+        PacketType::ServerFull | PacketType::Disconnect | PacketType::RoundEnd | PacketType::Refused => {},
+        PacketType::ConnectionRequest => {
+            let p = ConnectionRequest::from_buf(buf)?;
+            write!(out, " name {:?} spectator {} send rate {}", p.name, p.spectator, p.send_rate).unwrap();
+        },
+        PacketType::ConnectionResponse => {
+            let p = ConnectionResponse::from_buf(buf)?;
+            write!(out, " player {} seed {} tick rate {} map {}x{} {} {}", p.player_id, p.seed, p.tick_rate, p.map_width, p.map_height, map_choice(&p.map_choice), spawn_settings(&p.spawn_settings)).unwrap();
+        },
+        PacketType::HostTick => {
+            let p = HostTick::from_buf(buf)?;
+            write!(out, " seq {} part {} {}", p.seq_num, p.part, acks(p.rmt_num, p.ack)).unwrap();
+            for pl in &p.players {
+                write!(out, "\n  player {} pos ({:.1}, {:.1}) hp {} dir {:.2} events {}", pl.id, pl.pos.x, pl.pos.y, pl.hp, pl.dir, player_events(pl.events)).unwrap();
+                write!(out, " score {} kills {}/{} camps {} deaths {} powerups {:?}",
+                    pl.stats.score, pl.stats.enemies_killed, pl.stats.players_killed, pl.stats.camps_captured, pl.stats.deaths, pl.powerups.power_ups).unwrap();
+            }
+            for en in &p.enemies {
+                write!(out, "\n  enemy {} pos ({:.1}, {:.1}) hp {} events {}", en.id, en.pos.x, en.pos.y, en.hp, enemy_events(en.events)).unwrap();
+            }
+            for (ptype, pos) in &p.powerups {
+                write!(out, "\n  powerup {:?} pos ({:.1}, {:.1})", ptype, pos.x, pos.y).unwrap();
+            }
+            for (id, enemies) in &p.camps {
+                write!(out, "\n  camp {} enemies left {}", id, enemies).unwrap();
+            }
+            for (id, hp) in &p.chests {
+                write!(out, "\n  chest {} hp {}", id, hp).unwrap();
+            }
+        },
+        PacketType::ClientTick => {
+            let p = ClientTick::from_buf(buf)?;
+            write!(out, " seq {} {} pos ({:.1}, {:.1}) dir {:.2} events {}",
+                p.seq_num, acks(p.rmt_num, p.ack), p.tick.pos.x, p.tick.pos.y, p.tick.dir, player_events(p.tick.events)).unwrap();
+        },
+        PacketType::LobbyState => {
+            let p = LobbyState::from_buf(buf)?;
+            write!(out, " seed {} camps {} map {}x{} {} {} countdown {:?}", p.seed, p.num_camps, p.map_width, p.map_height, map_choice(&p.map_choice), spawn_settings(&p.spawn_settings), p.countdown).unwrap();
+            for (id, ready) in &p.players {
+                write!(out, "\n  player {} {}", id, if *ready { "ready" } else { "not ready" }).unwrap();
+            }
+        },
+        PacketType::LobbyReady => {
+            let p = LobbyReady::from_buf(buf)?;
+            write!(out, " ready {}", p.ready).unwrap();
+        },
+        PacketType::NameList => {
+            let p = NameList::from_buf(buf)?;
+            for (id, name) in &p.names {
+                write!(out, "\n  player {} {:?}", id, name).unwrap();
+            }
+        },
+        PacketType::ChatSend => {
+            let p = ChatSend::from_buf(buf)?;
+            write!(out, " seq {} {:?}", p.seq, p.text).unwrap();
+        },
+        PacketType::ChatBroadcast => {
+            let p = ChatBroadcast::from_buf(buf)?;
+            write!(out, " id {} from {} {:?}", p.id, p.from, p.text).unwrap();
+        },
+        PacketType::ChatAck => {
+            let p = ChatAck::from_buf(buf)?;
+            write!(out, " seq {}", p.seq).unwrap();
+        },
+        PacketType::Kicked => {
+            let p = Kicked::from_buf(buf)?;
+            write!(out, " reason {:?}", p.reason).unwrap();
+        },
+        PacketType::Restart => {
+            let p = Restart::from_buf(buf)?;
+            write!(out, " seed {}", p.seed).unwrap();
+        },
+        PacketType::PeerList => {
+            let p = PeerList::from_buf(buf)?;
+            write!(out, " max players {}", p.max_players).unwrap();
+            for (id, addr) in &p.peers {
+                write!(out, "\n  player {} at {}", id, addr).unwrap();
+            }
+        },
+        PacketType::Rejoin => {
+            let p = Rejoin::from_buf(buf)?;
+            write!(out, " player {}", p.player_id).unwrap();
+        },
+        // the handshake and encrypted packets, the contents can't be read without the session key
+        PacketType::Hello | PacketType::Accept => {
+            // nonces then the proof, 16 bytes each
+            let fields: Vec<String> = buf.chunks(16).map(|c| c.iter().map(|b| format!("{:02x}", b)).collect()).collect();
+            write!(out, " {}", fields.join(" ")).unwrap();
+        },
+        PacketType::Sealed => {
+            if buf.len() < 8 {
+                return Err(Error::from(ErrorKind::UnexpectedEof));
+            }
+            let counter = u64::from_be_bytes(buf[0..8].try_into().unwrap());
+            write!(out, " counter {}, encrypted", counter).unwrap();
+        },
+    }
+    return Ok(out);
+}
+
+// bit n of the field acks the tick n+1 before rmt_num
+fn acks(rmt_num: u16, bitfield: u32) -> String {
+    let acked: Vec<String> = (0..32)
+        .filter(|bit| bitfield & (1 << bit) != 0)
+        .map(|bit| rmt_num.wrapping_sub(bit + 1).to_string())
+        .collect();
+    format!("rmt {} ack {:#010x} [{}]", rmt_num, bitfield, acked.join(" "))
+}
+
+fn flags(events: u8, names: &[(u8, &str)]) -> String {
+    let set: Vec<&str> = names.iter().filter(|(bit, _)| events & bit != 0).map(|(_, name)| *name).collect();
+    format!("{:#04x} [{}]", events, set.join(" "))
+}
+
+fn player_events(events: u8) -> String {
+    flags(events, &[(player::ATTACK_BITFLAG, "attack"), (player::SPAWN_BITFLAG, "spawn"), (player::SHIELD_BITFLAG, "shield")])
+}
+
+fn enemy_events(events: u8) -> String {
+    flags(events, &[(enemy::ATTACK_BITFLAG, "attack"), (enemy::AGGRO_BITFLAG, "aggro")])
+}
+
+fn map_choice(choice: &MapChoice) -> String {
+    if choice.is_generated() {
+        return "generated".to_string();
+    }
+    format!("file {:?} hash {:016x}", choice.name, choice.hash)
+}
+
+fn spawn_settings(settings: &SpawnSettings) -> String {
+    format!("chests {} enemies per camp {} eid {}% symmetry {}", settings.num_chests, settings.enemies_per_camp, settings.eid_percentage, settings.symmetry.name())
+}
+
+/// `jordquest --inspect <capture.pcap>` prints every jordquest datagram in a capture instead of starting the game.
+/// Works on tcpdump/wireshark captures (ethernet, loopback, `-i any`) and on the files `--tap` writes.
+/// Returns the process exit code.
+pub fn run(path: &str) -> i32 {
+    match print_capture(path) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("can't inspect {}: {}", path, e);
+            1
+        },
+    }
+}
+
+fn print_capture(path: &str) -> Result<()> {
+    let mut file = BufReader::new(File::open(path)?);
+    let mut header = [0; 24];
+    file.read_exact(&mut header)?;
+    // the magic number is written in whatever byte order the capturing machine used
+    let (big_endian, nanos) = match header[0..4] {
+        [0xa1, 0xb2, 0xc3, 0xd4] => (true, false),
+        [0xd4, 0xc3, 0xb2, 0xa1] => (false, false),
+        [0xa1, 0xb2, 0x3c, 0x4d] => (true, true),
+        [0x4d, 0x3c, 0xb2, 0xa1] => (false, true),
+        _ => return Err(Error::new(ErrorKind::InvalidData, "not a pcap file (pcapng isn't supported, save as pcap)")),
+    };
+    let u32_at = |b: &[u8]| {
+        let b: [u8; 4] = b.try_into().unwrap();
+        if big_endian { u32::from_be_bytes(b) } else { u32::from_le_bytes(b) }
+    };
+    let link_type = u32_at(&header[20..24]);
+    let mut first: Option<f64> = None;
+    let (mut ours, mut skipped) = (0, 0);
+    loop {
+        let mut record = [0; 16];
+        // a capture cut off mid packet still prints up to there
+        if file.read_exact(&mut record).is_err() { break }
+        let secs = u32_at(&record[0..4]) as f64;
+        let frac = u32_at(&record[4..8]) as f64 / if nanos { 1e9 } else { 1e6 };
+        let mut frame = vec![0; u32_at(&record[8..12]) as usize];
+        if file.read_exact(&mut frame).is_err() { break }
+        let time = secs + frac - *first.get_or_insert(secs + frac);
+        let datagram = udp_payload(link_type, &frame, big_endian);
+        if datagram.is_none() {
+            skipped += 1;
+            continue;
+        }
+        let (from, to, payload) = datagram.unwrap();
+        if payload.len() < 2 || u16::from_be_bytes([payload[0], payload[1]]) != MAGIC_NUMBER {
+            skipped += 1;
+            continue;
+        }
+        ours += 1;
+        println!("{:>9.3}s {} -> {} {}", time, from, to, describe(payload));
+    }
+    println!("{} jordquest packets, {} other frames skipped", ours, skipped);
+    Ok(())
+}
+
+/// digs the UDP payload out of a captured frame, None for anything that isn't UDP over IP
+fn udp_payload(link_type: u32, frame: &[u8], big_endian: bool) -> Option<(SocketAddr, SocketAddr, &[u8])> {
+    let ip = match link_type {
+        LINKTYPE_NULL => {
+            // address family in the capturing machine's byte order, 2 is IPv4 and the rest are IPv6 on some OS
+            let family: [u8; 4] = frame.get(0..4)?.try_into().unwrap();
+            let family = if big_endian { u32::from_be_bytes(family) } else { u32::from_le_bytes(family) };
+            if family != 2 && family != 24 && family != 28 && family != 30 { return None }
+            &frame[4..]
+        },
+        LINKTYPE_ETHERNET => {
+            let mut i = 12;
+            // skip VLAN tags
+            while frame.get(i..i+2)? == [0x81, 0x00] {
+                i += 4;
+            }
+            if !matches!(frame.get(i..i+2)?, [0x08, 0x00] | [0x86, 0xdd]) { return None }
+            &frame[i+2..]
+        },
+        LINKTYPE_RAW => frame,
+        LINKTYPE_LINUX_SLL => {
+            if !matches!(frame.get(14..16)?, [0x08, 0x00] | [0x86, 0xdd]) { return None }
+            &frame[16..]
+        },
+        LINKTYPE_LINUX_SLL2 => {
+            if !matches!(frame.get(0..2)?, [0x08, 0x00] | [0x86, 0xdd]) { return None }
+            frame.get(20..)?
+        },
+        _ => return None,
+    };
+    let (src, dst, udp) = match ip.first()? >> 4 {
+        4 => {
+            let header_len = (ip[0] & 0x0f) as usize * 4;
+            // fragments would need reassembling, jordquest datagrams are small enough to never be split
+            let fragmented = u16::from_be_bytes([*ip.get(6)?, *ip.get(7)?]) & 0x3fff != 0;
+            if *ip.get(9)? != 17 || fragmented { return None }
+            let src: [u8; 4] = ip.get(12..16)?.try_into().unwrap();
+            let dst: [u8; 4] = ip.get(16..20)?.try_into().unwrap();
+            (IpAddr::V4(Ipv4Addr::from(src)), IpAddr::V4(Ipv4Addr::from(dst)), ip.get(header_len..)?)
+        },
+        6 => {
+            // extension headers aren't followed, the game never sends any
+            if *ip.get(6)? != 17 { return None }
+            let src: [u8; 16] = ip.get(8..24)?.try_into().unwrap();
+            let dst: [u8; 16] = ip.get(24..40)?.try_into().unwrap();
+            (IpAddr::V6(Ipv6Addr::from(src)), IpAddr::V6(Ipv6Addr::from(dst)), ip.get(40..)?)
+        },
+        _ => return None,
+    };
+    let src_port = u16::from_be_bytes([*udp.get(0)?, *udp.get(1)?]);
+    let dst_port = u16::from_be_bytes([*udp.get(2)?, *udp.get(3)?]);
+    let len = u16::from_be_bytes([*udp.get(4)?, *udp.get(5)?]) as usize;
+    // captures can cut frames short, take what's there
+    let payload = udp.get(8..len.max(8).min(udp.len()))?;
+    Some((SocketAddr::new(src, src_port), SocketAddr::new(dst, dst_port), payload))
+}
+
+/// the file --tap writes to, shared by every socket the game opens so a host migration lands in the same capture
+static TAP_FILE: OnceLock<Option<Mutex<File>>> = OnceLock::new();
+
+fn tap_file() -> Option<&'static Mutex<File>> {
+    TAP_FILE.get_or_init(|| {
+        let path = cli_arg("--tap")?;
+        let file = File::create(&path).and_then(|mut file| {
+            // classic pcap header, microsecond timestamps, raw IP frames
+            let mut header: Vec<u8> = Vec::new();
+            header.extend_from_slice(&0xa1b2c3d4_u32.to_be_bytes());
+            header.extend_from_slice(&2_u16.to_be_bytes());
+            header.extend_from_slice(&4_u16.to_be_bytes());
+            header.extend_from_slice(&[0; 8]);  // timezone, sigfigs
+            header.extend_from_slice(&65535_u32.to_be_bytes());
+            header.extend_from_slice(&LINKTYPE_RAW.to_be_bytes());
+            file.write_all(&header)?;
+            Ok(file)
+        });
+        match file {
+            Ok(file) => {
+                info!("capturing traffic to {}, read it back with --inspect", path);
+                Some(Mutex::new(file))
+            },
+            Err(e) => {
+                error!("can't capture to {}: {}", path, e);
+                None
+            },
+        }
+    }).as_ref()
+}
+
+/// builds an IP and UDP header around a datagram so wireshark and --inspect can read it
+fn frame(from: SocketAddr, to: SocketAddr, payload: &[u8]) -> Vec<u8> {
+    let udp_len = 8 + payload.len() as u16;
+    let mut frame: Vec<u8> = Vec::new();
+    match (from.ip(), to.ip()) {
+        (IpAddr::V4(src), IpAddr::V4(dst)) => {
+            frame.extend_from_slice(&[0x45, 0]);
+            frame.extend_from_slice(&(20 + udp_len).to_be_bytes());
+            frame.extend_from_slice(&[0, 0, 0, 0, 64, 17, 0, 0]);  // id, flags, ttl, UDP, checksum filled in below
+            frame.extend_from_slice(&src.octets());
+            frame.extend_from_slice(&dst.octets());
+            let mut sum: u32 = frame.chunks(2).map(|w| u16::from_be_bytes([w[0], w[1]]) as u32).sum();
+            while sum > 0xffff {
+                sum = (sum & 0xffff) + (sum >> 16);
+            }
+            frame[10..12].copy_from_slice(&(!(sum as u16)).to_be_bytes());
+        },
+        (src, dst) => {
+            let v6 = |ip: IpAddr| match ip {
+                IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+                IpAddr::V6(ip) => ip,
+            };
+            frame.extend_from_slice(&[0x60, 0, 0, 0]);
+            frame.extend_from_slice(&udp_len.to_be_bytes());
+            frame.extend_from_slice(&[17, 64]);  // UDP, hop limit
+            frame.extend_from_slice(&v6(src).octets());
+            frame.extend_from_slice(&v6(dst).octets());
+        },
+    }
+    frame.extend_from_slice(&from.port().to_be_bytes());
+    frame.extend_from_slice(&to.port().to_be_bytes());
+    frame.extend_from_slice(&udp_len.to_be_bytes());
+    frame.extend_from_slice(&[0, 0]);  // no checksum, allowed for UDP over IPv4
+    frame.extend_from_slice(payload);
+    return frame;
+}
+
+/// Logs every datagram a socket sends and receives, decoded, at debug level under jordquest::net::inspect,
+/// and writes it to the `--tap <file>` capture if one was asked for.
+pub struct TapTransport {
+    inner: Box<dyn Transport>,
+}
+
+impl TapTransport {
+    /// wraps inner, or hands it straight back if nobody wants to see the traffic
+    pub fn wrap(inner: Box<dyn Transport>) -> Box<dyn Transport> {
+        if tap_file().is_none() && !tracing::enabled!(Level::DEBUG) {
+            return inner;
+        }
+        Box::new(TapTransport { inner })
+    }
+
+    fn tap(&self, from: SocketAddr, to: SocketAddr, buf: &[u8]) {
+        debug!("{} -> {} {}", from, to, describe(buf));
+        if let Some(file) = tap_file() {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+            let frame = frame(from, to, buf);
+            let mut record: Vec<u8> = Vec::new();
+            record.extend_from_slice(&(now.as_secs() as u32).to_be_bytes());
+            record.extend_from_slice(&now.subsec_micros().to_be_bytes());
+            record.extend_from_slice(&(frame.len() as u32).to_be_bytes());
+            record.extend_from_slice(&(frame.len() as u32).to_be_bytes());
+            record.extend_from_slice(&frame);
+            if let Err(e) = file.lock().unwrap().write_all(&record) {
+                warn!("capture write failed: {}", e);
+            }
+        }
+    }
+
+    fn local(&self) -> SocketAddr {
+        self.inner.local_addr().unwrap_or(SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0))
+    }
+}
+
+impl Transport for TapTransport {
+    fn connect(&mut self, peer: SocketAddr) -> Result<()> {
+        self.inner.connect(peer)
+    }
+
+    fn send(&self, buf: &[u8]) -> Result<usize> {
+        let sent = self.inner.send(buf)?;
+        if let Ok(peer) = self.inner.peer_addr() {
+            self.tap(self.local(), peer, buf);
+        }
+        Ok(sent)
+    }
+
+    fn send_to(&self, buf: &[u8], peer: &SocketAddr) -> Result<usize> {
+        let sent = self.inner.send_to(buf, peer)?;
+        self.tap(self.local(), *peer, buf);
+        Ok(sent)
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr)> {
+        let (len, origin) = self.inner.recv_from(buf)?;
+        self.tap(origin, self.local(), &buf[..len]);
+        Ok((len, origin))
+    }
+
+    fn local_addr(&self) -> Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    fn peer_addr(&self) -> Result<SocketAddr> {
+        self.inner.peer_addr()
+    }
+
+    fn forget(&self, peer: &SocketAddr) {
+        self.inner.forget(peer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncated_packets_are_described() {
+        let packet = ChatBroadcast { id: 4, from: 1, text: "hello".to_string() };
+        let mut bytes: Vec<u8> = Vec::new();
+        packet.to_buf(&mut bytes);
+        assert!(describe(&bytes).ends_with("from 1 \"hello\""), "{}", describe(&bytes));
+        for len in 3..bytes.len() {
+            assert!(describe(&bytes[..len]).ends_with("truncated"), "{}", describe(&bytes[..len]));
+        }
+        let sealed = [&MAGIC_NUMBER.to_be_bytes()[..], &[PacketType::Sealed as u8, 0, 0, 1]].concat();
+        assert!(describe(&sealed).ends_with("truncated"));
+    }
+}
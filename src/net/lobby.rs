@@ -0,0 +1,183 @@
+use bevy::prelude::*;
+use crate::AppState;
+use crate::game::{MapConfig, PlayerId};
+use crate::game::map::{MapSeed, MapSize, NumCamps, SpawnSettings};
+use crate::game::mapfile::MapChoice;
+use crate::game::matchcode::{seed_from_text, MatchCode};
+use crate::game::player::{PlayerNames, SPECTATOR_ID};
+use crate::menus::LocalName;
+use crate::menus::components::{MapSeedInput, NumCampsInput};
+use crate::net;
+use crate::net::error::NetError;
+use crate::net::host::{self, Connections, Spectators};
+use crate::net::packets::*;
+
+pub const COUNTDOWN_SECS: f32 = 3.;
+
+/// Everyone waiting for the round to start.
+/// The host owns the real roster and sends it out in LobbyState, clients just show their copy of it.
+#[derive(Resource, Default)]
+pub struct Lobby {
+    pub players: Vec<(u8, bool)>,  // (player id, ready), host first
+    pub countdown: Option<u8>,  // ticks until the round starts
+    pub local_ready: bool,  // what the ready button on this computer is set to
+}
+
+impl Lobby {
+    pub fn set_ready(&mut self, id: u8, ready: bool) {
+        for (player, player_ready) in &mut self.players {
+            if *player == id {
+                *player_ready = ready;
+            }
+        }
+    }
+}
+
+/// runs on the main menu rather than entering the lobby, a restart goes back to the lobby with everyone still in it
+pub fn reset(
+    mut lobby: ResMut<Lobby>,
+    mut names: ResMut<PlayerNames>,
+) {
+    *lobby = Lobby::default();
+    // clients get everyone's names, their own included, from the host
+    names.0.clear();
+}
+
+/// Keeps the roster in sync with the connections, runs the countdown once everyone is ready
+/// and tells every client and spectator about it.
+pub fn host_fixed(
+    mut lobby: ResMut<Lobby>,
+    conns: Res<Connections>,
+    spectators: Res<Spectators>,
+    sock: Res<net::Socket>,
+    seed: Res<MapSeed>,
+    num_camps: Res<NumCamps>,
+    map_size: Res<MapSize>,
+    spawn_settings: Res<SpawnSettings>,
+    map_choice: Res<MapChoice>,
+    mut names: ResMut<PlayerNames>,
+    local_name: Res<LocalName>,
+    mut tick: ResMut<net::TickNum>,
+    tick_rate: Res<net::TickRate>,
+    mut app_state_next_state: ResMut<NextState<AppState>>,
+) -> Result<(), NetError> {
+    if sock.0.is_none() { return Ok(()) }
+    let sock = sock.0.as_ref().unwrap();
+    names.0.entry(0).or_insert_with(|| local_name.0.clone());
+    let mut players: Vec<(u8, bool)> = vec![(0, lobby.local_ready)];
+    for conn in conns.0.iter() {
+        let ready = lobby.players.iter().any(|(id, ready)| *id == conn.player_id && *ready);
+        players.push((conn.player_id, ready));
+    }
+    lobby.players = players;
+    if lobby.players.iter().all(|(_, ready)| *ready) {
+        if lobby.countdown.is_none() {
+            lobby.countdown = Some(tick_rate.ticks(COUNTDOWN_SECS) as u8);
+        }
+    } else {
+        lobby.countdown = None;
+    }
+    // count before sending, clients count down again on their next tick so they land on the same number as us
+    count_down(&mut lobby, &mut tick, &mut app_state_next_state);
+    let packet = LobbyState {
+        countdown: lobby.countdown,
+        seed: seed.0,
+        num_camps: num_camps.0,
+        map_width: map_size.width as u16,
+        map_height: map_size.height as u16,
+        spawn_settings: *spawn_settings,
+        map_choice: map_choice.clone(),
+        players: lobby.players.clone(),
+    };
+    let mut bytes: Vec<u8> = Vec::new();
+    packet.to_buf(&mut bytes);
+    host::send_all(bytes.as_slice(), sock.as_ref(), conns.0.iter().chain(spectators.0.iter()))
+        .and(host::send_name_list(&names, sock.as_ref(), &conns, &spectators))
+}
+
+/// Tells the host whether we're ready, and keeps counting down between LobbyStates
+/// so a lost packet doesn't hold us back.
+pub fn client_fixed(
+    mut lobby: ResMut<Lobby>,
+    sock: Res<net::Socket>,
+    res_id: Res<PlayerId>,
+    mut tick: ResMut<net::TickNum>,
+    mut app_state_next_state: ResMut<NextState<AppState>>,
+) -> Result<(), NetError> {
+    if sock.0.is_none() { return Ok(()) }
+    let sock = sock.0.as_ref().unwrap();
+    if res_id.0 != SPECTATOR_ID {
+        let packet = LobbyReady { ready: lobby.local_ready };
+        let mut bytes: Vec<u8> = Vec::new();
+        packet.to_buf(&mut bytes);
+        let host = sock.peer_addr().map_err(|_| NetError::NotConnected)?;
+        send_buf(bytes.as_slice(), sock.as_ref(), &host).map_err(|e| NetError::Send(host, e))?;
+    }
+    count_down(&mut lobby, &mut tick, &mut app_state_next_state);
+    Ok(())
+}
+
+// host and clients both run this once a tick, the round starts on tick 0 for everyone
+fn count_down(lobby: &mut Lobby, tick: &mut net::TickNum, app_state_next_state: &mut NextState<AppState>) {
+    if lobby.countdown.is_none() { return }
+    let remaining = lobby.countdown.unwrap();
+    if remaining == 0 {
+        tick.0 = 0;
+        app_state_next_state.set(AppState::Game);
+        return;
+    }
+    lobby.countdown = Some(remaining - 1);
+}
+
+/// The host can still change the map from the lobby, a match code in the seed box changes the rest of the settings with it
+/// and goes back to a generated map, since a code only describes those.
+/// Changing anything un-readies everyone so nobody gets dropped into a map they didn't agree to.
+pub fn host_settings(
+    mut lobby: ResMut<Lobby>,
+    num_camps_query: Query<&NumCampsInput, Changed<NumCampsInput>>,
+    map_seed_query: Query<&MapSeedInput, Changed<MapSeedInput>>,
+    mut map_config: ResMut<MapConfig>,
+    mut seed: ResMut<MapSeed>,
+    mut num_camps: ResMut<NumCamps>,
+    mut map_size: ResMut<MapSize>,
+    mut spawn_settings: ResMut<SpawnSettings>,
+    mut map_choice: ResMut<MapChoice>,
+) {
+    let mut changed = false;
+    for input in &num_camps_query {
+        if let Ok(parsed_num) = input.value.parse::<u8>() {
+            if parsed_num != num_camps.0 {
+                num_camps.0 = parsed_num;
+                changed = true;
+            }
+        }
+        map_config.num_camps = input.value.clone();
+    }
+    for input in &map_seed_query {
+        map_config.map_seed = input.value.clone();
+        if let Some(code) = MatchCode::decode(&input.value) {
+            let current = MatchCode { seed: seed.0, num_camps: num_camps.0, map_size: *map_size, spawn_settings: *spawn_settings };
+            if code != current || !map_choice.is_generated() {
+                *map_choice = MapChoice::default();
+                seed.0 = code.seed;
+                num_camps.0 = code.num_camps;
+                *map_size = code.map_size;
+                *spawn_settings = code.spawn_settings;
+                changed = true;
+            }
+            code.write_config(&mut map_config);
+            continue;
+        }
+        let parsed_num = seed_from_text(&input.value);
+        if parsed_num != seed.0 {
+            seed.0 = parsed_num;
+            changed = true;
+        }
+    }
+    if changed {
+        lobby.local_ready = false;
+        for (_, ready) in &mut lobby.players {
+            *ready = false;
+        }
+    }
+}
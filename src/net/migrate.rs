@@ -0,0 +1,149 @@
+use std::net::SocketAddr;
+use bevy::prelude::*;
+use crate::AppState;
+use crate::game::PlayerId;
+use crate::game::buffers::{HpBuffer, PosBuffer};
+use crate::game::components::Health;
+use crate::game::player::{PlayerLeaveEvent, PlayerNames, SPECTATOR_ID};
+use crate::net;
+use crate::net::chat::{Chat, SYSTEM_ID};
+use crate::net::conditioner::NetSim;
+use crate::net::error::NetError;
+use crate::net::host::{Connection, Connections, MaxPlayers, Spectators};
+use crate::net::packets::*;
+use crate::net::secure::MatchKey;
+use crate::net::transport::{self, LoopbackNetwork};
+
+pub const HOST_TIMEOUT_SECS: f32 = 3.;  // without hearing from the host before it's considered gone
+
+/// What a client needs to carry on if the host goes away.
+/// Everyone picks the same successor, the lowest player id still around, since they all got the same PeerList.
+#[derive(Resource, Default)]
+pub struct Migration {
+    pub peers: Vec<(u8, SocketAddr)>,  // from the last PeerList, us included
+    pub max_players: u8,
+    pub host_id: u8,  // 0 until someone takes over
+    pub silent_ticks: u16,  // fixed ticks since the host last sent anything
+    pub rejoining: bool,  // pointed at a new host, sending Rejoin until its first HostTick
+    pub took_over: bool,  // we're the new host, only then is a Rejoin any use
+    pub rejoined: Vec<(u8, SocketAddr)>,  // peers whose Rejoin we already took, the rest of theirs are ignored
+}
+
+/// we're the successor, become the host
+#[derive(Event)]
+pub struct TakeOverEvent;
+
+pub fn reset(mut migration: ResMut<Migration>) {
+    *migration = Migration::default();
+}
+
+/// Counts how long the host has been quiet, and once it's been too long
+/// drops it and moves on to the next one.
+pub fn watch_host(
+    mut migration: ResMut<Migration>,
+    mut sock: ResMut<net::Socket>,
+    res_id: Res<PlayerId>,
+    tick_rate: Res<net::TickRate>,
+    time: Res<Time>,
+    mut names: ResMut<PlayerNames>,
+    mut chat: ResMut<Chat>,
+    mut leave_writer: EventWriter<PlayerLeaveEvent>,
+    mut take_over_writer: EventWriter<TakeOverEvent>,
+    mut app_state_next_state: ResMut<NextState<AppState>>,
+) -> Result<(), NetError> {
+    if sock.0.is_none() { return Ok(()) }
+    let sock = sock.0.as_mut().unwrap();
+    migration.silent_ticks += 1;
+    if migration.rejoining {
+        let packet = Rejoin { player_id: res_id.0 };
+        let mut bytes: Vec<u8> = Vec::new();
+        packet.to_buf(&mut bytes);
+        let host = sock.peer_addr().map_err(|_| NetError::NotConnected)?;
+        // the successor might not have opened its port again yet, keep trying until it times out too
+        if let Err(e) = send_buf(bytes.as_slice(), sock.as_ref(), &host) {
+            debug!("Rejoin to {} didn't go out, trying again next tick: {}", host, e);
+        }
+    }
+    if migration.silent_ticks < tick_rate.ticks(HOST_TIMEOUT_SECS) { return Ok(()) }
+    // the host, or the successor we were waiting on, is gone
+    let old_host = migration.host_id;
+    migration.peers.retain(|(id, _)| *id != old_host);
+    let old_name = names.get(old_host);
+    names.0.remove(&old_host);
+    leave_writer.send(PlayerLeaveEvent(old_host));
+    let successor = migration.peers.iter().filter(|(id, _)| *id != SPECTATOR_ID).min_by_key(|(id, _)| *id).copied();
+    if successor.is_none() {
+        warn!("host left and there's nobody to take over");
+        app_state_next_state.set(AppState::GameOver);
+        return Ok(());
+    }
+    let (successor_id, successor_addr) = successor.unwrap();
+    info!("host {} timed out, player {} takes over", old_host, successor_id);
+    chat.push(SYSTEM_ID, format!("{} left, {} is hosting now", old_name, names.get(successor_id)), time.elapsed_seconds());
+    migration.host_id = successor_id;
+    migration.silent_ticks = 0;
+    if successor_id == res_id.0 {
+        migration.rejoining = false;
+        take_over_writer.send(TakeOverEvent);
+        return Ok(());
+    }
+    migration.rejoining = true;
+    sock.connect(successor_addr).map_err(|e| NetError::Connect(successor_addr, e))?;
+    Ok(())
+}
+
+/// Turns this client into the host, carrying on from the last snapshot it got.
+/// Everyone else in the PeerList is let straight back in, their Rejoin just confirms the address.
+pub fn take_over(
+    mut take_over_reader: EventReader<TakeOverEvent>,
+    mut migration: ResMut<Migration>,
+    res_id: Res<PlayerId>,
+    tick: Res<net::TickNum>,
+    mut is_host: ResMut<net::IsHost>,
+    mut sock: ResMut<net::Socket>,
+    mut conns: ResMut<Connections>,
+    mut spectators: ResMut<Spectators>,
+    mut max_players: ResMut<MaxPlayers>,
+    loopback: Option<Res<LoopbackNetwork>>,
+    sim: Res<NetSim>,
+    key: Res<MatchKey>,
+    mut buffers: Query<(&mut PosBuffer, &Transform, Option<(&mut HpBuffer, &Health)>)>,
+) -> Result<(), NetError> {
+    if take_over_reader.is_empty() { return Ok(()) }
+    take_over_reader.clear();
+    // our socket only hears from the old host, a fresh one on the same port hears from everyone
+    let local_addr = sock.0.take().ok_or(NetError::NotConnected)?.local_addr().map_err(|_| NetError::NotConnected)?;
+    sock.0 = Some(transport::bind(local_addr, loopback.as_deref(), &sim, &key).map_err(|e| NetError::Bind(local_addr.port(), e))?);
+    is_host.0 = true;
+    migration.took_over = true;
+    migration.rejoined.clear();
+    max_players.0 = migration.max_players;
+    conns.0.clear();
+    spectators.0.clear();
+    for (id, addr) in &migration.peers {
+        let conn = Connection {
+            addr: *addr,
+            player_id: *id,
+            rmt_num: 0,
+            ack: 0,
+            send_interval: 1,  // we don't know what they asked the old host for
+        };
+        if *id == SPECTATOR_ID {
+            spectators.0.push(conn);
+        } else if *id != res_id.0 {
+            conns.0.push(conn);
+        }
+    }
+    // anything out of view hasn't had a position in a while, the host needs one for everything it simulates
+    for (mut pb, tf, hp) in &mut buffers {
+        if pb.0.get(tick.0).is_none() {
+            pb.0.set(tick.0, Some(tf.translation.truncate()));
+        }
+        if let Some((mut hb, health)) = hp {
+            if hb.0.get(tick.0).is_none() {
+                hb.0.set(tick.0, Some(health.current));
+            }
+        }
+    }
+    Ok(())
+}
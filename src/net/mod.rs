@@ -1,9 +1,18 @@
 pub mod host;
+pub mod admin;
+pub mod chat;
 pub mod client;
+pub mod conditioner;
+pub mod error;
+pub mod inspect;
 pub mod lerp;
+pub mod lobby;
+pub mod migrate;
 pub mod packets;
+pub mod replay;
+pub mod secure;
+pub mod transport;
 
-use std::net::UdpSocket;
 use bevy::prelude::*;
 use crate::AppState;
 use crate::game::{enemy, movement};
@@ -11,10 +20,12 @@ use packets::{PlayerTickEvent, EnemyTickEvent, UserCmdEvent};
 use crate::game::buffers::{BUFFER_LEN, DirBuffer, EventBuffer, HpBuffer, PosBuffer};
 use crate::game::components::Player;
 use crate::game::player;
+use transport::Transport;
 
 
-pub const TICKRATE: u8 = 10;
-pub const TICKLEN_S: f32 = 1. / TICKRATE as f32;
+pub const DEFAULT_TICKRATE: u8 = 10;
+pub const MIN_TICKRATE: u8 = 5;
+pub const MAX_TICKRATE: u8 = 30;  // BUFFER_LEN ticks of history has to cover the lerp and DELAY
 pub const DELAY: u16 = 2;
 pub const MAGIC_NUMBER: u16 = 24835; // 8008135 % 69420
 pub const MAX_DATAGRAM_SIZE: usize = 1024;
@@ -22,12 +33,46 @@ pub const MAX_DATAGRAM_SIZE: usize = 1024;
 #[derive(Resource)]
 pub struct TickNum(pub u16);  // this is the tick we're writing to, NOT playing back
 
+/// Ticks simulated per second, picked by the host and sent to clients in the ConnectionResponse.
+/// Anything that used to be a number of ticks is kept in seconds and converted with this.
+#[derive(Resource, Clone, Copy)]
+pub struct TickRate(pub u8);
+
+impl TickRate {
+    pub fn tick_len(&self) -> f32 {
+        1. / self.0 as f32
+    }
+
+    /// how many ticks make up secs, at least 1
+    pub fn ticks(&self, secs: f32) -> u16 {
+        ((secs * self.0 as f32).round() as u16).max(1)
+    }
+}
+
+/// Snapshots per second. On the host, the most it sends to anyone,
+/// on a client, the most it asks the host for so a weak link isn't flooded.
+#[derive(Resource)]
+pub struct SendRate(pub u8);
+
+/// how many ticks the host waits between snapshots to one peer
+pub fn send_interval(tick_rate: u8, host_rate: u8, requested_rate: u8) -> u16 {
+    let mut rate = host_rate;
+    if requested_rate != 0 {
+        rate = rate.min(requested_rate);
+    }
+    let rate = rate.clamp(1, tick_rate);
+    (tick_rate as u16 + rate as u16 - 1) / rate as u16
+}
+
 #[derive(Resource)]
-pub struct Socket(pub Option<UdpSocket>);
+pub struct Socket(pub Option<Box<dyn Transport>>);
 
 #[derive(Resource)]
 pub struct IsHost(pub bool);
 
+#[derive(Resource)]
+pub struct IsSpectator(pub bool);  // client asked to join without a player
+
 #[derive(Resource)]
 pub struct Ack {
     pub rmt_num: u16,
@@ -39,33 +84,88 @@ pub struct NetPlugin;
 impl Plugin for NetPlugin {
     fn build(&self, app: &mut App) {
         app
-            .add_systems(Startup, (startup, host::startup))  // you cant conditionally run this unless you do a bunch of bullshit
+            .add_systems(Startup, (startup, host::startup, replay::startup))  // you cant conditionally run this unless you do a bunch of bullshit
             .add_systems(FixedUpdate,
                          (increment_tick.after(client::fixed).after(host::fixed).run_if(in_state(AppState::Game)),
-                         client::fixed.run_if(is_client).after(movement::update_buffer),
-                         host::fixed.run_if(is_host).after(enemy::fixed_move).after(movement::update_buffer),
-                         lerp::resolve_collisions.run_if(is_host).run_if(in_state(AppState::Game)).after(enemy::fixed_resolve).before(increment_tick)))
+                         client::fixed.pipe(error::log).run_if(is_client).after(movement::update_buffer),
+                         host::fixed.pipe(error::log).run_if(is_host).run_if(in_state(AppState::Game)).after(enemy::fixed_move).after(movement::update_buffer),
+                         lerp::resolve_collisions.run_if(is_host).run_if(in_state(AppState::Game)).after(enemy::fixed_resolve).before(increment_tick),
+                         replay::record.run_if(is_host).run_if(in_state(AppState::Game)).after(host::fixed).before(increment_tick),
+                         replay::playback.run_if(resource_exists::<replay::Replay>()).run_if(in_state(AppState::Game)).before(increment_tick)))
             .add_systems(Update,
                          (lerp::lerp_pos.after(host::update).after(player::handle_usercmd_events).after(increment_tick),
-                         client::update.run_if(is_client),
-                         host::update.run_if(is_host)))
-            .add_systems(OnEnter(AppState::Game), host::connect.run_if(is_host))
+                         client::update.pipe(error::return_to_menu).run_if(is_client),
+                         host::update.pipe(error::log).run_if(is_host),
+                         conditioner::cycle_preset))
+            .add_systems(Update, apply_tick_rate.run_if(resource_changed::<TickRate>()))
+            .add_systems(Update,
+                         (replay::start_replay.run_if(in_state(AppState::MainMenu)),
+                         replay::controls.run_if(in_state(AppState::Game))).run_if(resource_exists::<replay::Replay>()))
+            .add_systems(OnEnter(AppState::Game), replay::start_recording.run_if(is_host))
             .add_systems(OnExit(AppState::Game),
+                         (replay::stop_recording,
+                         replay::stop_replay.run_if(resource_exists::<replay::Replay>())))
+            .add_systems(Update,
+                         (chat::input,
+                         admin::run_commands.pipe(error::log).after(chat::input).before(chat::host_update),
+                         chat::host_update.pipe(error::log).run_if(is_host).after(chat::input).after(host::update),
+                         chat::client_update.pipe(error::log).run_if(is_client).after(chat::input).after(client::update)).run_if(in_state(AppState::Game)))
+            .add_systems(FixedUpdate,
+                         (chat::host_fixed.pipe(error::log).run_if(is_host),
+                         chat::client_fixed.pipe(error::log).run_if(is_client)).run_if(in_state(AppState::Game)).before(increment_tick))
+            .add_systems(OnEnter(AppState::Game), chat::reset)
+            .add_systems(FixedUpdate,
+                         (migrate::watch_host.pipe(error::return_to_menu).run_if(is_client),
+                         migrate::take_over.pipe(error::return_to_menu).after(migrate::watch_host)).run_if(in_state(AppState::Game)).before(increment_tick))
+            .add_systems(FixedUpdate,
+                         (lobby::host_fixed.pipe(error::log).run_if(is_host),
+                         lobby::client_fixed.pipe(error::return_to_menu).run_if(is_client)).run_if(in_state(AppState::Lobby)))
+            .add_systems(Update, lobby::host_settings.run_if(is_host).run_if(in_state(AppState::Lobby)))
+            .add_systems(OnEnter(AppState::Lobby), host::connect.pipe(error::return_to_menu).run_if(is_host))
+            .add_systems(OnEnter(AppState::GameOver),  // not OnExit(Game), a restart goes back to the lobby still connected
                      (client::disconnect.run_if(is_client),
                       host::disconnect.run_if(is_host)))
-            .add_systems(OnEnter(AppState::Connecting), client::connect.run_if(is_client))
+            .add_systems(OnEnter(AppState::MainMenu),  // backing out of the lobby
+                     (client::disconnect.run_if(is_client),
+                      host::disconnect.run_if(is_host),
+                      lobby::reset,
+                      migrate::reset))
+            .add_systems(OnEnter(AppState::Connecting), client::connect.pipe(error::return_to_menu).run_if(is_client))
             .add_event::<EnemyTickEvent>()
             .add_event::<PlayerTickEvent>()
-            .add_event::<UserCmdEvent>();
+            .add_event::<UserCmdEvent>()
+            .add_event::<chat::SendChatEvent>()
+            .add_event::<chat::SystemMessage>()
+            .add_event::<chat::ChatSendEvent>()
+            .add_event::<chat::ChatBroadcastEvent>()
+            .add_event::<chat::ChatAckEvent>()
+            .add_event::<admin::AdminCommand>()
+            .add_event::<migrate::TakeOverEvent>();
     }
 }
 
 pub fn startup(mut commands: Commands) {
-    commands.insert_resource(FixedTime::new_from_secs(TICKLEN_S));
+    commands.insert_resource(FixedTime::new_from_secs(TickRate(DEFAULT_TICKRATE).tick_len()));
+    commands.insert_resource(TickRate(DEFAULT_TICKRATE));
+    commands.insert_resource(SendRate(MAX_TICKRATE));
+    commands.insert_resource(secure::MatchKey::default());
     commands.insert_resource(TickNum { 0: 0 });
     commands.insert_resource(Socket(None));
     commands.insert_resource(IsHost(true));  // gets changed when you start the game
+    commands.insert_resource(IsSpectator(false));
+    commands.insert_resource(lobby::Lobby::default());
+    commands.insert_resource(chat::Chat::default());
+    commands.insert_resource(chat::ClientChat::default());
+    commands.insert_resource(chat::HostChat::default());
+    commands.insert_resource(admin::BanList::default());
+    commands.insert_resource(migrate::Migration::default());
     commands.insert_resource(Ack { rmt_num: 0, bitfield: 0 });
+    commands.insert_resource(conditioner::NetSim::from_args());
+}
+
+/// the host picked a tick rate, or a client just heard it in the ConnectionResponse
+pub fn apply_tick_rate(tick_rate: Res<TickRate>, mut fixed_time: ResMut<FixedTime>) {
+    *fixed_time = FixedTime::new_from_secs(tick_rate.tick_len());
 }
 
 pub fn increment_tick(
@@ -75,7 +175,8 @@ pub fn increment_tick(
     mut dir_buffers: Query<(&mut DirBuffer)>,
     mut hp_buffers: Query<(&mut HpBuffer)>,
 ) {
-    tick.0 += 1;
+    let _span = trace_span!("increment_tick", tick = tick.0).entered();
+    tick.0 = tick.0.wrapping_add(1);
     for (mut pb, pl) in &mut pos_buffers {
         if pb.0.get(tick.0).is_none() {
             let mut prev = None;
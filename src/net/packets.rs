@@ -1,11 +1,15 @@
-use std::io::Result;
-use std::net::{SocketAddr, UdpSocket};
+use std::io::{Error, ErrorKind, Result};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use bevy::prelude::*;
-use crate::game::components::{PowerUpType, Stats, StoredPowerUps};
-use crate::game::map::MAXCHESTS;
+use crate::game::components::{PowerUpType, Stats, StoredPowerUps, NUM_POWERUPS};
+use crate::game::map::SpawnSettings;
+use crate::game::symmetry::Symmetry;
+use crate::game::mapfile::MapChoice;
 use crate::net::MAGIC_NUMBER;
+use crate::net::transport::Transport;
 
 
+#[derive(Debug, Clone, Copy)]
 pub enum PacketType {
     ServerFull,  // sent by host every time request is received and server is full
     Disconnect,  // sent by client in disconnected state every time HostTick is received
@@ -13,6 +17,52 @@ pub enum PacketType {
     ConnectionResponse,  // sent by a host to a client who has requested connection
     HostTick,  // sent by host to all connected clients individually
     ClientTick,  // sent by client to host every FixedUpdate unless ServerFull received
+    LobbyState,  // sent by host to everyone every FixedUpdate while in the lobby
+    LobbyReady,  // sent by client to host every FixedUpdate while in the lobby
+    NameList,  // sent by host to everyone every FixedUpdate in the lobby, every second in game
+    ChatSend,  // sent by client to host until a ChatAck comes back
+    ChatBroadcast,  // sent by host to everyone until each of them sends a ChatAck
+    ChatAck,  // sent by whoever received a ChatSend or ChatBroadcast
+    Kicked,  // sent by host to a client it just kicked or banned
+    RoundEnd,  // sent by host to everyone when it ends the round early
+    Restart,  // sent by host to everyone when it restarts the round on a new seed
+    PeerList,  // sent by host to everyone so they can find each other if the host leaves
+    Rejoin,  // sent by client to the player taking over as host until a HostTick comes back
+    Hello,  // sent by a client with a match password instead of its first packet, see net::secure
+    Accept,  // sent by host back to a Hello with the right password
+    Refused,  // sent by host to a Hello with the wrong password, or anyone without one when it has one
+    Sealed,  // any other packet, encrypted with the session key
+}
+
+impl PacketType {
+    /// every type in wire order, so a byte off the wire can be turned back into one
+    const ALL: [PacketType; 21] = [
+        PacketType::ServerFull,
+        PacketType::Disconnect,
+        PacketType::ConnectionRequest,
+        PacketType::ConnectionResponse,
+        PacketType::HostTick,
+        PacketType::ClientTick,
+        PacketType::LobbyState,
+        PacketType::LobbyReady,
+        PacketType::NameList,
+        PacketType::ChatSend,
+        PacketType::ChatBroadcast,
+        PacketType::ChatAck,
+        PacketType::Kicked,
+        PacketType::RoundEnd,
+        PacketType::Restart,
+        PacketType::PeerList,
+        PacketType::Rejoin,
+        PacketType::Hello,
+        PacketType::Accept,
+        PacketType::Refused,
+        PacketType::Sealed,
+    ];
+
+    pub fn from_u8(pt: u8) -> Option<PacketType> {
+        PacketType::ALL.get(pt as usize).copied()
+    }
 }
 
 /// sent over the network to describe an enemy
@@ -64,22 +114,103 @@ pub struct UserCmdEvent {
     pub tick: UserCmd
 }
 
-pub fn send_buf(buf: &[u8], local: &UdpSocket, peer: &SocketAddr) -> Result<usize> {
+pub fn send_buf(buf: &[u8], local: &dyn Transport, peer: &SocketAddr) -> Result<usize> {
     if local.peer_addr().is_ok() {
         return local.send(buf);
     }
     return local.send_to(buf, peer);
 }
 
+/// errors unless there are len more bytes from i on, so a short or lying datagram can't index past the end
+fn need(buf: &[u8], i: usize, len: usize) -> Result<()> {
+    if i + len > buf.len() {
+        return Err(Error::from(ErrorKind::UnexpectedEof));
+    }
+    return Ok(());
+}
+
+/// strings go over the wire as a u8 length followed by utf8
+fn read_string(buf: &[u8], i: &mut usize) -> Result<String> {
+    need(buf, *i, 1)?;
+    let len = u8::from_be_bytes([buf[*i]].try_into().unwrap()) as usize;
+    *i += 1;
+    need(buf, *i, len)?;
+    let s = String::from_utf8(buf[*i..*i+len].to_vec()).map_err(|_| Error::from(ErrorKind::InvalidData))?;
+    *i += len;
+    return Ok(s);
+}
+
+/// the longest start of s that fits in max bytes without cutting a character in half
+pub fn truncate_str(s: &str, max: usize) -> &str {
+    let mut end = s.len().min(max);
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+fn write_string(bytes: &mut Vec<u8>, s: &str) {
+    let s = truncate_str(s, u8::MAX as usize);
+    bytes.extend_from_slice(&(s.len() as u8).to_be_bytes());
+    bytes.extend_from_slice(s.as_bytes());
+}
+
+// 4 or 6 | ip octets | port
+fn read_addr(buf: &[u8], i: &mut usize) -> Result<SocketAddr> {
+    need(buf, *i, 1)?;
+    let kind = buf[*i];
+    *i += 1;
+    need(buf, *i, if kind == 6 { 16 + 2 } else { 4 + 2 })?;
+    let ip = match kind {
+        4 => {
+            let octets: [u8; 4] = buf[*i..*i+4].try_into().unwrap();
+            *i += 4;
+            IpAddr::from(Ipv4Addr::from(octets))
+        },
+        6 => {
+            let octets: [u8; 16] = buf[*i..*i+16].try_into().unwrap();
+            *i += 16;
+            IpAddr::from(Ipv6Addr::from(octets))
+        },
+        _ => return Err(Error::from(ErrorKind::InvalidData)),
+    };
+    let port = u16::from_be_bytes(buf[*i..*i+2].try_into().unwrap());
+    *i += 2;
+    return Ok(SocketAddr::new(ip, port));
+}
+
+fn write_addr(bytes: &mut Vec<u8>, addr: &SocketAddr) {
+    match addr.ip() {
+        IpAddr::V4(ip) => {
+            bytes.push(4);
+            bytes.extend_from_slice(&ip.octets());
+        },
+        IpAddr::V6(ip) => {
+            bytes.push(6);
+            bytes.extend_from_slice(&ip.octets());
+        },
+    }
+    bytes.extend_from_slice(&addr.port().to_be_bytes());
+}
+
 pub trait Packet {
     fn from_buf(buf: &[u8]) -> Result<Self> where Self: Sized;
     fn to_buf(&self, bytes: &mut Vec<u8>);
 }
 
+// bytes each part of a HostTick takes in to_buf, for split
+const HOST_TICK_HEADER: usize = 3 + 2 + 2 + 4 + 1 + 5;  // magic and type, seq, rmt, ack, part, the five counts
+const ENEMY_TICK_SIZE: usize = 1 + 8 + 1 + 1;
+const PLAYER_TICK_SIZE: usize = 1 + 8 + 1 + 4 + 1 + 5 + 4 + NUM_POWERUPS;
+const POWERUP_SIZE: usize = 1 + 8;
+const CAMP_SIZE: usize = 2;
+const CHEST_SIZE: usize = 2;
+
 pub struct HostTick {
     pub seq_num: u16,
     pub rmt_num: u16,
     pub ack: u32,
+    pub part: u8,  // 0 unless the tick was too big for one datagram, see split
     pub enemies: Vec<EnemyTick>,
     pub players: Vec<PlayerTick>,
     pub powerups: Vec<(PowerUpType, Vec2)>,
@@ -89,6 +220,7 @@ pub struct HostTick {
 
 impl Packet for HostTick {
     fn from_buf(buf: &[u8]) -> Result<Self> {
+        need(buf, 0, HOST_TICK_HEADER - 3 - 2)?;  // the camp and chest counts are checked further down
         let mut i: usize = 0;
         let seq_num = u16::from_be_bytes(buf[i..i+2].try_into().unwrap());
         i += 2;
@@ -96,12 +228,16 @@ impl Packet for HostTick {
         i += 2;
         let ack = u32::from_be_bytes(buf[i..i+4].try_into().unwrap());
         i += 4;
+        let part = u8::from_be_bytes([buf[i]].try_into().unwrap());
+        i += 1;
         let enemy_count = u8::from_be_bytes([buf[i]].try_into().unwrap());
         i += 1;
         let player_count = u8::from_be_bytes([buf[i]].try_into().unwrap());
         i += 1;
         let powerup_count = u8::from_be_bytes([buf[i]].try_into().unwrap());
         i += 1;
+        // and the camp count after them
+        need(buf, i, enemy_count as usize * ENEMY_TICK_SIZE + player_count as usize * PLAYER_TICK_SIZE + powerup_count as usize * POWERUP_SIZE + 1)?;
         let mut enemies: Vec<EnemyTick> = Vec::new();
         for _ in 0..enemy_count {
             let id = u8::from_be_bytes([buf[i]].try_into().unwrap());
@@ -173,7 +309,7 @@ impl Packet for HostTick {
                 2 => PowerUpType::DamageReductionUp,
                 3 => PowerUpType::AttackSpeedUp,
                 4 => PowerUpType::MovementSpeedUp,
-                _ => panic!()
+                _ => return Err(Error::from(ErrorKind::InvalidData)),
             };
             let x = f32::from_be_bytes(buf[i..i+4].try_into().unwrap());
             i += 4;
@@ -184,14 +320,19 @@ impl Packet for HostTick {
         let mut camps: Vec<(u8, u8)> = Vec::new();
         let num_camps = u8::from_be_bytes([buf[i]].try_into().unwrap());
         i += 1;
+        need(buf, i, num_camps as usize * CAMP_SIZE + 1)?;
         for _ in 0..num_camps {
             let id = u8::from_be_bytes([buf[i]].try_into().unwrap());
             i += 1;
             let count = u8::from_be_bytes([buf[i]].try_into().unwrap());
+            i += 1;
             camps.push((id, count));
         }
         let mut chests: Vec<(u8, u8)> = Vec::new();
-        for _ in 0..MAXCHESTS {
+        let num_chests = u8::from_be_bytes([buf[i]].try_into().unwrap());
+        i += 1;
+        need(buf, i, num_chests as usize * CHEST_SIZE)?;
+        for _ in 0..num_chests {
             let id = u8::from_be_bytes([buf[i]].try_into().unwrap());
             i += 1;
             let hp = u8::from_be_bytes([buf[i]].try_into().unwrap());
@@ -202,6 +343,7 @@ impl Packet for HostTick {
             seq_num,
             rmt_num,
             ack,
+            part,
             enemies,
             players,
             powerups,
@@ -216,17 +358,24 @@ impl Packet for HostTick {
         bytes.extend_from_slice(&self.seq_num.to_be_bytes());
         bytes.extend_from_slice(&self.rmt_num.to_be_bytes());
         bytes.extend_from_slice(&self.ack.to_be_bytes());
-        bytes.extend_from_slice(&(self.enemies.len() as u8).to_be_bytes());
-        bytes.extend_from_slice(&(self.players.len() as u8).to_be_bytes());
-        bytes.extend_from_slice(&(self.powerups.len() as u8).to_be_bytes());
-        for enemy in &self.enemies {
+        bytes.extend_from_slice(&self.part.to_be_bytes());
+        // split keeps every list to a u8 count, anything past that would throw the count off so it's left out
+        let enemies = &self.enemies[..self.enemies.len().min(u8::MAX as usize)];
+        let players = &self.players[..self.players.len().min(u8::MAX as usize)];
+        let powerups = &self.powerups[..self.powerups.len().min(u8::MAX as usize)];
+        let camps = &self.camps[..self.camps.len().min(u8::MAX as usize)];
+        let chests = &self.chests[..self.chests.len().min(u8::MAX as usize)];
+        bytes.extend_from_slice(&(enemies.len() as u8).to_be_bytes());
+        bytes.extend_from_slice(&(players.len() as u8).to_be_bytes());
+        bytes.extend_from_slice(&(powerups.len() as u8).to_be_bytes());
+        for enemy in enemies {
             bytes.extend_from_slice(&enemy.id.to_be_bytes());
             bytes.extend_from_slice(&enemy.pos.x.to_be_bytes());
             bytes.extend_from_slice(&enemy.pos.y.to_be_bytes());
             bytes.extend_from_slice(&enemy.hp.to_be_bytes());
             bytes.extend_from_slice(&enemy.events.to_be_bytes());
         }
-        for player in &self.players {
+        for player in players {
             bytes.extend_from_slice(&player.id.to_be_bytes());
             bytes.extend_from_slice(&player.pos.x.to_be_bytes());
             bytes.extend_from_slice(&player.pos.y.to_be_bytes());
@@ -244,23 +393,70 @@ impl Packet for HostTick {
             }
         }
 
-        for powerup in &self.powerups {
+        for powerup in powerups {
             bytes.extend_from_slice(&(powerup.0 as u8).to_be_bytes());
             bytes.extend_from_slice(&powerup.1.x.to_be_bytes());
             bytes.extend_from_slice(&powerup.1.y.to_be_bytes());
         }
-        bytes.extend_from_slice(&(self.camps.len() as u8).to_be_bytes());
-        for camp in &self.camps {
+        bytes.extend_from_slice(&(camps.len() as u8).to_be_bytes());
+        for camp in camps {
             bytes.extend_from_slice(&camp.0.to_be_bytes());
             bytes.extend_from_slice(&camp.1.to_be_bytes());
         }
-        for chest in &self.chests {
+        bytes.extend_from_slice(&(chests.len() as u8).to_be_bytes());
+        for chest in chests {
             bytes.extend_from_slice(&chest.0.to_be_bytes());
             bytes.extend_from_slice(&chest.1.to_be_bytes());
         }
     }
 }
 
+impl HostTick {
+    /// Cuts the tick into parts that each encode to at most budget bytes, numbered from 0 and all with this tick's seq and acks.
+    /// Players go first, then powerups, enemies, camps and chests, each list carrying on into the next part where it runs out of room.
+    pub fn split(mut self, budget: usize) -> Vec<HostTick> {
+        let mut parts: Vec<HostTick> = Vec::new();
+        loop {
+            let mut part = HostTick {
+                seq_num: self.seq_num,
+                rmt_num: self.rmt_num,
+                ack: self.ack,
+                part: parts.len().min(u8::MAX as usize) as u8,
+                enemies: Vec::new(),
+                players: Vec::new(),
+                powerups: Vec::new(),
+                camps: Vec::new(),
+                chests: Vec::new(),
+            };
+            let mut room = budget.saturating_sub(HOST_TICK_HEADER);
+            take(&mut self.players, &mut part.players, PLAYER_TICK_SIZE, &mut room);
+            take(&mut self.powerups, &mut part.powerups, POWERUP_SIZE, &mut room);
+            take(&mut self.enemies, &mut part.enemies, ENEMY_TICK_SIZE, &mut room);
+            take(&mut self.camps, &mut part.camps, CAMP_SIZE, &mut room);
+            take(&mut self.chests, &mut part.chests, CHEST_SIZE, &mut room);
+            let empty = part.players.is_empty() && part.powerups.is_empty() && part.enemies.is_empty() && part.camps.is_empty() && part.chests.is_empty();
+            // an empty part is only any use as the first one, a tick with nothing in it still moves the client along
+            if !empty || parts.is_empty() {
+                parts.push(part);
+            }
+            let left = self.players.len() + self.powerups.len() + self.enemies.len() + self.camps.len() + self.chests.len();
+            if left == 0 { break }
+            if empty {
+                warn!("HostTick budget of {} bytes is too small, dropped {} entries", budget, left);
+                break;
+            }
+        }
+        return parts;
+    }
+}
+
+// moves as many off the front of from as fit in room, at most u8::MAX
+fn take<T>(from: &mut Vec<T>, to: &mut Vec<T>, size: usize, room: &mut usize) {
+    let n = from.len().min(*room / size).min(u8::MAX as usize);
+    to.extend(from.drain(..n));
+    *room -= n * size;
+}
+
 pub struct ClientTick {
     pub seq_num: u16,
     pub rmt_num: u16,
@@ -270,6 +466,7 @@ pub struct ClientTick {
 
 impl Packet for ClientTick {
     fn from_buf(buf: &[u8]) -> Result<Self> {
+        need(buf, 0, 2 + 2 + 4 + 8 + 4 + 1)?;
         let mut i: usize = 0;
         let seq_num = u16::from_be_bytes(buf[i..i+2].try_into().unwrap());
         i += 2;
@@ -311,16 +508,55 @@ impl Packet for ClientTick {
     }
 }
 
+pub struct ConnectionRequest {
+    pub spectator: bool,  // spectators don't get a player and see the whole map
+    pub name: String,  // what the player wants to be called, the host cleans it up
+    pub send_rate: u8,  // most snapshots per second the client wants, 0 for no limit
+}
+
+impl Packet for ConnectionRequest {
+    fn from_buf(buf: &[u8]) -> Result<Self> {
+        need(buf, 0, 1)?;
+        let mut i: usize = 0;
+        let spectator = u8::from_be_bytes([buf[i]].try_into().unwrap()) != 0;
+        i += 1;
+        let name = read_string(buf, &mut i)?;
+        need(buf, i, 1)?;
+        let send_rate = u8::from_be_bytes([buf[i]].try_into().unwrap());
+        return Ok(ConnectionRequest { spectator, name, send_rate });
+    }
+
+    fn to_buf(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&MAGIC_NUMBER.to_be_bytes());
+        bytes.extend_from_slice(&(PacketType::ConnectionRequest as u8).to_be_bytes());
+        bytes.extend_from_slice(&(self.spectator as u8).to_be_bytes());
+        write_string(bytes, &self.name);
+        bytes.extend_from_slice(&self.send_rate.to_be_bytes());
+    }
+}
+
 pub struct ConnectionResponse {
-    pub player_id: u8,
-    pub seed: u64
+    pub player_id: u8,  // SPECTATOR_ID for spectators
+    pub seed: u64,
+    pub tick_rate: u8,
+    pub map_width: u16,
+    pub map_height: u16,
+    pub spawn_settings: SpawnSettings,
+    pub map_choice: MapChoice,
 }
 
 impl Packet for ConnectionResponse {
     fn from_buf(buf: &[u8]) -> Result<Self> {
+        need(buf, 0, 14)?;
         let player_id = u8::from_be_bytes([buf[0]].try_into().unwrap());
         let seed = u64::from_be_bytes(buf[1..9].try_into().unwrap());
-        return Ok(ConnectionResponse { player_id, seed });
+        let tick_rate = u8::from_be_bytes([buf[9]].try_into().unwrap());
+        let map_width = u16::from_be_bytes(buf[10..12].try_into().unwrap());
+        let map_height = u16::from_be_bytes(buf[12..14].try_into().unwrap());
+        let mut i: usize = 14;
+        let spawn_settings = read_spawn_settings(buf, &mut i)?;
+        let map_choice = read_map_choice(buf, &mut i)?;
+        return Ok(ConnectionResponse { player_id, seed, tick_rate, map_width, map_height, spawn_settings, map_choice });
     }
 
     fn to_buf(&self, bytes: &mut Vec<u8>) {
@@ -328,10 +564,41 @@ impl Packet for ConnectionResponse {
         bytes.extend_from_slice(&(PacketType::ConnectionResponse as u8).to_be_bytes());
         bytes.extend_from_slice(&self.player_id.to_be_bytes());
         bytes.extend_from_slice(&self.seed.to_be_bytes());
+        bytes.extend_from_slice(&self.tick_rate.to_be_bytes());
+        bytes.extend_from_slice(&self.map_width.to_be_bytes());
+        bytes.extend_from_slice(&self.map_height.to_be_bytes());
+        write_spawn_settings(&self.spawn_settings, bytes);
+        write_map_choice(&self.map_choice, bytes);
     }
 }
 
-pub fn send_empty_packet(pt: PacketType, local: &UdpSocket, peer: &SocketAddr) -> Result<usize> {
+// chests, enemies per camp, EID percentage, symmetry, the reader runs them back through SpawnSettings::new to keep them in range
+fn write_spawn_settings(settings: &SpawnSettings, bytes: &mut Vec<u8>) {
+    bytes.extend_from_slice(&[settings.num_chests, settings.enemies_per_camp, settings.eid_percentage, settings.symmetry.to_byte()]);
+}
+
+fn read_spawn_settings(buf: &[u8], i: &mut usize) -> Result<SpawnSettings> {
+    need(buf, *i, 4)?;
+    let settings = SpawnSettings::new(buf[*i], buf[*i+1], buf[*i+2], Symmetry::from_byte(buf[*i+3]));
+    *i += 4;
+    return Ok(settings);
+}
+
+// map name, empty for a generated map | u64 hash of the map file
+fn read_map_choice(buf: &[u8], i: &mut usize) -> Result<MapChoice> {
+    let name = read_string(buf, i)?;
+    need(buf, *i, 8)?;
+    let hash = u64::from_be_bytes(buf[*i..*i+8].try_into().unwrap());
+    *i += 8;
+    return Ok(MapChoice { name, hash });
+}
+
+fn write_map_choice(choice: &MapChoice, bytes: &mut Vec<u8>) {
+    write_string(bytes, &choice.name);
+    bytes.extend_from_slice(&choice.hash.to_be_bytes());
+}
+
+pub fn send_empty_packet(pt: PacketType, local: &dyn Transport, peer: &SocketAddr) -> Result<usize> {
     let mut bytes: Vec<u8> = Vec::new();
     bytes.extend_from_slice(&MAGIC_NUMBER.to_be_bytes());
     bytes.extend_from_slice(&(pt as u8).to_be_bytes());
@@ -339,4 +606,442 @@ pub fn send_empty_packet(pt: PacketType, local: &UdpSocket, peer: &SocketAddr) -
         return local.send(bytes.as_slice());
     }
     return local.send_to(bytes.as_slice(), peer);
-}
\ No newline at end of file
+}
+
+/// who's in the lobby and what the round will look like
+pub struct LobbyState {
+    pub countdown: Option<u8>,  // ticks until the round starts, None if not everyone is ready
+    pub seed: u64,
+    pub num_camps: u8,
+    pub map_width: u16,
+    pub map_height: u16,
+    pub spawn_settings: SpawnSettings,
+    pub map_choice: MapChoice,
+    pub players: Vec<(u8, bool)>,  // (player id, ready)
+}
+
+impl Packet for LobbyState {
+    fn from_buf(buf: &[u8]) -> Result<Self> {
+        need(buf, 0, 1 + 8 + 1 + 2 + 2)?;
+        let mut i: usize = 0;
+        let countdown = u8::from_be_bytes([buf[i]].try_into().unwrap());
+        let countdown = if countdown == u8::MAX { None } else { Some(countdown) };
+        i += 1;
+        let seed = u64::from_be_bytes(buf[i..i+8].try_into().unwrap());
+        i += 8;
+        let num_camps = u8::from_be_bytes([buf[i]].try_into().unwrap());
+        i += 1;
+        let map_width = u16::from_be_bytes(buf[i..i+2].try_into().unwrap());
+        i += 2;
+        let map_height = u16::from_be_bytes(buf[i..i+2].try_into().unwrap());
+        i += 2;
+        let spawn_settings = read_spawn_settings(buf, &mut i)?;
+        let map_choice = read_map_choice(buf, &mut i)?;
+        need(buf, i, 1)?;
+        let player_count = u8::from_be_bytes([buf[i]].try_into().unwrap());
+        i += 1;
+        need(buf, i, player_count as usize * 2)?;
+        let mut players: Vec<(u8, bool)> = Vec::new();
+        for _ in 0..player_count {
+            let id = u8::from_be_bytes([buf[i]].try_into().unwrap());
+            i += 1;
+            let ready = u8::from_be_bytes([buf[i]].try_into().unwrap()) != 0;
+            i += 1;
+            players.push((id, ready));
+        }
+        return Ok(LobbyState { countdown, seed, num_camps, map_width, map_height, spawn_settings, map_choice, players });
+    }
+
+    fn to_buf(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&MAGIC_NUMBER.to_be_bytes());
+        bytes.extend_from_slice(&(PacketType::LobbyState as u8).to_be_bytes());
+        bytes.extend_from_slice(&self.countdown.unwrap_or(u8::MAX).to_be_bytes());
+        bytes.extend_from_slice(&self.seed.to_be_bytes());
+        bytes.extend_from_slice(&self.num_camps.to_be_bytes());
+        bytes.extend_from_slice(&self.map_width.to_be_bytes());
+        bytes.extend_from_slice(&self.map_height.to_be_bytes());
+        write_spawn_settings(&self.spawn_settings, bytes);
+        write_map_choice(&self.map_choice, bytes);
+        bytes.extend_from_slice(&(self.players.len() as u8).to_be_bytes());
+        for (id, ready) in &self.players {
+            bytes.extend_from_slice(&id.to_be_bytes());
+            bytes.extend_from_slice(&(*ready as u8).to_be_bytes());
+        }
+    }
+}
+
+pub struct LobbyReady {
+    pub ready: bool
+}
+
+impl Packet for LobbyReady {
+    fn from_buf(buf: &[u8]) -> Result<Self> {
+        need(buf, 0, 1)?;
+        let ready = u8::from_be_bytes([buf[0]].try_into().unwrap()) != 0;
+        return Ok(LobbyReady { ready });
+    }
+
+    fn to_buf(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&MAGIC_NUMBER.to_be_bytes());
+        bytes.extend_from_slice(&(PacketType::LobbyReady as u8).to_be_bytes());
+        bytes.extend_from_slice(&(self.ready as u8).to_be_bytes());
+    }
+}
+
+/// everyone's name, sent often enough that late joiners and lost packets catch up on their own
+pub struct NameList {
+    pub names: Vec<(u8, String)>,  // (player id, name)
+}
+
+impl Packet for NameList {
+    fn from_buf(buf: &[u8]) -> Result<Self> {
+        need(buf, 0, 1)?;
+        let mut i: usize = 0;
+        let count = u8::from_be_bytes([buf[i]].try_into().unwrap());
+        i += 1;
+        let mut names: Vec<(u8, String)> = Vec::new();
+        for _ in 0..count {
+            need(buf, i, 1)?;
+            let id = u8::from_be_bytes([buf[i]].try_into().unwrap());
+            i += 1;
+            let name = read_string(buf, &mut i)?;
+            names.push((id, name));
+        }
+        return Ok(NameList { names });
+    }
+
+    fn to_buf(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&MAGIC_NUMBER.to_be_bytes());
+        bytes.extend_from_slice(&(PacketType::NameList as u8).to_be_bytes());
+        bytes.extend_from_slice(&(self.names.len() as u8).to_be_bytes());
+        for (id, name) in &self.names {
+            bytes.extend_from_slice(&id.to_be_bytes());
+            write_string(bytes, name);
+        }
+    }
+}
+
+/// a chat message on its way to the host
+pub struct ChatSend {
+    pub seq: u16,  // counts up per client so the host can drop resends
+    pub text: String,
+}
+
+impl Packet for ChatSend {
+    fn from_buf(buf: &[u8]) -> Result<Self> {
+        need(buf, 0, 2)?;
+        let mut i: usize = 0;
+        let seq = u16::from_be_bytes(buf[i..i+2].try_into().unwrap());
+        i += 2;
+        let text = read_string(buf, &mut i)?;
+        return Ok(ChatSend { seq, text });
+    }
+
+    fn to_buf(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&MAGIC_NUMBER.to_be_bytes());
+        bytes.extend_from_slice(&(PacketType::ChatSend as u8).to_be_bytes());
+        bytes.extend_from_slice(&self.seq.to_be_bytes());
+        write_string(bytes, &self.text);
+    }
+}
+
+/// a chat message relayed by the host to everyone
+pub struct ChatBroadcast {
+    pub id: u16,  // counts up on the host so clients can drop resends
+    pub from: u8,  // player id, or chat::SYSTEM_ID for the host's own announcements
+    pub text: String,
+}
+
+impl Packet for ChatBroadcast {
+    fn from_buf(buf: &[u8]) -> Result<Self> {
+        need(buf, 0, 3)?;
+        let mut i: usize = 0;
+        let id = u16::from_be_bytes(buf[i..i+2].try_into().unwrap());
+        i += 2;
+        let from = u8::from_be_bytes([buf[i]].try_into().unwrap());
+        i += 1;
+        let text = read_string(buf, &mut i)?;
+        return Ok(ChatBroadcast { id, from, text });
+    }
+
+    fn to_buf(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&MAGIC_NUMBER.to_be_bytes());
+        bytes.extend_from_slice(&(PacketType::ChatBroadcast as u8).to_be_bytes());
+        bytes.extend_from_slice(&self.id.to_be_bytes());
+        bytes.extend_from_slice(&self.from.to_be_bytes());
+        write_string(bytes, &self.text);
+    }
+}
+
+/// acks a ChatSend (seq) or a ChatBroadcast (id)
+pub struct ChatAck {
+    pub seq: u16,
+}
+
+impl Packet for ChatAck {
+    fn from_buf(buf: &[u8]) -> Result<Self> {
+        need(buf, 0, 2)?;
+        let seq = u16::from_be_bytes(buf[0..2].try_into().unwrap());
+        return Ok(ChatAck { seq });
+    }
+
+    fn to_buf(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&MAGIC_NUMBER.to_be_bytes());
+        bytes.extend_from_slice(&(PacketType::ChatAck as u8).to_be_bytes());
+        bytes.extend_from_slice(&self.seq.to_be_bytes());
+    }
+}
+
+/// the host doesn't want us anymore
+pub struct Kicked {
+    pub reason: String,
+}
+
+impl Packet for Kicked {
+    fn from_buf(buf: &[u8]) -> Result<Self> {
+        let mut i: usize = 0;
+        let reason = read_string(buf, &mut i)?;
+        return Ok(Kicked { reason });
+    }
+
+    fn to_buf(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&MAGIC_NUMBER.to_be_bytes());
+        bytes.extend_from_slice(&(PacketType::Kicked as u8).to_be_bytes());
+        write_string(bytes, &self.reason);
+    }
+}
+
+/// back to the lobby with everyone still connected, to play again on a new map
+pub struct Restart {
+    pub seed: u64,
+}
+
+impl Packet for Restart {
+    fn from_buf(buf: &[u8]) -> Result<Self> {
+        need(buf, 0, 8)?;
+        let seed = u64::from_be_bytes(buf[0..8].try_into().unwrap());
+        return Ok(Restart { seed });
+    }
+
+    fn to_buf(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&MAGIC_NUMBER.to_be_bytes());
+        bytes.extend_from_slice(&(PacketType::Restart as u8).to_be_bytes());
+        bytes.extend_from_slice(&self.seed.to_be_bytes());
+    }
+}
+
+/// where everyone is, as the host sees them, so the next host can be found if this one goes away
+pub struct PeerList {
+    pub max_players: u8,
+    pub peers: Vec<(u8, SocketAddr)>,  // (player id, address), spectators have SPECTATOR_ID
+}
+
+impl Packet for PeerList {
+    fn from_buf(buf: &[u8]) -> Result<Self> {
+        need(buf, 0, 2)?;
+        let mut i: usize = 0;
+        let max_players = u8::from_be_bytes([buf[i]].try_into().unwrap());
+        i += 1;
+        let count = u8::from_be_bytes([buf[i]].try_into().unwrap());
+        i += 1;
+        let mut peers: Vec<(u8, SocketAddr)> = Vec::new();
+        for _ in 0..count {
+            need(buf, i, 1)?;
+            let id = u8::from_be_bytes([buf[i]].try_into().unwrap());
+            i += 1;
+            let addr = read_addr(buf, &mut i)?;
+            peers.push((id, addr));
+        }
+        return Ok(PeerList { max_players, peers });
+    }
+
+    fn to_buf(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&MAGIC_NUMBER.to_be_bytes());
+        bytes.extend_from_slice(&(PacketType::PeerList as u8).to_be_bytes());
+        bytes.extend_from_slice(&self.max_players.to_be_bytes());
+        bytes.extend_from_slice(&(self.peers.len() as u8).to_be_bytes());
+        for (id, addr) in &self.peers {
+            bytes.extend_from_slice(&id.to_be_bytes());
+            write_addr(bytes, addr);
+        }
+    }
+}
+
+/// the host left and we're still player_id, sent to whoever took over
+pub struct Rejoin {
+    pub player_id: u8,
+}
+
+impl Packet for Rejoin {
+    fn from_buf(buf: &[u8]) -> Result<Self> {
+        need(buf, 0, 1)?;
+        let player_id = u8::from_be_bytes([buf[0]].try_into().unwrap());
+        return Ok(Rejoin { player_id });
+    }
+
+    fn to_buf(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&MAGIC_NUMBER.to_be_bytes());
+        bytes.extend_from_slice(&(PacketType::Rejoin as u8).to_be_bytes());
+        bytes.extend_from_slice(&self.player_id.to_be_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::MAX_DATAGRAM_SIZE;
+
+    // what from_buf gets, the magic number and type already read off
+    fn body(packet: &impl Packet) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::new();
+        packet.to_buf(&mut bytes);
+        bytes.split_off(3)
+    }
+
+    fn host_tick() -> HostTick {
+        HostTick {
+            seq_num: 300,
+            rmt_num: 298,
+            ack: 0b1011,
+            part: 0,
+            enemies: vec![EnemyTick { id: 3, pos: Vec2::new(-12.5, 40.), hp: 80, events: 2 }],
+            players: vec![PlayerTick {
+                id: 1,
+                pos: Vec2::new(100., -7.25),
+                hp: 55,
+                dir: 1.5,
+                events: 4,
+                stats: Stats { score: 9, enemies_killed: 4, players_killed: 1, camps_captured: 2, deaths: 3, kd_ratio: 0.5 },
+                powerups: StoredPowerUps { power_ups: [1, 0, 2, 0, 5] },
+            }],
+            powerups: vec![(PowerUpType::AttackSpeedUp, Vec2::new(8., 16.))],
+            camps: vec![(0, 4), (2, 1), (7, 0)],
+            chests: vec![(1, 100), (4, 25)],
+        }
+    }
+
+    #[test]
+    fn host_tick_round_trip() {
+        let packet = host_tick();
+        let decoded = HostTick::from_buf(&body(&packet)).unwrap();
+        assert_eq!((decoded.seq_num, decoded.rmt_num, decoded.ack), (packet.seq_num, packet.rmt_num, packet.ack));
+        assert_eq!(decoded.enemies.len(), 1);
+        assert_eq!((decoded.enemies[0].id, decoded.enemies[0].pos, decoded.enemies[0].hp), (3, Vec2::new(-12.5, 40.), 80));
+        assert_eq!(decoded.players.len(), 1);
+        assert_eq!((decoded.players[0].pos, decoded.players[0].dir), (Vec2::new(100., -7.25), 1.5));
+        assert_eq!(decoded.players[0].stats.kd_ratio, 0.5);
+        assert!(decoded.players[0].powerups == packet.players[0].powerups);
+        assert_eq!(decoded.powerups, packet.powerups);
+        // every camp used to be read one byte short, throwing off the camps after it and the chests
+        assert_eq!(decoded.camps, packet.camps);
+        assert_eq!(decoded.chests, packet.chests);
+    }
+
+    // every packet reads all of its bytes, so anything cut short has to be an error and not a panic
+    fn truncations_fail<P: Packet>(packet: &P) {
+        let bytes = body(packet);
+        assert!(P::from_buf(&bytes).is_ok());
+        for len in 0..bytes.len() {
+            assert!(P::from_buf(&bytes[..len]).is_err(), "{} of {} bytes decoded", len, bytes.len());
+        }
+    }
+
+    #[test]
+    fn short_buffers_are_errors() {
+        truncations_fail(&host_tick());
+        truncations_fail(&ClientTick { seq_num: 1, rmt_num: 2, ack: 3, tick: UserCmd { pos: Vec2::ONE, dir: 0.5, events: 1 } });
+        truncations_fail(&ConnectionRequest { spectator: false, name: "ferris".to_string(), send_rate: 30 });
+        truncations_fail(&ConnectionResponse {
+            player_id: 2,
+            seed: 77,
+            tick_rate: 60,
+            map_width: 128,
+            map_height: 96,
+            spawn_settings: SpawnSettings::default(),
+            map_choice: MapChoice { name: "arena".to_string(), hash: 5 },
+        });
+        truncations_fail(&LobbyState {
+            countdown: Some(3),
+            seed: 77,
+            num_camps: 4,
+            map_width: 128,
+            map_height: 96,
+            spawn_settings: SpawnSettings::default(),
+            map_choice: MapChoice::default(),
+            players: vec![(0, true), (1, false)],
+        });
+        truncations_fail(&LobbyReady { ready: true });
+        truncations_fail(&NameList { names: vec![(0, "host".to_string()), (1, "ü".to_string())] });
+        truncations_fail(&ChatSend { seq: 9, text: "gg".to_string() });
+        truncations_fail(&ChatBroadcast { id: 9, from: 1, text: "gg".to_string() });
+        truncations_fail(&ChatAck { seq: 9 });
+        truncations_fail(&Kicked { reason: "banned".to_string() });
+        truncations_fail(&Restart { seed: 12 });
+        truncations_fail(&PeerList { max_players: 8, peers: vec![
+            (1, "10.0.0.2:5000".parse().unwrap()),
+            (2, "[::1]:5001".parse().unwrap()),
+        ] });
+        truncations_fail(&Rejoin { player_id: 3 });
+    }
+
+    #[test]
+    fn unknown_powerup_is_an_error() {
+        let mut packet = host_tick();
+        packet.enemies.clear();
+        packet.players.clear();
+        let mut bytes = body(&packet);
+        // seq, rmt, ack, part and the three counts, then the powerup's type
+        bytes[12] = 200;
+        assert!(HostTick::from_buf(&bytes).is_err());
+    }
+
+    #[test]
+    fn split_fits_every_part_in_a_datagram() {
+        let mut packet = host_tick();
+        let player = packet.players.pop().unwrap();
+        packet.players = (0..8).map(|id| PlayerTick {
+            id,
+            pos: player.pos,
+            hp: player.hp,
+            dir: player.dir,
+            events: player.events,
+            stats: player.stats.clone(),
+            powerups: player.powerups.clone(),
+        }).collect();
+        packet.enemies = (0..=u8::MAX).map(|id| EnemyTick { id, pos: Vec2::new(id as f32, 0.), hp: id, events: 0 }).collect();
+        packet.enemies.extend((0..50).map(|id| EnemyTick { id, pos: Vec2::ZERO, hp: 1, events: 0 }));
+        packet.powerups = (0..300).map(|i| (PowerUpType::Meat, Vec2::new(i as f32, 1.))).collect();
+        packet.camps = (0..300).map(|i| ((i % 256) as u8, 1)).collect();
+        packet.chests = (0..20).map(|i| (i, 100)).collect();
+
+        let parts = packet.split(MAX_DATAGRAM_SIZE);
+        assert!(parts.len() > 1);
+        let (mut enemies, mut players, mut powerups, mut camps, mut chests) = (0, 0, 0, 0, 0);
+        for (n, part) in parts.iter().enumerate() {
+            let mut bytes: Vec<u8> = Vec::new();
+            part.to_buf(&mut bytes);
+            assert!(bytes.len() <= MAX_DATAGRAM_SIZE, "part {} is {} bytes", n, bytes.len());
+            let decoded = HostTick::from_buf(&bytes[3..]).unwrap();
+            assert_eq!((decoded.seq_num, decoded.part as usize), (300, n));
+            enemies += decoded.enemies.len();
+            players += decoded.players.len();
+            powerups += decoded.powerups.len();
+            camps += decoded.camps.len();
+            chests += decoded.chests.len();
+        }
+        assert_eq!((enemies, players, powerups, camps, chests), (306, 8, 300, 300, 20));
+    }
+
+    #[test]
+    fn split_keeps_an_empty_tick() {
+        let mut packet = host_tick();
+        packet.enemies.clear();
+        packet.players.clear();
+        packet.powerups.clear();
+        packet.camps.clear();
+        packet.chests.clear();
+        let parts = packet.split(MAX_DATAGRAM_SIZE);
+        assert_eq!(parts.len(), 1);
+        assert_eq!(parts[0].part, 0);
+    }
+}
@@ -0,0 +1,351 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Result, Write};
+use std::time::Duration;
+use bevy::prelude::*;
+use crate::AppState;
+use crate::cli_arg;
+use crate::game::MapConfig;
+use crate::game::map::{MapSeed, MapSize, NumCamps, SpawnSettings};
+use crate::game::mapfile::{MapChoice, MapFile, MapFiles};
+use crate::game::symmetry::Symmetry;
+use crate::net::{self, TickRate, MAX_DATAGRAM_SIZE};
+use crate::net::client::SnapshotWriter;
+use crate::net::host::{self, EnemySnapshotQuery, PlayerSnapshotQuery};
+use crate::net::packets::{truncate_str, HostTick, Packet};
+use crate::components::*;
+
+// Replay file layout, everything big endian like the packets:
+//   "JQRP" | version u8 | tick rate u8 | map seed u64 | num camps u8 | map width u16 | map height u16
+//   | num chests u8 | enemies per camp u8 | eid percentage u8 | symmetry u8 | map file name (u8 length + utf8, empty if generated)
+//   | map file hash u64 | 5 MapConfig strings (u8 length + utf8)
+//   then one frame per HostTick part: u16 length | HostTick exactly as it would go over the wire
+const REPLAY_MAGIC: &[u8; 4] = b"JQRP";
+const REPLAY_VERSION: u8 = 1;
+
+const SEEK_SECS: f32 = 10.;
+const MIN_SPEED: f32 = 0.25;
+const MAX_SPEED: f32 = 4.;
+
+/// Writes every tick the host simulates to a file, enabled with `--record <file>`.
+#[derive(Resource)]
+pub struct Recorder {
+    pub path: Option<String>,
+    file: Option<BufWriter<File>>,
+}
+
+impl Recorder {
+    pub fn new(path: Option<String>) -> Recorder {
+        Recorder { path, file: None }
+    }
+}
+
+/// A recorded match loaded with `--replay <file>`, played back instead of joining a host.
+#[derive(Resource)]
+pub struct Replay {
+    pub tick_rate: u8,
+    pub seed: u64,
+    pub num_camps: u8,
+    pub map_size: MapSize,
+    pub spawn_settings: SpawnSettings,
+    pub map_choice: MapChoice,
+    pub config: [String; 5],  // num_camps, num_chests, enemy_per_camp, map_seed, eid_percentage
+    frames: Vec<Vec<Vec<u8>>>,  // the frames of each tick, more than one when its HostTick was split
+    cursor: usize,
+    paused: bool,
+    speed: f32,
+}
+
+impl Replay {
+    pub fn load(path: &str) -> Result<Replay> {
+        let mut file = BufReader::new(File::open(path)?);
+        let mut magic = [0; 4];
+        file.read_exact(&mut magic)?;
+        if &magic != REPLAY_MAGIC {
+            return Err(Error::new(ErrorKind::InvalidData, "not a replay file"));
+        }
+        let version = read_u8(&mut file)?;
+        if version != REPLAY_VERSION {
+            return Err(Error::new(ErrorKind::InvalidData, format!("replay version {} not supported", version)));
+        }
+        let tick_rate = read_u8(&mut file)?;
+        let mut seed = [0; 8];
+        file.read_exact(&mut seed)?;
+        let seed = u64::from_be_bytes(seed);
+        let num_camps = read_u8(&mut file)?;
+        let width = read_u16(&mut file)?;
+        let height = read_u16(&mut file)?;
+        let map_size = MapSize::new(width as usize, height as usize);
+        let (num_chests, enemies_per_camp, eid_percentage) = (read_u8(&mut file)?, read_u8(&mut file)?, read_u8(&mut file)?);
+        let symmetry = Symmetry::from_byte(read_u8(&mut file)?);
+        let spawn_settings = SpawnSettings::new(num_chests, enemies_per_camp, eid_percentage, symmetry);
+        let name = read_string(&mut file)?;
+        let mut hash = [0; 8];
+        file.read_exact(&mut hash)?;
+        let map_choice = MapChoice { name, hash: u64::from_be_bytes(hash) };
+        let config = [
+            read_string(&mut file)?,
+            read_string(&mut file)?,
+            read_string(&mut file)?,
+            read_string(&mut file)?,
+            read_string(&mut file)?,
+        ];
+        let mut frames: Vec<Vec<Vec<u8>>> = Vec::new();
+        loop {
+            let mut len = [0; 2];
+            // a recording cut off mid frame (game crashed) still plays up to there
+            if file.read_exact(&mut len).is_err() { break }
+            let mut frame = vec![0; u16::from_be_bytes(len) as usize];
+            if file.read_exact(&mut frame).is_err() { break }
+            // part 0 starts the next tick, the rest go with the tick before them
+            if frame.get(11).map_or(true, |part| *part == 0) || frames.is_empty() {
+                frames.push(Vec::new());
+            }
+            frames.last_mut().unwrap().push(frame);
+        }
+        return Ok(Replay {
+            tick_rate,
+            seed,
+            num_camps,
+            map_size,
+            spawn_settings,
+            map_choice,
+            config,
+            frames,
+            cursor: 0,
+            paused: false,
+            speed: 1.,
+        });
+    }
+}
+
+fn read_u8(file: &mut impl Read) -> Result<u8> {
+    let mut byte = [0; 1];
+    file.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+fn read_u16(file: &mut impl Read) -> Result<u16> {
+    let mut bytes = [0; 2];
+    file.read_exact(&mut bytes)?;
+    Ok(u16::from_be_bytes(bytes))
+}
+
+fn read_string(file: &mut impl Read) -> Result<String> {
+    let len = read_u8(file)?;
+    let mut bytes = vec![0; len as usize];
+    file.read_exact(&mut bytes)?;
+    String::from_utf8(bytes).map_err(|_| Error::new(ErrorKind::InvalidData, "bad string in replay header"))
+}
+
+fn write_string(file: &mut impl Write, s: &str) -> Result<()> {
+    let s = truncate_str(s, u8::MAX as usize);
+    file.write_all(&[s.len() as u8])?;
+    file.write_all(s.as_bytes())
+}
+
+fn write_header(file: &mut impl Write, tick_rate: u8, seed: u64, num_camps: u8, map_size: &MapSize, spawn_settings: &SpawnSettings, map_choice: &MapChoice, config: &MapConfig) -> Result<()> {
+    file.write_all(REPLAY_MAGIC)?;
+    file.write_all(&[REPLAY_VERSION])?;
+    file.write_all(&[tick_rate])?;
+    file.write_all(&seed.to_be_bytes())?;
+    file.write_all(&[num_camps])?;
+    file.write_all(&(map_size.width as u16).to_be_bytes())?;
+    file.write_all(&(map_size.height as u16).to_be_bytes())?;
+    file.write_all(&[spawn_settings.num_chests, spawn_settings.enemies_per_camp, spawn_settings.eid_percentage, spawn_settings.symmetry.to_byte()])?;
+    write_string(file, &map_choice.name)?;
+    file.write_all(&map_choice.hash.to_be_bytes())?;
+    for s in [&config.num_camps, &config.num_chests, &config.enemy_per_camp, &config.map_seed, &config.eid_percentage] {
+        write_string(file, s)?;
+    }
+    Ok(())
+}
+
+pub fn start_recording(
+    mut recorder: ResMut<Recorder>,
+    tick_rate: Res<TickRate>,
+    seed: Res<MapSeed>,
+    num_camps: Res<NumCamps>,
+    map_size: Res<MapSize>,
+    spawn_settings: Res<SpawnSettings>,
+    map_choice: Res<MapChoice>,
+    config: Res<MapConfig>,
+) {
+    if recorder.path.is_none() { return }
+    let path = recorder.path.clone().unwrap();
+    let file = File::create(&path);
+    if file.is_err() {
+        error!("can't record to {}: {}", path, file.unwrap_err());
+        return;
+    }
+    let mut file = BufWriter::new(file.unwrap());
+    if let Err(e) = write_header(&mut file, tick_rate.0, seed.0, num_camps.0, &map_size, &spawn_settings, &map_choice, &config) {
+        error!("can't record to {}: {}", path, e);
+        return;
+    }
+    info!("recording match to {}", path);
+    recorder.file = Some(file);
+}
+
+/// saves the whole world this tick, every enemy included since the replay can look anywhere.
+/// Split the same way as over the network so every frame still fits a datagram
+pub fn record(
+    mut recorder: ResMut<Recorder>,
+    tick: Res<net::TickNum>,
+    player_query: PlayerSnapshotQuery,
+    enemy_query: EnemySnapshotQuery,
+    powerups_query: Query<(&PowerUp, &Transform)>,
+    camp_query: Query<(&Camp, &CampStatus, &CampEnemies)>,
+    chests_query: Query<(&ItemChest, &Health)>
+) {
+    if recorder.file.is_none() { return }
+    let packet = host::snapshot(tick.0, Some(Vec2::ZERO), f32::MAX, None, &player_query, &enemy_query, &powerups_query, &camp_query, &chests_query);
+    let file = recorder.file.as_mut().unwrap();
+    for part in packet.split(MAX_DATAGRAM_SIZE) {
+        let mut bytes: Vec<u8> = Vec::new();
+        part.to_buf(&mut bytes);
+        let written = file.write_all(&(bytes.len() as u16).to_be_bytes()).and_then(|_| file.write_all(&bytes));
+        if let Err(e) = written {
+            error!("recording stopped: {}", e);
+            recorder.file = None;
+            return;
+        }
+    }
+}
+
+pub fn stop_recording(mut recorder: ResMut<Recorder>) {
+    if let Some(mut file) = recorder.file.take() {
+        let _ = file.flush();
+    }
+}
+
+/// jumps straight from the main menu into the recorded match as a client with no socket
+pub fn start_replay(
+    replay: Res<Replay>,
+    mut is_host: ResMut<net::IsHost>,
+    mut tick_rate: ResMut<TickRate>,
+    mut seed: ResMut<MapSeed>,
+    mut num_camps: ResMut<NumCamps>,
+    mut map_size: ResMut<MapSize>,
+    mut spawn_settings: ResMut<SpawnSettings>,
+    mut map_choice: ResMut<MapChoice>,
+    map_files: Res<Assets<MapFile>>,
+    map_file_handles: Res<MapFiles>,
+    asset_server: Res<AssetServer>,
+    mut config: ResMut<MapConfig>,
+    mut tick: ResMut<net::TickNum>,
+    mut app_state_next_state: ResMut<NextState<AppState>>,
+) {
+    // this runs on the first frame, give assets/maps the chance to load the replay's map
+    if !replay.map_choice.is_generated() && map_file_handles.loading(&asset_server) { return }
+    is_host.0 = false;
+    // changing it resets FixedTime to match through net::apply_tick_rate
+    tick_rate.0 = replay.tick_rate;
+    seed.0 = replay.seed;
+    num_camps.0 = replay.num_camps;
+    *map_size = replay.map_size;
+    config.num_camps = replay.config[0].clone();
+    config.num_chests = replay.config[1].clone();
+    config.enemy_per_camp = replay.config[2].clone();
+    config.map_seed = replay.config[3].clone();
+    config.eid_percentage = replay.config[4].clone();
+    *spawn_settings = replay.spawn_settings;
+    *map_choice = replay.map_choice.clone();
+    if !map_choice.is_generated() && map_choice.find(&map_files).is_none() {
+        warn!("this replay was played on {}.jqmap, which isn't in assets/maps", map_choice.name);
+    }
+    tick.0 = 0;
+    app_state_next_state.set(AppState::Game);
+}
+
+/// Feeds the next recorded tick through the same path a HostTick from the network takes,
+/// so the buffers and lerp systems play it back exactly like a live client would see it.
+pub fn playback(
+    mut replay: ResMut<Replay>,
+    mut tick: ResMut<net::TickNum>,
+    mut snapshots: SnapshotWriter,
+    mut app_state_next_state: ResMut<NextState<AppState>>,
+) {
+    if replay.cursor >= replay.frames.len() {
+        info!("replay finished");
+        app_state_next_state.set(AppState::GameOver);
+        return;
+    }
+    let cursor = replay.cursor;
+    replay.cursor += 1;
+    for frame in &replay.frames[cursor] {
+        let packet = if frame.len() > 3 { HostTick::from_buf(&frame[3..]) } else { Err(Error::from(ErrorKind::InvalidData)) };
+        if packet.is_err() {
+            warn!("Malformed HostTick in replay!");
+            continue;
+        }
+        let packet = packet.unwrap();
+        // increment_tick runs right after this and lands us on the recorded tick
+        tick.0 = packet.seq_num.saturating_sub(1);
+        snapshots.apply(packet);
+    }
+}
+
+/// Space pauses, Left/Right seek 10 seconds, Up/Down change speed.
+/// There's no local player in a replay so the spectator camera handles looking around.
+pub fn controls(
+    input: Res<Input<KeyCode>>,
+    mut replay: ResMut<Replay>,
+    tick_rate: Res<TickRate>,
+    mut fixed_time: ResMut<FixedTime>,
+    mut snapshots: SnapshotWriter,
+) {
+    let mut timing_changed = false;
+    if input.just_pressed(KeyCode::Space) {
+        replay.paused = !replay.paused;
+        timing_changed = true;
+        info!("replay {}", if replay.paused { "paused" } else { "playing" });
+    }
+    if input.just_pressed(KeyCode::Up) && replay.speed < MAX_SPEED {
+        replay.speed *= 2.;
+        timing_changed = true;
+        info!("replay speed {}x", replay.speed);
+    }
+    if input.just_pressed(KeyCode::Down) && replay.speed > MIN_SPEED {
+        replay.speed /= 2.;
+        timing_changed = true;
+        info!("replay speed {}x", replay.speed);
+    }
+    if input.just_pressed(KeyCode::Left) || input.just_pressed(KeyCode::Right) {
+        let last = replay.frames.len().saturating_sub(1);
+        replay.cursor = if input.just_pressed(KeyCode::Left) {
+            replay.cursor.saturating_sub(tick_rate.ticks(SEEK_SECS) as usize)
+        } else {
+            (replay.cursor + tick_rate.ticks(SEEK_SECS) as usize).min(last)
+        };
+        // camps only show up in snapshots while they're active, so start from nothing and let the next frame fill them in
+        snapshots.clear_camps();
+        info!("replay at {:.1}s", replay.cursor as f32 * tick_rate.tick_len());
+    }
+    if timing_changed {
+        // a fresh FixedTime also throws away time accumulated while paused so we don't burst through ticks
+        *fixed_time = if replay.paused {
+            FixedTime::new(Duration::MAX)
+        } else {
+            FixedTime::new_from_secs(tick_rate.tick_len() / replay.speed)
+        };
+    }
+}
+
+/// back to normal speed, and drop the replay so the main menu doesn't start it again
+pub fn stop_replay(mut commands: Commands, tick_rate: Res<TickRate>, mut fixed_time: ResMut<FixedTime>) {
+    *fixed_time = FixedTime::new_from_secs(tick_rate.tick_len());
+    commands.remove_resource::<Replay>();
+}
+
+pub fn startup(mut commands: Commands) {
+    commands.insert_resource(Recorder::new(cli_arg("--record")));
+    if let Some(path) = cli_arg("--replay") {
+        match Replay::load(&path) {
+            Ok(replay) => {
+                info!("loaded replay {} ({} ticks)", path, replay.frames.len());
+                commands.insert_resource(replay);
+            },
+            Err(e) => error!("can't load replay {}: {}", path, e),
+        }
+    }
+}
@@ -0,0 +1,582 @@
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind, Result};
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use bevy::prelude::*;
+use chacha20poly1305::{ChaCha20Poly1305, KeyInit, Nonce};
+use chacha20poly1305::aead::{Aead, Payload};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use sha2::Sha256;
+use crate::net::{MAGIC_NUMBER, MAX_DATAGRAM_SIZE};
+use crate::net::packets::PacketType;
+use crate::net::transport::Transport;
+
+// slow on purpose, a captured Hello shouldn't make the password quick to guess offline
+const PASSWORD_ROUNDS: u32 = 100_000;
+const PASSWORD_SALT: &[u8] = b"jordquest match password";
+const SESSION_INFO: &[u8] = b"jordquest session";
+const NONCE_LEN: usize = 16;
+const TAG_LEN: usize = 16;
+const HELLO_RESEND: Duration = Duration::from_millis(250);
+const MAX_QUEUED: usize = 8;  // packets held back while the handshake finishes
+const MAX_PENDING: usize = 16;  // accepted handshakes still waiting on their first sealed packet
+const PENDING_TIMEOUT: Duration = Duration::from_secs(5);  // after which a pending handshake makes room for others
+const SEAL_OVERHEAD: usize = 3 + 8 + TAG_LEN;  // magic and type, counter and tag on top of the packet
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Key stretched from the match password typed into the host or join page, None without a password.
+/// Everyone in the match has to type the same password, sessions fail on anything else.
+#[derive(Resource, Default, Clone)]
+pub struct MatchKey(pub Option<[u8; 32]>);
+
+impl MatchKey {
+    pub fn from_password(password: &str) -> MatchKey {
+        if password.is_empty() {
+            return MatchKey(None);
+        }
+        let mut key = [0; 32];
+        pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), PASSWORD_SALT, PASSWORD_ROUNDS, &mut key);
+        MatchKey(Some(key))
+    }
+}
+
+/// what one side of a handshake says about the nonce it picked, so the other knows it has the password
+fn proof(key: &[u8; 32], label: &[u8], nonces: &[u8]) -> HmacSha256 {
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(key).unwrap();
+    mac.update(label);
+    mac.update(nonces);
+    mac
+}
+
+/// One peer we share a key with. Whoever sent the Hello is the initiator,
+/// the direction goes into every AEAD nonce so the two sides never reuse one.
+struct Session {
+    cipher: ChaCha20Poly1305,
+    initiator: bool,
+    client_nonce: [u8; NONCE_LEN],
+    accept: Vec<u8>,  // the Accept we sent, resent if the Hello comes again because it got lost
+    started: Instant,
+    send_counter: u64,
+    recv_max: u64,
+    recv_window: u64,  // bit n set if recv_max - n was already seen
+}
+
+impl Session {
+    fn new(key: &[u8; 32], client_nonce: [u8; NONCE_LEN], host_nonce: [u8; NONCE_LEN], initiator: bool) -> Session {
+        let mut salt = client_nonce.to_vec();
+        salt.extend_from_slice(&host_nonce);
+        let mut session_key = [0; 32];
+        Hkdf::<Sha256>::new(Some(&salt[..]), key).expand(SESSION_INFO, &mut session_key).unwrap();
+        Session {
+            cipher: ChaCha20Poly1305::new_from_slice(&session_key).unwrap(),
+            initiator,
+            client_nonce,
+            accept: Vec::new(),
+            started: Instant::now(),
+            send_counter: 0,
+            recv_max: 0,
+            recv_window: 0,
+        }
+    }
+
+    fn nonce(counter: u64, from_initiator: bool) -> [u8; 12] {
+        let mut nonce = [0; 12];
+        nonce[0] = if from_initiator { 0 } else { 1 };
+        nonce[4..].copy_from_slice(&counter.to_be_bytes());
+        nonce
+    }
+
+    /// MAGIC | Sealed | counter u64 | ciphertext and tag, the header is authenticated too
+    fn seal(&mut self, packet: &[u8]) -> Vec<u8> {
+        let counter = self.send_counter;
+        self.send_counter += 1;
+        let mut bytes: Vec<u8> = Vec::new();
+        bytes.extend_from_slice(&MAGIC_NUMBER.to_be_bytes());
+        bytes.extend_from_slice(&(PacketType::Sealed as u8).to_be_bytes());
+        bytes.extend_from_slice(&counter.to_be_bytes());
+        let sealed = self.cipher.encrypt(Nonce::from_slice(&Session::nonce(counter, self.initiator)), Payload { msg: packet, aad: &bytes }).unwrap();
+        bytes.extend_from_slice(&sealed);
+        return bytes;
+    }
+
+    /// None for anything tampered with, sealed with another key or already seen
+    fn open(&mut self, buf: &[u8]) -> Option<Vec<u8>> {
+        if buf.len() < SEAL_OVERHEAD { return None }
+        let counter = u64::from_be_bytes(buf[3..11].try_into().unwrap());
+        let back = self.recv_max.checked_sub(counter);
+        if back.is_some_and(|back| back >= 64 || self.recv_window & (1 << back) != 0) { return None }
+        let nonce = Session::nonce(counter, !self.initiator);
+        let packet = self.cipher.decrypt(Nonce::from_slice(&nonce), Payload { msg: &buf[11..], aad: &buf[..11] }).ok()?;
+        // only move the window once the tag checked out, or garbage could push real packets out of it
+        match back {
+            Some(back) => self.recv_window |= 1 << back,
+            None => {
+                let shift = counter - self.recv_max;
+                self.recv_window = if shift >= 64 { 0 } else { self.recv_window << shift };
+                self.recv_window |= 1;
+                self.recv_max = counter;
+            },
+        }
+        Some(packet)
+    }
+}
+
+/// the Hello we sent to the peer we're connected to, and what we wanted to send it in the meantime
+struct Hello {
+    peer: SocketAddr,
+    nonce: [u8; NONCE_LEN],
+    sent: Option<Instant>,
+    queued: Vec<Vec<u8>>,
+}
+
+struct SecureState {
+    sessions: HashMap<SocketAddr, Session>,
+    // Accepts we sent, only made sessions once the peer seals something with them.
+    // A Hello proves nothing about freshness, a replayed one mustn't take over a peer's session
+    pending: HashMap<SocketAddr, Session>,
+    hello: Option<Hello>,
+}
+
+/// Encrypts and authenticates everything after the handshake when the match has a password.
+///
+/// A client's first send to the host it's connected to is held back and a Hello goes out instead:
+///   Hello    MAGIC | client nonce | HMAC(key, client nonce)
+///   Accept   MAGIC | client nonce | host nonce | HMAC(key, both nonces)
+///   Refused  MAGIC, the password (or lack of one) didn't match
+/// Both sides then derive a session key from the password key and both nonces, and every packet
+/// between them goes out Sealed with ChaCha20-Poly1305 and a counter. Datagrams that don't open,
+/// replayed counters and anything unsealed from a peer are dropped before the game sees them,
+/// so a spoofed source address alone can't get a ClientTick in.
+/// The host only counts a session once the first Sealed packet opens with it, until then any older one for that address stays.
+/// Without a password this only answers Hellos with Refused and lets everything else through.
+pub struct SecureTransport {
+    inner: Box<dyn Transport>,
+    key: Option<[u8; 32]>,
+    state: Mutex<SecureState>,
+}
+
+impl SecureTransport {
+    pub fn new(inner: Box<dyn Transport>, key: &MatchKey) -> SecureTransport {
+        SecureTransport {
+            inner,
+            key: key.0,
+            state: Mutex::new(SecureState { sessions: HashMap::new(), pending: HashMap::new(), hello: None }),
+        }
+    }
+
+    fn send_raw(&self, bytes: &[u8], peer: &SocketAddr) -> Result<usize> {
+        if self.inner.peer_addr().is_ok() {
+            return self.inner.send(bytes);
+        }
+        self.inner.send_to(bytes, peer)
+    }
+
+    fn send_hello(&self, key: &[u8; 32], hello: &mut Hello) -> Result<usize> {
+        let mut bytes: Vec<u8> = Vec::new();
+        bytes.extend_from_slice(&MAGIC_NUMBER.to_be_bytes());
+        bytes.extend_from_slice(&(PacketType::Hello as u8).to_be_bytes());
+        bytes.extend_from_slice(&hello.nonce);
+        bytes.extend_from_slice(&proof(key, b"hello", &hello.nonce).finalize().into_bytes()[..TAG_LEN]);
+        hello.sent = Some(Instant::now());
+        self.send_raw(&bytes, &hello.peer)
+    }
+
+    fn send_refused(&self, peer: &SocketAddr) {
+        let mut bytes: Vec<u8> = Vec::new();
+        bytes.extend_from_slice(&MAGIC_NUMBER.to_be_bytes());
+        bytes.extend_from_slice(&(PacketType::Refused as u8).to_be_bytes());
+        let _ = self.send_raw(&bytes, peer);
+    }
+
+    fn send_packet(&self, buf: &[u8], peer: &SocketAddr) -> Result<usize> {
+        if self.key.is_none() {
+            return self.send_raw(buf, peer);
+        }
+        let key = self.key.as_ref().unwrap();
+        let mut state = self.state.lock().unwrap();
+        if let Some(session) = state.sessions.get_mut(peer) {
+            let sealed = session.seal(buf);
+            self.send_raw(&sealed, peer)?;
+            return Ok(buf.len());
+        }
+        // only the side that connected starts a handshake, a host never knows who to expect
+        if self.inner.peer_addr().ok() != Some(*peer) {
+            trace!("dropping packet to {}, no session", peer);
+            return Ok(buf.len());
+        }
+        if state.hello.as_ref().is_some_and(|hello| hello.peer == *peer) {
+            let hello = state.hello.as_mut().unwrap();
+            if hello.queued.len() < MAX_QUEUED {
+                hello.queued.push(buf.to_vec());
+            }
+            return Ok(buf.len());
+        }
+        let mut hello = Hello { peer: *peer, nonce: rand::thread_rng().gen(), sent: None, queued: vec![buf.to_vec()] };
+        self.send_hello(key, &mut hello)?;
+        state.hello = Some(hello);
+        Ok(buf.len())
+    }
+
+    /// handles one datagram, Some(packet) if it's something the game should see
+    fn receive(&self, buf: &[u8], origin: SocketAddr) -> Result<Option<Vec<u8>>> {
+        if buf.len() < 3 || u16::from_be_bytes([buf[0], buf[1]]) != MAGIC_NUMBER {
+            return Ok(None);
+        }
+        let pt = buf[2];
+        if pt == PacketType::Refused as u8 {
+            let mut state = self.state.lock().unwrap();
+            // only believe it from someone we're waiting on, or anyone could kick us out of the match
+            let waiting = if self.key.is_some() { state.hello.as_ref().is_some_and(|hello| hello.peer == origin) } else { self.inner.peer_addr().ok() == Some(origin) };
+            if !waiting || state.sessions.contains_key(&origin) { return Ok(None) }
+            state.hello = None;
+            return Err(Error::new(ErrorKind::PermissionDenied, "match password refused"));
+        }
+        if self.key.is_none() {
+            if pt == PacketType::Hello as u8 {
+                self.send_refused(&origin);
+                return Ok(None);
+            }
+            if pt == PacketType::Accept as u8 || pt == PacketType::Sealed as u8 { return Ok(None) }
+            return Ok(Some(buf.to_vec()));
+        }
+        let key = self.key.as_ref().unwrap();
+        let mut state = self.state.lock().unwrap();
+        match pt {
+            pt if pt == PacketType::Hello as u8 => {
+                if buf.len() < 3 + NONCE_LEN + TAG_LEN { return Ok(None) }
+                let client_nonce: [u8; NONCE_LEN] = buf[3..3+NONCE_LEN].try_into().unwrap();
+                if proof(key, b"hello", &client_nonce).verify_truncated_left(&buf[3+NONCE_LEN..3+NONCE_LEN+TAG_LEN]).is_err() {
+                    debug!("Hello from {} with the wrong password", origin);
+                    self.send_refused(&origin);
+                    return Ok(None);
+                }
+                let known = [state.pending.get(&origin), state.sessions.get(&origin)].into_iter().flatten().find(|session| session.client_nonce == client_nonce);
+                if let Some(session) = known {
+                    // our Accept got lost, send the same one again
+                    let _ = self.send_raw(&session.accept, &origin);
+                    return Ok(None);
+                }
+                // anything else is a fresh handshake, maybe the same player reconnecting from the same port.
+                // It waits in pending so any session already there keeps going until the new one is used
+                state.pending.retain(|_, session| session.started.elapsed() < PENDING_TIMEOUT);
+                if state.pending.len() >= MAX_PENDING && !state.pending.contains_key(&origin) {
+                    debug!("Hello from {} dropped, too many handshakes going", origin);
+                    return Ok(None);
+                }
+                let host_nonce: [u8; NONCE_LEN] = rand::thread_rng().gen();
+                let mut nonces = client_nonce.to_vec();
+                nonces.extend_from_slice(&host_nonce);
+                let mut session = Session::new(key, client_nonce, host_nonce, false);
+                let mut bytes: Vec<u8> = Vec::new();
+                bytes.extend_from_slice(&MAGIC_NUMBER.to_be_bytes());
+                bytes.extend_from_slice(&(PacketType::Accept as u8).to_be_bytes());
+                bytes.extend_from_slice(&nonces);
+                bytes.extend_from_slice(&proof(key, b"accept", &nonces).finalize().into_bytes()[..TAG_LEN]);
+                let _ = self.send_raw(&bytes, &origin);
+                session.accept = bytes;
+                state.pending.insert(origin, session);
+                Ok(None)
+            },
+            pt if pt == PacketType::Accept as u8 => {
+                if buf.len() < 3 + 2 * NONCE_LEN + TAG_LEN { return Ok(None) }
+                if !state.hello.as_ref().is_some_and(|hello| hello.peer == origin && buf[3..3+NONCE_LEN] == hello.nonce) {
+                    return Ok(None);
+                }
+                let nonces = &buf[3..3+2*NONCE_LEN];
+                if proof(key, b"accept", nonces).verify_truncated_left(&buf[3+2*NONCE_LEN..3+2*NONCE_LEN+TAG_LEN]).is_err() {
+                    return Ok(None);
+                }
+                let hello = state.hello.take().unwrap();
+                let host_nonce: [u8; NONCE_LEN] = nonces[NONCE_LEN..].try_into().unwrap();
+                let mut session = Session::new(key, hello.nonce, host_nonce, true);
+                debug!("session with {} established", origin);
+                for packet in &hello.queued {
+                    let sealed = session.seal(packet);
+                    let _ = self.send_raw(&sealed, &origin);
+                }
+                state.sessions.insert(origin, session);
+                Ok(None)
+            },
+            pt if pt == PacketType::Sealed as u8 => {
+                let packet = state.sessions.get_mut(&origin).and_then(|session| session.open(buf));
+                if packet.is_some() { return Ok(packet) }
+                // the first packet of a new handshake, only now does it replace the old session
+                let packet = state.pending.get_mut(&origin).and_then(|session| session.open(buf));
+                if packet.is_none() {
+                    debug!("dropped a tampered or replayed packet from {}", origin);
+                    return Ok(None);
+                }
+                let session = state.pending.remove(&origin).unwrap();
+                debug!("session with {} established", origin);
+                state.sessions.insert(origin, session);
+                Ok(packet)
+            },
+            pt => {
+                // asking to join without the password gets told so, everything else unsealed is ignored
+                if pt == PacketType::ConnectionRequest as u8 || pt == PacketType::Rejoin as u8 {
+                    self.send_refused(&origin);
+                }
+                Ok(None)
+            },
+        }
+    }
+}
+
+impl Transport for SecureTransport {
+    fn connect(&mut self, peer: SocketAddr) -> Result<()> {
+        self.inner.connect(peer)?;
+        // a new host means a new handshake, the old session is no use to it
+        self.state.get_mut().unwrap().hello = None;
+        Ok(())
+    }
+
+    fn send(&self, buf: &[u8]) -> Result<usize> {
+        let peer = self.inner.peer_addr()?;
+        self.send_packet(buf, &peer)
+    }
+
+    fn send_to(&self, buf: &[u8], peer: &SocketAddr) -> Result<usize> {
+        self.send_packet(buf, peer)
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr)> {
+        if let Some(key) = self.key.as_ref() {
+            // lost Hellos and Accepts are covered by saying Hello again
+            let mut state = self.state.lock().unwrap();
+            if let Some(hello) = state.hello.as_mut() {
+                if hello.sent.map_or(true, |sent| sent.elapsed() > HELLO_RESEND) {
+                    let _ = self.send_hello(key, hello);
+                }
+            }
+        }
+        let mut datagram = [0; MAX_DATAGRAM_SIZE + SEAL_OVERHEAD];
+        loop {
+            let (len, origin) = self.inner.recv_from(&mut datagram)?;
+            if let Some(packet) = self.receive(&datagram[..len], origin)? {
+                let len = packet.len().min(buf.len());
+                buf[..len].copy_from_slice(&packet[..len]);
+                return Ok((len, origin));
+            }
+        }
+    }
+
+    fn local_addr(&self) -> Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    fn peer_addr(&self) -> Result<SocketAddr> {
+        self.inner.peer_addr()
+    }
+
+    fn forget(&self, peer: &SocketAddr) {
+        let mut state = self.state.lock().unwrap();
+        state.sessions.remove(peer);
+        state.pending.remove(peer);
+        if state.hello.as_ref().is_some_and(|hello| hello.peer == *peer) {
+            state.hello = None;
+        }
+        self.inner.forget(peer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::packets::{ChatAck, ConnectionRequest, Packet};
+    use crate::net::transport::{LoopbackNetwork, LoopbackSocket};
+
+    const HOST: &str = "127.0.0.1:7000";
+    const RELAY: &str = "127.0.0.1:7001";
+    const CLIENT: &str = "127.0.0.1:7002";
+    const KEY: MatchKey = MatchKey(Some([7; 32]));  // from_password is slow on purpose, not what these are testing
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    fn packet(seq: u16) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::new();
+        ChatAck { seq }.to_buf(&mut bytes);
+        bytes
+    }
+
+    // a Hello like any client with the password would send, say one captured from an earlier session
+    fn hello(nonce: [u8; NONCE_LEN]) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::new();
+        bytes.extend_from_slice(&MAGIC_NUMBER.to_be_bytes());
+        bytes.extend_from_slice(&(PacketType::Hello as u8).to_be_bytes());
+        bytes.extend_from_slice(&nonce);
+        bytes.extend_from_slice(&proof(KEY.0.as_ref().unwrap(), b"hello", &nonce).finalize().into_bytes()[..TAG_LEN]);
+        bytes
+    }
+
+    fn recv(sock: &dyn Transport) -> Result<Vec<u8>> {
+        let mut buf = [0; MAX_DATAGRAM_SIZE];
+        let (len, _) = sock.recv_from(&mut buf)?;
+        Ok(buf[..len].to_vec())
+    }
+
+    /// the client talks to the host through the relay, which can do what it likes with everything on the way
+    struct Setup {
+        host: SecureTransport,
+        client: SecureTransport,
+        relay: LoopbackSocket,
+        _net: LoopbackNetwork,
+    }
+
+    impl Setup {
+        fn new(client_key: &MatchKey) -> Setup {
+            let net = LoopbackNetwork::new();
+            let host = SecureTransport::new(Box::new(net.bind(addr(HOST)).unwrap()), &KEY);
+            let mut client = SecureTransport::new(Box::new(net.bind(addr(CLIENT)).unwrap()), client_key);
+            client.connect(addr(RELAY)).unwrap();
+            let relay = net.bind(addr(RELAY)).unwrap();
+            Setup { host, client, relay, _net: net }
+        }
+
+        /// passes on everything waiting at the relay, edit says what actually goes out for each datagram
+        fn forward(&self, mut edit: impl FnMut(&[u8]) -> Vec<Vec<u8>>) {
+            let mut buf = [0; MAX_DATAGRAM_SIZE + SEAL_OVERHEAD];
+            while let Ok((len, origin)) = self.relay.recv_from(&mut buf) {
+                let to = if origin == addr(CLIENT) { addr(HOST) } else { addr(CLIENT) };
+                for datagram in edit(&buf[..len]) {
+                    self.relay.send_to(&datagram, &to).unwrap();
+                }
+            }
+        }
+
+        fn pass(&self) {
+            self.forward(|d| vec![d.to_vec()]);
+        }
+
+        /// Hello, Accept, then the first packet the client wanted to send
+        fn handshake(&self) {
+            self.client.send(&packet(1)).unwrap();
+            self.pass();
+            assert!(recv(&self.host).is_err());
+            self.pass();
+            assert!(recv(&self.client).is_err());
+            self.pass();
+            assert_eq!(recv(&self.host).unwrap(), packet(1));
+        }
+    }
+
+    #[test]
+    fn tampered_packets_are_dropped() {
+        let s = Setup::new(&KEY);
+        s.handshake();
+        s.client.send(&packet(2)).unwrap();
+        s.forward(|d| {
+            let mut d = d.to_vec();
+            *d.last_mut().unwrap() ^= 1;
+            vec![d]
+        });
+        assert!(recv(&s.host).is_err_and(|e| e.kind() == ErrorKind::WouldBlock));
+        // a bad packet doesn't break the session
+        s.client.send(&packet(3)).unwrap();
+        s.pass();
+        assert_eq!(recv(&s.host).unwrap(), packet(3));
+    }
+
+    #[test]
+    fn replayed_packets_are_dropped() {
+        let s = Setup::new(&KEY);
+        s.handshake();
+        let mut captured: Vec<u8> = Vec::new();
+        s.client.send(&packet(2)).unwrap();
+        s.forward(|d| {
+            captured = d.to_vec();
+            vec![d.to_vec(), d.to_vec()]
+        });
+        assert_eq!(recv(&s.host).unwrap(), packet(2));
+        assert!(recv(&s.host).is_err_and(|e| e.kind() == ErrorKind::WouldBlock));
+        s.client.send(&packet(3)).unwrap();
+        s.pass();
+        assert_eq!(recv(&s.host).unwrap(), packet(3));
+        // and again later, once it's behind newer ones
+        s.relay.send_to(&captured, &addr(HOST)).unwrap();
+        assert!(recv(&s.host).is_err_and(|e| e.kind() == ErrorKind::WouldBlock));
+    }
+
+    #[test]
+    fn wrong_password_is_refused() {
+        let s = Setup::new(&MatchKey(Some([8; 32])));
+        s.client.send(&packet(1)).unwrap();
+        s.pass();
+        assert!(recv(&s.host).is_err_and(|e| e.kind() == ErrorKind::WouldBlock));
+        s.pass();
+        assert!(recv(&s.client).is_err_and(|e| e.kind() == ErrorKind::PermissionDenied));
+        assert!(s.host.state.lock().unwrap().sessions.is_empty());
+        assert!(s.host.state.lock().unwrap().pending.is_empty());
+    }
+
+    #[test]
+    fn no_password_is_refused() {
+        let s = Setup::new(&MatchKey(None));
+        let mut request: Vec<u8> = Vec::new();
+        ConnectionRequest { spectator: false, name: "ferris".to_string(), send_rate: 0 }.to_buf(&mut request);
+        s.client.send(&request).unwrap();
+        s.pass();
+        // unsealed, the host tells it to go away rather than handing it to the game
+        assert!(recv(&s.host).is_err_and(|e| e.kind() == ErrorKind::WouldBlock));
+        s.pass();
+        assert!(recv(&s.client).is_err_and(|e| e.kind() == ErrorKind::PermissionDenied));
+    }
+
+    #[test]
+    fn forgotten_peers_lose_their_session() {
+        let s = Setup::new(&KEY);
+        s.handshake();
+        s.host.forget(&addr(RELAY));
+        assert!(s.host.state.lock().unwrap().sessions.is_empty());
+        s.client.send(&packet(2)).unwrap();
+        s.pass();
+        assert!(recv(&s.host).is_err_and(|e| e.kind() == ErrorKind::WouldBlock));
+    }
+
+    #[test]
+    fn replayed_hellos_keep_the_session() {
+        let s = Setup::new(&KEY);
+        s.handshake();
+        s.relay.send_to(&hello([9; NONCE_LEN]), &addr(HOST)).unwrap();
+        assert!(recv(&s.host).is_err_and(|e| e.kind() == ErrorKind::WouldBlock));
+        // the host answers it, but the client's session still works
+        s.client.send(&packet(2)).unwrap();
+        s.pass();
+        assert_eq!(recv(&s.host).unwrap(), packet(2));
+    }
+
+    #[test]
+    fn reconnecting_clients_get_a_new_session() {
+        let s = Setup::new(&KEY);
+        s.handshake();
+        // the game restarted on the same port, it doesn't know the old session any more
+        s.client.state.lock().unwrap().sessions.clear();
+        s.client.send(&packet(2)).unwrap();
+        s.pass();
+        assert!(recv(&s.host).is_err());
+        s.pass();
+        assert!(recv(&s.client).is_err());
+        s.pass();
+        assert_eq!(recv(&s.host).unwrap(), packet(2));
+        let state = s.host.state.lock().unwrap();
+        assert_eq!(state.sessions.len(), 1);
+        assert!(state.pending.is_empty());
+    }
+
+    #[test]
+    fn pending_handshakes_are_capped() {
+        let s = Setup::new(&KEY);
+        let spoofers: Vec<LoopbackSocket> = (0..MAX_PENDING as u16 * 2).map(|i| s._net.bind(addr(&format!("127.0.0.1:{}", 7100 + i))).unwrap()).collect();
+        for (i, spoofer) in spoofers.iter().enumerate() {
+            spoofer.send_to(&hello([i as u8; NONCE_LEN]), &addr(HOST)).unwrap();
+        }
+        assert!(recv(&s.host).is_err_and(|e| e.kind() == ErrorKind::WouldBlock));
+        assert_eq!(s.host.state.lock().unwrap().pending.len(), MAX_PENDING);
+        assert!(s.host.state.lock().unwrap().sessions.is_empty());
+    }
+}
@@ -0,0 +1,577 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::{Error, ErrorKind, Result};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex};
+use bevy::prelude::*;
+use crate::net::conditioner::{ConditionedTransport, NetSim};
+use crate::net::inspect::TapTransport;
+use crate::net::secure::{MatchKey, SecureTransport};
+
+/// Everything the net code needs from a socket.
+/// The real game uses a nonblocking UdpSocket, tests use a LoopbackSocket so several Apps
+/// can talk to each other inside one process without touching the OS network stack.
+pub trait Transport: Send + Sync {
+    /// after this, send goes to peer and recv_from only returns datagrams from peer
+    fn connect(&mut self, peer: SocketAddr) -> Result<()>;
+    /// sends to the connected peer
+    fn send(&self, buf: &[u8]) -> Result<usize>;
+    fn send_to(&self, buf: &[u8], peer: &SocketAddr) -> Result<usize>;
+    /// never blocks, returns ErrorKind::WouldBlock when there is nothing to read
+    fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr)>;
+    fn local_addr(&self) -> Result<SocketAddr>;
+    fn peer_addr(&self) -> Result<SocketAddr>;
+    /// the host is done with peer (it left, or got kicked or banned), let go of anything kept for it
+    fn forget(&self, _peer: &SocketAddr) {}
+}
+
+impl Transport for UdpSocket {
+    fn connect(&mut self, peer: SocketAddr) -> Result<()> {
+        UdpSocket::connect(self, peer)
+    }
+
+    fn send(&self, buf: &[u8]) -> Result<usize> {
+        UdpSocket::send(self, buf)
+    }
+
+    fn send_to(&self, buf: &[u8], peer: &SocketAddr) -> Result<usize> {
+        UdpSocket::send_to(self, buf, peer)
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr)> {
+        UdpSocket::recv_from(self, buf)
+    }
+
+    fn local_addr(&self) -> Result<SocketAddr> {
+        UdpSocket::local_addr(self)
+    }
+
+    fn peer_addr(&self) -> Result<SocketAddr> {
+        UdpSocket::peer_addr(self)
+    }
+}
+
+/// (origin, datagram) queues for every bound loopback address
+type Inboxes = HashMap<SocketAddr, VecDeque<(SocketAddr, Vec<u8>)>>;
+
+/// An in-memory "network" shared between Apps.
+/// Insert a clone of the same LoopbackNetwork into the host App and every client App and
+/// connect() will bind loopback sockets on it instead of real UDP sockets.
+/// Datagrams are delivered in order and instantly, so a test can step the Apps one
+/// update at a time and get the same result every run.
+#[derive(Resource, Clone, Default)]
+pub struct LoopbackNetwork(Arc<Mutex<Inboxes>>);
+
+impl LoopbackNetwork {
+    pub fn new() -> LoopbackNetwork {
+        LoopbackNetwork::default()
+    }
+
+    pub fn bind(&self, addr: SocketAddr) -> Result<LoopbackSocket> {
+        let addr = normalize(addr);
+        let mut inboxes = self.0.lock().unwrap();
+        if inboxes.contains_key(&addr) {
+            return Err(Error::from(ErrorKind::AddrInUse));
+        }
+        inboxes.insert(addr, VecDeque::new());
+        return Ok(LoopbackSocket {
+            addr,
+            peer: None,
+            net: self.clone(),
+        });
+    }
+
+    /// number of datagrams waiting to be read on every address, handy for asserting the network drained
+    pub fn in_flight(&self) -> usize {
+        self.0.lock().unwrap().values().map(|q| q.len()).sum()
+    }
+}
+
+pub struct LoopbackSocket {
+    addr: SocketAddr,
+    peer: Option<SocketAddr>,
+    net: LoopbackNetwork,
+}
+
+impl Transport for LoopbackSocket {
+    fn connect(&mut self, peer: SocketAddr) -> Result<()> {
+        self.peer = Some(normalize(peer));
+        Ok(())
+    }
+
+    fn send(&self, buf: &[u8]) -> Result<usize> {
+        let peer = self.peer_addr()?;
+        self.send_to(buf, &peer)
+    }
+
+    fn send_to(&self, buf: &[u8], peer: &SocketAddr) -> Result<usize> {
+        let mut inboxes = self.net.0.lock().unwrap();
+        // like UDP, sending to nobody isn't an error, the datagram is just lost
+        if let Some(inbox) = inboxes.get_mut(&normalize(*peer)) {
+            inbox.push_back((self.addr, buf.to_vec()));
+        }
+        Ok(buf.len())
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr)> {
+        let mut inboxes = self.net.0.lock().unwrap();
+        let inbox = inboxes.get_mut(&self.addr).ok_or(Error::from(ErrorKind::NotConnected))?;
+        loop {
+            let (origin, datagram) = inbox.pop_front().ok_or(Error::from(ErrorKind::WouldBlock))?;
+            if self.peer.is_some_and(|peer| peer != origin) { continue }
+            let len = datagram.len().min(buf.len());
+            buf[..len].copy_from_slice(&datagram[..len]);
+            return Ok((len, origin));
+        }
+    }
+
+    fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.addr)
+    }
+
+    fn peer_addr(&self) -> Result<SocketAddr> {
+        self.peer.ok_or(Error::from(ErrorKind::NotConnected))
+    }
+}
+
+impl Drop for LoopbackSocket {
+    fn drop(&mut self) {
+        // free the address so it can be bound again, same as closing a real socket
+        if let Ok(mut inboxes) = self.net.0.lock() {
+            inboxes.remove(&self.addr);
+        }
+    }
+}
+
+/// the host binds 0.0.0.0 but clients send to 127.0.0.1, on the loopback those are the same place
+fn normalize(addr: SocketAddr) -> SocketAddr {
+    if addr.ip().is_unspecified() {
+        return SocketAddr::new(IpAddr::from(Ipv4Addr::LOCALHOST), addr.port());
+    }
+    addr
+}
+
+/// binds a nonblocking transport at addr, on the loopback network if one is given,
+/// and puts the network condition simulator in front of it, and the --tap capture and the match password behind it
+pub fn bind(addr: SocketAddr, loopback: Option<&LoopbackNetwork>, sim: &NetSim, key: &MatchKey) -> Result<Box<dyn Transport>> {
+    let inner: Box<dyn Transport> = if let Some(net) = loopback {
+        Box::new(net.bind(addr)?)
+    } else {
+        let sock = UdpSocket::bind(addr)?;
+        sock.set_nonblocking(true)?;
+        Box::new(sock)
+    };
+    // the tap sees packets before they're sealed so it can still decode them
+    let secure = Box::new(SecureTransport::new(inner, key));
+    Ok(Box::new(ConditionedTransport::new(TapTransport::wrap(secure), sim.clone())))
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::*;
+    use bevy::asset::AssetPlugin;
+    use bevy::core::TaskPoolPlugin;
+    use crate::AppState;
+    use crate::game::{enemy, player, PlayerId, PowerupAtlas};
+    use crate::game::buffers::{CircularBuffer, DirBuffer, EventBuffer, HpBuffer, PosBuffer};
+    use crate::game::components::*;
+    use crate::game::enemy::IsSpecial;
+    use crate::game::grid::Grid;
+    use crate::game::map::{Biome, MapSeed, MapSize, NumCamps, SpawnSettings, WorldMap, TILESIZE};
+    use crate::game::mapfile::{MapChoice, MapFile};
+    use crate::game::player::{AttackEvent, Cooldown, LocalPlayer, PlayerJoinEvent, PlayerLeaveEvent, PlayerNames, PlayerShield, SetIdEvent, SpawnEvent};
+    use crate::menus::{LocalName, NetworkAddresses};
+    use crate::net::{self, chat, client, error, host, migrate};
+    use crate::net::admin::BanList;
+    use crate::net::conditioner::{Conditions, NetSim};
+    use crate::net::lobby::{self, Lobby};
+    use crate::net::migrate::{Migration, TakeOverEvent};
+    use crate::net::packets::{EnemyTickEvent, PlayerTickEvent, UserCmdEvent};
+    use crate::net::secure::MatchKey;
+    use std::net::SocketAddr;
+    use super::LoopbackNetwork;
+
+    const HOST_START: u16 = 10;  // host::update drops ClientTicks older than DELAY ticks, keep clear of wrapping
+    const FREEZE_TICK: u16 = 40;  // everything stops moving here so the last snapshots can be compared
+    const STEPS: usize = 60;
+    const HOST_PORT: u16 = 7000;  // clients get HOST_PORT + their player id
+    const MIGRATING_CLIENTS: u8 = 3;
+
+    // where the sims put things on a tick
+    fn host_pos(tick: u16) -> Vec2 { Vec2::new(tick.min(FREEZE_TICK) as f32 * 3., 0.) }
+    fn client_pos(tick: u16) -> Vec2 { Vec2::new(-50., tick.min(FREEZE_TICK) as f32 * 4.) }
+    fn enemy_pos(tick: u16) -> Vec2 { Vec2::new(100., tick.min(FREEZE_TICK) as f32 * 2.) }
+    fn hp(tick: u16) -> u8 { 100 - tick.min(FREEZE_TICK) as u8 }
+
+    fn spawn_player(world: &mut World, id: u8) -> Entity {
+        world.spawn((
+            Player(id),
+            PosBuffer(CircularBuffer::new()),
+            HpBuffer(CircularBuffer::new()),
+            DirBuffer(CircularBuffer::new()),
+            EventBuffer(CircularBuffer::new()),
+            PlayerShield { active: false },
+            Stats { score: 0, enemies_killed: 0, players_killed: 0, camps_captured: 0, deaths: 0, kd_ratio: 0. },
+            StoredPowerUps { power_ups: [0; NUM_POWERUPS] },
+            Cooldown(Timer::from_seconds(1., TimerMode::Once)),
+        )).id()
+    }
+
+    // what both sides need, the host and client apps add their own on top
+    fn base_app(net: &LoopbackNetwork, tick: u16) -> App {
+        let mut app = App::new();
+        app.insert_resource(net.clone())
+            .insert_resource(NetSim::new(Conditions::OFF, 0))
+            .insert_resource(MatchKey::default())
+            .insert_resource(NetworkAddresses { host_port: "7000".to_string(), client_port: "7001".to_string(), ip: "127.0.0.1".to_string() })
+            .insert_resource(net::Socket(None))
+            .insert_resource(net::TickNum(tick))
+            .insert_resource(net::TickRate(net::DEFAULT_TICKRATE))
+            .insert_resource(net::SendRate(net::MAX_TICKRATE))
+            .insert_resource(net::Ack { rmt_num: 0, bitfield: 0 })
+            .insert_resource(MapSeed(1))
+            .insert_resource(MapSize::new(64, 64))
+            .insert_resource(NumCamps(1))
+            .insert_resource(SpawnSettings::default())
+            .insert_resource(MapChoice::default())
+            .insert_resource(PlayerNames::default())
+            .insert_resource(Lobby::default())
+            .insert_resource(Migration::default())
+            .add_event::<PlayerJoinEvent>()
+            .add_event::<PlayerLeaveEvent>()
+            .add_event::<chat::ChatAckEvent>();
+        spawn_player(&mut app.world, 0);
+        spawn_player(&mut app.world, 1);
+        app
+    }
+
+    // moves the host and the enemy, and wears down HP, the camp and the chest, until FREEZE_TICK
+    fn host_sim(
+        tick: Res<net::TickNum>,
+        mut players: Query<(&Player, &mut PosBuffer, &mut HpBuffer, &mut DirBuffer, &mut EventBuffer)>,
+        mut enemies: Query<(&mut PosBuffer, &mut Health), (With<Enemy>, Without<Player>)>,
+        mut camps: Query<&mut CampEnemies>,
+        mut chests: Query<&mut Health, (With<ItemChest>, Without<Enemy>)>,
+    ) {
+        for (pl, mut pb, mut hb, mut db, mut eb) in &mut players {
+            hb.0.set(tick.0, Some(hp(tick.0) - pl.0));
+            if pl.0 != 0 { continue }
+            pb.0.set(tick.0, Some(host_pos(tick.0)));
+            db.0.set(tick.0, Some(0.));
+            eb.0.set(tick.0, Some(0));
+        }
+        for (mut pb, mut health) in &mut enemies {
+            pb.0.set(tick.0, Some(enemy_pos(tick.0)));
+            health.current = hp(tick.0) / 2;
+        }
+        for mut camp in &mut camps {
+            camp.current_enemies = if tick.0 < FREEZE_TICK { 3 } else { 1 };
+        }
+        for mut health in &mut chests {
+            health.current = hp(tick.0) / 4;
+        }
+    }
+
+    fn client_sim(tick: Res<net::TickNum>, mut players: Query<(&mut PosBuffer, &mut DirBuffer, &mut EventBuffer), With<LocalPlayer>>) {
+        for (mut pb, mut db, mut eb) in &mut players {
+            pb.0.set(tick.0, Some(client_pos(tick.0)));
+            db.0.set(tick.0, Some(1.));
+            eb.0.set(tick.0, Some(0));
+        }
+    }
+
+    fn host_app(net: &LoopbackNetwork) -> App {
+        let mut app = base_app(net, HOST_START);
+        app.insert_resource(net::IsHost(true))
+            .insert_resource(host::Connections(Vec::new()))
+            .insert_resource(host::Spectators(Vec::new()))
+            .insert_resource(host::MaxPlayers(player::DEFAULT_MAX_PLAYERS))
+            .insert_resource(BanList::default())
+            .insert_resource(WorldMap { tile_size: TILESIZE, biome_map: Grid::new(64, 64, Biome::Free) })
+            .insert_resource(State::new(AppState::Game))
+            .insert_resource(NextState::<AppState>::default())
+            .add_event::<UserCmdEvent>()
+            .add_event::<AttackEvent>()
+            .add_event::<SpawnEvent>()
+            .add_event::<chat::ChatSendEvent>()
+            .add_event::<chat::SystemMessage>()
+            .add_systems(Startup, host::connect.pipe(error::log))
+            .add_systems(Update, (host::update.pipe(error::log), player::handle_usercmd_events).chain())
+            .add_systems(FixedUpdate, (host_sim, host::fixed.pipe(error::log).run_if(in_state(AppState::Game)), net::increment_tick).chain());
+        app.world.spawn((Enemy(0), PosBuffer(CircularBuffer::new()), Health { current: 100, max: 100, dead: false }, EventBuffer(CircularBuffer::new())));
+        app.world.spawn((Camp(0), CampStatus(true), CampEnemies { max_enemies: 3, current_enemies: 3 }));
+        app.world.spawn((ItemChest { id: 0, contents: [0; CHEST_CONTENTS] }, Health { current: 100, max: 100, dead: false }));
+        app
+    }
+
+    // the client that the host will give id, on its own port
+    fn client_app(net: &LoopbackNetwork, id: u8) -> App {
+        let mut app = base_app(net, 0);
+        app.world.resource_mut::<NetworkAddresses>().client_port = (HOST_PORT + id as u16).to_string();
+        if id > 1 {
+            spawn_player(&mut app.world, id);
+        }
+        app.add_plugins((TaskPoolPlugin::default(), AssetPlugin::default()))
+            .add_asset::<MapFile>()
+            .insert_resource(net::IsHost(false))
+            .insert_resource(net::IsSpectator(false))
+            .insert_resource(LocalName("client".to_string()))
+            .insert_resource(PowerupAtlas { handle: Handle::default() })
+            .insert_resource(State::new(AppState::Game))
+            .insert_resource(NextState::<AppState>::default())
+            .add_event::<PlayerTickEvent>()
+            .add_event::<EnemyTickEvent>()
+            .add_event::<SetIdEvent>()
+            .add_event::<chat::ChatBroadcastEvent>()
+            .add_systems(Startup, client::connect.pipe(error::log))
+            .add_systems(Update, (client::update.pipe(error::log).run_if(net::is_client), player::handle_player_ticks, enemy::handle_packet).chain())
+            .add_systems(FixedUpdate, (client_sim, client::fixed.pipe(error::log).run_if(net::is_client), net::increment_tick).chain());
+        let mut local = app.world.query::<(Entity, &Player)>();
+        let local = local.iter(&app.world).find(|(_, pl)| pl.0 == id).unwrap().0;
+        app.world.entity_mut(local).insert(LocalPlayer);
+        app.world.spawn((Enemy(0), PosBuffer(CircularBuffer::new()), HpBuffer(CircularBuffer::new()), EventBuffer(CircularBuffer::new()), IsSpecial(false)));
+        app.world.spawn((Camp(0), CampStatus(false), CampEnemies { max_enemies: 3, current_enemies: 3 }));
+        app.world.spawn((ItemChest { id: 0, contents: [0; CHEST_CONTENTS] }, Health { current: 100, max: 100, dead: false }));
+        app
+    }
+
+    // a client that watches its host and can take over from it, with everything the host side needs
+    fn migrating_client_app(net: &LoopbackNetwork, id: u8) -> App {
+        let mut app = client_app(net, id);
+        // the new host sends snapshots to whoever it has a player for
+        for other in 2..=MIGRATING_CLIENTS {
+            if other != id {
+                spawn_player(&mut app.world, other);
+            }
+        }
+        app.insert_resource(PlayerId(id))
+            .insert_resource(Time::default())
+            .insert_resource(chat::Chat::default())
+            .insert_resource(host::Connections(Vec::new()))
+            .insert_resource(host::Spectators(Vec::new()))
+            .insert_resource(host::MaxPlayers(player::DEFAULT_MAX_PLAYERS))
+            .insert_resource(BanList::default())
+            .insert_resource(WorldMap { tile_size: TILESIZE, biome_map: Grid::new(64, 64, Biome::Free) })
+            .add_event::<TakeOverEvent>()
+            .add_event::<UserCmdEvent>()
+            .add_event::<chat::ChatSendEvent>()
+            .add_event::<chat::SystemMessage>()
+            .add_systems(Update, host::update.pipe(error::log).run_if(net::is_host))
+            .add_systems(FixedUpdate,
+                (migrate::watch_host.pipe(error::log).run_if(net::is_client),
+                migrate::take_over.pipe(error::log),
+                host::fixed.pipe(error::log).run_if(net::is_host)).chain().after(client::fixed).before(net::increment_tick));
+        app
+    }
+
+    // lets every app read what's waiting for it until nothing is left on the network
+    fn drain(net: &LoopbackNetwork, apps: &mut [&mut App]) {
+        for _ in 0..10 {
+            if net.in_flight() == 0 { return }
+            for app in apps.iter_mut() {
+                app.update();
+            }
+        }
+        panic!("{} datagrams never got read", net.in_flight());
+    }
+
+    // throws away everything waiting for addr, like the network lost it on the way
+    fn lose(net: &LoopbackNetwork, addr: SocketAddr) {
+        net.0.lock().unwrap().get_mut(&addr).unwrap().clear();
+    }
+
+    fn tick(app: &App) -> u16 {
+        app.world.resource::<net::TickNum>().0
+    }
+
+    fn player_pos(app: &mut App, id: u8, tick: u16) -> Option<Vec2> {
+        let mut query = app.world.query::<(&Player, &PosBuffer)>();
+        query.iter(&app.world).find(|(pl, _)| pl.0 == id).and_then(|(_, pb)| *pb.0.get(tick))
+    }
+
+    fn player_hp(app: &mut App, id: u8, tick: u16) -> Option<u8> {
+        let mut query = app.world.query::<(&Player, &HpBuffer)>();
+        query.iter(&app.world).find(|(pl, _)| pl.0 == id).and_then(|(_, hb)| *hb.0.get(tick))
+    }
+
+    #[test]
+    fn host_and_client_converge() {
+        let net = LoopbackNetwork::new();
+        let mut host = host_app(&net);
+        let mut client = client_app(&net, 1);
+        // startup binds the host first, then the client asks to join
+        host.update();
+        client.update();
+        drain(&net, &mut [&mut host, &mut client]);
+        assert_eq!(host.world.resource::<host::Connections>().0.len(), 1);
+
+        for _ in 0..STEPS {
+            host.world.run_schedule(FixedUpdate);
+            client.world.run_schedule(FixedUpdate);
+            drain(&net, &mut [&mut host, &mut client]);
+        }
+        assert_eq!(net.in_flight(), 0);
+        assert!(tick(&host) > FREEZE_TICK + 5);
+
+        // the last tick the host sent, and the tick the client applied it on
+        let sent = tick(&host) - 1;
+        let applied = tick(&client);
+        assert_eq!(player_pos(&mut host, 0, sent), Some(host_pos(sent)));
+        assert_eq!(player_pos(&mut client, 0, sent), Some(host_pos(sent)));
+        assert_eq!(player_pos(&mut host, 1, sent), Some(client_pos(sent)));
+        assert_eq!(player_hp(&mut client, 0, applied), player_hp(&mut host, 0, sent));
+        assert_eq!(player_hp(&mut client, 1, applied), player_hp(&mut host, 1, sent));
+        assert_eq!(player_hp(&mut client, 1, applied), Some(hp(sent) - 1));
+
+        let mut enemies = client.world.query::<(&PosBuffer, &HpBuffer, &Enemy)>();
+        let (pb, hb, _) = enemies.single(&client.world);
+        assert_eq!(*pb.0.get(sent), Some(enemy_pos(sent)));
+        assert_eq!(*hb.0.get(applied), Some(hp(sent) / 2));
+
+        let mut camps = client.world.query::<(&CampStatus, &CampEnemies)>();
+        let (status, camp) = camps.single(&client.world);
+        assert!(status.0);
+        assert_eq!(camp.current_enemies, 1);
+
+        let mut chests = client.world.query::<(&Health, &ItemChest)>();
+        let (health, _) = chests.single(&client.world);
+        assert_eq!(health.current, hp(sent) / 4);
+    }
+
+    #[test]
+    fn host_and_client_leave_the_lobby_together() {
+        let net = LoopbackNetwork::new();
+        let mut host = host_app(&net);
+        let mut client = client_app(&net, 1);
+        host.insert_resource(State::new(AppState::Lobby))
+            .insert_resource(LocalName("host".to_string()))
+            .add_systems(FixedUpdate, lobby::host_fixed.pipe(error::log).before(net::increment_tick));
+        client.insert_resource(State::new(AppState::Lobby))
+            .insert_resource(PlayerId(1))
+            .add_systems(FixedUpdate, lobby::client_fixed.pipe(error::log).before(net::increment_tick));
+        host.update();
+        client.update();
+        drain(&net, &mut [&mut host, &mut client]);
+        assert_eq!(host.world.resource::<host::Connections>().0.len(), 1);
+        host.world.resource_mut::<Lobby>().local_ready = true;
+        client.world.resource_mut::<Lobby>().local_ready = true;
+
+        // nothing moves the apps into Game here, so the step NextState first asks for it is when they'd go
+        let entered = |app: &App| app.world.resource::<NextState<AppState>>().0 == Some(AppState::Game);
+        let countdown = net::TickRate(net::DEFAULT_TICKRATE).ticks(lobby::COUNTDOWN_SECS) as usize;
+        let (mut host_entered, mut client_entered) = (None, None);
+        for step in 0..countdown + 10 {
+            host.world.run_schedule(FixedUpdate);
+            client.world.run_schedule(FixedUpdate);
+            if host_entered.is_none() && entered(&host) {
+                host_entered = Some((step, tick(&host)));
+            }
+            if client_entered.is_none() && entered(&client) {
+                client_entered = Some((step, tick(&client)));
+            }
+            drain(&net, &mut [&mut host, &mut client]);
+        }
+        assert!(host_entered.is_some());
+        assert_eq!(host_entered, client_entered);
+    }
+
+    #[test]
+    fn lost_chat_arrives_exactly_once() {
+        let net = LoopbackNetwork::new();
+        let mut host = host_app(&net);
+        let mut client = client_app(&net, 1);
+        host.insert_resource(Time::default())
+            .insert_resource(chat::Chat::default())
+            .insert_resource(chat::HostChat::default())
+            .add_event::<chat::SendChatEvent>()
+            .add_systems(Update, chat::host_update.pipe(error::log).after(host::update))
+            .add_systems(FixedUpdate, chat::host_fixed.pipe(error::log).before(net::increment_tick));
+        client.insert_resource(Time::default())
+            .insert_resource(chat::Chat::default())
+            .insert_resource(chat::ClientChat::default())
+            .add_event::<chat::SendChatEvent>()
+            .add_systems(Update, chat::client_update.pipe(error::log).after(client::update))
+            .add_systems(FixedUpdate, chat::client_fixed.pipe(error::log).before(net::increment_tick));
+        host.update();
+        client.update();
+        drain(&net, &mut [&mut host, &mut client]);
+        let host_addr: SocketAddr = format!("127.0.0.1:{}", HOST_PORT).parse().unwrap();
+        let client_addr: SocketAddr = format!("127.0.0.1:{}", HOST_PORT + 1).parse().unwrap();
+
+        // the first ChatSend never makes it
+        client.world.send_event(chat::SendChatEvent("hello".to_string()));
+        client.update();
+        client.world.run_schedule(FixedUpdate);
+        lose(&net, host_addr);
+
+        let hellos = |app: &App| app.world.resource::<chat::Chat>().lines.iter().filter(|line| line.from == 1 && line.text == "hello").count();
+        let mut lost_ack = false;
+        for _ in 0..net::DEFAULT_TICKRATE as usize * 2 {
+            host.world.run_schedule(FixedUpdate);
+            client.world.run_schedule(FixedUpdate);
+            host.update();
+            // and neither does the ack for the resend, so the host sees it a second time
+            if !lost_ack && hellos(&host) == 1 {
+                lose(&net, client_addr);
+                lost_ack = true;
+            }
+            drain(&net, &mut [&mut host, &mut client]);
+        }
+        assert!(lost_ack);
+        assert_eq!(net.in_flight(), 0);
+        assert_eq!(hellos(&host), 1);
+        assert_eq!(hellos(&client), 1);
+    }
+
+    #[test]
+    fn lowest_id_takes_over_when_the_host_leaves() {
+        let net = LoopbackNetwork::new();
+        let mut host = host_app(&net);
+        for id in 2..=MIGRATING_CLIENTS {
+            spawn_player(&mut host.world, id);
+        }
+        let mut a = migrating_client_app(&net, 1);
+        let mut b = migrating_client_app(&net, 2);
+        let mut c = migrating_client_app(&net, 3);
+        // joining in order, so a, b and c get ids 1, 2 and 3
+        host.update();
+        a.update();
+        b.update();
+        c.update();
+        drain(&net, &mut [&mut host, &mut a, &mut b, &mut c]);
+        assert_eq!(host.world.resource::<host::Connections>().0.len(), 3);
+
+        // long enough for a PeerList to go round
+        for _ in 0..net::DEFAULT_TICKRATE as usize * 2 {
+            for app in [&mut host, &mut a, &mut b, &mut c] {
+                app.world.run_schedule(FixedUpdate);
+            }
+            drain(&net, &mut [&mut host, &mut a, &mut b, &mut c]);
+        }
+        for client in [&a, &b, &c] {
+            assert_eq!(client.world.resource::<Migration>().peers.len(), 3);
+        }
+
+        // the host closes its socket without a word, everyone waits it out then follows player 1
+        drop(host);
+        let timeout = net::TickRate(net::DEFAULT_TICKRATE).ticks(migrate::HOST_TIMEOUT_SECS) as usize;
+        for _ in 0..timeout * 2 {
+            for client in [&mut a, &mut b, &mut c] {
+                client.world.run_schedule(FixedUpdate);
+            }
+            drain(&net, &mut [&mut a, &mut b, &mut c]);
+        }
+        assert!(a.world.resource::<net::IsHost>().0);
+        assert!(a.world.resource::<Migration>().took_over);
+        let new_host: std::net::SocketAddr = format!("127.0.0.1:{}", HOST_PORT + 1).parse().unwrap();
+        for client in [&b, &c] {
+            assert!(!client.world.resource::<net::IsHost>().0);
+            let migration = client.world.resource::<Migration>();
+            assert_eq!(migration.host_id, 1);
+            assert!(!migration.rejoining);
+            assert!(migration.silent_ticks < timeout as u16);
+            assert_eq!(client.world.resource::<net::Socket>().0.as_ref().unwrap().peer_addr().unwrap(), new_host);
+        }
+        let conns: Vec<u8> = a.world.resource::<host::Connections>().0.iter().map(|conn| conn.player_id).collect();
+        assert_eq!(conns, vec![2, 3]);
+    }
+}